@@ -1,11 +1,15 @@
 pub enum ActionRequest {
     Dummy,
+    ResolveAttachment,
 }
 
 impl ActionRequest {
     pub fn from(inner: wysiwyg::ActionRequest) -> Self {
         match inner {
             wysiwyg::ActionRequest::Dummy => Self::Dummy,
+            wysiwyg::ActionRequest::ResolveAttachment => {
+                Self::ResolveAttachment
+            }
         }
     }
 }
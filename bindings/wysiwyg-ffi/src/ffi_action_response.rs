@@ -1,11 +1,19 @@
 pub enum ActionResponse {
     Dummy,
+    AttachmentReady { html: String },
+    AttachmentRemoved,
 }
 
 impl From<ActionResponse> for wysiwyg::ActionResponse {
     fn from(inner: ActionResponse) -> Self {
         match inner {
             ActionResponse::Dummy => wysiwyg::ActionResponse::Dummy,
+            ActionResponse::AttachmentReady { html } => {
+                wysiwyg::ActionResponse::AttachmentReady { html }
+            }
+            ActionResponse::AttachmentRemoved => {
+                wysiwyg::ActionResponse::AttachmentRemoved
+            }
         }
     }
 }
@@ -0,0 +1,33 @@
+pub struct AnchoredLocation {
+    pub node_handle: Vec<u32>,
+    pub offset: u32,
+}
+
+impl AnchoredLocation {
+    pub fn from(inner: wysiwyg::AnchoredLocation) -> Self {
+        Self {
+            node_handle: inner
+                .handle
+                .raw()
+                .iter()
+                .map(|&index| u32::try_from(index).unwrap())
+                .collect(),
+            offset: u32::try_from(inner.offset).unwrap(),
+        }
+    }
+}
+
+impl From<AnchoredLocation> for wysiwyg::AnchoredLocation {
+    fn from(inner: AnchoredLocation) -> Self {
+        Self {
+            handle: wysiwyg::DomHandle::from_raw(
+                inner
+                    .node_handle
+                    .into_iter()
+                    .map(|index| usize::try_from(index).unwrap())
+                    .collect(),
+            ),
+            offset: usize::try_from(inner.offset).unwrap(),
+        }
+    }
+}
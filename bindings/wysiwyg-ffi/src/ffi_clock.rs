@@ -0,0 +1,23 @@
+use std::sync::Mutex;
+
+pub trait Clock: Send {
+    fn now_ms(&self) -> u64;
+}
+
+pub struct ClockAdapter {
+    clock: Mutex<Box<dyn Clock>>,
+}
+
+impl ClockAdapter {
+    pub fn new(clock: Box<dyn Clock>) -> Self {
+        Self {
+            clock: Mutex::new(clock),
+        }
+    }
+}
+
+impl wysiwyg::Clock for ClockAdapter {
+    fn now_ms(&self) -> u64 {
+        self.clock.lock().unwrap().now_ms()
+    }
+}
@@ -0,0 +1,40 @@
+pub enum ComposerCommand {
+    Bold,
+    Italic,
+    ToggleListType,
+    Indent,
+    Unindent,
+    MoveListItemUp,
+    MoveListItemDown,
+    RemoveList,
+    Undo,
+    Redo,
+    ApplyCopiedFormatting,
+    SetLinkWithText { link: String, text: String },
+}
+
+impl From<ComposerCommand> for wysiwyg::ComposerCommand<u16> {
+    fn from(inner: ComposerCommand) -> Self {
+        match inner {
+            ComposerCommand::Bold => Self::Bold,
+            ComposerCommand::Italic => Self::Italic,
+            ComposerCommand::ToggleListType => Self::ToggleListType,
+            ComposerCommand::Indent => Self::Indent,
+            ComposerCommand::Unindent => Self::Unindent,
+            ComposerCommand::MoveListItemUp => Self::MoveListItemUp,
+            ComposerCommand::MoveListItemDown => Self::MoveListItemDown,
+            ComposerCommand::RemoveList => Self::RemoveList,
+            ComposerCommand::Undo => Self::Undo,
+            ComposerCommand::Redo => Self::Redo,
+            ComposerCommand::ApplyCopiedFormatting => {
+                Self::ApplyCopiedFormatting
+            }
+            ComposerCommand::SetLinkWithText { link, text } => {
+                Self::SetLinkWithText {
+                    link: link.encode_utf16().collect(),
+                    text: text.encode_utf16().collect(),
+                }
+            }
+        }
+    }
+}
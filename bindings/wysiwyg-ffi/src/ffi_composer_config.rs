@@ -0,0 +1,61 @@
+use crate::ffi_enter_behaviour::EnterBehaviour;
+use crate::ffi_feature::Feature;
+use crate::ffi_link_formatting::LinkFormatting;
+use crate::ffi_mention_formatting::MentionFormatting;
+use crate::ffi_undo_coalescing::UndoCoalescing;
+
+pub struct ComposerConfig {
+    pub features: Vec<Feature>,
+    pub enter_behaviour: EnterBehaviour,
+    pub markdown_shortcuts: bool,
+    pub max_length: Option<u32>,
+    pub allowed_link_schemes: Vec<String>,
+    pub double_space_full_stop_ms: Option<u64>,
+    pub normalize_input: bool,
+    pub link_formatting: LinkFormatting,
+    pub mention_formatting: MentionFormatting,
+    pub word_locale: Option<String>,
+    pub operation_budget_ms: Option<u64>,
+    pub max_depth: Option<u32>,
+    pub format_word_at_cursor: bool,
+    pub max_undo_entries: Option<u32>,
+    pub max_link_text_length: Option<u32>,
+    pub max_undo_bytes: Option<u64>,
+    pub undo_coalescing: UndoCoalescing,
+}
+
+impl From<ComposerConfig> for wysiwyg::ComposerConfig {
+    fn from(inner: ComposerConfig) -> Self {
+        Self {
+            features: inner.features.into_iter().map(Into::into).collect(),
+            enter_behaviour: inner.enter_behaviour.into(),
+            markdown_shortcuts: inner.markdown_shortcuts,
+            max_length: inner
+                .max_length
+                .map(|max_length| usize::try_from(max_length).unwrap()),
+            allowed_link_schemes: inner.allowed_link_schemes,
+            double_space_full_stop: inner.double_space_full_stop_ms,
+            normalize_input: inner.normalize_input,
+            link_formatting: inner.link_formatting.into(),
+            mention_formatting: inner.mention_formatting.into(),
+            word_locale: inner.word_locale,
+            operation_budget_ms: inner.operation_budget_ms,
+            max_depth: inner
+                .max_depth
+                .map_or(wysiwyg::DEFAULT_MAX_DEPTH, |max_depth| {
+                    usize::try_from(max_depth).unwrap()
+                }),
+            format_word_at_cursor: inner.format_word_at_cursor,
+            max_undo_entries: inner
+                .max_undo_entries
+                .map(|max| usize::try_from(max).unwrap()),
+            max_link_text_length: inner
+                .max_link_text_length
+                .map(|max| usize::try_from(max).unwrap()),
+            max_undo_bytes: inner
+                .max_undo_bytes
+                .map(|max| usize::try_from(max).unwrap()),
+            undo_coalescing: inner.undo_coalescing.into(),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::ffi_action_request::ActionRequest;
+use crate::ffi_composer_error::ComposerError;
+use crate::ffi_menu_action::MenuAction;
+use crate::ffi_menu_state::MenuState;
+use crate::ffi_sanitize_report::SanitizeReport;
+use crate::ffi_text_update::TextUpdate;
+
+pub enum ComposerEffect {
+    TextUpdated {
+        text_update: TextUpdate,
+    },
+    MenuStateChanged {
+        menu_state: MenuState,
+    },
+    MenuActionChanged {
+        menu_action: MenuAction,
+    },
+    ActionRequested {
+        action_id: String,
+        action: ActionRequest,
+    },
+    Sanitized {
+        report: SanitizeReport,
+    },
+    Diagnostic {
+        message: String,
+    },
+    Failed {
+        error: ComposerError,
+    },
+}
+
+impl ComposerEffect {
+    pub fn from(inner: wysiwyg::ComposerEffect<u16>) -> Self {
+        match inner {
+            wysiwyg::ComposerEffect::TextUpdated(text_update) => {
+                Self::TextUpdated {
+                    text_update: TextUpdate::from(text_update),
+                }
+            }
+            wysiwyg::ComposerEffect::MenuStateChanged(menu_state) => {
+                Self::MenuStateChanged {
+                    menu_state: MenuState::from(menu_state),
+                }
+            }
+            wysiwyg::ComposerEffect::MenuActionChanged(menu_action) => {
+                Self::MenuActionChanged {
+                    menu_action: MenuAction::from(menu_action),
+                }
+            }
+            wysiwyg::ComposerEffect::ActionRequested(action) => {
+                Self::ActionRequested {
+                    action_id: action.action_id,
+                    action: ActionRequest::from(action.action),
+                }
+            }
+            wysiwyg::ComposerEffect::Sanitized(report) => Self::Sanitized {
+                report: SanitizeReport::from(report),
+            },
+            wysiwyg::ComposerEffect::Diagnostic(message) => {
+                Self::Diagnostic { message }
+            }
+            wysiwyg::ComposerEffect::Failed(error) => Self::Failed {
+                error: ComposerError::from(error),
+            },
+        }
+    }
+}
@@ -0,0 +1,15 @@
+pub enum ComposerError {
+    BudgetExceeded,
+    TooDeep,
+    Protected,
+}
+
+impl From<wysiwyg::ComposerError> for ComposerError {
+    fn from(inner: wysiwyg::ComposerError) -> Self {
+        match inner {
+            wysiwyg::ComposerError::BudgetExceeded => Self::BudgetExceeded,
+            wysiwyg::ComposerError::TooDeep => Self::TooDeep,
+            wysiwyg::ComposerError::Protected => Self::Protected,
+        }
+    }
+}
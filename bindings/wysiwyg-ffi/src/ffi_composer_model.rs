@@ -1,8 +1,30 @@
 use std::sync::{Arc, Mutex};
 
 use crate::ffi_action_response::ActionResponse;
+use crate::ffi_anchored_location::AnchoredLocation;
+use crate::ffi_clock::{Clock, ClockAdapter};
+use crate::ffi_composer_command::ComposerCommand;
+use crate::ffi_composer_config::ComposerConfig;
+use crate::ffi_composer_snapshot::ComposerSnapshot;
 use crate::ffi_composer_state::ComposerState;
 use crate::ffi_composer_update::ComposerUpdate;
+use crate::ffi_coverage::Coverage;
+use crate::ffi_dom_fragment::DomFragment;
+use crate::ffi_dom_observer::{DomObserver, ObserverAdapter};
+use crate::ffi_format::Format;
+use crate::ffi_history_item::HistoryItem;
+use crate::ffi_html_dialect::HtmlDialect;
+use crate::ffi_html_violation::HtmlViolation;
+use crate::ffi_input_event::InputEvent;
+use crate::ffi_line_col::LineCol;
+use crate::ffi_link_info::LinkInfo;
+use crate::ffi_markdown_style::MarkdownStyle;
+use crate::ffi_mention::Mention;
+use crate::ffi_pinned_node::PinnedNode;
+use crate::ffi_restore_options::RestoreOptions;
+use crate::ffi_restored_message::RestoredMessage;
+use crate::ffi_suggestion_pattern::SuggestionPattern;
+use crate::ffi_word_dictionary::{DictionaryAdapter, WordDictionary};
 
 pub struct ComposerModel {
     inner: Mutex<wysiwyg::ComposerModel<u16>>,
@@ -15,6 +37,156 @@ impl ComposerModel {
         }
     }
 
+    pub fn new_with_config(config: ComposerConfig) -> Self {
+        Self {
+            inner: Mutex::new(wysiwyg::ComposerModel::new_with_config(
+                config.into(),
+            )),
+        }
+    }
+
+    pub fn fork(self: &Arc<Self>) -> Arc<ComposerModel> {
+        Arc::new(Self {
+            inner: Mutex::new(self.inner.lock().unwrap().fork()),
+        })
+    }
+
+    pub fn set_content_from_html(
+        self: &Arc<Self>,
+        html: String,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().set_content_from_html(
+                &html.encode_utf16().collect::<Vec<_>>(),
+            ),
+        ))
+    }
+
+    pub fn set_content_from_markdown(
+        self: &Arc<Self>,
+        markdown: String,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().set_content_from_markdown(
+                &markdown.encode_utf16().collect::<Vec<_>>(),
+            ),
+        ))
+    }
+
+    pub fn set_content_from_message_html(
+        self: &Arc<Self>,
+        html: String,
+        options: RestoreOptions,
+    ) -> Arc<RestoredMessage> {
+        Arc::new(RestoredMessage::from(
+            self.inner.lock().unwrap().set_content_from_message_html(
+                &html.encode_utf16().collect::<Vec<_>>(),
+                &options.into(),
+            ),
+        ))
+    }
+
+    pub fn to_event_content(self: &Arc<Self>) -> String {
+        self.inner.lock().unwrap().to_event_content()
+    }
+
+    pub fn from_event_content(
+        self: &Arc<Self>,
+        json: String,
+        options: RestoreOptions,
+    ) -> Option<Arc<RestoredMessage>> {
+        self.inner
+            .lock()
+            .unwrap()
+            .from_event_content(&json, &options.into())
+            .ok()
+            .map(|restored| Arc::new(RestoredMessage::from(restored)))
+    }
+
+    pub fn from_legacy_draft(
+        self: &Arc<Self>,
+        draft: String,
+        options: RestoreOptions,
+    ) -> Option<Arc<RestoredMessage>> {
+        self.inner
+            .lock()
+            .unwrap()
+            .from_legacy_draft(&draft, &options.into())
+            .ok()
+            .map(|restored| Arc::new(RestoredMessage::from(restored)))
+    }
+
+    pub fn set_content_from_html_keeping_selection(
+        self: &Arc<Self>,
+        html: String,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner
+                .lock()
+                .unwrap()
+                .set_content_from_html_keeping_selection(
+                    &html.encode_utf16().collect::<Vec<_>>(),
+                ),
+        ))
+    }
+
+    pub fn paste_html(self: &Arc<Self>, html: String) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner
+                .lock()
+                .unwrap()
+                .paste_html(&html.encode_utf16().collect::<Vec<_>>()),
+        ))
+    }
+
+    pub fn get_content_as_html(
+        self: &Arc<Self>,
+        dialect: HtmlDialect,
+    ) -> String {
+        String::from_utf16_lossy(
+            &self
+                .inner
+                .lock()
+                .unwrap()
+                .get_content_as_html(dialect.into()),
+        )
+    }
+
+    pub fn get_content_as_message_html(self: &Arc<Self>) -> String {
+        String::from_utf16_lossy(
+            &self.inner.lock().unwrap().get_content_as_message_html(),
+        )
+    }
+
+    pub fn get_content_trimmed(self: &Arc<Self>) -> String {
+        String::from_utf16_lossy(
+            &self.inner.lock().unwrap().get_content_trimmed(),
+        )
+    }
+
+    pub fn validate_message_html(self: &Arc<Self>) -> Vec<HtmlViolation> {
+        self.inner
+            .lock()
+            .unwrap()
+            .validate_message_html()
+            .into_iter()
+            .map(HtmlViolation::from)
+            .collect()
+    }
+
+    pub fn get_content_as_plain_text(
+        self: &Arc<Self>,
+        fallback_formatting: bool,
+    ) -> String {
+        String::from_utf16_lossy(
+            &self
+                .inner
+                .lock()
+                .unwrap()
+                .get_content_as_plain_text(fallback_formatting),
+        )
+    }
+
     pub fn select(
         self: &Arc<Self>,
         start_utf16_codeunit: u32,
@@ -30,6 +202,17 @@ impl ComposerModel {
         self.inner.lock().unwrap().select(start, end);
     }
 
+    pub fn select_anchored(
+        self: &Arc<Self>,
+        start: AnchoredLocation,
+        end: AnchoredLocation,
+    ) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .select_anchored(&start.into(), &end.into())
+    }
+
     pub fn replace_text(
         self: &Arc<Self>,
         new_text: String,
@@ -42,6 +225,61 @@ impl ComposerModel {
         ))
     }
 
+    pub fn replace_text_at_time(
+        self: &Arc<Self>,
+        new_text: String,
+        time_ms: u64,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().replace_text_at_time(
+                &new_text.encode_utf16().collect::<Vec<_>>(),
+                time_ms,
+            ),
+        ))
+    }
+
+    pub fn apply_plain_text_edit(
+        self: &Arc<Self>,
+        old_text: String,
+        new_text: String,
+        new_cursor_utf16_codeunit: u32,
+    ) -> Arc<ComposerUpdate> {
+        let new_cursor = usize::try_from(new_cursor_utf16_codeunit).unwrap();
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().apply_plain_text_edit(
+                &old_text.encode_utf16().collect::<Vec<_>>(),
+                &new_text.encode_utf16().collect::<Vec<_>>(),
+                new_cursor,
+            ),
+        ))
+    }
+
+    pub fn set_plain_text_mode(
+        self: &Arc<Self>,
+        plain_text_mode: bool,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner
+                .lock()
+                .unwrap()
+                .set_plain_text_mode(plain_text_mode),
+        ))
+    }
+
+    pub fn is_plain_text_mode(self: &Arc<Self>) -> bool {
+        self.inner.lock().unwrap().is_plain_text_mode()
+    }
+
+    pub fn get_markdown_styles(self: &Arc<Self>) -> Vec<MarkdownStyle> {
+        self.inner
+            .lock()
+            .unwrap()
+            .get_markdown_styles()
+            .into_iter()
+            .map(MarkdownStyle::from)
+            .collect()
+    }
+
     pub fn replace_text_in(
         self: &Arc<Self>,
         new_text: String,
@@ -59,6 +297,22 @@ impl ComposerModel {
         ))
     }
 
+    pub fn insert_at(
+        self: &Arc<Self>,
+        location_utf16_codeunit: u32,
+        html: String,
+    ) -> Arc<ComposerUpdate> {
+        let location = wysiwyg::Location::from(
+            usize::try_from(location_utf16_codeunit).unwrap(),
+        );
+        Arc::new(ComposerUpdate::from(
+            self.inner
+                .lock()
+                .unwrap()
+                .insert_at(location, &html.encode_utf16().collect::<Vec<_>>()),
+        ))
+    }
+
     pub fn backspace(self: &Arc<Self>) -> Arc<ComposerUpdate> {
         Arc::new(ComposerUpdate::from(self.inner.lock().unwrap().backspace()))
     }
@@ -79,6 +333,18 @@ impl ComposerModel {
         ))
     }
 
+    pub fn apply_events(
+        self: &Arc<Self>,
+        events: Vec<InputEvent>,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner
+                .lock()
+                .unwrap()
+                .apply_events(events.into_iter().map(Into::into).collect()),
+        ))
+    }
+
     pub fn action_response(
         self: &Arc<Self>,
         action_id: String,
@@ -96,19 +362,544 @@ impl ComposerModel {
         Arc::new(ComposerUpdate::from(self.inner.lock().unwrap().enter()))
     }
 
+    pub fn subscribe(self: &Arc<Self>, observer: Box<dyn DomObserver>) {
+        self.inner
+            .lock()
+            .unwrap()
+            .subscribe(Box::new(ObserverAdapter::new(observer)));
+    }
+
+    pub fn set_word_dictionary(
+        self: &Arc<Self>,
+        dictionary: Box<dyn WordDictionary>,
+    ) {
+        self.inner
+            .lock()
+            .unwrap()
+            .set_word_dictionary(Arc::new(DictionaryAdapter::new(dictionary)));
+    }
+
+    pub fn set_clock(self: &Arc<Self>, clock: Box<dyn Clock>) {
+        self.inner
+            .lock()
+            .unwrap()
+            .set_clock(Arc::new(ClockAdapter::new(clock)));
+    }
+
+    pub fn select_word_at_cursor(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().select_word_at_cursor(),
+        ))
+    }
+
+    pub fn select_to_start_of_block(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().select_to_start_of_block(),
+        ))
+    }
+
+    pub fn select_to_end_of_block(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().select_to_end_of_block(),
+        ))
+    }
+
+    pub fn select_to_start_of_document(
+        self: &Arc<Self>,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().select_to_start_of_document(),
+        ))
+    }
+
+    pub fn select_to_end_of_document(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().select_to_end_of_document(),
+        ))
+    }
+
+    pub fn revision(self: &Arc<Self>) -> u64 {
+        self.inner.lock().unwrap().revision()
+    }
+
+    pub fn has_same_state(self: &Arc<Self>, other: Arc<ComposerModel>) -> bool {
+        // Locking the same model twice would deadlock
+        Arc::ptr_eq(self, &other)
+            || self
+                .inner
+                .lock()
+                .unwrap()
+                .has_same_state(&other.inner.lock().unwrap())
+    }
+
+    pub fn state_hash(self: &Arc<Self>) -> u64 {
+        self.inner.lock().unwrap().state_hash()
+    }
+
+    pub fn content_hash(self: &Arc<Self>) -> u64 {
+        self.inner.lock().unwrap().content_hash()
+    }
+
+    pub fn is_empty(self: &Arc<Self>, ignore_whitespace: bool) -> bool {
+        self.inner.lock().unwrap().is_empty(ignore_whitespace)
+    }
+
+    pub fn can_recall_previous(self: &Arc<Self>) -> bool {
+        self.inner.lock().unwrap().can_recall_previous()
+    }
+
+    pub fn up(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(self.inner.lock().unwrap().up()))
+    }
+
+    pub fn copy_formatting(self: &Arc<Self>) {
+        self.inner.lock().unwrap().copy_formatting();
+    }
+
+    pub fn apply_copied_formatting(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().apply_copied_formatting(),
+        ))
+    }
+
+    pub fn apply_formats(
+        self: &Arc<Self>,
+        formats: Vec<Format>,
+    ) -> Arc<ComposerUpdate> {
+        let formats: Vec<wysiwyg::Format> =
+            formats.into_iter().map(|f| f.into()).collect();
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().apply_formats(&formats),
+        ))
+    }
+
+    pub fn is_range_formatted(
+        self: &Arc<Self>,
+        start_utf16_codeunit: u32,
+        end_utf16_codeunit: u32,
+        format: Format,
+    ) -> Coverage {
+        let start = wysiwyg::Location::from(
+            usize::try_from(start_utf16_codeunit).unwrap(),
+        );
+        let end = wysiwyg::Location::from(
+            usize::try_from(end_utf16_codeunit).unwrap(),
+        );
+        Coverage::from(self.inner.lock().unwrap().is_range_formatted(
+            start,
+            end,
+            format.into(),
+        ))
+    }
+
+    pub fn toggle_list_type(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().toggle_list_type(),
+        ))
+    }
+
+    pub fn tab(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(self.inner.lock().unwrap().tab()))
+    }
+
+    pub fn shift_tab(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(self.inner.lock().unwrap().shift_tab()))
+    }
+
+    pub fn move_list_item_up(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().move_list_item_up(),
+        ))
+    }
+
+    pub fn move_list_item_down(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().move_list_item_down(),
+        ))
+    }
+
+    pub fn toggle_comment(
+        self: &Arc<Self>,
+        prefix: String,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner
+                .lock()
+                .unwrap()
+                .toggle_comment(&prefix.encode_utf16().collect::<Vec<_>>()),
+        ))
+    }
+
+    pub fn duplicate_line(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().duplicate_line(),
+        ))
+    }
+
+    pub fn move_line_up(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().move_line_up(),
+        ))
+    }
+
+    pub fn move_line_down(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().move_line_down(),
+        ))
+    }
+
+    pub fn remove_list(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().remove_list(),
+        ))
+    }
+
+    pub fn undo(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(self.inner.lock().unwrap().undo()))
+    }
+
+    pub fn redo(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(self.inner.lock().unwrap().redo()))
+    }
+
+    pub fn set_history_limits(
+        self: &Arc<Self>,
+        max_entries: Option<u32>,
+        max_bytes: Option<u64>,
+    ) {
+        self.inner.lock().unwrap().set_history_limits(
+            max_entries.map(|max| usize::try_from(max).unwrap()),
+            max_bytes.map(|max| usize::try_from(max).unwrap()),
+        )
+    }
+
+    pub fn undo_history(self: &Arc<Self>) -> Vec<HistoryItem> {
+        self.inner
+            .lock()
+            .unwrap()
+            .undo_history()
+            .map(HistoryItem::from)
+            .collect()
+    }
+
+    pub fn redo_history(self: &Arc<Self>) -> Vec<HistoryItem> {
+        self.inner
+            .lock()
+            .unwrap()
+            .redo_history()
+            .map(HistoryItem::from)
+            .collect()
+    }
+
+    pub fn snapshot(self: &Arc<Self>, max_history: u32) -> ComposerSnapshot {
+        ComposerSnapshot::from(
+            self.inner.lock().unwrap().snapshot(max_history as usize),
+        )
+    }
+
+    pub fn restore_snapshot(
+        self: &Arc<Self>,
+        snapshot: ComposerSnapshot,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner
+                .lock()
+                .unwrap()
+                .restore_snapshot(&snapshot.into()),
+        ))
+    }
+
+    pub fn apply(
+        self: &Arc<Self>,
+        command: ComposerCommand,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().apply(command.into()),
+        ))
+    }
+
     pub fn bold(self: &Arc<Self>) -> Arc<ComposerUpdate> {
         Arc::new(ComposerUpdate::from(self.inner.lock().unwrap().bold()))
     }
 
+    pub fn italic(self: &Arc<Self>) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(self.inner.lock().unwrap().italic()))
+    }
+
+    pub fn set_link_with_text(
+        self: &Arc<Self>,
+        link: String,
+        text: String,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().set_link_with_text(
+                &link.encode_utf16().collect::<Vec<_>>(),
+                &text.encode_utf16().collect::<Vec<_>>(),
+            ),
+        ))
+    }
+
+    pub fn offset_of(
+        self: &Arc<Self>,
+        substring: String,
+        from_utf16_codeunit: u32,
+    ) -> Option<u32> {
+        let from = wysiwyg::Location::from(
+            usize::try_from(from_utf16_codeunit).unwrap(),
+        );
+        self.inner
+            .lock()
+            .unwrap()
+            .offset_of(&substring.encode_utf16().collect::<Vec<_>>(), from)
+            .map(|location| {
+                let location: usize = location.into();
+                u32::try_from(location).unwrap()
+            })
+    }
+
+    pub fn text_in_range(
+        self: &Arc<Self>,
+        start_utf16_codeunit: u32,
+        end_utf16_codeunit: u32,
+    ) -> String {
+        let start = wysiwyg::Location::from(
+            usize::try_from(start_utf16_codeunit).unwrap(),
+        );
+        let end = wysiwyg::Location::from(
+            usize::try_from(end_utf16_codeunit).unwrap(),
+        );
+        String::from_utf16_lossy(
+            &self.inner.lock().unwrap().text_in_range(start, end),
+        )
+    }
+
+    pub fn char_at(
+        self: &Arc<Self>,
+        location_utf16_codeunit: u32,
+    ) -> Option<String> {
+        let location = wysiwyg::Location::from(
+            usize::try_from(location_utf16_codeunit).unwrap(),
+        );
+        self.inner
+            .lock()
+            .unwrap()
+            .char_at(location)
+            .map(String::from)
+    }
+
+    pub fn count_visual_lines(self: &Arc<Self>, wrap_hint_chars: u32) -> u32 {
+        let wrap_hint_chars = usize::try_from(wrap_hint_chars).unwrap();
+        let count = self
+            .inner
+            .lock()
+            .unwrap()
+            .count_visual_lines(wrap_hint_chars);
+        u32::try_from(count).unwrap()
+    }
+
+    pub fn location_to_line_col(
+        self: &Arc<Self>,
+        location_utf16_codeunit: u32,
+    ) -> LineCol {
+        let location = wysiwyg::Location::from(
+            usize::try_from(location_utf16_codeunit).unwrap(),
+        );
+        let (line, col) =
+            self.inner.lock().unwrap().location_to_line_col(location);
+        LineCol {
+            line: line as u32,
+            col: col as u32,
+        }
+    }
+
+    pub fn line_col_to_location(self: &Arc<Self>, line: u32, col: u32) -> u32 {
+        let line = usize::try_from(line).unwrap();
+        let col = usize::try_from(col).unwrap();
+        let location: usize = self
+            .inner
+            .lock()
+            .unwrap()
+            .line_col_to_location(line, col)
+            .into();
+        location as u32
+    }
+
+    pub fn replace_text_at_suggestion(
+        self: &Arc<Self>,
+        suggestion: SuggestionPattern,
+        mention: Mention,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().replace_text_at_suggestion(
+                &suggestion.into(),
+                &mention.into(),
+            ),
+        ))
+    }
+
+    pub fn update_mentions(
+        self: &Arc<Self>,
+        mentions: Vec<Mention>,
+    ) -> Arc<ComposerUpdate> {
+        let mentions: Vec<wysiwyg::Mention<u16>> =
+            mentions.into_iter().map(Mention::into).collect();
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().update_mentions(&mentions),
+        ))
+    }
+
+    pub fn mentioned_user_ids(self: &Arc<Self>) -> Vec<String> {
+        self.inner
+            .lock()
+            .unwrap()
+            .mentioned_user_ids()
+            .into_iter()
+            .map(|id| String::from_utf16_lossy(&id))
+            .collect()
+    }
+
+    pub fn get_link_at(
+        self: &Arc<Self>,
+        location_utf16_codeunit: u32,
+    ) -> Option<LinkInfo> {
+        let location = wysiwyg::Location::from(
+            usize::try_from(location_utf16_codeunit).unwrap(),
+        );
+        self.inner
+            .lock()
+            .unwrap()
+            .get_link_at(location)
+            .map(LinkInfo::from)
+    }
+
+    pub fn update_link(
+        self: &Arc<Self>,
+        url: String,
+        text: String,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().update_link(
+                &url.encode_utf16().collect::<Vec<_>>(),
+                &text.encode_utf16().collect::<Vec<_>>(),
+            ),
+        ))
+    }
+
+    pub fn insert_attachment_placeholder(
+        self: &Arc<Self>,
+        id: String,
+        label: String,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().insert_attachment_placeholder(
+                &id,
+                &label.encode_utf16().collect::<Vec<_>>(),
+            ),
+        ))
+    }
+
+    pub fn insert_custom_emote(
+        self: &Arc<Self>,
+        mxc_url: String,
+        shortcode: String,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().insert_custom_emote(
+                &mxc_url,
+                &shortcode.encode_utf16().collect::<Vec<_>>(),
+            ),
+        ))
+    }
+
+    pub fn insert_fragment(
+        self: &Arc<Self>,
+        fragment: Arc<DomFragment>,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().insert_fragment(fragment.inner()),
+        ))
+    }
+
+    pub fn pin(
+        self: &Arc<Self>,
+        node_handle: Vec<u32>,
+    ) -> Option<Arc<PinnedNode>> {
+        let handle = wysiwyg::DomHandle::from_raw(
+            node_handle
+                .into_iter()
+                .map(|index| usize::try_from(index).unwrap())
+                .collect(),
+        );
+        self.inner
+            .lock()
+            .unwrap()
+            .pin(&handle)
+            .map(|pinned| Arc::new(PinnedNode::from(pinned)))
+    }
+
+    pub fn handle_of_pinned(
+        self: &Arc<Self>,
+        node: Arc<PinnedNode>,
+    ) -> Option<Vec<u32>> {
+        self.inner
+            .lock()
+            .unwrap()
+            .handle_of_pinned(node.inner())
+            .map(|handle| {
+                handle
+                    .raw()
+                    .iter()
+                    .map(|&index| u32::try_from(index).unwrap())
+                    .collect()
+            })
+    }
+
+    pub fn set_protected(
+        self: &Arc<Self>,
+        node_handle: Vec<u32>,
+        protected: bool,
+    ) -> Arc<ComposerUpdate> {
+        let handle = wysiwyg::DomHandle::from_raw(
+            node_handle
+                .into_iter()
+                .map(|index| usize::try_from(index).unwrap())
+                .collect(),
+        );
+        Arc::new(ComposerUpdate::from(
+            self.inner.lock().unwrap().set_protected(&handle, protected),
+        ))
+    }
+
+    pub fn set_placeholder(self: &Arc<Self>, placeholder: String) {
+        self.inner
+            .lock()
+            .unwrap()
+            .set_placeholder(&placeholder.encode_utf16().collect::<Vec<_>>());
+    }
+
     pub fn dump_state(self: &Arc<Self>) -> ComposerState {
         let model = self.inner.lock().unwrap();
         let (start, end) = model.get_selection();
         let start: usize = start.into();
         let end: usize = end.into();
+        let (start_anchor, end_anchor) = model.get_anchored_selection();
         ComposerState {
             html: model.get_html(),
             start: start as u32,
             end: end as u32,
+            visible_length: u32::try_from(model.visible_length()).unwrap(),
+            html_length: u32::try_from(model.html_length()).unwrap(),
+            placeholder: model.placeholder().map(<[u16]>::to_vec),
+            plain_text_mode: model.is_plain_text_mode(),
+            start_anchor: AnchoredLocation::from(start_anchor),
+            end_anchor: AnchoredLocation::from(end_anchor),
+            revision: model.revision(),
         }
     }
 }
+
+impl Default for ComposerModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,27 @@
+use crate::ffi_snapshot_state::SnapshotState;
+
+pub struct ComposerSnapshot {
+    pub current: SnapshotState,
+    pub undo: Vec<SnapshotState>,
+    pub redo: Vec<SnapshotState>,
+}
+
+impl From<wysiwyg::ComposerSnapshot<u16>> for ComposerSnapshot {
+    fn from(inner: wysiwyg::ComposerSnapshot<u16>) -> Self {
+        Self {
+            current: inner.current.into(),
+            undo: inner.undo.into_iter().map(SnapshotState::from).collect(),
+            redo: inner.redo.into_iter().map(SnapshotState::from).collect(),
+        }
+    }
+}
+
+impl From<ComposerSnapshot> for wysiwyg::ComposerSnapshot<u16> {
+    fn from(snapshot: ComposerSnapshot) -> Self {
+        Self {
+            current: snapshot.current.into(),
+            undo: snapshot.undo.into_iter().map(Into::into).collect(),
+            redo: snapshot.redo.into_iter().map(Into::into).collect(),
+        }
+    }
+}
@@ -1,5 +1,14 @@
+use crate::ffi_anchored_location::AnchoredLocation;
+
 pub struct ComposerState {
     pub html: Vec<u16>,
     pub start: u32,
     pub end: u32,
+    pub visible_length: u32,
+    pub html_length: u32,
+    pub placeholder: Option<Vec<u16>>,
+    pub plain_text_mode: bool,
+    pub start_anchor: AnchoredLocation,
+    pub end_anchor: AnchoredLocation,
+    pub revision: u64,
 }
@@ -1,7 +1,11 @@
 use std::sync::Arc;
 
 use crate::ffi_composer_action::ComposerAction;
+use crate::ffi_composer_effect::ComposerEffect;
+use crate::ffi_composer_error::ComposerError;
+use crate::ffi_menu_action::MenuAction;
 use crate::ffi_menu_state::MenuState;
+use crate::ffi_sanitize_report::SanitizeReport;
 use crate::ffi_text_update::TextUpdate;
 
 pub struct ComposerUpdate {
@@ -21,6 +25,10 @@ impl ComposerUpdate {
         MenuState::from(self.inner.menu_state.clone())
     }
 
+    pub fn menu_action(&self) -> MenuAction {
+        MenuAction::from(self.inner.menu_action.clone())
+    }
+
     pub fn actions(&self) -> Vec<Arc<ComposerAction>> {
         self.inner
             .actions
@@ -28,4 +36,28 @@ impl ComposerUpdate {
             .map(|action| Arc::new(ComposerAction::from(action.clone())))
             .collect()
     }
+
+    pub fn diagnostics(&self) -> Vec<String> {
+        self.inner.diagnostics.clone()
+    }
+
+    pub fn error(&self) -> Option<ComposerError> {
+        self.inner.error.map(ComposerError::from)
+    }
+
+    pub fn sanitize_report(&self) -> SanitizeReport {
+        SanitizeReport::from(self.inner.sanitize_report.clone())
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.inner.revision
+    }
+
+    pub fn effects(&self) -> Vec<ComposerEffect> {
+        self.inner
+            .effects()
+            .into_iter()
+            .map(ComposerEffect::from)
+            .collect()
+    }
 }
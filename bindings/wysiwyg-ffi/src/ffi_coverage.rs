@@ -0,0 +1,15 @@
+pub enum Coverage {
+    Full,
+    Partial,
+    None,
+}
+
+impl From<wysiwyg::Coverage> for Coverage {
+    fn from(inner: wysiwyg::Coverage) -> Self {
+        match inner {
+            wysiwyg::Coverage::Full => Self::Full,
+            wysiwyg::Coverage::Partial => Self::Partial,
+            wysiwyg::Coverage::None => Self::None,
+        }
+    }
+}
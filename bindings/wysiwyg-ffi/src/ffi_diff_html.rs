@@ -0,0 +1,13 @@
+pub struct DiffHtml {
+    pub html: String,
+    pub has_changes: bool,
+}
+
+impl From<wysiwyg::DiffHtml<u16>> for DiffHtml {
+    fn from(inner: wysiwyg::DiffHtml<u16>) -> Self {
+        Self {
+            html: String::from_utf16_lossy(&inner.html),
+            has_changes: inner.has_changes,
+        }
+    }
+}
@@ -0,0 +1,43 @@
+pub enum DomEvent {
+    NodeInserted {
+        node_handle: Vec<u32>,
+        html: Vec<u16>,
+    },
+    NodeRemoved {
+        node_handle: Vec<u32>,
+    },
+    TextChanged {
+        node_handle: Vec<u32>,
+        new_text: Vec<u16>,
+    },
+}
+
+impl From<&wysiwyg::DomEvent<u16>> for DomEvent {
+    fn from(inner: &wysiwyg::DomEvent<u16>) -> Self {
+        match inner {
+            wysiwyg::DomEvent::NodeInserted { handle, html } => {
+                Self::NodeInserted {
+                    node_handle: raw_handle(handle),
+                    html: html.clone(),
+                }
+            }
+            wysiwyg::DomEvent::NodeRemoved { handle } => Self::NodeRemoved {
+                node_handle: raw_handle(handle),
+            },
+            wysiwyg::DomEvent::TextChanged { handle, new_text } => {
+                Self::TextChanged {
+                    node_handle: raw_handle(handle),
+                    new_text: new_text.clone(),
+                }
+            }
+        }
+    }
+}
+
+fn raw_handle(handle: &wysiwyg::DomHandle) -> Vec<u32> {
+    handle
+        .raw()
+        .iter()
+        .map(|&index| u32::try_from(index).unwrap())
+        .collect()
+}
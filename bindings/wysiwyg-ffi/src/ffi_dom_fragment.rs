@@ -0,0 +1,23 @@
+use std::sync::Arc;
+
+pub struct DomFragment {
+    inner: wysiwyg::DomFragment<u16>,
+}
+
+impl DomFragment {
+    pub fn from(inner: wysiwyg::DomFragment<u16>) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &wysiwyg::DomFragment<u16> {
+        &self.inner
+    }
+
+    pub fn quoted(self: &Arc<Self>) -> Arc<DomFragment> {
+        Arc::new(Self::from(self.inner.quoted()))
+    }
+
+    pub fn to_html(self: &Arc<Self>) -> String {
+        String::from_utf16_lossy(&self.inner.to_html())
+    }
+}
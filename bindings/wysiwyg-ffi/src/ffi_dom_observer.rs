@@ -0,0 +1,21 @@
+use crate::ffi_dom_event::DomEvent;
+
+pub trait DomObserver: Send {
+    fn on_event(&self, event: DomEvent);
+}
+
+pub struct ObserverAdapter {
+    observer: Box<dyn DomObserver>,
+}
+
+impl ObserverAdapter {
+    pub fn new(observer: Box<dyn DomObserver>) -> Self {
+        Self { observer }
+    }
+}
+
+impl wysiwyg::DomObserver<u16> for ObserverAdapter {
+    fn on_event(&mut self, event: &wysiwyg::DomEvent<u16>) {
+        self.observer.on_event(DomEvent::from(event));
+    }
+}
@@ -0,0 +1,13 @@
+pub enum EnterBehaviour {
+    Send,
+    NewLine,
+}
+
+impl From<EnterBehaviour> for wysiwyg::EnterBehaviour {
+    fn from(inner: EnterBehaviour) -> Self {
+        match inner {
+            EnterBehaviour::Send => wysiwyg::EnterBehaviour::Send,
+            EnterBehaviour::NewLine => wysiwyg::EnterBehaviour::NewLine,
+        }
+    }
+}
@@ -0,0 +1,31 @@
+pub enum Feature {
+    Bold,
+    Italic,
+    Links,
+    OrderedList,
+    UnorderedList,
+}
+
+impl From<Feature> for wysiwyg::Feature {
+    fn from(inner: Feature) -> Self {
+        match inner {
+            Feature::Bold => wysiwyg::Feature::Bold,
+            Feature::Italic => wysiwyg::Feature::Italic,
+            Feature::Links => wysiwyg::Feature::Links,
+            Feature::OrderedList => wysiwyg::Feature::OrderedList,
+            Feature::UnorderedList => wysiwyg::Feature::UnorderedList,
+        }
+    }
+}
+
+impl From<wysiwyg::Feature> for Feature {
+    fn from(inner: wysiwyg::Feature) -> Self {
+        match inner {
+            wysiwyg::Feature::Bold => Feature::Bold,
+            wysiwyg::Feature::Italic => Feature::Italic,
+            wysiwyg::Feature::Links => Feature::Links,
+            wysiwyg::Feature::OrderedList => Feature::OrderedList,
+            wysiwyg::Feature::UnorderedList => Feature::UnorderedList,
+        }
+    }
+}
@@ -0,0 +1,23 @@
+pub enum Format {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Superscript,
+    Subscript,
+    InlineCode,
+}
+
+impl From<Format> for wysiwyg::Format {
+    fn from(inner: Format) -> Self {
+        match inner {
+            Format::Bold => Self::Bold,
+            Format::Italic => Self::Italic,
+            Format::Underline => Self::Underline,
+            Format::Strikethrough => Self::Strikethrough,
+            Format::Superscript => Self::Superscript,
+            Format::Subscript => Self::Subscript,
+            Format::InlineCode => Self::InlineCode,
+        }
+    }
+}
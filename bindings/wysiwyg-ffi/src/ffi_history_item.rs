@@ -0,0 +1,15 @@
+pub struct HistoryItem {
+    pub label: String,
+    pub timestamp_ms: Option<u64>,
+    pub content_len: u32,
+}
+
+impl From<&wysiwyg::HistoryItem> for HistoryItem {
+    fn from(inner: &wysiwyg::HistoryItem) -> Self {
+        Self {
+            label: inner.label.to_owned(),
+            timestamp_ms: inner.timestamp_ms,
+            content_len: u32::try_from(inner.content_len).unwrap(),
+        }
+    }
+}
@@ -0,0 +1,15 @@
+pub enum HtmlDialect {
+    MatrixMessage,
+    Editor,
+    Preview,
+}
+
+impl From<HtmlDialect> for wysiwyg::HtmlDialect {
+    fn from(inner: HtmlDialect) -> Self {
+        match inner {
+            HtmlDialect::MatrixMessage => wysiwyg::HtmlDialect::MatrixMessage,
+            HtmlDialect::Editor => wysiwyg::HtmlDialect::Editor,
+            HtmlDialect::Preview => wysiwyg::HtmlDialect::Preview,
+        }
+    }
+}
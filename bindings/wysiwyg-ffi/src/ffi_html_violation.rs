@@ -0,0 +1,34 @@
+pub enum HtmlViolation {
+    Element {
+        name: String,
+    },
+    Attribute {
+        element: String,
+        attribute: String,
+    },
+    AttributeValue {
+        element: String,
+        attribute: String,
+        value: String,
+    },
+}
+
+impl From<wysiwyg::HtmlViolation> for HtmlViolation {
+    fn from(inner: wysiwyg::HtmlViolation) -> Self {
+        match inner {
+            wysiwyg::HtmlViolation::Element { name } => Self::Element { name },
+            wysiwyg::HtmlViolation::Attribute { element, attribute } => {
+                Self::Attribute { element, attribute }
+            }
+            wysiwyg::HtmlViolation::AttributeValue {
+                element,
+                attribute,
+                value,
+            } => Self::AttributeValue {
+                element,
+                attribute,
+                value,
+            },
+        }
+    }
+}
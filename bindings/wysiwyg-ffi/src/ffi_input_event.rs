@@ -0,0 +1,62 @@
+pub enum InputEvent {
+    Select {
+        start_utf16_codeunit: u32,
+        end_utf16_codeunit: u32,
+    },
+    ReplaceText {
+        new_text: String,
+    },
+    ReplaceTextIn {
+        new_text: String,
+        start: u32,
+        end: u32,
+    },
+    Backspace,
+    Delete,
+    DeleteIn {
+        start: u32,
+        end: u32,
+    },
+    Enter,
+}
+
+impl From<InputEvent> for wysiwyg::InputEvent<u16> {
+    fn from(inner: InputEvent) -> Self {
+        match inner {
+            InputEvent::Select {
+                start_utf16_codeunit,
+                end_utf16_codeunit,
+            } => wysiwyg::InputEvent::Select {
+                start: wysiwyg::Location::from(
+                    usize::try_from(start_utf16_codeunit).unwrap(),
+                ),
+                end: wysiwyg::Location::from(
+                    usize::try_from(end_utf16_codeunit).unwrap(),
+                ),
+            },
+            InputEvent::ReplaceText { new_text } => {
+                wysiwyg::InputEvent::ReplaceText {
+                    new_text: new_text.encode_utf16().collect(),
+                }
+            }
+            InputEvent::ReplaceTextIn {
+                new_text,
+                start,
+                end,
+            } => wysiwyg::InputEvent::ReplaceTextIn {
+                new_text: new_text.encode_utf16().collect(),
+                start: usize::try_from(start).unwrap(),
+                end: usize::try_from(end).unwrap(),
+            },
+            InputEvent::Backspace => wysiwyg::InputEvent::Backspace,
+            InputEvent::Delete => wysiwyg::InputEvent::Delete,
+            InputEvent::DeleteIn { start, end } => {
+                wysiwyg::InputEvent::DeleteIn {
+                    start: usize::try_from(start).unwrap(),
+                    end: usize::try_from(end).unwrap(),
+                }
+            }
+            InputEvent::Enter => wysiwyg::InputEvent::Enter,
+        }
+    }
+}
@@ -0,0 +1,4 @@
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
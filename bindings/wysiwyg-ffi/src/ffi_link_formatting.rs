@@ -0,0 +1,13 @@
+pub enum LinkFormatting {
+    Inside,
+    SplitLink,
+}
+
+impl From<LinkFormatting> for wysiwyg::LinkFormatting {
+    fn from(inner: LinkFormatting) -> Self {
+        match inner {
+            LinkFormatting::Inside => wysiwyg::LinkFormatting::Inside,
+            LinkFormatting::SplitLink => wysiwyg::LinkFormatting::SplitLink,
+        }
+    }
+}
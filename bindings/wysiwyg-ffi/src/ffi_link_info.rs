@@ -0,0 +1,19 @@
+pub struct LinkInfo {
+    pub url: String,
+    pub text: String,
+    pub start_utf16_codeunit: u32,
+    pub end_utf16_codeunit: u32,
+}
+
+impl From<wysiwyg::LinkInfo<u16>> for LinkInfo {
+    fn from(inner: wysiwyg::LinkInfo<u16>) -> Self {
+        let start_utf16_codeunit: usize = inner.start.into();
+        let end_utf16_codeunit: usize = inner.end.into();
+        Self {
+            url: String::from_utf16_lossy(&inner.url),
+            text: String::from_utf16_lossy(&inner.text),
+            start_utf16_codeunit: u32::try_from(start_utf16_codeunit).unwrap(),
+            end_utf16_codeunit: u32::try_from(end_utf16_codeunit).unwrap(),
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use crate::ffi_markdown_style_kind::MarkdownStyleKind;
+
+pub struct MarkdownStyle {
+    pub kind: MarkdownStyleKind,
+    pub start_utf16_codeunit: u32,
+    pub end_utf16_codeunit: u32,
+}
+
+impl From<wysiwyg::MarkdownStyle> for MarkdownStyle {
+    fn from(inner: wysiwyg::MarkdownStyle) -> Self {
+        let start_utf16_codeunit: usize = inner.start.into();
+        let end_utf16_codeunit: usize = inner.end.into();
+        Self {
+            kind: MarkdownStyleKind::from(inner.kind),
+            start_utf16_codeunit: u32::try_from(start_utf16_codeunit).unwrap(),
+            end_utf16_codeunit: u32::try_from(end_utf16_codeunit).unwrap(),
+        }
+    }
+}
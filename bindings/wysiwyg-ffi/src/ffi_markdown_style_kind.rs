@@ -0,0 +1,45 @@
+pub enum MarkdownStyleKind {
+    BoldMarker,
+    Bold,
+    ItalicMarker,
+    Italic,
+    StrikethroughMarker,
+    Strikethrough,
+    CodeMarker,
+    Code,
+    LinkMarker,
+    LinkText,
+    LinkUrl,
+    QuoteMarker,
+    ListMarker,
+    CodeBlockFence,
+    CodeBlock,
+    Escape,
+    Html,
+}
+
+impl From<wysiwyg::MarkdownStyleKind> for MarkdownStyleKind {
+    fn from(inner: wysiwyg::MarkdownStyleKind) -> Self {
+        match inner {
+            wysiwyg::MarkdownStyleKind::BoldMarker => Self::BoldMarker,
+            wysiwyg::MarkdownStyleKind::Bold => Self::Bold,
+            wysiwyg::MarkdownStyleKind::ItalicMarker => Self::ItalicMarker,
+            wysiwyg::MarkdownStyleKind::Italic => Self::Italic,
+            wysiwyg::MarkdownStyleKind::StrikethroughMarker => {
+                Self::StrikethroughMarker
+            }
+            wysiwyg::MarkdownStyleKind::Strikethrough => Self::Strikethrough,
+            wysiwyg::MarkdownStyleKind::CodeMarker => Self::CodeMarker,
+            wysiwyg::MarkdownStyleKind::Code => Self::Code,
+            wysiwyg::MarkdownStyleKind::LinkMarker => Self::LinkMarker,
+            wysiwyg::MarkdownStyleKind::LinkText => Self::LinkText,
+            wysiwyg::MarkdownStyleKind::LinkUrl => Self::LinkUrl,
+            wysiwyg::MarkdownStyleKind::QuoteMarker => Self::QuoteMarker,
+            wysiwyg::MarkdownStyleKind::ListMarker => Self::ListMarker,
+            wysiwyg::MarkdownStyleKind::CodeBlockFence => Self::CodeBlockFence,
+            wysiwyg::MarkdownStyleKind::CodeBlock => Self::CodeBlock,
+            wysiwyg::MarkdownStyleKind::Escape => Self::Escape,
+            wysiwyg::MarkdownStyleKind::Html => Self::Html,
+        }
+    }
+}
@@ -0,0 +1,17 @@
+pub struct Mention {
+    pub id: String,
+    pub display_name: String,
+    pub avatar_url: Option<String>,
+}
+
+impl From<Mention> for wysiwyg::Mention<u16> {
+    fn from(inner: Mention) -> Self {
+        Self {
+            id: inner.id.encode_utf16().collect(),
+            display_name: inner.display_name.encode_utf16().collect(),
+            avatar_url: inner
+                .avatar_url
+                .map(|url| url.encode_utf16().collect()),
+        }
+    }
+}
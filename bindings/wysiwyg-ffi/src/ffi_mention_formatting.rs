@@ -0,0 +1,15 @@
+pub enum MentionFormatting {
+    Skip,
+    StyleText,
+}
+
+impl From<MentionFormatting> for wysiwyg::MentionFormatting {
+    fn from(inner: MentionFormatting) -> Self {
+        match inner {
+            MentionFormatting::Skip => wysiwyg::MentionFormatting::Skip,
+            MentionFormatting::StyleText => {
+                wysiwyg::MentionFormatting::StyleText
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use crate::ffi_pattern_key::PatternKey;
+
+pub enum MenuAction {
+    None,
+    NavigateHistory,
+    Suggestion {
+        key: PatternKey,
+        text: String,
+        start_utf16_codeunit: u32,
+        end_utf16_codeunit: u32,
+    },
+}
+
+impl MenuAction {
+    pub fn from(inner: wysiwyg::MenuAction<u16>) -> Self {
+        match inner {
+            wysiwyg::MenuAction::None => Self::None,
+            wysiwyg::MenuAction::NavigateHistory => Self::NavigateHistory,
+            wysiwyg::MenuAction::Suggestion(pattern) => {
+                let start_utf16_codeunit: usize = pattern.start.into();
+                let end_utf16_codeunit: usize = pattern.end.into();
+                Self::Suggestion {
+                    key: pattern.key.into(),
+                    text: String::from_utf16_lossy(&pattern.text),
+                    start_utf16_codeunit: u32::try_from(start_utf16_codeunit)
+                        .unwrap(),
+                    end_utf16_codeunit: u32::try_from(end_utf16_codeunit)
+                        .unwrap(),
+                }
+            }
+        }
+    }
+}
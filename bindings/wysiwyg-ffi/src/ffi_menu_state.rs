@@ -1,11 +1,36 @@
+use crate::ffi_feature::Feature;
+
 pub enum MenuState {
     None,
+    Update {
+        active_features: Vec<Feature>,
+        partially_active_features: Vec<Feature>,
+        can_undo: bool,
+        can_redo: bool,
+    },
 }
 
 impl MenuState {
     pub fn from(inner: wysiwyg::MenuState) -> Self {
         match inner {
             wysiwyg::MenuState::None => Self::None,
+            wysiwyg::MenuState::Update {
+                active_features,
+                partially_active_features,
+                can_undo,
+                can_redo,
+            } => Self::Update {
+                active_features: active_features
+                    .into_iter()
+                    .map(Feature::from)
+                    .collect(),
+                partially_active_features: partially_active_features
+                    .into_iter()
+                    .map(Feature::from)
+                    .collect(),
+                can_undo,
+                can_redo,
+            },
         }
     }
 }
@@ -0,0 +1,25 @@
+pub enum ParseWarning {
+    UnclosedElement { name: String },
+    StrayEndTag { name: String },
+    UnterminatedComment,
+    TooDeep { max_depth: u32 },
+}
+
+impl From<wysiwyg::ParseWarning> for ParseWarning {
+    fn from(inner: wysiwyg::ParseWarning) -> Self {
+        match inner {
+            wysiwyg::ParseWarning::UnclosedElement { name } => {
+                Self::UnclosedElement { name }
+            }
+            wysiwyg::ParseWarning::StrayEndTag { name } => {
+                Self::StrayEndTag { name }
+            }
+            wysiwyg::ParseWarning::UnterminatedComment => {
+                Self::UnterminatedComment
+            }
+            wysiwyg::ParseWarning::TooDeep { max_depth } => Self::TooDeep {
+                max_depth: u32::try_from(max_depth).unwrap_or(u32::MAX),
+            },
+        }
+    }
+}
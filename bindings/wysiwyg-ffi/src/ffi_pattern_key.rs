@@ -0,0 +1,25 @@
+pub enum PatternKey {
+    At,
+    Hash,
+    Slash,
+}
+
+impl From<wysiwyg::PatternKey> for PatternKey {
+    fn from(inner: wysiwyg::PatternKey) -> Self {
+        match inner {
+            wysiwyg::PatternKey::At => PatternKey::At,
+            wysiwyg::PatternKey::Hash => PatternKey::Hash,
+            wysiwyg::PatternKey::Slash => PatternKey::Slash,
+        }
+    }
+}
+
+impl From<PatternKey> for wysiwyg::PatternKey {
+    fn from(inner: PatternKey) -> Self {
+        match inner {
+            PatternKey::At => wysiwyg::PatternKey::At,
+            PatternKey::Hash => wysiwyg::PatternKey::Hash,
+            PatternKey::Slash => wysiwyg::PatternKey::Slash,
+        }
+    }
+}
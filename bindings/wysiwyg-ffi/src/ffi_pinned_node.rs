@@ -0,0 +1,13 @@
+pub struct PinnedNode {
+    inner: wysiwyg::PinnedNode,
+}
+
+impl PinnedNode {
+    pub fn from(inner: wysiwyg::PinnedNode) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &wysiwyg::PinnedNode {
+        &self.inner
+    }
+}
@@ -0,0 +1,16 @@
+use crate::ffi_html_violation::HtmlViolation;
+use crate::ffi_removal_severity::RemovalSeverity;
+
+pub struct Removal {
+    pub violation: HtmlViolation,
+    pub severity: RemovalSeverity,
+}
+
+impl From<wysiwyg::HtmlViolation> for Removal {
+    fn from(inner: wysiwyg::HtmlViolation) -> Self {
+        Self {
+            severity: inner.severity().into(),
+            violation: inner.into(),
+        }
+    }
+}
@@ -0,0 +1,15 @@
+pub enum RemovalSeverity {
+    Cosmetic,
+    Unsupported,
+    Dangerous,
+}
+
+impl From<wysiwyg::RemovalSeverity> for RemovalSeverity {
+    fn from(inner: wysiwyg::RemovalSeverity) -> Self {
+        match inner {
+            wysiwyg::RemovalSeverity::Cosmetic => Self::Cosmetic,
+            wysiwyg::RemovalSeverity::Unsupported => Self::Unsupported,
+            wysiwyg::RemovalSeverity::Dangerous => Self::Dangerous,
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use crate::ffi_html_violation::HtmlViolation;
+
+pub enum RestoreChange {
+    ReplyRemoved,
+    Downgraded {
+        element: String,
+        replacement: Option<String>,
+    },
+    Stripped {
+        violation: HtmlViolation,
+    },
+    MentionResolved {
+        id: String,
+    },
+}
+
+impl From<wysiwyg::RestoreChange> for RestoreChange {
+    fn from(inner: wysiwyg::RestoreChange) -> Self {
+        match inner {
+            wysiwyg::RestoreChange::ReplyRemoved => Self::ReplyRemoved,
+            wysiwyg::RestoreChange::Downgraded {
+                element,
+                replacement,
+            } => Self::Downgraded {
+                element,
+                replacement,
+            },
+            wysiwyg::RestoreChange::Stripped(violation) => Self::Stripped {
+                violation: violation.into(),
+            },
+            wysiwyg::RestoreChange::MentionResolved { id } => {
+                Self::MentionResolved { id }
+            }
+        }
+    }
+}
@@ -0,0 +1,15 @@
+pub struct RestoreOptions {
+    pub strip_reply: bool,
+    pub resolve_mentions: bool,
+    pub downgrade_unsupported: bool,
+}
+
+impl From<RestoreOptions> for wysiwyg::RestoreOptions {
+    fn from(inner: RestoreOptions) -> Self {
+        Self {
+            strip_reply: inner.strip_reply,
+            resolve_mentions: inner.resolve_mentions,
+            downgrade_unsupported: inner.downgrade_unsupported,
+        }
+    }
+}
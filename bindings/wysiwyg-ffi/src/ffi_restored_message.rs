@@ -0,0 +1,27 @@
+use std::sync::Arc;
+
+use crate::ffi_composer_update::ComposerUpdate;
+use crate::ffi_restore_change::RestoreChange;
+
+pub struct RestoredMessage {
+    inner: wysiwyg::RestoredMessage<u16>,
+}
+
+impl RestoredMessage {
+    pub fn from(inner: wysiwyg::RestoredMessage<u16>) -> Self {
+        Self { inner }
+    }
+
+    pub fn update(&self) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(self.inner.update.clone()))
+    }
+
+    pub fn changes(&self) -> Vec<RestoreChange> {
+        self.inner
+            .changes
+            .iter()
+            .cloned()
+            .map(RestoreChange::from)
+            .collect()
+    }
+}
@@ -0,0 +1,18 @@
+use crate::ffi_removal::Removal;
+use crate::ffi_removal_severity::RemovalSeverity;
+
+pub struct SanitizeReport {
+    pub removals: Vec<Removal>,
+    pub severity: Option<RemovalSeverity>,
+    pub is_meaningful: bool,
+}
+
+impl From<wysiwyg::SanitizeReport> for SanitizeReport {
+    fn from(inner: wysiwyg::SanitizeReport) -> Self {
+        Self {
+            severity: inner.severity().map(RemovalSeverity::from),
+            is_meaningful: inner.is_meaningful(),
+            removals: inner.removals.into_iter().map(Removal::from).collect(),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+pub struct SnapshotState {
+    pub html: Vec<u16>,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl From<wysiwyg::SnapshotState<u16>> for SnapshotState {
+    fn from(inner: wysiwyg::SnapshotState<u16>) -> Self {
+        let start: usize = inner.start.into();
+        let end: usize = inner.end.into();
+        Self {
+            html: inner.html,
+            start: u32::try_from(start).unwrap(),
+            end: u32::try_from(end).unwrap(),
+        }
+    }
+}
+
+impl From<SnapshotState> for wysiwyg::SnapshotState<u16> {
+    fn from(state: SnapshotState) -> Self {
+        Self {
+            html: state.html,
+            start: wysiwyg::Location::from(state.start as usize),
+            end: wysiwyg::Location::from(state.end as usize),
+        }
+    }
+}
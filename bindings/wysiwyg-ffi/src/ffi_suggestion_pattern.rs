@@ -0,0 +1,23 @@
+use crate::ffi_pattern_key::PatternKey;
+
+pub struct SuggestionPattern {
+    pub key: PatternKey,
+    pub text: String,
+    pub start_utf16_codeunit: u32,
+    pub end_utf16_codeunit: u32,
+}
+
+impl From<SuggestionPattern> for wysiwyg::SuggestionPattern<u16> {
+    fn from(inner: SuggestionPattern) -> Self {
+        Self {
+            key: inner.key.into(),
+            text: inner.text.encode_utf16().collect(),
+            start: wysiwyg::Location::from(
+                usize::try_from(inner.start_utf16_codeunit).unwrap(),
+            ),
+            end: wysiwyg::Location::from(
+                usize::try_from(inner.end_utf16_codeunit).unwrap(),
+            ),
+        }
+    }
+}
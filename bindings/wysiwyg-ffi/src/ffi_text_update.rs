@@ -5,6 +5,12 @@ pub enum TextUpdate {
         start_utf16_codeunit: u32,
         end_utf16_codeunit: u32,
     },
+    UpdateTextNode {
+        node_handle: Vec<u32>,
+        new_text: Vec<u16>,
+        start_utf16_codeunit: u32,
+        end_utf16_codeunit: u32,
+    },
 }
 
 impl TextUpdate {
@@ -22,6 +28,23 @@ impl TextUpdate {
                         .unwrap(),
                 }
             }
+            wysiwyg::TextUpdate::UpdateTextNode(update) => {
+                let start_utf16_codeunit: usize = update.start.into();
+                let end_utf16_codeunit: usize = update.end.into();
+                Self::UpdateTextNode {
+                    node_handle: update
+                        .node_handle
+                        .raw()
+                        .iter()
+                        .map(|&index| u32::try_from(index).unwrap())
+                        .collect(),
+                    new_text: update.new_text,
+                    start_utf16_codeunit: u32::try_from(start_utf16_codeunit)
+                        .unwrap(),
+                    end_utf16_codeunit: u32::try_from(end_utf16_codeunit)
+                        .unwrap(),
+                }
+            }
         }
     }
 }
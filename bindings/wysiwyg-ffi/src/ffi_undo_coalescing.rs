@@ -0,0 +1,21 @@
+pub enum UndoCoalescing {
+    EachEdit,
+    Words,
+    Pauses { max_pause_ms: u64 },
+    Characters { max: u32 },
+}
+
+impl From<UndoCoalescing> for wysiwyg::UndoCoalescing {
+    fn from(inner: UndoCoalescing) -> Self {
+        match inner {
+            UndoCoalescing::EachEdit => Self::EachEdit,
+            UndoCoalescing::Words => Self::Words,
+            UndoCoalescing::Pauses { max_pause_ms } => {
+                Self::Pauses { max_pause_ms }
+            }
+            UndoCoalescing::Characters { max } => Self::Characters {
+                max: usize::try_from(max).unwrap(),
+            },
+        }
+    }
+}
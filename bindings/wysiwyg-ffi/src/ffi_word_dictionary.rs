@@ -0,0 +1,29 @@
+use std::sync::Mutex;
+
+pub trait WordDictionary: Send {
+    fn words(&self, run: String, locale: Option<String>) -> Vec<u32>;
+}
+
+pub struct DictionaryAdapter {
+    dictionary: Mutex<Box<dyn WordDictionary>>,
+}
+
+impl DictionaryAdapter {
+    pub fn new(dictionary: Box<dyn WordDictionary>) -> Self {
+        Self {
+            dictionary: Mutex::new(dictionary),
+        }
+    }
+}
+
+impl wysiwyg::WordDictionary for DictionaryAdapter {
+    fn words(&self, run: &str, locale: Option<&str>) -> Vec<usize> {
+        self.dictionary
+            .lock()
+            .unwrap()
+            .words(String::from(run), locale.map(String::from))
+            .into_iter()
+            .map(|length| usize::try_from(length).unwrap())
+            .collect()
+    }
+}
@@ -12,28 +12,138 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+// The scaffolding generated by uniffi does not satisfy every clippy lint
+#![allow(clippy::unneeded_struct_pattern)]
+
 uniffi_macros::include_scaffolding!("wysiwyg_composer");
 
 mod ffi_action_request;
 mod ffi_action_response;
+mod ffi_anchored_location;
+mod ffi_clock;
 mod ffi_composer_action;
+mod ffi_composer_command;
+mod ffi_composer_config;
+mod ffi_composer_effect;
+mod ffi_composer_error;
 mod ffi_composer_model;
+mod ffi_composer_snapshot;
 mod ffi_composer_state;
 mod ffi_composer_update;
+mod ffi_coverage;
+mod ffi_diff_html;
+mod ffi_dom_event;
+mod ffi_dom_fragment;
+mod ffi_dom_observer;
+mod ffi_enter_behaviour;
+mod ffi_feature;
+mod ffi_format;
+mod ffi_history_item;
+mod ffi_html_dialect;
+mod ffi_html_violation;
+mod ffi_input_event;
+mod ffi_line_col;
+mod ffi_link_formatting;
+mod ffi_link_info;
+mod ffi_markdown_style;
+mod ffi_markdown_style_kind;
+mod ffi_mention;
+mod ffi_mention_formatting;
+mod ffi_menu_action;
 mod ffi_menu_state;
+mod ffi_parse_warning;
+mod ffi_pattern_key;
+mod ffi_pinned_node;
+mod ffi_removal;
+mod ffi_removal_severity;
+mod ffi_restore_change;
+mod ffi_restore_options;
+mod ffi_restored_message;
+mod ffi_sanitize_report;
+mod ffi_snapshot_state;
+mod ffi_suggestion_pattern;
 mod ffi_text_update;
+mod ffi_undo_coalescing;
+mod ffi_word_dictionary;
 
 use std::sync::Arc;
 
 pub use crate::ffi_action_request::ActionRequest;
 pub use crate::ffi_action_response::ActionResponse;
+pub use crate::ffi_anchored_location::AnchoredLocation;
+pub use crate::ffi_clock::Clock;
 pub use crate::ffi_composer_action::ComposerAction;
+pub use crate::ffi_composer_command::ComposerCommand;
+pub use crate::ffi_composer_config::ComposerConfig;
+pub use crate::ffi_composer_effect::ComposerEffect;
+pub use crate::ffi_composer_error::ComposerError;
 pub use crate::ffi_composer_model::ComposerModel;
+pub use crate::ffi_composer_snapshot::ComposerSnapshot;
 pub use crate::ffi_composer_state::ComposerState;
 pub use crate::ffi_composer_update::ComposerUpdate;
+pub use crate::ffi_coverage::Coverage;
+pub use crate::ffi_diff_html::DiffHtml;
+pub use crate::ffi_dom_event::DomEvent;
+pub use crate::ffi_dom_fragment::DomFragment;
+pub use crate::ffi_dom_observer::DomObserver;
+pub use crate::ffi_enter_behaviour::EnterBehaviour;
+pub use crate::ffi_feature::Feature;
+pub use crate::ffi_format::Format;
+pub use crate::ffi_history_item::HistoryItem;
+pub use crate::ffi_html_dialect::HtmlDialect;
+pub use crate::ffi_html_violation::HtmlViolation;
+pub use crate::ffi_input_event::InputEvent;
+pub use crate::ffi_line_col::LineCol;
+pub use crate::ffi_link_formatting::LinkFormatting;
+pub use crate::ffi_link_info::LinkInfo;
+pub use crate::ffi_markdown_style::MarkdownStyle;
+pub use crate::ffi_markdown_style_kind::MarkdownStyleKind;
+pub use crate::ffi_mention::Mention;
+pub use crate::ffi_mention_formatting::MentionFormatting;
+pub use crate::ffi_menu_action::MenuAction;
 pub use crate::ffi_menu_state::MenuState;
+pub use crate::ffi_parse_warning::ParseWarning;
+pub use crate::ffi_pattern_key::PatternKey;
+pub use crate::ffi_pinned_node::PinnedNode;
+pub use crate::ffi_removal::Removal;
+pub use crate::ffi_removal_severity::RemovalSeverity;
+pub use crate::ffi_restore_change::RestoreChange;
+pub use crate::ffi_restore_options::RestoreOptions;
+pub use crate::ffi_restored_message::RestoredMessage;
+pub use crate::ffi_sanitize_report::SanitizeReport;
+pub use crate::ffi_snapshot_state::SnapshotState;
+pub use crate::ffi_suggestion_pattern::SuggestionPattern;
 pub use crate::ffi_text_update::TextUpdate;
+pub use crate::ffi_undo_coalescing::UndoCoalescing;
+pub use crate::ffi_word_dictionary::WordDictionary;
 
 pub fn new_composer_model() -> Arc<ComposerModel> {
     Arc::new(ComposerModel::new())
 }
+
+pub fn new_composer_model_with_config(
+    config: ComposerConfig,
+) -> Arc<ComposerModel> {
+    Arc::new(ComposerModel::new_with_config(config))
+}
+
+pub fn html_parse_warnings(html: String) -> Vec<ParseWarning> {
+    wysiwyg::parse_with_warnings(&html.encode_utf16().collect::<Vec<_>>())
+        .warnings
+        .into_iter()
+        .map(ParseWarning::from)
+        .collect()
+}
+
+pub fn diff_html(old_html: String, new_html: String) -> DiffHtml {
+    DiffHtml::from(wysiwyg::diff_html(
+        &old_html.encode_utf16().collect::<Vec<_>>(),
+        &new_html.encode_utf16().collect::<Vec<_>>(),
+    ))
+}
+
+pub fn parse_message_html(html: String) -> Arc<DomFragment> {
+    Arc::new(DomFragment::from(wysiwyg::parse_message_html(
+        &html.encode_utf16().collect::<Vec<_>>(),
+    )))
+}
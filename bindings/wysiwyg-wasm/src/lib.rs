@@ -21,6 +21,73 @@ pub fn new_composer_model() -> ComposerModel {
     }
 }
 
+#[wasm_bindgen]
+pub fn diff_html(old_html: &str, new_html: &str) -> DiffHtml {
+    let inner = wysiwyg::diff_html(
+        &old_html.encode_utf16().collect::<Vec<_>>(),
+        &new_html.encode_utf16().collect::<Vec<_>>(),
+    );
+    DiffHtml {
+        html: String::from_utf16_lossy(&inner.html),
+        has_changes: inner.has_changes,
+    }
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct DiffHtml {
+    pub html: String,
+    pub has_changes: bool,
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct HistoryItem {
+    pub label: String,
+    pub timestamp_ms: Option<f64>,
+    pub content_len: u32,
+}
+
+impl From<&wysiwyg::HistoryItem> for HistoryItem {
+    fn from(inner: &wysiwyg::HistoryItem) -> Self {
+        Self {
+            label: inner.label.to_owned(),
+            timestamp_ms: inner.timestamp_ms.map(|ms| ms as f64),
+            content_len: u32::try_from(inner.content_len).unwrap(),
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub fn parse_message_html(html: &str) -> DomFragment {
+    DomFragment {
+        inner: wysiwyg::parse_message_html(
+            &html.encode_utf16().collect::<Vec<_>>(),
+        ),
+    }
+}
+
+#[wasm_bindgen]
+pub struct PinnedNode {
+    inner: wysiwyg::PinnedNode,
+}
+
+#[wasm_bindgen]
+pub struct DomFragment {
+    inner: wysiwyg::DomFragment<u16>,
+}
+
+#[wasm_bindgen]
+impl DomFragment {
+    pub fn quoted(&self) -> DomFragment {
+        Self {
+            inner: self.inner.quoted(),
+        }
+    }
+
+    pub fn to_html(&self) -> String {
+        String::from_utf16_lossy(&self.inner.to_html())
+    }
+}
+
 #[wasm_bindgen]
 pub struct ComposerModel {
     inner: wysiwyg::ComposerModel<u16>,
@@ -34,6 +101,146 @@ impl ComposerModel {
         }
     }
 
+    pub fn fork(&self) -> ComposerModel {
+        Self {
+            inner: self.inner.fork(),
+        }
+    }
+
+    pub fn set_content_from_html(&mut self, html: &str) -> ComposerUpdate {
+        ComposerUpdate::from(
+            self.inner.set_content_from_html(
+                &html.encode_utf16().collect::<Vec<_>>(),
+            ),
+        )
+    }
+
+    pub fn set_content_from_markdown(
+        &mut self,
+        markdown: &str,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.set_content_from_markdown(
+            &markdown.encode_utf16().collect::<Vec<_>>(),
+        ))
+    }
+
+    pub fn set_content_from_message_html(
+        &mut self,
+        html: &str,
+        strip_reply: bool,
+        resolve_mentions: bool,
+        downgrade_unsupported: bool,
+    ) -> RestoredMessage {
+        RestoredMessage {
+            inner: self.inner.set_content_from_message_html(
+                &html.encode_utf16().collect::<Vec<_>>(),
+                &wysiwyg::RestoreOptions {
+                    strip_reply,
+                    resolve_mentions,
+                    downgrade_unsupported,
+                },
+            ),
+        }
+    }
+
+    pub fn to_event_content(&self) -> String {
+        self.inner.to_event_content()
+    }
+
+    pub fn from_event_content(
+        &mut self,
+        json: &str,
+        strip_reply: bool,
+        resolve_mentions: bool,
+        downgrade_unsupported: bool,
+    ) -> Option<RestoredMessage> {
+        self.inner
+            .from_event_content(
+                json,
+                &wysiwyg::RestoreOptions {
+                    strip_reply,
+                    resolve_mentions,
+                    downgrade_unsupported,
+                },
+            )
+            .ok()
+            .map(|inner| RestoredMessage { inner })
+    }
+
+    pub fn from_legacy_draft(
+        &mut self,
+        draft: &str,
+        strip_reply: bool,
+        resolve_mentions: bool,
+        downgrade_unsupported: bool,
+    ) -> Option<RestoredMessage> {
+        self.inner
+            .from_legacy_draft(
+                draft,
+                &wysiwyg::RestoreOptions {
+                    strip_reply,
+                    resolve_mentions,
+                    downgrade_unsupported,
+                },
+            )
+            .ok()
+            .map(|inner| RestoredMessage { inner })
+    }
+
+    pub fn set_content_from_html_keeping_selection(
+        &mut self,
+        html: &str,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(
+            self.inner.set_content_from_html_keeping_selection(
+                &html.encode_utf16().collect::<Vec<_>>(),
+            ),
+        )
+    }
+
+    pub fn paste_html(&mut self, html: &str) -> ComposerUpdate {
+        ComposerUpdate::from(
+            self.inner
+                .paste_html(&html.encode_utf16().collect::<Vec<_>>()),
+        )
+    }
+
+    pub fn get_content_as_html(&self, dialect: HtmlDialect) -> String {
+        String::from_utf16_lossy(
+            &self.inner.get_content_as_html(dialect.into()),
+        )
+    }
+
+    pub fn get_content_as_message_html(&self) -> String {
+        String::from_utf16_lossy(&self.inner.get_content_as_message_html())
+    }
+
+    pub fn get_content_trimmed(&self) -> String {
+        String::from_utf16_lossy(&self.inner.get_content_trimmed())
+    }
+
+    pub fn visible_length(&self) -> u32 {
+        u32::try_from(self.inner.visible_length()).unwrap()
+    }
+
+    pub fn html_length(&self) -> u32 {
+        u32::try_from(self.inner.html_length()).unwrap()
+    }
+
+    pub fn count_visual_lines(&self, wrap_hint_chars: u32) -> u32 {
+        let wrap_hint_chars = usize::try_from(wrap_hint_chars).unwrap();
+        u32::try_from(self.inner.count_visual_lines(wrap_hint_chars)).unwrap()
+    }
+
+    pub fn get_content_as_plain_text(
+        &self,
+        fallback_formatting: bool,
+    ) -> String {
+        String::from_utf16_lossy(
+            &self.inner.get_content_as_plain_text(fallback_formatting),
+        )
+    }
+
     pub fn select(
         &mut self,
         start_utf16_codeunit: u32,
@@ -59,70 +266,761 @@ impl ComposerModel {
         )
     }
 
-    pub fn enter(&mut self) -> ComposerUpdate {
-        ComposerUpdate::from(self.inner.enter())
+    pub fn apply_plain_text_edit(
+        &mut self,
+        old_text: &str,
+        new_text: &str,
+        new_cursor_utf16_codeunit: u32,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.apply_plain_text_edit(
+            &old_text.encode_utf16().collect::<Vec<_>>(),
+            &new_text.encode_utf16().collect::<Vec<_>>(),
+            new_cursor_utf16_codeunit as usize,
+        ))
+    }
+
+    pub fn set_plain_text_mode(
+        &mut self,
+        plain_text_mode: bool,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.set_plain_text_mode(plain_text_mode))
+    }
+
+    pub fn is_plain_text_mode(&self) -> bool {
+        self.inner.is_plain_text_mode()
+    }
+
+    pub fn get_markdown_styles(&self) -> Vec<JsValue> {
+        self.inner
+            .get_markdown_styles()
+            .into_iter()
+            .map(|style| JsValue::from(MarkdownStyle::from(style)))
+            .collect()
+    }
+
+    pub fn insert_at(
+        &mut self,
+        location_utf16_codeunit: u32,
+        html: &str,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.insert_at(
+            wysiwyg::Location::from(
+                usize::try_from(location_utf16_codeunit).unwrap(),
+            ),
+            &html.encode_utf16().collect::<Vec<_>>(),
+        ))
+    }
+
+    pub fn replace_text_at_time(
+        &mut self,
+        new_text: &str,
+        time_ms: f64,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.replace_text_at_time(
+            &new_text.encode_utf16().collect::<Vec<_>>(),
+            time_ms as u64,
+        ))
+    }
+
+    pub fn replace_text_at_suggestion(
+        &mut self,
+        suggestion: &SuggestionPattern,
+        id: &str,
+        display_name: &str,
+        avatar_url: Option<String>,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.replace_text_at_suggestion(
+            &wysiwyg::SuggestionPattern {
+                key: suggestion.key.into(),
+                text: suggestion.text.encode_utf16().collect(),
+                start: wysiwyg::Location::from(
+                    usize::try_from(suggestion.start_utf16_codeunit).unwrap(),
+                ),
+                end: wysiwyg::Location::from(
+                    usize::try_from(suggestion.end_utf16_codeunit).unwrap(),
+                ),
+            },
+            &wysiwyg::Mention {
+                id: id.encode_utf16().collect(),
+                display_name: display_name.encode_utf16().collect(),
+                avatar_url: avatar_url.map(|url| url.encode_utf16().collect()),
+            },
+        ))
+    }
+
+    pub fn update_mention(
+        &mut self,
+        id: &str,
+        display_name: &str,
+        avatar_url: Option<String>,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.update_mentions(&[wysiwyg::Mention {
+            id: id.encode_utf16().collect(),
+            display_name: display_name.encode_utf16().collect(),
+            avatar_url: avatar_url.map(|url| url.encode_utf16().collect()),
+        }]))
+    }
+
+    pub fn mentioned_user_ids(&self) -> Vec<JsValue> {
+        self.inner
+            .mentioned_user_ids()
+            .into_iter()
+            .map(|id| JsValue::from(String::from_utf16_lossy(&id)))
+            .collect()
+    }
+
+    pub fn get_link_at(
+        &self,
+        location_utf16_codeunit: u32,
+    ) -> Option<LinkInfo> {
+        self.inner
+            .get_link_at(wysiwyg::Location::from(
+                usize::try_from(location_utf16_codeunit).unwrap(),
+            ))
+            .map(LinkInfo::from)
+    }
+
+    pub fn update_link(&mut self, url: &str, text: &str) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.update_link(
+            &url.encode_utf16().collect::<Vec<_>>(),
+            &text.encode_utf16().collect::<Vec<_>>(),
+        ))
+    }
+
+    pub fn enter(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.enter())
+    }
+
+    pub fn subscribe(&mut self, callback: js_sys::Function) {
+        self.inner.subscribe(Box::new(JsDomObserver { callback }));
+    }
+
+    pub fn set_word_dictionary(&mut self, callback: js_sys::Function) {
+        self.inner
+            .set_word_dictionary(std::sync::Arc::new(JsWordDictionary {
+                callback,
+            }));
+    }
+
+    pub fn set_clock(&mut self, callback: js_sys::Function) {
+        self.inner
+            .set_clock(std::sync::Arc::new(JsClock { callback }));
+    }
+
+    pub fn get_anchored_selection(&self) -> Vec<JsValue> {
+        let (start, end) = self.inner.get_anchored_selection();
+        vec![
+            JsValue::from(AnchoredLocation::from(start)),
+            JsValue::from(AnchoredLocation::from(end)),
+        ]
+    }
+
+    pub fn select_anchored(
+        &mut self,
+        start_node_handle: Vec<u32>,
+        start_offset: u32,
+        end_node_handle: Vec<u32>,
+        end_offset: u32,
+    ) -> bool {
+        self.inner.select_anchored(
+            &anchored_location(start_node_handle, start_offset),
+            &anchored_location(end_node_handle, end_offset),
+        )
+    }
+
+    pub fn select_word_at_cursor(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.select_word_at_cursor())
+    }
+
+    pub fn select_to_start_of_block(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.select_to_start_of_block())
+    }
+
+    pub fn select_to_end_of_block(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.select_to_end_of_block())
+    }
+
+    pub fn select_to_start_of_document(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.select_to_start_of_document())
+    }
+
+    pub fn select_to_end_of_document(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.select_to_end_of_document())
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.inner.revision()
+    }
+
+    pub fn has_same_state(&self, other: &ComposerModel) -> bool {
+        self.inner.has_same_state(&other.inner)
+    }
+
+    pub fn state_hash(&self) -> u64 {
+        self.inner.state_hash()
+    }
+
+    pub fn content_hash(&self) -> u64 {
+        self.inner.content_hash()
+    }
+
+    pub fn is_empty(&self, ignore_whitespace: bool) -> bool {
+        self.inner.is_empty(ignore_whitespace)
+    }
+
+    pub fn can_recall_previous(&self) -> bool {
+        self.inner.can_recall_previous()
+    }
+
+    pub fn up(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.up())
+    }
+
+    pub fn set_placeholder(&mut self, placeholder: &str) {
+        self.inner
+            .set_placeholder(&placeholder.encode_utf16().collect::<Vec<_>>());
+    }
+
+    pub fn placeholder(&self) -> Option<String> {
+        self.inner
+            .placeholder()
+            .map(|placeholder| String::from_utf16_lossy(placeholder))
+    }
+
+    pub fn copy_formatting(&mut self) {
+        self.inner.copy_formatting();
+    }
+
+    pub fn apply_copied_formatting(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.apply_copied_formatting())
+    }
+
+    pub fn apply_formats(
+        &mut self,
+        format_names: Vec<JsValue>,
+    ) -> ComposerUpdate {
+        let formats: Vec<wysiwyg::Format> = format_names
+            .iter()
+            .filter_map(|name| name.as_string())
+            .filter_map(|name| wysiwyg::Format::from_name(&name))
+            .collect();
+        ComposerUpdate::from(self.inner.apply_formats(&formats))
+    }
+
+    pub fn is_range_formatted(
+        &self,
+        start_utf16_codeunit: u32,
+        end_utf16_codeunit: u32,
+        format_name: &str,
+    ) -> String {
+        let start = wysiwyg::Location::from(
+            usize::try_from(start_utf16_codeunit).unwrap(),
+        );
+        let end = wysiwyg::Location::from(
+            usize::try_from(end_utf16_codeunit).unwrap(),
+        );
+        let coverage = match wysiwyg::Format::from_name(format_name) {
+            Some(format) => self.inner.is_range_formatted(start, end, format),
+            None => wysiwyg::Coverage::None,
+        };
+        String::from(coverage.name())
+    }
+
+    pub fn toggle_list_type(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.toggle_list_type())
+    }
+
+    pub fn tab(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.tab())
+    }
+
+    pub fn shift_tab(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.shift_tab())
+    }
+
+    pub fn move_list_item_up(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.move_list_item_up())
+    }
+
+    pub fn move_list_item_down(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.move_list_item_down())
+    }
+
+    pub fn toggle_comment(&mut self, prefix: &str) -> ComposerUpdate {
+        ComposerUpdate::from(
+            self.inner
+                .toggle_comment(&prefix.encode_utf16().collect::<Vec<_>>()),
+        )
+    }
+
+    pub fn duplicate_line(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.duplicate_line())
+    }
+
+    pub fn move_line_up(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.move_line_up())
+    }
+
+    pub fn move_line_down(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.move_line_down())
+    }
+
+    pub fn remove_list(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.remove_list())
+    }
+
+    pub fn backspace(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.backspace())
+    }
+
+    pub fn delete(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.delete())
+    }
+
+    pub fn offset_of(
+        &self,
+        substring: &str,
+        from_utf16_codeunit: u32,
+    ) -> Option<u32> {
+        self.inner
+            .offset_of(
+                &substring.encode_utf16().collect::<Vec<_>>(),
+                wysiwyg::Location::from(
+                    usize::try_from(from_utf16_codeunit).unwrap(),
+                ),
+            )
+            .map(|location| {
+                let location: usize = location.into();
+                u32::try_from(location).unwrap()
+            })
+    }
+
+    pub fn text_in_range(
+        &self,
+        start_utf16_codeunit: u32,
+        end_utf16_codeunit: u32,
+    ) -> String {
+        String::from_utf16_lossy(&self.inner.text_in_range(
+            wysiwyg::Location::from(
+                usize::try_from(start_utf16_codeunit).unwrap(),
+            ),
+            wysiwyg::Location::from(
+                usize::try_from(end_utf16_codeunit).unwrap(),
+            ),
+        ))
+    }
+
+    pub fn char_at(&self, location_utf16_codeunit: u32) -> Option<String> {
+        self.inner
+            .char_at(wysiwyg::Location::from(
+                usize::try_from(location_utf16_codeunit).unwrap(),
+            ))
+            .map(String::from)
+    }
+
+    pub fn undo(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.undo())
+    }
+
+    pub fn redo(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.redo())
+    }
+
+    pub fn set_history_limits(
+        &mut self,
+        max_entries: Option<u32>,
+        max_bytes: Option<u32>,
+    ) {
+        self.inner.set_history_limits(
+            max_entries.map(|max| max as usize),
+            max_bytes.map(|max| max as usize),
+        );
+    }
+
+    pub fn undo_history(&self) -> Vec<JsValue> {
+        self.inner
+            .undo_history()
+            .map(|item| JsValue::from(HistoryItem::from(item)))
+            .collect()
+    }
+
+    pub fn redo_history(&self) -> Vec<JsValue> {
+        self.inner
+            .redo_history()
+            .map(|item| JsValue::from(HistoryItem::from(item)))
+            .collect()
+    }
+
+    pub fn apply(&mut self, command: ComposerCommand) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.apply(command.inner))
+    }
+
+    pub fn bold(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.bold())
+    }
+
+    pub fn italic(&mut self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.italic())
+    }
+
+    pub fn set_link_with_text(
+        &mut self,
+        link: &str,
+        text: &str,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.set_link_with_text(
+            &link.encode_utf16().collect::<Vec<_>>(),
+            &text.encode_utf16().collect::<Vec<_>>(),
+        ))
+    }
+
+    pub fn insert_attachment_placeholder(
+        &mut self,
+        id: &str,
+        label: &str,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.insert_attachment_placeholder(
+            id,
+            &label.encode_utf16().collect::<Vec<_>>(),
+        ))
+    }
+
+    pub fn insert_custom_emote(
+        &mut self,
+        mxc_url: &str,
+        shortcode: &str,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.insert_custom_emote(
+            mxc_url,
+            &shortcode.encode_utf16().collect::<Vec<_>>(),
+        ))
+    }
+
+    pub fn insert_fragment(
+        &mut self,
+        fragment: &DomFragment,
+    ) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.insert_fragment(&fragment.inner))
+    }
+
+    pub fn pin(&self, node_handle: Vec<u32>) -> Option<PinnedNode> {
+        let handle = wysiwyg::DomHandle::from_raw(
+            node_handle
+                .into_iter()
+                .map(|index| usize::try_from(index).unwrap())
+                .collect(),
+        );
+        self.inner.pin(&handle).map(|inner| PinnedNode { inner })
+    }
+
+    pub fn handle_of_pinned(&self, node: &PinnedNode) -> Option<Vec<u32>> {
+        self.inner
+            .handle_of_pinned(&node.inner)
+            .map(|h| raw_handle(&h))
+    }
+
+    pub fn set_protected(
+        &mut self,
+        node_handle: Vec<u32>,
+        protected: bool,
+    ) -> ComposerUpdate {
+        let handle = wysiwyg::DomHandle::from_raw(
+            node_handle
+                .into_iter()
+                .map(|index| usize::try_from(index).unwrap())
+                .collect(),
+        );
+        ComposerUpdate::from(self.inner.set_protected(&handle, protected))
+    }
+
+    /*pub fn action_response(
+        self: &Arc<Self>,
+        action_id: String,
+        response: ActionResponse,
+    ) -> Arc<ComposerUpdate> {
+        Arc::new(ComposerUpdate::from(
+            self.inner
+                .lock()
+                .unwrap()
+                .action_response(action_id, response.into()),
+        ))
+    }*/
+}
+
+#[wasm_bindgen]
+pub enum HtmlDialect {
+    MatrixMessage,
+    Editor,
+    Preview,
+}
+
+impl From<HtmlDialect> for wysiwyg::HtmlDialect {
+    fn from(inner: HtmlDialect) -> Self {
+        match inner {
+            HtmlDialect::MatrixMessage => wysiwyg::HtmlDialect::MatrixMessage,
+            HtmlDialect::Editor => wysiwyg::HtmlDialect::Editor,
+            HtmlDialect::Preview => wysiwyg::HtmlDialect::Preview,
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub struct RestoredMessage {
+    inner: wysiwyg::RestoredMessage<u16>,
+}
+
+#[wasm_bindgen]
+impl RestoredMessage {
+    pub fn update(&self) -> ComposerUpdate {
+        ComposerUpdate::from(self.inner.update.clone())
+    }
+
+    pub fn changes(&self) -> Vec<JsValue> {
+        self.inner
+            .changes
+            .iter()
+            .cloned()
+            .map(|change| JsValue::from(RestoreChange::from(change)))
+            .collect()
+    }
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct RestoreChange {
+    pub kind: String,
+    pub element: Option<String>,
+    pub replacement: Option<String>,
+    pub id: Option<String>,
+}
+
+impl From<wysiwyg::RestoreChange> for RestoreChange {
+    fn from(inner: wysiwyg::RestoreChange) -> Self {
+        let (kind, element, replacement, id) = match inner {
+            wysiwyg::RestoreChange::ReplyRemoved => {
+                ("reply_removed", None, None, None)
+            }
+            wysiwyg::RestoreChange::Downgraded {
+                element,
+                replacement,
+            } => ("downgraded", Some(element), replacement, None),
+            wysiwyg::RestoreChange::Stripped(violation) => {
+                let element = match violation {
+                    wysiwyg::HtmlViolation::Element { name } => name,
+                    wysiwyg::HtmlViolation::Attribute { element, .. } => {
+                        element
+                    }
+                    wysiwyg::HtmlViolation::AttributeValue {
+                        element, ..
+                    } => element,
+                };
+                ("stripped", Some(element), None, None)
+            }
+            wysiwyg::RestoreChange::MentionResolved { id } => {
+                ("mention_resolved", None, None, Some(id))
+            }
+        };
+        Self {
+            kind: String::from(kind),
+            element,
+            replacement,
+            id,
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub struct ComposerUpdate {
+    inner: wysiwyg::ComposerUpdate<u16>,
+}
+
+impl ComposerUpdate {
+    fn from(inner: wysiwyg::ComposerUpdate<u16>) -> Self {
+        Self { inner }
+    }
+}
+
+#[wasm_bindgen]
+impl ComposerUpdate {
+    pub fn text_update(&self) -> TextUpdate {
+        TextUpdate::from(self.inner.text_update.clone())
+    }
+
+    pub fn menu_state(&self) -> MenuState {
+        MenuState::from(self.inner.menu_state.clone())
+    }
+
+    pub fn menu_action(&self) -> MenuAction {
+        MenuAction::from(self.inner.menu_action.clone())
+    }
+
+    pub fn diagnostics(&self) -> Vec<JsValue> {
+        self.inner
+            .diagnostics
+            .iter()
+            .map(|diagnostic| JsValue::from(diagnostic.as_str()))
+            .collect()
+    }
+
+    pub fn sanitize_removals(&self) -> Vec<JsValue> {
+        self.inner
+            .sanitize_report
+            .removals
+            .iter()
+            .map(|removal| JsValue::from(Removal::from(removal.clone())))
+            .collect()
+    }
+
+    pub fn sanitize_severity(&self) -> Option<String> {
+        self.inner
+            .sanitize_report
+            .severity()
+            .map(|severity| String::from(severity_name(severity)))
+    }
+
+    pub fn is_sanitize_meaningful(&self) -> bool {
+        self.inner.sanitize_report.is_meaningful()
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.inner.revision
+    }
+
+    pub fn effects(&self) -> Vec<JsValue> {
+        self.inner
+            .effects()
+            .into_iter()
+            .map(|inner| JsValue::from(ComposerEffect { inner }))
+            .collect()
+    }
+
+    /*pub fn actions(&self) -> Vec<ComposerAction> {
+        self.inner
+            .actions
+            .iter()
+            .map(|action| ComposerAction::from(action.clone()))
+            .collect()
+    }*/
+}
+
+#[wasm_bindgen]
+pub struct ComposerEffect {
+    inner: wysiwyg::ComposerEffect<u16>,
+}
+
+#[wasm_bindgen]
+impl ComposerEffect {
+    pub fn kind(&self) -> String {
+        String::from(match self.inner {
+            wysiwyg::ComposerEffect::TextUpdated(_) => "text_updated",
+            wysiwyg::ComposerEffect::MenuStateChanged(_) => {
+                "menu_state_changed"
+            }
+            wysiwyg::ComposerEffect::MenuActionChanged(_) => {
+                "menu_action_changed"
+            }
+            wysiwyg::ComposerEffect::ActionRequested(_) => "action_requested",
+            wysiwyg::ComposerEffect::Sanitized(_) => "sanitized",
+            wysiwyg::ComposerEffect::Diagnostic(_) => "diagnostic",
+            wysiwyg::ComposerEffect::Failed(_) => "failed",
+        })
+    }
+
+    pub fn text_update(&self) -> Option<TextUpdate> {
+        match &self.inner {
+            wysiwyg::ComposerEffect::TextUpdated(update) => {
+                Some(TextUpdate::from(update.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn menu_state(&self) -> Option<MenuState> {
+        match &self.inner {
+            wysiwyg::ComposerEffect::MenuStateChanged(state) => {
+                Some(MenuState::from(state.clone()))
+            }
+            _ => None,
+        }
     }
 
-    pub fn backspace(&mut self) -> ComposerUpdate {
-        ComposerUpdate::from(self.inner.backspace())
+    pub fn menu_action(&self) -> Option<MenuAction> {
+        match &self.inner {
+            wysiwyg::ComposerEffect::MenuActionChanged(action) => {
+                Some(MenuAction::from(action.clone()))
+            }
+            _ => None,
+        }
     }
 
-    pub fn delete(&mut self) -> ComposerUpdate {
-        ComposerUpdate::from(self.inner.delete())
+    pub fn sanitize_removals(&self) -> Vec<JsValue> {
+        match &self.inner {
+            wysiwyg::ComposerEffect::Sanitized(report) => report
+                .removals
+                .iter()
+                .map(|removal| JsValue::from(Removal::from(removal.clone())))
+                .collect(),
+            _ => Vec::new(),
+        }
     }
 
-    pub fn bold(&mut self) -> ComposerUpdate {
-        ComposerUpdate::from(self.inner.bold())
+    pub fn diagnostic(&self) -> Option<String> {
+        match &self.inner {
+            wysiwyg::ComposerEffect::Diagnostic(message) => {
+                Some(message.clone())
+            }
+            _ => None,
+        }
     }
-
-    /*pub fn action_response(
-        self: &Arc<Self>,
-        action_id: String,
-        response: ActionResponse,
-    ) -> Arc<ComposerUpdate> {
-        Arc::new(ComposerUpdate::from(
-            self.inner
-                .lock()
-                .unwrap()
-                .action_response(action_id, response.into()),
-        ))
-    }*/
 }
 
-#[wasm_bindgen]
-pub struct ComposerUpdate {
-    inner: wysiwyg::ComposerUpdate<u16>,
+#[wasm_bindgen(getter_with_clone)]
+pub struct Removal {
+    pub kind: String,
+    pub element: String,
+    pub attribute: Option<String>,
+    pub value: Option<String>,
+    pub severity: String,
 }
 
-impl ComposerUpdate {
-    fn from(inner: wysiwyg::ComposerUpdate<u16>) -> Self {
-        Self { inner }
+impl From<wysiwyg::HtmlViolation> for Removal {
+    fn from(inner: wysiwyg::HtmlViolation) -> Self {
+        let severity = String::from(severity_name(inner.severity()));
+        let (kind, element, attribute, value) = match inner {
+            wysiwyg::HtmlViolation::Element { name } => {
+                ("element", name, None, None)
+            }
+            wysiwyg::HtmlViolation::Attribute { element, attribute } => {
+                ("attribute", element, Some(attribute), None)
+            }
+            wysiwyg::HtmlViolation::AttributeValue {
+                element,
+                attribute,
+                value,
+            } => ("attribute_value", element, Some(attribute), Some(value)),
+        };
+        Self {
+            kind: String::from(kind),
+            element,
+            attribute,
+            value,
+            severity,
+        }
     }
 }
 
-#[wasm_bindgen]
-impl ComposerUpdate {
-    pub fn text_update(&self) -> TextUpdate {
-        TextUpdate::from(self.inner.text_update.clone())
-    }
-
-    pub fn menu_state(&self) -> MenuState {
-        MenuState::from(self.inner.menu_state.clone())
+fn severity_name(severity: wysiwyg::RemovalSeverity) -> &'static str {
+    match severity {
+        wysiwyg::RemovalSeverity::Cosmetic => "cosmetic",
+        wysiwyg::RemovalSeverity::Unsupported => "unsupported",
+        wysiwyg::RemovalSeverity::Dangerous => "dangerous",
     }
-
-    /*pub fn actions(&self) -> Vec<ComposerAction> {
-        self.inner
-            .actions
-            .iter()
-            .map(|action| ComposerAction::from(action.clone()))
-            .collect()
-    }*/
 }
 
 #[wasm_bindgen(getter_with_clone)]
 pub struct TextUpdate {
     pub keep: Option<Keep>,
     pub replace_all: Option<ReplaceAll>,
+    pub update_text_node: Option<UpdateTextNode>,
 }
 
 impl TextUpdate {
@@ -131,6 +1029,7 @@ impl TextUpdate {
             wysiwyg::TextUpdate::Keep => Self {
                 keep: Some(Keep),
                 replace_all: None,
+                update_text_node: None,
             },
             wysiwyg::TextUpdate::ReplaceAll(r) => {
                 let start_utf16_codeunit: usize = r.start.into();
@@ -138,10 +1037,33 @@ impl TextUpdate {
                 Self {
                     keep: None,
                     replace_all: Some(ReplaceAll {
-                        replacement_html: String::from_utf16(
+                        replacement_html: String::from_utf16_lossy(
                             &r.replacement_html,
+                        ),
+                        start_utf16_codeunit: u32::try_from(
+                            start_utf16_codeunit,
                         )
-                        .expect("Model returned invalid UTF-16"),
+                        .unwrap(),
+                        end_utf16_codeunit: u32::try_from(end_utf16_codeunit)
+                            .unwrap(),
+                    }),
+                    update_text_node: None,
+                }
+            }
+            wysiwyg::TextUpdate::UpdateTextNode(u) => {
+                let start_utf16_codeunit: usize = u.start.into();
+                let end_utf16_codeunit: usize = u.end.into();
+                Self {
+                    keep: None,
+                    replace_all: None,
+                    update_text_node: Some(UpdateTextNode {
+                        node_handle: u
+                            .node_handle
+                            .raw()
+                            .iter()
+                            .map(|&index| u32::try_from(index).unwrap())
+                            .collect(),
+                        new_text: String::from_utf16_lossy(&u.new_text),
                         start_utf16_codeunit: u32::try_from(
                             start_utf16_codeunit,
                         )
@@ -167,9 +1089,174 @@ pub struct ReplaceAll {
     pub end_utf16_codeunit: u32,
 }
 
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct UpdateTextNode {
+    pub node_handle: Vec<u32>,
+    pub new_text: String,
+    pub start_utf16_codeunit: u32,
+    pub end_utf16_codeunit: u32,
+}
+
+struct JsDomObserver {
+    callback: js_sys::Function,
+}
+
+// JavaScript values cannot be sent between threads, but WASM runs this
+// whole library on the one thread
+unsafe impl Send for JsDomObserver {}
+
+impl wysiwyg::DomObserver<u16> for JsDomObserver {
+    fn on_event(&mut self, event: &wysiwyg::DomEvent<u16>) {
+        let event = JsValue::from(DomEvent::from(event));
+        // An exception in the callback must not stop the edit
+        let _ = self.callback.call1(&JsValue::NULL, &event);
+    }
+}
+
+struct JsWordDictionary {
+    callback: js_sys::Function,
+}
+
+// As for JsDomObserver, WASM runs this whole library on the one thread
+unsafe impl Send for JsWordDictionary {}
+unsafe impl Sync for JsWordDictionary {}
+
+impl wysiwyg::WordDictionary for JsWordDictionary {
+    fn words(&self, run: &str, locale: Option<&str>) -> Vec<usize> {
+        let locale = locale.map_or(JsValue::NULL, JsValue::from_str);
+        match self.callback.call2(
+            &JsValue::NULL,
+            &JsValue::from_str(run),
+            &locale,
+        ) {
+            // Lengths that are not whole numbers will not add up to the
+            // length of run, so the answer is ignored
+            Ok(lengths) => js_sys::Array::from(&lengths)
+                .iter()
+                .map(|length| length.as_f64().unwrap_or(0.0) as usize)
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+struct JsClock {
+    callback: js_sys::Function,
+}
+
+// As for JsDomObserver, WASM runs this whole library on the one thread
+unsafe impl Send for JsClock {}
+unsafe impl Sync for JsClock {}
+
+impl wysiwyg::Clock for JsClock {
+    fn now_ms(&self) -> u64 {
+        match self.callback.call0(&JsValue::NULL) {
+            Ok(ms) => ms.as_f64().unwrap_or(0.0) as u64,
+            Err(_) => 0,
+        }
+    }
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct DomEvent {
+    pub node_inserted: Option<NodeInserted>,
+    pub node_removed: Option<NodeRemoved>,
+    pub text_changed: Option<TextChanged>,
+}
+
+impl DomEvent {
+    pub fn from(inner: &wysiwyg::DomEvent<u16>) -> Self {
+        let mut event = Self {
+            node_inserted: None,
+            node_removed: None,
+            text_changed: None,
+        };
+        match inner {
+            wysiwyg::DomEvent::NodeInserted { handle, html } => {
+                event.node_inserted = Some(NodeInserted {
+                    node_handle: raw_handle(handle),
+                    html: String::from_utf16_lossy(html),
+                })
+            }
+            wysiwyg::DomEvent::NodeRemoved { handle } => {
+                event.node_removed = Some(NodeRemoved {
+                    node_handle: raw_handle(handle),
+                })
+            }
+            wysiwyg::DomEvent::TextChanged { handle, new_text } => {
+                event.text_changed = Some(TextChanged {
+                    node_handle: raw_handle(handle),
+                    new_text: String::from_utf16_lossy(new_text),
+                })
+            }
+        }
+        event
+    }
+}
+
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct NodeInserted {
+    pub node_handle: Vec<u32>,
+    pub html: String,
+}
+
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct NodeRemoved {
+    pub node_handle: Vec<u32>,
+}
+
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct TextChanged {
+    pub node_handle: Vec<u32>,
+    pub new_text: String,
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct AnchoredLocation {
+    pub node_handle: Vec<u32>,
+    pub offset: u32,
+}
+
+impl From<wysiwyg::AnchoredLocation> for AnchoredLocation {
+    fn from(inner: wysiwyg::AnchoredLocation) -> Self {
+        Self {
+            node_handle: raw_handle(&inner.handle),
+            offset: u32::try_from(inner.offset).unwrap(),
+        }
+    }
+}
+
+fn anchored_location(
+    node_handle: Vec<u32>,
+    offset: u32,
+) -> wysiwyg::AnchoredLocation {
+    wysiwyg::AnchoredLocation {
+        handle: wysiwyg::DomHandle::from_raw(
+            node_handle
+                .into_iter()
+                .map(|index| usize::try_from(index).unwrap())
+                .collect(),
+        ),
+        offset: usize::try_from(offset).unwrap(),
+    }
+}
+
+fn raw_handle(handle: &wysiwyg::DomHandle) -> Vec<u32> {
+    handle
+        .raw()
+        .iter()
+        .map(|&index| u32::try_from(index).unwrap())
+        .collect()
+}
+
 #[wasm_bindgen]
 pub struct MenuState {
     _none: Option<NoneMenuState>,
+    update: Option<UpdateMenuState>,
 }
 
 impl MenuState {
@@ -177,14 +1264,279 @@ impl MenuState {
         match inner {
             wysiwyg::MenuState::None => Self {
                 _none: Some(NoneMenuState),
+                update: None,
+            },
+            wysiwyg::MenuState::Update {
+                active_features,
+                partially_active_features,
+                can_undo,
+                can_redo,
+            } => Self {
+                _none: None,
+                update: Some(UpdateMenuState {
+                    active_features,
+                    partially_active_features,
+                    can_undo,
+                    can_redo,
+                }),
             },
         }
     }
 }
 
+#[wasm_bindgen]
+impl MenuState {
+    pub fn update(&self) -> Option<UpdateMenuState> {
+        self.update.clone()
+    }
+}
+
 #[wasm_bindgen]
 pub struct NoneMenuState;
 
+#[derive(Clone)]
+#[wasm_bindgen]
+pub struct UpdateMenuState {
+    active_features: Vec<wysiwyg::Feature>,
+    partially_active_features: Vec<wysiwyg::Feature>,
+    can_undo: bool,
+    can_redo: bool,
+}
+
+#[wasm_bindgen]
+impl UpdateMenuState {
+    pub fn is_active(&self, feature: Feature) -> bool {
+        self.active_features.contains(&feature.into())
+    }
+
+    pub fn is_partially_active(&self, feature: Feature) -> bool {
+        self.partially_active_features.contains(&feature.into())
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.can_undo
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.can_redo
+    }
+}
+
+#[wasm_bindgen]
+pub struct ComposerCommand {
+    inner: wysiwyg::ComposerCommand<u16>,
+}
+
+#[wasm_bindgen]
+impl ComposerCommand {
+    pub fn bold() -> Self {
+        Self::from(wysiwyg::ComposerCommand::Bold)
+    }
+
+    pub fn italic() -> Self {
+        Self::from(wysiwyg::ComposerCommand::Italic)
+    }
+
+    pub fn toggle_list_type() -> Self {
+        Self::from(wysiwyg::ComposerCommand::ToggleListType)
+    }
+
+    pub fn indent() -> Self {
+        Self::from(wysiwyg::ComposerCommand::Indent)
+    }
+
+    pub fn unindent() -> Self {
+        Self::from(wysiwyg::ComposerCommand::Unindent)
+    }
+
+    pub fn move_list_item_up() -> Self {
+        Self::from(wysiwyg::ComposerCommand::MoveListItemUp)
+    }
+
+    pub fn move_list_item_down() -> Self {
+        Self::from(wysiwyg::ComposerCommand::MoveListItemDown)
+    }
+
+    pub fn remove_list() -> Self {
+        Self::from(wysiwyg::ComposerCommand::RemoveList)
+    }
+
+    pub fn undo() -> Self {
+        Self::from(wysiwyg::ComposerCommand::Undo)
+    }
+
+    pub fn redo() -> Self {
+        Self::from(wysiwyg::ComposerCommand::Redo)
+    }
+
+    pub fn apply_copied_formatting() -> Self {
+        Self::from(wysiwyg::ComposerCommand::ApplyCopiedFormatting)
+    }
+
+    pub fn set_link_with_text(link: &str, text: &str) -> Self {
+        Self::from(wysiwyg::ComposerCommand::SetLinkWithText {
+            link: link.encode_utf16().collect(),
+            text: text.encode_utf16().collect(),
+        })
+    }
+}
+
+impl From<wysiwyg::ComposerCommand<u16>> for ComposerCommand {
+    fn from(inner: wysiwyg::ComposerCommand<u16>) -> Self {
+        Self { inner }
+    }
+}
+
+#[derive(Clone, Copy)]
+#[wasm_bindgen]
+pub enum Feature {
+    Bold,
+    Italic,
+    Links,
+    OrderedList,
+    UnorderedList,
+}
+
+impl From<Feature> for wysiwyg::Feature {
+    fn from(inner: Feature) -> Self {
+        match inner {
+            Feature::Bold => Self::Bold,
+            Feature::Italic => Self::Italic,
+            Feature::Links => Self::Links,
+            Feature::OrderedList => Self::OrderedList,
+            Feature::UnorderedList => Self::UnorderedList,
+        }
+    }
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct MenuAction {
+    pub none: Option<NoneMenuAction>,
+    pub navigate_history: Option<NavigateHistoryMenuAction>,
+    pub suggestion: Option<SuggestionPattern>,
+}
+
+impl MenuAction {
+    pub fn from(inner: wysiwyg::MenuAction<u16>) -> Self {
+        match inner {
+            wysiwyg::MenuAction::None => Self {
+                none: Some(NoneMenuAction),
+                navigate_history: None,
+                suggestion: None,
+            },
+            wysiwyg::MenuAction::NavigateHistory => Self {
+                none: None,
+                navigate_history: Some(NavigateHistoryMenuAction),
+                suggestion: None,
+            },
+            wysiwyg::MenuAction::Suggestion(pattern) => {
+                let start_utf16_codeunit: usize = pattern.start.into();
+                let end_utf16_codeunit: usize = pattern.end.into();
+                Self {
+                    none: None,
+                    navigate_history: None,
+                    suggestion: Some(SuggestionPattern {
+                        key: pattern.key.into(),
+                        text: String::from_utf16_lossy(&pattern.text),
+                        start_utf16_codeunit: u32::try_from(
+                            start_utf16_codeunit,
+                        )
+                        .unwrap(),
+                        end_utf16_codeunit: u32::try_from(end_utf16_codeunit)
+                            .unwrap(),
+                    }),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+#[wasm_bindgen]
+pub struct NoneMenuAction;
+
+#[derive(Clone)]
+#[wasm_bindgen]
+pub struct NavigateHistoryMenuAction;
+
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct LinkInfo {
+    pub url: String,
+    pub text: String,
+    pub start_utf16_codeunit: u32,
+    pub end_utf16_codeunit: u32,
+}
+
+impl From<wysiwyg::LinkInfo<u16>> for LinkInfo {
+    fn from(inner: wysiwyg::LinkInfo<u16>) -> Self {
+        let start_utf16_codeunit: usize = inner.start.into();
+        let end_utf16_codeunit: usize = inner.end.into();
+        Self {
+            url: String::from_utf16_lossy(&inner.url),
+            text: String::from_utf16_lossy(&inner.text),
+            start_utf16_codeunit: u32::try_from(start_utf16_codeunit).unwrap(),
+            end_utf16_codeunit: u32::try_from(end_utf16_codeunit).unwrap(),
+        }
+    }
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct MarkdownStyle {
+    pub kind: String,
+    pub start_utf16_codeunit: u32,
+    pub end_utf16_codeunit: u32,
+}
+
+impl From<wysiwyg::MarkdownStyle> for MarkdownStyle {
+    fn from(inner: wysiwyg::MarkdownStyle) -> Self {
+        let start_utf16_codeunit: usize = inner.start.into();
+        let end_utf16_codeunit: usize = inner.end.into();
+        Self {
+            kind: String::from(inner.kind.name()),
+            start_utf16_codeunit: u32::try_from(start_utf16_codeunit).unwrap(),
+            end_utf16_codeunit: u32::try_from(end_utf16_codeunit).unwrap(),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[wasm_bindgen(getter_with_clone)]
+pub struct SuggestionPattern {
+    pub key: PatternKey,
+    pub text: String,
+    pub start_utf16_codeunit: u32,
+    pub end_utf16_codeunit: u32,
+}
+
+#[derive(Clone, Copy)]
+#[wasm_bindgen]
+pub enum PatternKey {
+    At,
+    Hash,
+    Slash,
+}
+
+impl From<wysiwyg::PatternKey> for PatternKey {
+    fn from(inner: wysiwyg::PatternKey) -> Self {
+        match inner {
+            wysiwyg::PatternKey::At => Self::At,
+            wysiwyg::PatternKey::Hash => Self::Hash,
+            wysiwyg::PatternKey::Slash => Self::Slash,
+        }
+    }
+}
+
+impl From<PatternKey> for wysiwyg::PatternKey {
+    fn from(inner: PatternKey) -> Self {
+        match inner {
+            PatternKey::At => Self::At,
+            PatternKey::Hash => Self::Hash,
+            PatternKey::Slash => Self::Slash,
+        }
+    }
+}
+
 #[wasm_bindgen]
 pub struct ComposerAction {
     inner: wysiwyg::ComposerAction,
@@ -204,6 +1556,7 @@ impl ComposerAction {
 #[wasm_bindgen]
 pub struct ActionRequest {
     _dummy: Option<Dummy>,
+    _resolve_attachment: Option<ResolveAttachment>,
 }
 
 impl ActionRequest {
@@ -211,6 +1564,11 @@ impl ActionRequest {
         match inner {
             wysiwyg::ActionRequest::Dummy => Self {
                 _dummy: Some(Dummy),
+                _resolve_attachment: None,
+            },
+            wysiwyg::ActionRequest::ResolveAttachment => Self {
+                _dummy: None,
+                _resolve_attachment: Some(ResolveAttachment),
             },
         }
     }
@@ -233,3 +1591,5 @@ impl ActionResponse {
 }
 
 pub struct Dummy;
+
+pub struct ResolveAttachment;
@@ -0,0 +1,82 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Time serializing a long message after every keystroke, comparing
+//! ComposerModel::get_html, which returns a new Vec each time, with
+//! serialize_into and serialize_into_string, which reuse one buffer:
+//!
+//!     cargo run --release --example serialization_benchmark
+
+use std::time::{Duration, Instant};
+
+use wysiwyg::ComposerModel;
+
+const PARAGRAPHS: usize = 200;
+const KEYSTROKES: usize = 2000;
+
+fn main() {
+    let new_vec = time_serializing(|model| model.get_html().len());
+    let mut units = Vec::new();
+    let reused_vec = time_serializing(|model| {
+        model.serialize_into(&mut units);
+        units.len()
+    });
+    let mut string = String::new();
+    let reused_string = time_serializing(|model| {
+        model.serialize_into_string(&mut string);
+        string.len()
+    });
+    println!(
+        "{} keystrokes into {} paragraphs, serializing after each:\n  \
+        get_html: {:?} ({:?} each)\n  \
+        serialize_into: {:?} ({:?} each)\n  \
+        serialize_into_string: {:?} ({:?} each)",
+        KEYSTROKES,
+        PARAGRAPHS,
+        new_vec,
+        new_vec / KEYSTROKES as u32,
+        reused_vec,
+        reused_vec / KEYSTROKES as u32,
+        reused_string,
+        reused_string / KEYSTROKES as u32,
+    );
+}
+
+/**
+ * The time spent in serialize, which returns the length of what it
+ * serialized so that the work cannot be optimised away.
+ */
+fn time_serializing(
+    mut serialize: impl FnMut(&ComposerModel<u16>) -> usize,
+) -> Duration {
+    let mut model: ComposerModel<u16> = ComposerModel::new();
+    let html = "<p>Some <strong>formatted</strong> text</p>".repeat(PARAGRAPHS);
+    model.set_content_from_html(&utf16(&html));
+    model.select(20.into(), 20.into());
+    let typed = utf16("a");
+    let mut elapsed = Duration::ZERO;
+    let mut serialized = 0;
+    for _ in 0..KEYSTROKES {
+        model.replace_text(&typed);
+        let start = Instant::now();
+        serialized += serialize(&model);
+        elapsed += start.elapsed();
+    }
+    assert!(serialized > 0);
+    elapsed
+}
+
+fn utf16(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
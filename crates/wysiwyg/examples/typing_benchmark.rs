@@ -0,0 +1,58 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Time typing into a long message, one character at a time, comparing
+//! ASCII (which ComposerModel::replace_text handles by going straight to
+//! the text node typed into) with text that goes through the full path:
+//!
+//!     cargo run --release --example typing_benchmark
+
+use std::time::{Duration, Instant};
+
+use wysiwyg::ComposerModel;
+
+const PARAGRAPHS: usize = 200;
+const KEYSTROKES: usize = 2000;
+
+fn main() {
+    let ascii = time_typing("a");
+    let other = time_typing("é");
+    println!(
+        "{} keystrokes into {} paragraphs:\n  ascii: {:?} ({:?} each)\n  \
+        other: {:?} ({:?} each)",
+        KEYSTROKES,
+        PARAGRAPHS,
+        ascii,
+        ascii / KEYSTROKES as u32,
+        other,
+        other / KEYSTROKES as u32,
+    );
+}
+
+fn time_typing(typed: &str) -> Duration {
+    let mut model: ComposerModel<u16> = ComposerModel::new();
+    let html = "<p>Some <strong>formatted</strong> text</p>".repeat(PARAGRAPHS);
+    model.set_content_from_html(&utf16(&html));
+    model.select(20.into(), 20.into());
+    let typed = utf16(typed);
+    let start = Instant::now();
+    for _ in 0..KEYSTROKES {
+        model.replace_text(&typed);
+    }
+    start.elapsed()
+}
+
+fn utf16(s: &str) -> Vec<u16> {
+    s.encode_utf16().collect()
+}
@@ -0,0 +1,109 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/*!
+ * Finding where a location in one version of some content ended up in
+ * another version, by looking for the same text around it. Content is
+ * given as one entry per location: Some(code unit) for text, and None for
+ * a void element such as a line break.
+ */
+
+/**
+ * How far either side of a location to look for matching content.
+ */
+const CONTEXT: usize = 32;
+
+/**
+ * The location in new with the longest run of content matching the
+ * content either side of location in old. Of equally good matches, the
+ * one nearest to location is chosen.
+ */
+pub(crate) fn map_location<C>(
+    old: &[Option<C>],
+    new: &[Option<C>],
+    location: usize,
+) -> usize
+where
+    C: PartialEq,
+{
+    let location = location.min(old.len());
+    let before = &old[location.saturating_sub(CONTEXT)..location];
+    let after = &old[location..(location + CONTEXT).min(old.len())];
+    let mut best = location.min(new.len());
+    let mut best_score = 0;
+    for candidate in 0..=new.len() {
+        let score = common_suffix(before, &new[..candidate])
+            + common_prefix(after, &new[candidate..]);
+        let nearer = candidate.abs_diff(location) < best.abs_diff(location);
+        if score > best_score || (score == best_score && score > 0 && nearer) {
+            best = candidate;
+            best_score = score;
+        }
+    }
+    best
+}
+
+fn common_prefix<C: PartialEq>(a: &[Option<C>], b: &[Option<C>]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+fn common_suffix<C: PartialEq>(a: &[Option<C>], b: &[Option<C>]) -> usize {
+    a.iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count()
+}
+
+#[cfg(test)]
+mod test {
+    use super::map_location;
+
+    fn units(text: &str) -> Vec<Option<u8>> {
+        text.bytes()
+            .map(|b| if b == b'/' { None } else { Some(b) })
+            .collect()
+    }
+
+    fn map(old: &str, new: &str, location: usize) -> usize {
+        map_location(&units(old), &units(new), location)
+    }
+
+    #[test]
+    fn a_location_follows_its_text_when_content_is_added_before_it() {
+        assert_eq!(map("hello world", "oh, hello world", 5), 9);
+    }
+
+    #[test]
+    fn a_location_stays_put_when_content_is_added_after_it() {
+        assert_eq!(map("hello world", "hello world!!", 5), 5);
+    }
+
+    #[test]
+    fn a_location_next_to_changed_text_keeps_its_other_side() {
+        assert_eq!(map("one two three", "one 2 three", 7), 5);
+    }
+
+    #[test]
+    fn void_elements_only_match_void_elements() {
+        assert_eq!(map("a/b", "xa/b", 2), 3);
+        assert_eq!(map("ab", "a/b", 1), 1);
+    }
+
+    #[test]
+    fn without_any_matching_text_the_location_is_clamped() {
+        assert_eq!(map("abc", "xy", 3), 2);
+        assert_eq!(map("", "xyz", 0), 0);
+    }
+}
@@ -0,0 +1,26 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/**
+ * Tells a ComposerModel the time, in place of the system clock, so that
+ * tests and replayed traces behave the same on every platform and every
+ * run. See ComposerModel::set_clock.
+ */
+pub trait Clock: Send + Sync {
+    /**
+     * The time in milliseconds since the Unix epoch. It should not go
+     * backwards.
+     */
+    fn now_ms(&self) -> u64;
+}
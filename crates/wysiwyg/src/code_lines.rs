@@ -0,0 +1,281 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/*!
+ * Edits to the lines of the text of a code block, for the composer's
+ * code-editing shortcuts. Each takes the text, and the start and end of
+ * the selection as offsets into it, and returns the new text and where the
+ * selection should be in it, or None if nothing would change.
+ */
+
+use crate::CodeUnit;
+
+type LineEdit<C> = Option<(Vec<C>, usize, usize)>;
+
+/**
+ * Comment out the lines start..end touches by putting prefix and a space
+ * after their indentation, or uncomment them if they all start with
+ * prefix. Blank lines are left alone, unless all the lines are blank.
+ */
+pub(crate) fn toggle_comment<C>(
+    text: &[C],
+    start: usize,
+    end: usize,
+    prefix: &[C],
+) -> LineEdit<C>
+where
+    C: CodeUnit,
+{
+    if prefix.is_empty() {
+        return None;
+    }
+    let (first, last) = line_span(text, start, end);
+    let mut lines = Vec::new();
+    let mut line_start = first;
+    loop {
+        let line_end = line_end(text, line_start);
+        let indent = text[line_start..line_end]
+            .iter()
+            .take_while(|&c| is(c, b' ') || is(c, b'\t'))
+            .count();
+        lines.push((line_start + indent, line_end));
+        if line_end >= last {
+            break;
+        }
+        line_start = line_end + 1;
+    }
+    if lines.iter().any(|(text_start, end)| text_start < end) {
+        lines.retain(|(text_start, end)| text_start < end);
+    }
+    let commented = lines
+        .iter()
+        .all(|&(text_start, end)| text[text_start..end].starts_with(prefix));
+    // Each edit removes a number of code units at an offset and inserts
+    // others in their place
+    let edits: Vec<(usize, usize, Vec<C>)> = lines
+        .into_iter()
+        .map(|(text_start, end)| {
+            if commented {
+                let after = text_start + prefix.len();
+                let space = after < end && is(&text[after], b' ');
+                (text_start, prefix.len() + usize::from(space), Vec::new())
+            } else {
+                let mut insert = prefix.to_vec();
+                insert.extend(C::encode(" "));
+                (text_start, 0, insert)
+            }
+        })
+        .collect();
+    let mut new_text = Vec::with_capacity(text.len());
+    let mut pos = 0;
+    for (at, removed, inserted) in &edits {
+        new_text.extend_from_slice(&text[pos..*at]);
+        new_text.extend_from_slice(inserted);
+        pos = at + removed;
+    }
+    new_text.extend_from_slice(&text[pos..]);
+    // What is inserted where a selection starts goes inside it
+    let map = |offset: usize, inside: bool| {
+        let mut shift: isize = 0;
+        for (at, removed, inserted) in &edits {
+            if offset < *at || (inside && offset == *at && *removed == 0) {
+                break;
+            }
+            let kept = (offset - at).min(*removed);
+            shift += inserted.len() as isize - kept as isize;
+        }
+        (offset as isize + shift) as usize
+    };
+    Some((new_text, map(start, start < end), map(end, false)))
+}
+
+/**
+ * Copy the lines start..end touches to below them, and move the selection
+ * to the copy.
+ */
+pub(crate) fn duplicate_lines<C>(
+    text: &[C],
+    start: usize,
+    end: usize,
+) -> LineEdit<C>
+where
+    C: CodeUnit,
+{
+    let (first, last) = line_span(text, start, end);
+    let mut new_text = text[..last].to_vec();
+    new_text.extend(C::encode("\n"));
+    new_text.extend_from_slice(&text[first..]);
+    let shift = last - first + 1;
+    Some((new_text, start + shift, end + shift))
+}
+
+/**
+ * Swap the lines start..end touches with the line above them, moving the
+ * selection with them. None on the first line.
+ */
+pub(crate) fn move_lines_up<C>(
+    text: &[C],
+    start: usize,
+    end: usize,
+) -> LineEdit<C>
+where
+    C: CodeUnit,
+{
+    let (first, last) = line_span(text, start, end);
+    if first == 0 {
+        return None;
+    }
+    let above = line_start(text, first - 1);
+    let mut new_text = text[..above].to_vec();
+    new_text.extend_from_slice(&text[first..last]);
+    new_text.extend(C::encode("\n"));
+    new_text.extend_from_slice(&text[above..first - 1]);
+    new_text.extend_from_slice(&text[last..]);
+    let shift = first - above;
+    Some((new_text, start - shift, end - shift))
+}
+
+/**
+ * Swap the lines start..end touches with the line below them, moving the
+ * selection with them. None on the last line.
+ */
+pub(crate) fn move_lines_down<C>(
+    text: &[C],
+    start: usize,
+    end: usize,
+) -> LineEdit<C>
+where
+    C: CodeUnit,
+{
+    let (first, last) = line_span(text, start, end);
+    if last == text.len() {
+        return None;
+    }
+    let below = line_end(text, last + 1);
+    let mut new_text = text[..first].to_vec();
+    new_text.extend_from_slice(&text[last + 1..below]);
+    new_text.extend(C::encode("\n"));
+    new_text.extend_from_slice(&text[first..last]);
+    new_text.extend_from_slice(&text[below..]);
+    let shift = below - last;
+    Some((new_text, start + shift, end + shift))
+}
+
+/**
+ * The start of the first line start..end touches and the end of the last,
+ * not including the newline after it.
+ */
+fn line_span<C>(text: &[C], start: usize, end: usize) -> (usize, usize)
+where
+    C: CodeUnit,
+{
+    (line_start(text, start), line_end(text, end))
+}
+
+fn line_start<C>(text: &[C], offset: usize) -> usize
+where
+    C: CodeUnit,
+{
+    text[..offset]
+        .iter()
+        .rposition(|c| is(c, b'\n'))
+        .map_or(0, |i| i + 1)
+}
+
+fn line_end<C>(text: &[C], offset: usize) -> usize
+where
+    C: CodeUnit,
+{
+    text[offset..]
+        .iter()
+        .position(|c| is(c, b'\n'))
+        .map_or(text.len(), |i| offset + i)
+}
+
+fn is<C>(c: &C, ascii: u8) -> bool
+where
+    C: CodeUnit,
+{
+    c.to_ascii() == Some(ascii)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        duplicate_lines, move_lines_down, move_lines_up, toggle_comment,
+    };
+
+    type Edit = fn(&[u8], usize, usize) -> Option<(Vec<u8>, usize, usize)>;
+
+    fn edit(
+        f: Edit,
+        text: &str,
+        start: usize,
+        end: usize,
+    ) -> Option<(String, usize, usize)> {
+        f(text.as_bytes(), start, end)
+            .map(|(t, s, e)| (String::from_utf8(t).unwrap(), s, e))
+    }
+
+    fn comment(
+        text: &str,
+        start: usize,
+        end: usize,
+    ) -> Option<(String, usize, usize)> {
+        edit(|t, s, e| toggle_comment(t, s, e, b"//"), text, start, end)
+    }
+
+    #[test]
+    fn comments_go_after_the_indentation_of_non_blank_lines() {
+        assert_eq!(
+            comment("a\n  b\n\nc", 3, 8),
+            Some((String::from("a\n  // b\n\n// c"), 3, 14))
+        );
+        assert_eq!(comment("  ", 2, 2), Some((String::from("  // "), 5, 5)));
+    }
+
+    #[test]
+    fn commenting_commented_lines_uncomments_them() {
+        assert_eq!(
+            comment("  // b\n//c", 5, 10),
+            Some((String::from("  b\nc"), 2, 5))
+        );
+        assert_eq!(
+            comment("// b\nc", 0, 6),
+            Some((String::from("// // b\n// c"), 0, 12))
+        );
+    }
+
+    #[test]
+    fn lines_are_duplicated_below_themselves() {
+        assert_eq!(
+            edit(duplicate_lines, "a\nbc\nd", 3, 3),
+            Some((String::from("a\nbc\nbc\nd"), 6, 6))
+        );
+    }
+
+    #[test]
+    fn lines_move_past_their_neighbours() {
+        assert_eq!(
+            edit(move_lines_up, "a\nbc\nd\ne", 3, 6),
+            Some((String::from("bc\nd\na\ne"), 1, 4))
+        );
+        assert_eq!(edit(move_lines_up, "a\nb", 1, 1), None);
+        assert_eq!(
+            edit(move_lines_down, "a\nbc\nd", 0, 3),
+            Some((String::from("d\na\nbc"), 2, 5))
+        );
+        assert_eq!(edit(move_lines_down, "a\nb", 2, 3), None);
+    }
+}
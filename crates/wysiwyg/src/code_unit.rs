@@ -0,0 +1,134 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+use std::fmt::Debug;
+
+/**
+ * A code unit of the encoding used to store and exchange text, e.g. u16
+ * for UTF-16 or u8 for UTF-8. Locations are measured in these units.
+ */
+pub trait CodeUnit: Copy + Debug + Eq {
+    /**
+     * Encode the supplied str as a sequence of code units.
+     */
+    fn encode(text: &str) -> Vec<Self>;
+
+    /**
+     * Encode the supplied str onto the end of out, which is only
+     * reallocated if it lacks the capacity.
+     */
+    fn encode_into(text: &str, out: &mut Vec<Self>) {
+        out.extend(Self::encode(text));
+    }
+
+    /**
+     * Decode a sequence of code units into a String, replacing any invalid
+     * sequences with U+FFFD REPLACEMENT CHARACTER.
+     */
+    fn decode(units: &[Self]) -> String;
+
+    /**
+     * Decode a sequence of code units onto the end of out, replacing
+     * invalid sequences as decode does.
+     */
+    fn decode_into(units: &[Self], out: &mut String) {
+        out.push_str(&Self::decode(units));
+    }
+
+    /**
+     * Whether units is a valid sequence, i.e. decode would not replace
+     * anything in it. In UTF-16, for example, a lone surrogate is invalid.
+     */
+    fn is_valid(units: &[Self]) -> bool {
+        Self::encode(&Self::decode(units)) == units
+    }
+
+    /**
+     * If this code unit represents an ASCII character on its own, return
+     * it.
+     */
+    fn to_ascii(self) -> Option<u8>;
+}
+
+impl CodeUnit for u16 {
+    fn encode(text: &str) -> Vec<Self> {
+        text.encode_utf16().collect()
+    }
+
+    fn encode_into(text: &str, out: &mut Vec<Self>) {
+        out.extend(text.encode_utf16());
+    }
+
+    fn decode(units: &[Self]) -> String {
+        String::from_utf16_lossy(units)
+    }
+
+    fn decode_into(units: &[Self], out: &mut String) {
+        out.extend(
+            char::decode_utf16(units.iter().copied())
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+        );
+    }
+
+    fn is_valid(units: &[Self]) -> bool {
+        char::decode_utf16(units.iter().copied()).all(|c| c.is_ok())
+    }
+
+    fn to_ascii(self) -> Option<u8> {
+        u8::try_from(self).ok().filter(u8::is_ascii)
+    }
+}
+
+impl CodeUnit for u8 {
+    fn encode(text: &str) -> Vec<Self> {
+        text.as_bytes().to_vec()
+    }
+
+    fn encode_into(text: &str, out: &mut Vec<Self>) {
+        out.extend_from_slice(text.as_bytes());
+    }
+
+    fn decode(units: &[Self]) -> String {
+        String::from_utf8_lossy(units).into_owned()
+    }
+
+    fn decode_into(units: &[Self], out: &mut String) {
+        out.push_str(&String::from_utf8_lossy(units));
+    }
+
+    fn is_valid(units: &[Self]) -> bool {
+        std::str::from_utf8(units).is_ok()
+    }
+
+    fn to_ascii(self) -> Option<u8> {
+        Some(self).filter(u8::is_ascii)
+    }
+}
+
+/**
+ * units, with any invalid sequences replaced as decode replaces them, so
+ * that text given to the composer can always be decoded and encoded again
+ * without changing.
+ */
+pub(crate) fn replace_invalid<C>(units: &[C]) -> Cow<'_, [C]>
+where
+    C: CodeUnit,
+{
+    if C::is_valid(units) {
+        Cow::Borrowed(units)
+    } else {
+        Cow::Owned(C::encode(&C::decode(units)))
+    }
+}
@@ -21,9 +21,26 @@ pub struct ComposerAction {
 #[derive(Debug, Clone)]
 pub enum ActionRequest {
     Dummy,
+    /**
+     * An attachment placeholder was inserted. Respond with
+     * AttachmentReady once the attachment is available, or
+     * AttachmentRemoved if it will never be.
+     */
+    ResolveAttachment,
 }
 
 #[derive(Debug, Clone)]
 pub enum ActionResponse {
     Dummy,
+    /**
+     * Replace the attachment placeholder with html, e.g. an image or a
+     * link to the uploaded file.
+     */
+    AttachmentReady {
+        html: String,
+    },
+    /**
+     * Remove the attachment placeholder, e.g. because its upload failed.
+     */
+    AttachmentRemoved,
 }
@@ -0,0 +1,56 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/**
+ * An action offered by a toolbar or keyboard shortcut, for
+ * ComposerModel::apply. Having one entry point for all of them means a new
+ * action needs only a new variant, rather than a new method in every
+ * binding.
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ComposerCommand<C> {
+    Bold,
+    Italic,
+    /**
+     * See ComposerModel::toggle_list_type.
+     */
+    ToggleListType,
+    /**
+     * See ComposerModel::tab.
+     */
+    Indent,
+    /**
+     * See ComposerModel::shift_tab.
+     */
+    Unindent,
+    /**
+     * See ComposerModel::move_list_item_up.
+     */
+    MoveListItemUp,
+    /**
+     * See ComposerModel::move_list_item_down.
+     */
+    MoveListItemDown,
+    /**
+     * See ComposerModel::remove_list.
+     */
+    RemoveList,
+    Undo,
+    Redo,
+    ApplyCopiedFormatting,
+    SetLinkWithText {
+        link: Vec<C>,
+        text: Vec<C>,
+    },
+}
@@ -0,0 +1,287 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::DEFAULT_MAX_DEPTH;
+
+/**
+ * Options that change how a ComposerModel behaves, so that each platform
+ * can get the behaviour it needs from the same model.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ComposerConfig {
+    /**
+     * The formatting features the user may use. Requests to use any other
+     * feature leave the content unchanged.
+     */
+    pub features: Vec<Feature>,
+    pub enter_behaviour: EnterBehaviour,
+    /**
+     * Whether typing markdown such as **bold** or *italic* formats it.
+     */
+    pub markdown_shortcuts: bool,
+    /**
+     * The maximum length of the content, in code units. Typed or replaced
+     * text that would make the content longer is truncated.
+     */
+    pub max_length: Option<usize>,
+    /**
+     * The schemes (e.g. "https") that links may use. Requests to create a
+     * link with any other scheme, or with no scheme, are ignored, and
+     * links using them are removed from messages and pasted content.
+     */
+    pub allowed_link_schemes: Vec<String>,
+    /**
+     * If set, typing two spaces after a word within this many milliseconds
+     * of each other replaces them with ". ". Only text typed via
+     * ComposerModel::replace_text_at_time is timed, or via replace_text
+     * once the model has a Clock (see ComposerModel::set_clock).
+     */
+    pub double_space_full_stop: Option<u64>,
+    /**
+     * Whether typed or replaced text is put into Unicode canonical composed
+     * form (NFC) before it is inserted, so that the same text has the same
+     * length, and hence the same locations, on every platform.
+     */
+    pub normalize_input: bool,
+    /**
+     * What formatting a selection that covers only part of a link does to
+     * the link.
+     */
+    pub link_formatting: LinkFormatting,
+    /**
+     * What formatting a selection that covers a mention does to the
+     * mention.
+     */
+    pub mention_formatting: MentionFormatting,
+    /**
+     * A BCP 47 language tag (e.g. "th") for the language of the content,
+     * passed to the WordDictionary as a hint.
+     */
+    pub word_locale: Option<String>,
+    /**
+     * If set, an edit that takes this many milliseconds or more is
+     * abandoned, leaving the content as it was, and reports
+     * ComposerError::BudgetExceeded, so that repeated pathological input
+     * does not keep changing the content slowly. This is a soft limit: it
+     * is checked between the steps of an edit (the events of a batch, the
+     * lines of typed text) and once the edit is done, so a single
+     * expensive step, such as parsing, deleting or serializing a very
+     * large document, still runs to completion before the edit is
+     * abandoned. Ignored on WebAssembly, which has no clock to measure by.
+     */
+    pub operation_budget_ms: Option<u64>,
+    /**
+     * How deeply elements in HTML given to the composer may be nested.
+     * HTML nested any deeper is refused with ComposerError::TooDeep, so
+     * that pathological input cannot exhaust the stack.
+     */
+    pub max_depth: usize,
+    /**
+     * Whether applying an inline format with the cursor inside a word, and
+     * nothing selected, formats the whole word, as many word processors
+     * do. Otherwise the format applies to what is typed next.
+     */
+    pub format_word_at_cursor: bool,
+    /**
+     * How many edits can be undone. Once there are more, the oldest are
+     * forgotten.
+     */
+    pub max_undo_entries: Option<usize>,
+    /**
+     * The most characters to show of a URL given as the text of a link to
+     * itself (see ComposerModel::set_link_with_text). Longer URLs are cut
+     * short with an ellipsis, but the link still goes to the whole URL,
+     * and plain text bodies show it whole.
+     */
+    pub max_link_text_length: Option<usize>,
+    /**
+     * Roughly how much memory, in bytes, the edits that can be undone may
+     * take up. Once they take up more, the oldest are forgotten, so that
+     * drafting a huge message cannot use up a phone's memory.
+     */
+    pub max_undo_bytes: Option<usize>,
+    /**
+     * Which typed text is undone together in one step, rather than each
+     * call to replace_text separately.
+     */
+    pub undo_coalescing: UndoCoalescing,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Feature {
+    Bold,
+    Italic,
+    Links,
+    OrderedList,
+    UnorderedList,
+}
+
+/**
+ * What pressing Enter does.
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnterBehaviour {
+    /**
+     * Leave the content unchanged, so that the platform can send the
+     * message.
+     */
+    Send,
+    /**
+     * Insert a line break.
+     */
+    NewLine,
+}
+
+/**
+ * What formatting (e.g. bold) a selection that starts or ends inside a link
+ * does to the link.
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LinkFormatting {
+    /**
+     * Keep the link whole, and format the selected part of its text inside
+     * it, e.g. a<a>b</a> bolded from a to b gives
+     * <strong>a</strong><a><strong>b</strong></a>.
+     */
+    Inside,
+    /**
+     * Split the link at the edges of the selection, so that the selected
+     * part becomes a separate link inside the formatting, e.g.
+     * <strong>a<a>b</a></strong>.
+     */
+    SplitLink,
+}
+
+/**
+ * What formatting (e.g. bold) a selection that covers a mention does to
+ * the mention. Either way the mention is treated as a whole: a selection
+ * that starts or ends inside one covers all of it.
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MentionFormatting {
+    /**
+     * Leave mentions unformatted, so that they keep looking like pills.
+     */
+    Skip,
+    /**
+     * Format the text of mentions along with the rest of the selection.
+     */
+    StyleText,
+}
+
+/**
+ * How consecutive typing, i.e. text inserted by replace_text or
+ * replace_text_at_time at the cursor, is grouped into the steps that undo
+ * takes. Any other edit, undo, or moving the cursor ends a group.
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UndoCoalescing {
+    /**
+     * Each call to replace_text is undone on its own.
+     */
+    EachEdit,
+    /**
+     * Typing is undone a word at a time, along with the whitespace that
+     * follows it, as on iOS and macOS.
+     */
+    Words,
+    /**
+     * Typing is undone back to the last pause of more than max_pause_ms
+     * milliseconds. Only typing with a timestamp (see
+     * HistoryItem::timestamp_ms) is grouped.
+     */
+    Pauses { max_pause_ms: u64 },
+    /**
+     * Typing is undone up to max characters at a time.
+     */
+    Characters { max: usize },
+}
+
+impl ComposerConfig {
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /**
+     * Whether link is allowed by allowed_link_schemes. Schemes are compared
+     * ignoring case.
+     */
+    pub fn is_allowed_link(&self, link: &str) -> bool {
+        let scheme = match link.split_once(':') {
+            Some((scheme, _)) => scheme,
+            None => return false,
+        };
+        let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        valid
+            && self
+                .allowed_link_schemes
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+    }
+}
+
+impl Default for ComposerConfig {
+    fn default() -> Self {
+        Self {
+            features: vec![
+                Feature::Bold,
+                Feature::Italic,
+                Feature::Links,
+                Feature::OrderedList,
+                Feature::UnorderedList,
+            ],
+            enter_behaviour: EnterBehaviour::Send,
+            markdown_shortcuts: false,
+            max_length: None,
+            // The schemes allowed in links by the Matrix specification, plus
+            // matrix: URIs
+            allowed_link_schemes: ["https", "http", "ftp", "mailto", "magnet"]
+                .iter()
+                .chain(&["matrix"])
+                .map(|s| String::from(*s))
+                .collect(),
+            double_space_full_stop: None,
+            normalize_input: false,
+            link_formatting: LinkFormatting::Inside,
+            mention_formatting: MentionFormatting::Skip,
+            word_locale: None,
+            operation_budget_ms: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            format_word_at_cursor: false,
+            max_undo_entries: None,
+            max_link_text_length: None,
+            max_undo_bytes: None,
+            undo_coalescing: UndoCoalescing::EachEdit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ComposerConfig;
+
+    #[test]
+    fn links_must_use_an_allowed_scheme() {
+        let config = ComposerConfig::default();
+        assert!(config.is_allowed_link("https://matrix.org"));
+        assert!(config.is_allowed_link("MAILTO:a@b.c"));
+        assert!(config.is_allowed_link("matrix:r/room:example.org"));
+        assert!(!config.is_allowed_link("javascript:alert(1)"));
+        assert!(!config.is_allowed_link("matrix.org"));
+        assert!(!config.is_allowed_link("/relative:path"));
+    }
+}
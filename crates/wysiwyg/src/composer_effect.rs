@@ -0,0 +1,44 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    ComposerAction, ComposerError, MenuAction, MenuState, SanitizeReport,
+    TextUpdate,
+};
+
+/**
+ * One thing an operation did that the platform may need to act on, as
+ * listed in order by ComposerUpdate::effects.
+ */
+#[derive(Debug, Clone)]
+pub enum ComposerEffect<C> {
+    /**
+     * The content or selection changed, and the editing surface should be
+     * updated to match.
+     */
+    TextUpdated(TextUpdate<C>),
+    MenuStateChanged(MenuState),
+    /**
+     * What the platform should offer is now this, e.g. MenuAction::None
+     * once a suggestion has been completed or dismissed.
+     */
+    MenuActionChanged(MenuAction<C>),
+    ActionRequested(ComposerAction),
+    /**
+     * Something was removed from HTML given to the operation.
+     */
+    Sanitized(SanitizeReport),
+    Diagnostic(String),
+    Failed(ComposerError),
+}
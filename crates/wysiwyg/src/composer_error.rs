@@ -0,0 +1,37 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/**
+ * Why an operation failed. A failed operation leaves the content and
+ * selection as they were before it.
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ComposerError {
+    /**
+     * The operation took longer than ComposerConfig's operation_budget_ms,
+     * e.g. because the pasted content was pathologically large, so it was
+     * abandoned once the budget was found to be used up.
+     */
+    BudgetExceeded,
+    /**
+     * The HTML given had elements nested more deeply than ComposerConfig's
+     * max_depth, so it was refused.
+     */
+    TooDeep,
+    /**
+     * The operation would have changed or removed protected content (see
+     * ComposerModel::set_protected), so it was refused.
+     */
+    Protected,
+}
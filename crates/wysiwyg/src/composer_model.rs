@@ -12,25 +12,515 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{ActionResponse, ComposerUpdate, Location};
+use std::borrow::Cow;
+use std::cell::{Cell, Ref, RefCell};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::anchoring::map_location;
+use crate::code_lines;
+use crate::code_unit::replace_invalid;
+use crate::dom::{
+    element_spec, markdown_styles, markdown_to_html, parse_to_depth,
+    plain_text_to_html, shorten_url, Atom, Dom, DomFragment, DomNode, NodeId,
+    PinnedNode, StableHasher, ATTACHMENT_ELEMENT, ELEMENTS, PROTECTED_ATTR,
+};
+use crate::graphemes::grapheme_count;
+use crate::history::{History, HistoryEntry, HistoryItem, Typing};
+use crate::mention::{
+    MATRIX_TO_PREFIX, MENTION_AVATAR_ATTR, MENTION_ID_ATTR, MENTION_KIND_ATTR,
+};
+use crate::normalization::nfc;
+use crate::words;
+use crate::{
+    ActionRequest, ActionResponse, AnchoredLocation, Clock, CodeUnit,
+    ComposerAction, ComposerCommand, ComposerConfig, ComposerError,
+    ComposerSnapshot, ComposerUpdate, Coverage, DomHandle, DomObserver,
+    EnterBehaviour, Feature, Format, FormatSet, HtmlDialect, HtmlViolation,
+    InputEvent, LinkFormatting, LinkInfo, Location, MarkdownStyle, Mention,
+    MentionFormatting, MentionKind, MenuAction, MenuState, ParseWarning,
+    PatternKey, RestoreOptions, RestoredMessage, SanitizeReport, SnapshotState,
+    SuggestionPattern, WordDictionary,
+};
+
 pub struct ComposerModel<C>
 where
-    C: Clone,
+    C: CodeUnit,
 {
-    html: Vec<C>, // TODO: not an AST yet!
+    config: ComposerConfig,
+    dom: Dom<C>,
     start: Location,
     end: Location,
+    history: History<C>,
+    /**
+     * Where the cursor was left after typing a space, and when, so that a
+     * second space can be turned into a full stop.
+     */
+    last_space: Option<(Location, u64)>,
+    /**
+     * Problems found by check_dom, waiting to be reported in the next
+     * ComposerUpdate.
+     */
+    diagnostics: Vec<String>,
+    /**
+     * What was removed from HTML given to the composer, waiting to be
+     * reported in the next ComposerUpdate.
+     */
+    sanitize_report: SanitizeReport,
+    /**
+     * The formatting captured by copy_formatting.
+     */
+    copied_formatting: Option<FormatSet>,
+    /**
+     * Formats toggled while the selection was empty, to be applied to the
+     * next text typed there rather than leaving empty elements behind.
+     */
+    pending_formats: Vec<String>,
+    /**
+     * Formats toggled off while the selection was empty and inside them,
+     * to be left off the next text typed there.
+     */
+    pending_removals: Vec<String>,
+    /**
+     * The block that enter just moved the cursor into. Its start is the
+     * same location as the end of the block before it, so typing there
+     * must be directed into it explicitly.
+     */
+    cursor_block: Option<NodeId>,
+    /**
+     * Those notified of each change to the Dom by subscribe, and the Dom
+     * as they last saw it.
+     */
+    observers: Vec<Box<dyn DomObserver<C>>>,
+    observed: Option<Dom<C>>,
+    /**
+     * The Editor HTML last rendered, and the revision of the Dom it was
+     * rendered from. Dom revisions are never reused, so this is stale
+     * exactly when the revision differs, however the Dom was changed or
+     * replaced.
+     */
+    rendered: RefCell<Option<(u64, Vec<C>)>>,
+    /**
+     * The model's revision (see revision), and the Dom revision and
+     * selection it was last reported for.
+     */
+    revision: Cell<(u64, (u64, Location, Location))>,
+    /**
+     * Divides text from scripts written without spaces into words for
+     * select_word_at_cursor.
+     */
+    word_dictionary: Option<Arc<dyn WordDictionary>>,
+    /**
+     * Tells the time in place of the system clock (see set_clock).
+     */
+    clock: Option<Arc<dyn Clock>>,
+    /**
+     * Text shown in place of the content while it is empty. Never part of
+     * the content.
+     */
+    placeholder: Vec<C>,
+    /**
+     * When the edit in progress runs out of its operation_budget_ms, and
+     * the error to report with the next update.
+     */
+    deadline: Option<Deadline>,
+    error: Option<ComposerError>,
+    /**
+     * The label of the edit in progress, for its HistoryItem.
+     */
+    edit_label: &'static str,
+    /**
+     * What the edit in progress typed, if all it does is type text, so
+     * that the history can coalesce typing.
+     */
+    edit_typing: Option<Typing>,
+    /**
+     * The protected elements when the edit in progress began, and their
+     * HTML, so that end_edit can refuse the edit if it changed them.
+     */
+    edit_protected: Option<Vec<(NodeId, Vec<C>)>>,
+    /**
+     * Whether the content is markdown being edited as plain text (see
+     * set_plain_text_mode).
+     */
+    plain_text_mode: bool,
+    /**
+     * The MenuState last reported, and what it was computed from if the
+     * selection was empty, so that it is only recomputed when the
+     * cursor's surroundings change and only reported when it differs.
+     */
+    last_menu: Option<(Option<MenuKey>, MenuState)>,
+}
+
+/**
+ * When an edit runs out of its operation_budget_ms, by the system clock or
+ * by the model's Clock if it has one.
+ */
+#[derive(Clone, Copy)]
+enum Deadline {
+    Instant(Instant),
+    ClockMs(u64),
+}
+
+/**
+ * Everything the MenuState at an empty selection depends on.
+ */
+#[derive(Clone, PartialEq)]
+struct MenuKey {
+    ancestry: Vec<(NodeId, String)>,
+    list: Option<(NodeId, String)>,
+    pending_formats: Vec<String>,
+    pending_removals: Vec<String>,
+    can_undo_and_redo: (bool, bool),
+}
+
+/**
+ * A copy of the model including its undo history, which can be edited
+ * without affecting the original. Observers stay subscribed to the
+ * original only. See also fork, which leaves the history behind.
+ */
+impl<C> Clone for ComposerModel<C>
+where
+    C: CodeUnit,
+{
+    fn clone(&self) -> Self {
+        Self {
+            history: self.history.clone(),
+            diagnostics: self.diagnostics.clone(),
+            ..self.fork()
+        }
+    }
 }
 
 impl<C> ComposerModel<C>
 where
-    C: Clone,
+    C: CodeUnit,
 {
     pub fn new() -> Self {
+        Self::new_with_config(ComposerConfig::default())
+    }
+
+    /**
+     * Create a model that behaves as described by config. See also
+     * ComposerModelBuilder.
+     */
+    pub fn new_with_config(config: ComposerConfig) -> Self {
+        let history = History::new(
+            config.max_undo_entries,
+            config.max_undo_bytes,
+            config.undo_coalescing,
+        );
         Self {
-            html: Vec::new(),
+            config,
+            dom: Dom::new(),
             start: Location::from(0),
             end: Location::from(0),
+            history,
+            last_space: None,
+            diagnostics: Vec::new(),
+            sanitize_report: SanitizeReport::default(),
+            copied_formatting: None,
+            pending_formats: Vec::new(),
+            pending_removals: Vec::new(),
+            cursor_block: None,
+            observers: Vec::new(),
+            observed: None,
+            rendered: RefCell::new(None),
+            revision: Cell::new((
+                0,
+                (u64::MAX, Location::from(0), Location::from(0)),
+            )),
+            word_dictionary: None,
+            clock: None,
+            placeholder: Vec::new(),
+            deadline: None,
+            edit_label: "",
+            edit_typing: None,
+            edit_protected: None,
+            error: None,
+            plain_text_mode: false,
+            last_menu: None,
+        }
+    }
+
+    pub fn config(&self) -> &ComposerConfig {
+        &self.config
+    }
+
+    /**
+     * Notify observer of every change to the content from now on, as
+     * DomEvents that turn a platform DOM built from the Editor HTML before
+     * each operation into one built from the HTML after it. The events for
+     * an operation are sent before it returns its ComposerUpdate.
+     */
+    pub fn subscribe(&mut self, observer: Box<dyn DomObserver<C>>) {
+        if self.observed.is_none() {
+            self.observed = Some(self.dom.clone());
+        }
+        self.observers.push(observer);
+    }
+
+    /**
+     * Use dictionary to find the words in text from scripts written
+     * without spaces between words, such as Thai or Japanese, when
+     * selecting words.
+     */
+    pub fn set_word_dictionary(&mut self, dictionary: Arc<dyn WordDictionary>) {
+        self.word_dictionary = Some(dictionary);
+    }
+
+    /**
+     * Use clock instead of the system clock for everything timed: the
+     * operation_budget_ms, the timestamps of HistoryItems, and the
+     * double_space_full_stop interval, which replace_text then applies as
+     * replace_text_at_time does. With a clock that only moves when told
+     * to, editing is deterministic, including where there is no system
+     * clock, such as WebAssembly.
+     */
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = Some(clock);
+    }
+
+    /**
+     * A new model with the same config, content and selection as this
+     * one, but no undo history, diagnostics or observers, e.g. to preview what
+     * would be sent after some transformation without changing the
+     * composer being edited. Copying only the content keeps this cheap.
+     */
+    pub fn fork(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            dom: self.dom.clone(),
+            start: self.start,
+            end: self.end,
+            history: History::new(
+                self.config.max_undo_entries,
+                self.config.max_undo_bytes,
+                self.config.undo_coalescing,
+            ),
+            last_space: self.last_space,
+            diagnostics: Vec::new(),
+            sanitize_report: SanitizeReport::default(),
+            copied_formatting: self.copied_formatting.clone(),
+            pending_formats: self.pending_formats.clone(),
+            pending_removals: self.pending_removals.clone(),
+            cursor_block: self.cursor_block,
+            observers: Vec::new(),
+            observed: None,
+            rendered: self.rendered.clone(),
+            revision: self.revision.clone(),
+            word_dictionary: self.word_dictionary.clone(),
+            clock: self.clock.clone(),
+            placeholder: self.placeholder.clone(),
+            deadline: None,
+            edit_label: "",
+            edit_typing: None,
+            edit_protected: None,
+            error: None,
+            plain_text_mode: self.plain_text_mode,
+            last_menu: None,
+        }
+    }
+
+    /**
+     * A number that goes up by one each time it is asked for after the
+     * content or the selection has changed, and is otherwise the same.
+     * Every update carries the revision it leaves the model at, so that a
+     * platform can tell when it has missed one, skip rendering an update
+     * it has already rendered, and match up logs from either side.
+     */
+    pub fn revision(&self) -> u64 {
+        let current = (self.dom.revision(), self.start, self.end);
+        let (revision, reported) = self.revision.get();
+        if reported == current {
+            revision
+        } else {
+            self.revision.set((revision + 1, current));
+            revision + 1
+        }
+    }
+
+    /**
+     * Whether other has the same content, as Dom's PartialEq compares it,
+     * and the same selection. Nothing else (e.g. the history or the
+     * config) is compared.
+     */
+    pub fn has_same_state(&self, other: &ComposerModel<C>) -> bool {
+        self.get_selection() == other.get_selection() && self.dom == other.dom
+    }
+
+    /**
+     * A hash of the content and the selection, which is the same for any
+     * two models that have the same state (see has_same_state) and, like
+     * Dom::content_hash, can be stored. The selection is hashed as offsets
+     * in characters rather than code units, so the hash does not depend on
+     * the code unit either. To only save a draft when its content has
+     * changed, use content_hash instead.
+     */
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        hasher.write_u64(self.content_hash());
+        hasher.write_u64(self.char_offset(self.start) as u64);
+        hasher.write_u64(self.char_offset(self.end) as u64);
+        hasher.finish()
+    }
+
+    /**
+     * The number of characters before location, each void element (e.g. a
+     * line break) counting as one.
+     */
+    fn char_offset(&self, location: Location) -> usize {
+        let units = self.dom.location_units();
+        let location = usize::from(location).min(units.len());
+        let mut offset = 0;
+        let mut text = Vec::new();
+        for unit in &units[..location] {
+            match unit {
+                Some(unit) => text.push(*unit),
+                None => {
+                    offset += C::decode(&text).chars().count() + 1;
+                    text.clear();
+                }
+            }
+        }
+        offset + C::decode(&text).chars().count()
+    }
+
+    /**
+     * A hash of the content alone, as Dom::content_hash gives it.
+     */
+    pub fn content_hash(&self) -> u64 {
+        self.dom.content_hash()
+    }
+
+    #[cfg(feature = "long-form")]
+    pub(crate) fn dom(&self) -> &Dom<C> {
+        &self.dom
+    }
+
+    /**
+     * Replaces all the content with the supplied HTML, and moves the cursor
+     * to the start.
+     */
+    pub fn set_content_from_html(&mut self, html: &[C]) -> ComposerUpdate<C> {
+        self.dom = match self.parse_input(html) {
+            Some(dom) => dom,
+            None => return self.create_update_replace_all(),
+        };
+        self.history.clear();
+        self.clear_pending_formats();
+        self.cursor_block = None;
+        self.start = Location::from(0);
+        self.end = Location::from(0);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Replaces all the content with markdown, e.g. a draft written in
+     * another client, and moves the cursor to the start. The markdown is
+     * read as parse_markdown reads it, and anything in it that the Matrix
+     * specification does not allow, such as unsupported HTML, is removed
+     * and reported in the update's sanitize report. In plain text mode the
+     * markdown is shown as it is.
+     */
+    pub fn set_content_from_markdown(
+        &mut self,
+        markdown: &[C],
+    ) -> ComposerUpdate<C> {
+        let markdown = C::decode(markdown);
+        let html = if self.plain_text_mode {
+            plain_text_to_html(&markdown)
+        } else {
+            markdown_to_html(&markdown, self.config.max_depth)
+        };
+        let mut dom = match self.parse_input(&C::encode(&html)) {
+            Some(dom) => dom,
+            None => return self.create_update_replace_all(),
+        };
+        let report = dom.sanitize(&self.config.allowed_link_schemes);
+        self.sanitize_report.extend(report);
+        self.dom = dom;
+        self.history.clear();
+        self.clear_pending_formats();
+        self.cursor_block = None;
+        self.start = Location::from(0);
+        self.end = Location::from(0);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Replaces all the content with the HTML of a sent message, e.g. to
+     * edit it, like set_content_from_html, but first makes it editable as
+     * described by options (see Dom::restore_message), reporting what was
+     * changed so that the platform can warn the user if needed.
+     */
+    pub fn set_content_from_message_html(
+        &mut self,
+        html: &[C],
+        options: &RestoreOptions,
+    ) -> RestoredMessage<C> {
+        let mut dom = match self.parse_input(html) {
+            Some(dom) => dom,
+            None => {
+                return RestoredMessage {
+                    update: self.create_update_replace_all(),
+                    changes: Vec::new(),
+                }
+            }
+        };
+        let changes =
+            dom.restore_message(options, &self.config.allowed_link_schemes);
+        dom.canonicalize_empty_lines();
+        dom.canonicalize_trailing_breaks();
+        self.dom = dom;
+        self.history.clear();
+        self.clear_pending_formats();
+        self.cursor_block = None;
+        self.start = Location::from(0);
+        self.end = Location::from(0);
+        RestoredMessage {
+            update: self.create_update_replace_all(),
+            changes,
+        }
+    }
+
+    /**
+     * Replaces all the content with the supplied HTML, like
+     * set_content_from_html, but keeps the selection next to the same text
+     * as before where it can be found, e.g. when a draft is updated from
+     * another device while it is being edited.
+     */
+    pub fn set_content_from_html_keeping_selection(
+        &mut self,
+        html: &[C],
+    ) -> ComposerUpdate<C> {
+        let old = self.dom.location_units();
+        let (s, e) = self.safe_selection();
+        self.dom = match self.parse_input(html) {
+            Some(dom) => dom,
+            None => return self.create_update_replace_all(),
+        };
+        self.history.clear();
+        self.last_space = None;
+        self.keep_selection(&old, (s, e));
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Move the selection to where s..e, its safe_selection, was in old,
+     * the location units of the content before it changed (see
+     * anchoring::map_location).
+     */
+    fn keep_selection(&mut self, old: &[Option<C>], (s, e): (usize, usize)) {
+        let new = self.dom.location_units();
+        let new_s = map_location(old, &new, s);
+        let new_e = map_location(old, &new, e).max(new_s);
+        if self.start <= self.end {
+            self.start = Location::from(new_s);
+            self.end = Location::from(new_e);
+        } else {
+            self.start = Location::from(new_e);
+            self.end = Location::from(new_s);
         }
     }
 
@@ -38,374 +528,6346 @@ where
      * Cursor is at end.
      */
     pub fn select(&mut self, start: Location, end: Location) {
+        self.last_space = None;
+        let (s, e) = (usize::from(start), usize::from(end));
+        let (start, end) = if s <= e {
+            let (s, e) = self.dom.snap_out_of_protected(s, e);
+            (Location::from(s), Location::from(e))
+        } else {
+            let (e, s) = self.dom.snap_out_of_protected(e, s);
+            (Location::from(s), Location::from(e))
+        };
+        if (start, end) != (self.start, self.end) {
+            self.clear_pending_formats();
+            self.cursor_block = None;
+        }
         self.start = start;
         self.end = end;
     }
 
+    /**
+     * Whether pressing Up should recall the user's previous message rather
+     * than move the cursor: the composer is empty, so the cursor is at its
+     * start.
+     */
+    pub fn can_recall_previous(&self) -> bool {
+        self.dom.text_len() == 0
+    }
+
+    /**
+     * Report that the user pressed Up. The content is unchanged; if
+     * can_recall_previous, the update's menu action is NavigateHistory,
+     * and the platform should recall the previous message instead of
+     * moving the cursor as it otherwise would.
+     */
+    pub fn up(&mut self) -> ComposerUpdate<C> {
+        let mut update = self.with_menu(ComposerUpdate::keep());
+        if self.can_recall_previous() {
+            update.menu_action = MenuAction::NavigateHistory;
+        }
+        update
+    }
+
+    /**
+     * Select the word around the cursor, e.g. when the user double-clicks.
+     * Words are found following Unicode's word boundary rules, and do not
+     * continue across line breaks, blocks or images. If the cursor is
+     * between a word and something else, such as a space, the word is
+     * selected; if it is between two words, the one after it is.
+     */
+    pub fn select_word_at_cursor(&mut self) -> ComposerUpdate<C> {
+        let location = usize::from(self.end).min(self.dom.text_len());
+        if let Some((s, e, _)) = self.segment_at(location) {
+            self.select(Location::from(s), Location::from(e));
+        }
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Move the cursor to the start of its line, keeping the other end of
+     * the selection where it is, as Shift+Home does. Lines are those of
+     * location_to_line_col, so the start of a block is also the end of the
+     * one before it, which is where the cursor is taken to be.
+     */
+    pub fn select_to_start_of_block(&mut self) -> ComposerUpdate<C> {
+        let (start, _) = self.line_around(self.end);
+        self.select(self.start, Location::from(start));
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Move the cursor to the end of its line, keeping the other end of the
+     * selection where it is, as Shift+End does.
+     */
+    pub fn select_to_end_of_block(&mut self) -> ComposerUpdate<C> {
+        let (_, end) = self.line_around(self.end);
+        self.select(self.start, Location::from(end));
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Move the cursor to the start of the content, keeping the other end
+     * of the selection where it is, as Ctrl+Shift+Home (Cmd+Shift+Up on
+     * macOS) does.
+     */
+    pub fn select_to_start_of_document(&mut self) -> ComposerUpdate<C> {
+        self.select(self.start, Location::from(0));
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Move the cursor to the end of the content, keeping the other end of
+     * the selection where it is, as Ctrl+Shift+End (Cmd+Shift+Down on
+     * macOS) does.
+     */
+    pub fn select_to_end_of_document(&mut self) -> ComposerUpdate<C> {
+        self.select(self.start, Location::from(self.dom.text_len()));
+        self.create_update_replace_all()
+    }
+
+    fn line_around(&self, location: Location) -> (usize, usize) {
+        let location = usize::from(location).min(self.dom.text_len());
+        self.dom
+            .lines()
+            .into_iter()
+            .find(|&(start, end)| start <= location && location <= end)
+            .unwrap_or((location, location))
+    }
+
+    /**
+     * The start and end of the word, or the run of spaces or punctuation
+     * between words, around location, chosen as select_word_at_cursor
+     * chooses, and whether it is a word.
+     */
+    fn segment_at(&self, location: usize) -> Option<(usize, usize, bool)> {
+        let units = self.dom.location_units();
+        let (line_start, line_end) = self
+            .dom
+            .lines()
+            .into_iter()
+            .find(|&(start, end)| start <= location && location <= end)
+            .unwrap_or((location, location));
+        let line = &units[line_start..line_end];
+        let run_start = line_start
+            + line[..location - line_start]
+                .iter()
+                .rposition(Option::is_none)
+                .map_or(0, |i| i + 1);
+        let run_end = line_start
+            + line[location - line_start..]
+                .iter()
+                .position(Option::is_none)
+                .map_or(line.len(), |i| location - line_start + i);
+        let run: Vec<C> = units[run_start..run_end]
+            .iter()
+            .flatten()
+            .cloned()
+            .collect();
+        let text = C::decode(&run);
+        let offset = C::decode(&run[..location - run_start]).len();
+        let (s, e) = words::word_at(
+            &text,
+            offset,
+            self.word_dictionary.as_deref(),
+            self.config.word_locale.as_deref(),
+        )?;
+        Some((
+            run_start + C::encode(&text[..s]).len(),
+            run_start + C::encode(&text[..e]).len(),
+            words::is_word(&text[s..e]),
+        ))
+    }
+
     /**
      * Return the start and end of the selection, ensuring the first number
-     * returned is <= the second, and they are both 0<=n<=html.len().
+     * returned is <= the second, and they are both 0<=n<=text length.
      */
     fn safe_selection(&self) -> (usize, usize) {
-        let mut s: usize = self.start.into();
-        let mut e: usize = self.end.into();
-        s = s.clamp(0, self.html.len());
-        e = e.clamp(0, self.html.len());
+        self.safe_range(self.start.into(), self.end.into())
+    }
+
+    /**
+     * Return start and end ordered and clamped to the content length, and
+     * widened to cover whole any mention a non-empty range ends inside
+     * (see Dom::expand_to_mentions), so that mentions are deleted,
+     * replaced, formatted and copied as single units.
+     */
+    fn safe_range(&self, start: usize, end: usize) -> (usize, usize) {
+        let len = self.dom.text_len();
+        let s = start.clamp(0, len);
+        let e = end.clamp(0, len);
         if s > e {
-            (e, s)
+            self.dom.expand_to_mentions(e, s)
         } else {
-            (s, e)
+            self.dom.expand_to_mentions(s, e)
         }
     }
 
     /**
-     * Replaces text in the current selection with new_text.
+     * Replaces text in the current selection with new_text. Newlines in
+     * new_text ("\n", "\r\n" or "\r") become line breaks, except that a
+     * lone newline is treated as Enter when EnterBehaviour::Send is
+     * configured.
      */
     pub fn replace_text(&mut self, new_text: &[C]) -> ComposerUpdate<C> {
-        // TODO: escape any HTML?
-        let (s, e) = self.safe_selection();
-        self.replace_text_in(&new_text, s, e)
+        if self.config.double_space_full_stop.is_some() {
+            if let Some(clock) = &self.clock {
+                let time_ms = clock.now_ms();
+                return self.replace_text_at_time(new_text, time_ms);
+            }
+        }
+        if let Some(update) = self.type_ascii(new_text) {
+            return update;
+        }
+        let selection = self.begin_edit("replace_text");
+        self.edit_typing = Typing::of(new_text);
+        let before = self.dom.clone();
+        self.do_replace_text(new_text);
+        self.end_edit(selection);
+        self.create_update_for_edit(&before)
     }
 
     /**
-     * Replaces text in the an arbitrary start..end range with new_text.
+     * Keeps the content in step with a plain text input (e.g. a <textarea>
+     * used as a fallback) that was showing old_text, as
+     * get_content_as_plain_text(false) gives it, and now shows new_text
+     * with the cursor new_cursor code units into it. The part of old_text
+     * that changed is replaced by the new part of new_text, keeping the
+     * formatting of the rest, and the cursor is moved to match. Newlines
+     * typed become line breaks. Deleting the newline between two blocks
+     * deletes the text around it, but not the boundary, which has no
+     * location. If old_text is not the current content, the change is
+     * worked out from the current content instead.
      */
-    pub fn replace_text_in(
+    pub fn apply_plain_text_edit(
         &mut self,
+        old_text: &[C],
         new_text: &[C],
-        start: usize,
-        end: usize,
+        new_cursor: usize,
     ) -> ComposerUpdate<C> {
-        let mut new_html = self.html[..start].to_vec();
-        new_html.extend_from_slice(new_text);
-        new_html.extend_from_slice(&self.html[end..]);
-        self.html = new_html;
-
-        self.start = Location::from(start + new_text.len());
+        let (current, locations) = self.dom.text_with_locations();
+        if old_text != current.as_slice() {
+            self.diagnostics.push(String::from(
+                "The plain text edited was not the current content",
+            ));
+        }
+        let (prefix, old_end, new_end) = changed_range(&current, new_text);
+        let selection = self.begin_edit("apply_plain_text_edit");
+        let before = self.dom.clone();
+        self.do_replace_text_in(
+            &new_text[prefix..new_end],
+            locations[prefix],
+            locations[old_end],
+        );
+        let (_, locations) = self.dom.text_with_locations();
+        let cursor = locations[new_cursor.min(locations.len() - 1)];
+        self.start = Location::from(cursor);
         self.end = self.start;
+        self.end_edit(selection);
+        self.create_update_for_edit(&before)
+    }
 
-        // TODO: for now, we replace every time, to check ourselves, but
-        // at least some of the time we should not
+    /**
+     * Switch between editing rich content and editing it as markdown in
+     * plain text. Entering plain text mode replaces the content with its
+     * markdown (see Dom::to_markdown), shown as text with line breaks, in
+     * which formatting is typed rather than applied: bold and the other
+     * formats do nothing. Leaving it parses the markdown back into rich
+     * content, so switching there and back keeps the formatting, and
+     * removes anything in it that the Matrix specification does not allow,
+     * as set_content_from_markdown does. While in
+     * plain text mode, the message HTML is parsed from the markdown. Either
+     * way the undo history is cleared and the cursor moves to the end.
+     */
+    pub fn set_plain_text_mode(
+        &mut self,
+        plain_text_mode: bool,
+    ) -> ComposerUpdate<C> {
+        if plain_text_mode == self.plain_text_mode {
+            return self.with_menu(ComposerUpdate::keep());
+        }
+        let html = if plain_text_mode {
+            plain_text_to_html(&C::decode(&self.dom.to_markdown()))
+        } else {
+            markdown_to_html(
+                &C::decode(&self.dom.to_plain_text(false)),
+                self.config.max_depth,
+            )
+        };
+        let mut dom = match self.parse_input(&C::encode(&html)) {
+            Some(dom) => dom,
+            None => return self.create_update_replace_all(),
+        };
+        if !plain_text_mode {
+            let report = dom.sanitize(&self.config.allowed_link_schemes);
+            self.sanitize_report.extend(report);
+        }
+        self.dom = dom;
+        self.plain_text_mode = plain_text_mode;
+        self.history.clear();
+        self.clear_pending_formats();
+        self.cursor_block = None;
+        self.start = Location::from(self.dom.text_len());
+        self.end = self.start;
         self.create_update_replace_all()
-        //ComposerUpdate::keep()
     }
 
-    pub fn enter(&mut self) -> ComposerUpdate<C> {
-        ComposerUpdate::keep()
+    pub fn is_plain_text_mode(&self) -> bool {
+        self.plain_text_mode
     }
 
-    pub fn backspace(&mut self) -> ComposerUpdate<C> {
-        if self.start == self.end {
-            // Go back 1 from the current location
-            self.start -= 1;
+    /**
+     * The parts of the markdown being edited in plain text mode that might
+     * be styled to show what it means, e.g. the markers around bold text
+     * and the URL of a link, in order of where they start, with any range
+     * inside another after it. Nothing is styled outside plain text mode.
+     */
+    pub fn get_markdown_styles(&self) -> Vec<MarkdownStyle> {
+        if !self.plain_text_mode {
+            return Vec::new();
+        }
+        let (text, locations) = self.dom.text_with_locations();
+        let markdown = C::decode(&text);
+        // The code unit index at which each byte of the markdown starts
+        let mut indices = Vec::with_capacity(markdown.len() + 1);
+        let mut index = 0;
+        for c in markdown.chars() {
+            indices.extend(std::iter::repeat(index).take(c.len_utf8()));
+            index += C::encode(&c.to_string()).len();
         }
+        indices.push(index);
+        markdown_styles(&markdown, self.config.max_depth)
+            .into_iter()
+            .map(|(kind, start, end)| MarkdownStyle {
+                kind,
+                start: Location::from(locations[indices[start]]),
+                end: Location::from(locations[indices[end] - 1] + 1),
+            })
+            .collect()
+    }
 
-        self.replace_text(&[])
+    /**
+     * Whether there is nothing to send, as described in Dom::is_empty. This
+     * is more reliable than checking the length of the HTML, which may
+     * contain placeholders and empty paragraphs.
+     */
+    pub fn is_empty(&self, ignore_whitespace: bool) -> bool {
+        self.dom.is_empty(ignore_whitespace)
     }
 
     /**
-     * Deletes text in an arbitrary start..end range.
+     * Replaces text in the current selection with new_text, as replace_text
+     * does, for platforms that can say when it was typed, in milliseconds
+     * since any fixed point. If ComposerConfig::double_space_full_stop is
+     * set, a second space typed soon enough after one that follows a word
+     * turns the two spaces into ". ", as on mobile keyboards. Undoing that
+     * leaves the two spaces.
      */
-    pub fn delete_in(&mut self, start: usize, end: usize) -> ComposerUpdate<C> {
-        self.end = Location::from(start);
-        self.replace_text_in(&[], start, end)
+    pub fn replace_text_at_time(
+        &mut self,
+        new_text: &[C],
+        time_ms: u64,
+    ) -> ComposerUpdate<C> {
+        let previous_space = self.last_space.take();
+        let before = self.dom.clone();
+        let selection = self.begin_edit("replace_text_at_time");
+        self.edit_typing = Typing::of(new_text);
+        self.do_replace_text(new_text);
+        self.end_edit(selection);
+        let typed = self.create_update_for_edit(&before);
+        if new_text == C::encode(" ").as_slice() && self.start == self.end {
+            let quick_enough =
+                match (previous_space, self.config.double_space_full_stop) {
+                    (Some((location, then)), Some(max_interval_ms)) => {
+                        location == selection.0
+                            && selection.0 == selection.1
+                            && time_ms.saturating_sub(then) <= max_interval_ms
+                    }
+                    _ => false,
+                };
+            if quick_enough {
+                let spaced = self.dom.clone();
+                if self.apply_double_space_full_stop() {
+                    return typed.then(self.create_update_for_edit(&spaced));
+                }
+            }
+            self.last_space = Some((self.start, time_ms));
+        }
+        typed
     }
 
     /**
-     * Deletes the character after the current cursor position.
+     * Inserts html at location rather than at the selection, e.g. when
+     * content is dropped into the composer. Anything the Matrix
+     * specification does not allow is removed first, as is formatting
+     * whose feature is disabled, and whatever would go beyond max_length.
+     * The selection stays on the same content; if location is inside it,
+     * as when the selected text is dragged onto itself, the content is
+     * inserted after it, so that the selection can then be deleted to
+     * complete a move.
      */
-    pub fn delete(&mut self) -> ComposerUpdate<C> {
-        if self.start == self.end {
-            // Go forward 1 from the current location
-            self.end += 1;
+    pub fn insert_at(
+        &mut self,
+        location: Location,
+        html: &[C],
+    ) -> ComposerUpdate<C> {
+        let mut fragment = match self.parse_input(html) {
+            Some(fragment) => fragment,
+            None => return self.create_update_replace_all(),
+        };
+        let report = fragment.sanitize(&self.config.allowed_link_schemes);
+        self.sanitize_report.extend(report);
+        self.fit_to_config(&mut fragment);
+        let selection = self.begin_edit("insert_at");
+        let (s, e) = self.safe_selection();
+        let mut location = usize::from(location).min(self.dom.text_len());
+        if s < location && location < e {
+            location = e;
         }
+        let len = self.dom.insert_dom(location, &fragment);
+        let new_s = if s >= location { s + len } else { s };
+        let new_e = if e > location || (e == location && s == e) {
+            e + len
+        } else {
+            e
+        };
+        if self.start <= self.end {
+            self.start = Location::from(new_s);
+            self.end = Location::from(new_e);
+        } else {
+            self.start = Location::from(new_e);
+            self.end = Location::from(new_s);
+        }
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
 
-        self.replace_text(&[])
+    /**
+     * Replaces the selection with HTML from the clipboard, cleaned up
+     * according to the application it came from (see Dom::clean_pasted),
+     * and moves the cursor after it.
+     */
+    pub fn paste_html(&mut self, html: &[C]) -> ComposerUpdate<C> {
+        let mut fragment = match self.parse_input(html) {
+            Some(fragment) => fragment,
+            None => return self.create_update_replace_all(),
+        };
+        let report = fragment.clean_pasted(&self.config.allowed_link_schemes);
+        self.sanitize_report.extend(report);
+        let selection = self.begin_edit("paste_html");
+        let (s, e) = self.safe_selection();
+        self.delete_selection(s, e);
+        let len = self.dom.insert_dom(s, &fragment);
+        self.start = Location::from(s + len);
+        self.end = self.start;
+        self.end_edit(selection);
+        self.create_update_replace_all()
     }
 
-    pub fn action_response(
+    /**
+     * Replaces text in the an arbitrary start..end range with new_text.
+     */
+    pub fn replace_text_in(
         &mut self,
-        action_id: String,
-        response: ActionResponse,
+        new_text: &[C],
+        start: usize,
+        end: usize,
     ) -> ComposerUpdate<C> {
-        drop(action_id);
-        drop(response);
-        ComposerUpdate::keep()
+        let selection = self.begin_edit("replace_text_in");
+        let before = self.dom.clone();
+        self.do_replace_text_in(new_text, start, end);
+        self.end_edit(selection);
+        self.create_update_for_edit(&before)
     }
 
+    /**
+     * Handles Enter, which does nothing with EnterBehaviour::Send. With
+     * EnterBehaviour::NewLine it replaces the selection with a line break,
+     * or inside a list item, splits the item at the cursor so that the
+     * content after it continues in a new item.
+     */
+    pub fn enter(&mut self) -> ComposerUpdate<C> {
+        match self.config.enter_behaviour {
+            EnterBehaviour::Send => self.unchanged(),
+            EnterBehaviour::NewLine => {
+                let selection = self.begin_edit("enter");
+                self.do_enter();
+                self.end_edit_keeping_cursor_block(selection);
+                self.create_update_replace_all()
+            }
+        }
+    }
+
+    /**
+     * Enter with EnterBehaviour::NewLine, as enter describes, within an
+     * edit that has already begun.
+     */
+    fn do_enter(&mut self) {
+        let cursor_block = self.cursor_block_at(self.start);
+        let (s, e) = self.safe_selection();
+        self.delete_selection(s, e);
+        let li = cursor_block
+            .filter(|&block| {
+                matches!(
+                    self.dom.node(block),
+                    DomNode::Container(c) if c.name() == "li"
+                )
+            })
+            .or_else(|| self.dom.list_item_at(s))
+            .or_else(|| self.dom.element_at(s, "li"));
+        match li {
+            Some(li) => {
+                // Continue the list, carrying over the content after the
+                // cursor
+                let new_li = self.dom.split_at(li, s);
+                self.start = Location::from(s);
+                self.cursor_block = Some(new_li);
+            }
+            None => {
+                let br =
+                    self.dom.create_container("br", Vec::new(), Vec::new());
+                self.dom.insert_node(s, br);
+                self.start = Location::from(s + 1);
+                self.cursor_block = None;
+            }
+        }
+        self.end = self.start;
+    }
+
+    pub fn backspace(&mut self) -> ComposerUpdate<C> {
+        let selection = self.begin_edit("backspace");
+        let before = self.dom.clone();
+        self.do_backspace();
+        self.end_edit(selection);
+        self.create_update_for_edit(&before)
+    }
+
+    /**
+     * Deletes text in an arbitrary start..end range.
+     */
+    pub fn delete_in(&mut self, start: usize, end: usize) -> ComposerUpdate<C> {
+        let selection = self.begin_edit("delete_in");
+        let before = self.dom.clone();
+        self.do_delete_in(start, end);
+        self.end_edit(selection);
+        self.create_update_for_edit(&before)
+    }
+
+    /**
+     * Deletes the character after the current cursor position.
+     */
+    pub fn delete(&mut self) -> ComposerUpdate<C> {
+        let selection = self.begin_edit("delete");
+        let before = self.dom.clone();
+        self.do_delete();
+        self.end_edit(selection);
+        self.create_update_for_edit(&before)
+    }
+
+    /**
+     * Applies a batch of events reported together by the platform (e.g. an
+     * Android IME committing several edits at once), in order, and returns
+     * a single update describing the combined result.
+     */
+    pub fn apply_events(
+        &mut self,
+        events: Vec<InputEvent<C>>,
+    ) -> ComposerUpdate<C> {
+        let selection = self.begin_edit("apply_events");
+        let before = self.dom.clone();
+        let mut content_changed = false;
+        for event in events {
+            if self.out_of_time() {
+                break;
+            }
+            match event {
+                InputEvent::Select { start, end } => self.select(start, end),
+                InputEvent::ReplaceText { new_text } => {
+                    self.do_replace_text(&new_text);
+                    content_changed = true;
+                }
+                InputEvent::ReplaceTextIn {
+                    new_text,
+                    start,
+                    end,
+                } => {
+                    self.do_replace_text_in(&new_text, start, end);
+                    content_changed = true;
+                }
+                InputEvent::Backspace => {
+                    self.do_backspace();
+                    content_changed = true;
+                }
+                InputEvent::Delete => {
+                    self.do_delete();
+                    content_changed = true;
+                }
+                InputEvent::DeleteIn { start, end } => {
+                    self.do_delete_in(start, end);
+                    content_changed = true;
+                }
+                InputEvent::Enter => {
+                    if let EnterBehaviour::NewLine = self.config.enter_behaviour
+                    {
+                        self.do_enter();
+                        content_changed = true;
+                    }
+                }
+            }
+        }
+        self.end_edit_keeping_cursor_block(selection);
+
+        if content_changed || self.error.is_some() {
+            self.create_update_for_edit(&before)
+        } else {
+            self.unchanged()
+        }
+    }
+
+    /**
+     * Reverses the most recent edit that has not been undone, restoring the
+     * selection from before it.
+     */
+    pub fn undo(&mut self) -> ComposerUpdate<C> {
+        match self.history.take_undo() {
+            Some(entry) => {
+                let redo = self.revert(entry);
+                self.history.push_redo(redo);
+                self.create_update_replace_all()
+            }
+            None => self.unchanged(),
+        }
+    }
+
+    /**
+     * Repeats the most recently undone edit.
+     */
+    pub fn redo(&mut self) -> ComposerUpdate<C> {
+        match self.history.take_redo() {
+            Some(entry) => {
+                let undo = self.revert(entry);
+                self.history.push_undo(undo);
+                self.create_update_replace_all()
+            }
+            None => self.unchanged(),
+        }
+    }
+
+    /**
+     * Change how many edits can be undone, and roughly how much memory in
+     * bytes they may take up (see ComposerConfig::max_undo_entries and
+     * max_undo_bytes), forgetting the oldest edits if there are now too
+     * many.
+     */
+    pub fn set_history_limits(
+        &mut self,
+        max_entries: Option<usize>,
+        max_bytes: Option<usize>,
+    ) {
+        self.config.max_undo_entries = max_entries;
+        self.config.max_undo_bytes = max_bytes;
+        self.history.set_limits(max_entries, max_bytes);
+    }
+
+    /**
+     * The edits that undo would reverse, oldest first.
+     */
+    pub fn undo_history(&self) -> impl Iterator<Item = &HistoryItem> + '_ {
+        self.history.undo_entries().iter().map(|entry| &entry.item)
+    }
+
+    /**
+     * The edits that redo would repeat, oldest first, so the next one to
+     * be redone comes last.
+     */
+    pub fn redo_history(&self) -> impl Iterator<Item = &HistoryItem> + '_ {
+        self.history.redo_entries().iter().map(|entry| &entry.item)
+    }
+
+    /**
+     * Capture the content, selection and up to max_history undo and redo
+     * steps each, so that the composer can be restored later with
+     * restore_snapshot.
+     */
+    pub fn snapshot(&self, max_history: usize) -> ComposerSnapshot<C> {
+        let undo_entries = self.history.undo_entries();
+        let redo_entries = self.history.redo_entries();
+        let mut undo = Self::states_along(&self.dom, undo_entries, max_history);
+        undo.reverse();
+        ComposerSnapshot {
+            current: SnapshotState {
+                html: self.dom.to_html(HtmlDialect::MatrixMessage),
+                start: self.start,
+                end: self.end,
+            },
+            undo,
+            redo: Self::states_along(&self.dom, redo_entries, max_history),
+        }
+    }
+
+    /**
+     * Replace the content, selection and history with those captured by
+     * snapshot. Undo and redo step through the captured states, with each
+     * step replacing the whole content. Each state's HTML is parsed as
+     * set_content_from_html parses it: if any is nested more deeply than
+     * the config allows, nothing changes and the update's error is
+     * ComposerError::TooDeep.
+     */
+    pub fn restore_snapshot(
+        &mut self,
+        snapshot: &ComposerSnapshot<C>,
+    ) -> ComposerUpdate<C> {
+        let states: Vec<&SnapshotState<C>> = snapshot
+            .undo
+            .iter()
+            .chain(std::iter::once(&snapshot.current))
+            .chain(&snapshot.redo)
+            .collect();
+        let mut doms = Vec::with_capacity(states.len());
+        for state in &states {
+            match self.parse_input(&state.html) {
+                Some(dom) => doms.push(dom),
+                None => return self.create_update_replace_all(),
+            }
+        }
+        let mut doms = doms.into_iter();
+        let first = states[0];
+        self.dom = doms.next().expect("A snapshot has a current state");
+        self.history.clear();
+        self.start = first.start;
+        self.end = first.end;
+        for (state, dom) in states[1..].iter().zip(doms) {
+            self.restore_state(state, dom);
+        }
+        for _ in &snapshot.redo {
+            self.undo();
+        }
+        self.last_space = None;
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Insert a placeholder for an attachment that is still being prepared,
+     * replacing the selection. Like an image, it takes up one location,
+     * and it is left out of message HTML. The update includes a
+     * ResolveAttachment action whose action_id is id: respond to it to
+     * replace or remove the placeholder. Does nothing if there is already
+     * a placeholder with this id.
+     */
+    pub fn insert_attachment_placeholder(
+        &mut self,
+        id: &str,
+        label: &[C],
+    ) -> ComposerUpdate<C> {
+        let label = self.valid_input(label).into_owned();
+        let id_value = C::encode(id);
+        if self
+            .dom
+            .find_element(ATTACHMENT_ELEMENT, "data-id", &id_value)
+            .is_some()
+        {
+            return self.unchanged();
+        }
+        let selection = self.begin_edit("insert_attachment_placeholder");
+        let (s, e) = self.safe_selection();
+        self.delete_selection(s, e);
+        let placeholder = self.dom.create_container(
+            ATTACHMENT_ELEMENT,
+            vec![
+                (Atom::from("data-id"), id_value),
+                (Atom::from("data-label"), label),
+            ],
+            Vec::new(),
+        );
+        self.dom.insert_node(s, placeholder);
+        self.start = Location::from(s + 1);
+        self.end = self.start;
+        self.end_edit(selection);
+        let mut update = self.create_update_replace_all();
+        update.actions.push(ComposerAction {
+            action_id: String::from(id),
+            action: ActionRequest::ResolveAttachment,
+        });
+        update
+    }
+
+    /**
+     * Insert a custom emote (see MSC2545), replacing the selection: an
+     * image of mxc_url marked with data-mx-emoticon, whose alt text, and
+     * plain text, is ":shortcode:". Like any image, it takes up one
+     * location. Does nothing unless mxc_url is an mxc: URI.
+     */
+    pub fn insert_custom_emote(
+        &mut self,
+        mxc_url: &str,
+        shortcode: &[C],
+    ) -> ComposerUpdate<C> {
+        if !mxc_url.starts_with("mxc://") {
+            return self.unchanged();
+        }
+        let shortcode = C::decode(shortcode);
+        let alt = format!(":{}:", shortcode.trim_matches(':'));
+        let selection = self.begin_edit("insert_custom_emote");
+        let (s, e) = self.safe_selection();
+        self.delete_selection(s, e);
+        let emote = self.dom.create_container(
+            "img",
+            vec![
+                (Atom::from("data-mx-emoticon"), Vec::new()),
+                (Atom::from("src"), C::encode(mxc_url)),
+                (Atom::from("alt"), C::encode(&alt)),
+            ],
+            Vec::new(),
+        );
+        self.dom.insert_node(s, emote);
+        self.start = Location::from(s + 1);
+        self.end = self.start;
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Replace the selection with a copy of fragment, e.g. a received
+     * message parsed with dom::parse_message_html to quote or forward it,
+     * and move the cursor after it.
+     */
+    pub fn insert_fragment(
+        &mut self,
+        fragment: &DomFragment<C>,
+    ) -> ComposerUpdate<C> {
+        let selection = self.begin_edit("insert_fragment");
+        let (s, e) = self.safe_selection();
+        self.delete_selection(s, e);
+        let len = self.dom.insert_dom(s, fragment.dom());
+        self.start = Location::from(s + len);
+        self.end = self.start;
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    pub fn action_response(
+        &mut self,
+        action_id: String,
+        response: ActionResponse,
+    ) -> ComposerUpdate<C> {
+        match response {
+            ActionResponse::Dummy => self.unchanged(),
+            ActionResponse::AttachmentReady { html } => {
+                self.resolve_attachment(&action_id, &C::encode(&html))
+            }
+            ActionResponse::AttachmentRemoved => {
+                self.resolve_attachment(&action_id, &[])
+            }
+        }
+    }
+
+    /**
+     * The content as HTML for the editing surface.
+     */
     pub fn get_html(&self) -> Vec<C> {
-        self.html.clone()
+        self.get_content_as_html(HtmlDialect::Editor)
+    }
+
+    /**
+     * The content as HTML in the supplied dialect.
+     */
+    pub fn get_content_as_html(&self, dialect: HtmlDialect) -> Vec<C> {
+        if dialect != HtmlDialect::Editor {
+            return self.dom.to_html(dialect);
+        }
+        self.rendered_html().clone()
+    }
+
+    /**
+     * Replace the contents of out with the HTML for the editing surface.
+     * Once out has grown to the size of the content, serializing again
+     * after each keystroke allocates nothing.
+     */
+    pub fn serialize_into(&self, out: &mut Vec<C>) {
+        out.clear();
+        out.extend_from_slice(&self.rendered_html());
+    }
+
+    /**
+     * Replace the contents of out with the HTML for the editing surface,
+     * decoded into a String, reusing out as serialize_into does.
+     */
+    pub fn serialize_into_string(&self, out: &mut String) {
+        out.clear();
+        C::decode_into(&self.rendered_html(), out);
+    }
+
+    /**
+     * The Editor HTML for the current Dom, rendering it again only if the
+     * Dom has changed, and then into the buffer already holding the last
+     * rendering.
+     */
+    fn rendered_html(&self) -> Ref<'_, Vec<C>> {
+        let revision = self.dom.revision();
+        {
+            let mut rendered = self.rendered.borrow_mut();
+            match &mut *rendered {
+                Some((rendered_revision, html))
+                    if *rendered_revision == revision =>
+                {
+                    debug_assert!(
+                        *html == self.dom.to_html(HtmlDialect::Editor),
+                        "Rendered HTML is stale although the Dom revision is not"
+                    );
+                }
+                Some((rendered_revision, html)) => {
+                    html.clear();
+                    self.dom.write_html(HtmlDialect::Editor, html);
+                    *rendered_revision = revision;
+                }
+                None => {
+                    *rendered =
+                        Some((revision, self.dom.to_html(HtmlDialect::Editor)))
+                }
+            }
+        }
+        Ref::map(self.rendered.borrow(), |rendered| match rendered {
+            Some((_, html)) => html,
+            None => unreachable!("Rendered HTML was not stored"),
+        })
+    }
+
+    /**
+     * The content as HTML suitable for the formatted_body of a Matrix
+     * message. Anything the Matrix specification does not allow is removed
+     * (see validate_message_html), so that receiving clients' sanitizers
+     * will show the message as it was composed, and so is formatting that
+     * covers only whitespace at either end (see Dom::drop_edge_formatting).
+     */
+    pub fn get_content_as_message_html(&self) -> Vec<C> {
+        self.message_dom().to_html(HtmlDialect::MatrixMessage)
+    }
+
+    /**
+     * The content as get_content_as_message_html returns it, but without
+     * the whitespace, line breaks and empty paragraphs that are often left
+     * at the start or end of the composer. The content being edited is not
+     * changed.
+     */
+    pub fn get_content_trimmed(&self) -> Vec<C> {
+        let mut dom = self.message_dom();
+        dom.trim();
+        dom.to_html(HtmlDialect::MatrixMessage)
+    }
+
+    /**
+     * The parts of the content that get_content_as_message_html would
+     * remove because the Matrix specification does not allow them. Links
+     * are allowed if they use one of the allowed_link_schemes.
+     */
+    pub fn validate_message_html(&self) -> Vec<HtmlViolation> {
+        self.dom
+            .matrix_violations(&self.config.allowed_link_schemes)
+    }
+
+    /**
+     * The content as plain text, suitable for the body of a Matrix message.
+     * If fallback_formatting is true, quotes and emphasis are shown using
+     * the plain text conventions described in the Matrix spec ("> " before
+     * quoted lines, "*" and "**" around emphasis), and mentions of rooms
+     * and events by their IDs or permalinks; otherwise they are stripped.
+     * Links whose text is their URL cut short show the whole URL.
+     */
+    pub fn get_content_as_plain_text(
+        &self,
+        fallback_formatting: bool,
+    ) -> Vec<C> {
+        self.plain_text_dom(fallback_formatting)
+            .to_plain_text(fallback_formatting)
+    }
+
+    /**
+     * The number of characters in the content as the user would count
+     * them (grapheme clusters), e.g. for a character counter. Line breaks
+     * and the boundaries between paragraphs count as one character each.
+     * Locations are not measured this way: they count code units.
+     */
+    pub fn visible_length(&self) -> usize {
+        grapheme_count(&C::decode(&self.dom.to_plain_text(false)))
+    }
+
+    /**
+     * The length of get_content_as_message_html in code units, which is
+     * what counts towards the size of the message event.
+     */
+    pub fn html_length(&self) -> usize {
+        self.get_content_as_message_html().len()
+    }
+
+    /**
+     * Set the text to show while the content is empty, e.g. "Message
+     * #room". An empty placeholder means there is none.
+     */
+    pub fn set_placeholder(&mut self, placeholder: &[C]) {
+        self.placeholder = self.valid_input(placeholder).into_owned();
+    }
+
+    /**
+     * The placeholder, if there is one and the content is empty (it has
+     * no text or elements), so that it should be shown. It is never
+     * included in the content in any form.
+     */
+    pub fn placeholder(&self) -> Option<&[C]> {
+        let empty = self.dom.children(self.dom.document()).is_empty();
+        Some(self.placeholder.as_slice())
+            .filter(|placeholder| empty && !placeholder.is_empty())
+    }
+
+    pub fn get_selection(&self) -> (Location, Location) {
+        (self.start, self.end)
+    }
+
+    /**
+     * The selection as nodes and offsets into them (see AnchoredLocation),
+     * for platforms whose rendering of mentions does not match their
+     * length in the content.
+     */
+    pub fn get_anchored_selection(
+        &self,
+    ) -> (AnchoredLocation, AnchoredLocation) {
+        let (s, e) = self.safe_selection();
+        let (start, end) = if self.start <= self.end {
+            (s, e)
+        } else {
+            (e, s)
+        };
+        (self.dom.anchor_at(start), self.dom.anchor_at(end))
+    }
+
+    /**
+     * Select from start to end, given as nodes and offsets into them.
+     * Returns false, leaving the selection as it is, if either identifies
+     * no node.
+     */
+    pub fn select_anchored(
+        &mut self,
+        start: &AnchoredLocation,
+        end: &AnchoredLocation,
+    ) -> bool {
+        match (
+            self.dom.location_of_anchor(start),
+            self.dom.location_of_anchor(end),
+        ) {
+            (Some(start), Some(end)) => {
+                self.select(Location::from(start), Location::from(end));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /**
+     * Protect the element that node_handle identifies, and everything in
+     * it, or stop protecting it. Protected content (e.g. the header of a
+     * forwarded message) is shown but cannot be edited: any edit that
+     * would change or remove it is refused with ComposerError::Protected,
+     * text typed at its edges goes outside it, and the selection snaps out
+     * of it (see Dom::snap_out_of_protected). Text nodes cannot be
+     * protected on their own, so handles to them change nothing. Content
+     * can also be protected by giving an element the PROTECTED_ATTR
+     * attribute in HTML; messages are sent without it.
+     */
+    pub fn set_protected(
+        &mut self,
+        node_handle: &DomHandle,
+        protected: bool,
+    ) -> ComposerUpdate<C> {
+        let id = match self.dom.node_at_handle(node_handle) {
+            Some(id) if id != self.dom.document() => id,
+            _ => return self.with_menu(ComposerUpdate::keep()),
+        };
+        let is_protected = match self.dom.node(id) {
+            DomNode::Container(c) => c.attr(PROTECTED_ATTR).is_some(),
+            DomNode::Text(_) => {
+                return self.with_menu(ComposerUpdate::keep());
+            }
+        };
+        if is_protected == protected {
+            return self.with_menu(ComposerUpdate::keep());
+        }
+        let selection = self.begin_edit("set_protected");
+        // Changing what is protected is allowed, unlike changing the content
+        self.edit_protected = None;
+        if protected {
+            self.dom.set_attr(id, PROTECTED_ATTR, Vec::new());
+        } else {
+            self.dom.remove_attr(id, PROTECTED_ATTR);
+        }
+        self.select(self.start, self.end);
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Pin the node that handle identifies, e.g. one from a TextUpdate or a
+     * DomEvent, so that handle_of_pinned can find it after later edits
+     * have moved it. None if handle identifies no node.
+     */
+    pub fn pin(&self, handle: &DomHandle) -> Option<PinnedNode> {
+        self.dom.pin(handle)
+    }
+
+    /**
+     * The handle that identifies pinned now, or None if it has been
+     * removed, including by replacing all the content.
+     */
+    pub fn handle_of_pinned(&self, pinned: &PinnedNode) -> Option<DomHandle> {
+        self.dom.handle_of_pinned(pinned)
+    }
+
+    /**
+     * The location of the first occurrence of substring in the visible text
+     * of the content (i.e. ignoring tags) starting at or after from, if
+     * there is one.
+     */
+    pub fn offset_of(
+        &self,
+        substring: &[C],
+        from: Location,
+    ) -> Option<Location> {
+        self.dom
+            .find_text(substring, from.into())
+            .map(Location::from)
+    }
+
+    /**
+     * The visible text of the content between start and end, without
+     * serializing it, e.g. to quote the selected text. Line breaks and the
+     * boundaries between blocks become "\n".
+     */
+    pub fn text_in_range(&self, start: Location, end: Location) -> Vec<C> {
+        let (s, e) = self.safe_range(start.into(), end.into());
+        self.dom.text_in_range(s, e)
+    }
+
+    /**
+     * The character that starts at location, or None if there is no text
+     * there (e.g. it is the end of the content or an image) or location is
+     * in the middle of a character.
+     */
+    pub fn char_at(&self, location: Location) -> Option<char> {
+        let location: usize = location.into();
+        let units: Vec<C> = self
+            .dom
+            .location_units()
+            .into_iter()
+            .skip(location)
+            .take(4)
+            .map_while(|unit| unit)
+            .collect();
+        let c = C::decode(&units).chars().next()?;
+        let encoded = C::encode(c.encode_utf8(&mut [0; 4]));
+        units.starts_with(&encoded).then(|| c)
+    }
+
+    /**
+     * An estimate of how many lines the content takes up when rendered in
+     * a composer wrap_hint_chars characters wide, e.g. to size the
+     * composer to fit the content without measuring it after every
+     * change. Lines are ended by line breaks and blocks, and wrapped at
+     * spaces. A wrap_hint_chars of 0 means lines are not wrapped.
+     */
+    pub fn count_visual_lines(&self, wrap_hint_chars: usize) -> usize {
+        self.dom.visual_line_count(wrap_hint_chars)
+    }
+
+    /**
+     * The (line, column) of location, both counting from 0. Lines are ended
+     * by line breaks and block boundaries, and columns count code units
+     * from the start of the line. A location on the boundary between two
+     * blocks is reported as the end of the first one.
+     */
+    pub fn location_to_line_col(&self, location: Location) -> (usize, usize) {
+        let location = usize::from(location).min(self.dom.text_len());
+        let lines = self.dom.lines();
+        lines
+            .iter()
+            .enumerate()
+            .find(|(_, (start, end))| *start <= location && location <= *end)
+            .map(|(line, (start, _))| (line, location - start))
+            .unwrap_or((lines.len() - 1, 0))
+    }
+
+    /**
+     * The location at column col of line line. A line or column past the
+     * end is clamped to the last line or the end of the line, so that
+     * moving the caret up or down onto a shorter line lands at its end.
+     */
+    pub fn line_col_to_location(&self, line: usize, col: usize) -> Location {
+        let lines = self.dom.lines();
+        let (start, end) = lines[line.min(lines.len() - 1)];
+        Location::from((start + col).min(end))
+    }
+
+    /**
+     * Perform command, as the method it stands for would.
+     */
+    pub fn apply(&mut self, command: ComposerCommand<C>) -> ComposerUpdate<C> {
+        match command {
+            ComposerCommand::Bold => self.bold(),
+            ComposerCommand::Italic => self.italic(),
+            ComposerCommand::ToggleListType => self.toggle_list_type(),
+            ComposerCommand::Indent => self.tab(),
+            ComposerCommand::Unindent => self.shift_tab(),
+            ComposerCommand::MoveListItemUp => self.move_list_item_up(),
+            ComposerCommand::MoveListItemDown => self.move_list_item_down(),
+            ComposerCommand::RemoveList => self.remove_list(),
+            ComposerCommand::Undo => self.undo(),
+            ComposerCommand::Redo => self.redo(),
+            ComposerCommand::ApplyCopiedFormatting => {
+                self.apply_copied_formatting()
+            }
+            ComposerCommand::SetLinkWithText { link, text } => {
+                self.set_link_with_text(&link, &text)
+            }
+        }
+    }
+
+    /**
+     * Make the selection bold, or not bold if it all is already, so that
+     * bolding twice leaves the content as it was. If the selection is
+     * empty, bold is pending instead: the next text typed at the cursor
+     * will be bold, or not bold if the cursor is in bold text, and bolding
+     * again before then cancels it.
+     */
+    pub fn bold(&mut self) -> ComposerUpdate<C> {
+        if !self.config.is_enabled(Feature::Bold) {
+            return self.unchanged();
+        }
+        self.format(element_name(Feature::Bold))
+    }
+
+    /**
+     * Make the selection italic, or make italic pending, as bold does.
+     */
+    pub fn italic(&mut self) -> ComposerUpdate<C> {
+        if !self.config.is_enabled(Feature::Italic) {
+            return self.unchanged();
+        }
+        self.format(element_name(Feature::Italic))
+    }
+
+    /**
+     * Wrap the selection in the inline format name, or remove name from it
+     * if it is all inside name already, or toggle name as a pending format
+     * if the selection is empty. If the config's
+     * format_word_at_cursor is set, an empty selection inside a word
+     * formats the whole word instead, leaving the cursor where it is.
+     */
+    fn format(&mut self, name: &str) -> ComposerUpdate<C> {
+        if self.plain_text_mode {
+            return self.with_menu(ComposerUpdate::keep());
+        }
+        let (mut s, mut e) = self.safe_selection();
+        if s == e && self.config.format_word_at_cursor {
+            if let Some((ws, we, true)) = self.segment_at(s) {
+                if ws < s && s < we {
+                    (s, e) = self.dom.expand_to_mentions(ws, we);
+                }
+            }
+        }
+        if s == e {
+            if let Some(i) = self.pending_formats.iter().position(|p| p == name)
+            {
+                self.pending_formats.remove(i);
+            } else if let Some(i) =
+                self.pending_removals.iter().position(|p| p == name)
+            {
+                self.pending_removals.remove(i);
+            } else if Coverage::of(&self.dom, s, s, name) == Coverage::Full {
+                // Typing here would continue the format, so turn it off
+                self.pending_removals.push(name.to_owned());
+            } else {
+                self.pending_formats.push(name.to_owned());
+            }
+            return self.with_menu(ComposerUpdate::keep());
+        }
+        let formatted = self.is_formatted(s, e, name);
+        let selection = self.begin_edit("format");
+        // Either way, the format is first removed from the selection, so
+        // that it is applied without nesting it inside itself
+        self.dom.unwrap_inline_range(s, e, name);
+        if !formatted {
+            if self.config.link_formatting == LinkFormatting::SplitLink {
+                self.dom.split_elements_at(s, "a");
+                self.dom.split_elements_at(e, "a");
+            }
+            self.wrap_formatting(s, e, name);
+            self.dom.merge_adjacent_formatting(name);
+        }
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Whether all the text in start..end that wrap_formatting would wrap
+     * in name is inside name already, so formatting it again removes it.
+     */
+    fn is_formatted(&self, start: usize, end: usize, name: &str) -> bool {
+        let ranges = match self.config.mention_formatting {
+            MentionFormatting::Skip => {
+                self.dom.ranges_outside_mentions(start, end)
+            }
+            MentionFormatting::StyleText => vec![(start, end)],
+        };
+        !ranges.is_empty()
+            && ranges.iter().all(|&(s, e)| {
+                Coverage::of(&self.dom, s, e, name) == Coverage::Full
+            })
+    }
+
+    /**
+     * Whether all, some or none of the text in start..end has format. An
+     * empty range is covered fully if text typed there would have format,
+     * ignoring pending formats. Bold and italic use this to decide whether
+     * to remove their format or apply it.
+     */
+    pub fn is_range_formatted(
+        &self,
+        start: Location,
+        end: Location,
+        format: Format,
+    ) -> Coverage {
+        let (s, e) = self.safe_range(start.into(), end.into());
+        Coverage::of(&self.dom, s, e, format.element_name())
+    }
+
+    /**
+     * Wrap start..end in the inline format name, leaving out the mentions
+     * in it if the config's mention_formatting says to.
+     */
+    fn wrap_formatting(&mut self, start: usize, end: usize, name: &str) {
+        match self.config.mention_formatting {
+            MentionFormatting::Skip => {
+                for (s, e) in self.dom.ranges_outside_mentions(start, end) {
+                    self.dom.wrap_inline_range(s, e, name);
+                }
+            }
+            MentionFormatting::StyleText => {
+                self.dom.wrap_inline_range(start, end, name)
+            }
+        }
+    }
+
+    /**
+     * Remember the inline formatting (bold, italic and so on, but not links)
+     * at the cursor or shared by all of the selection, for
+     * apply_copied_formatting to apply elsewhere.
+     */
+    pub fn copy_formatting(&mut self) {
+        let (s, e) = self.safe_selection();
+        self.copied_formatting =
+            Some(FormatSet::around(&self.dom, s, e).inline_formats());
+    }
+
+    /**
+     * Add the formatting remembered by copy_formatting to the selection.
+     * Formatting already on the selection is kept. Does nothing if the
+     * selection is empty or no formatting was copied. Formats that are
+     * features (e.g. bold) are only applied if they are enabled.
+     */
+    pub fn apply_copied_formatting(&mut self) -> ComposerUpdate<C> {
+        let (s, e) = self.safe_selection();
+        let copied = match &self.copied_formatting {
+            Some(copied) if s != e => copied.clone(),
+            _ => return self.unchanged(),
+        };
+        let existing = FormatSet::around(&self.dom, s, e);
+        let to_apply: Vec<&String> = copied
+            .names()
+            .iter()
+            .filter(|name| !existing.contains(name))
+            .filter(|name| {
+                [Feature::Bold, Feature::Italic].into_iter().all(|feature| {
+                    name.as_str() != element_name(feature)
+                        || self.config.is_enabled(feature)
+                })
+            })
+            .collect();
+        if to_apply.is_empty() {
+            return self.unchanged();
+        }
+        let selection = self.begin_edit("apply_copied_formatting");
+        let names: Vec<&str> =
+            to_apply.iter().map(|name| name.as_str()).collect();
+        self.add_formatting(s, e, &names);
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Format all of start..end with each of names, outermost first, as
+     * format does when it adds a format: any of them already on part of
+     * the range is removed from it first, so that none is nested inside
+     * itself, and each is merged with the same format next to it.
+     */
+    fn add_formatting(&mut self, start: usize, end: usize, names: &[&str]) {
+        if self.config.link_formatting == LinkFormatting::SplitLink {
+            self.dom.split_elements_at(start, "a");
+            self.dom.split_elements_at(end, "a");
+        }
+        for name in names {
+            self.dom.unwrap_inline_range(start, end, name);
+        }
+        // Innermost first, so that each wraps the ones before it
+        for name in names.iter().rev() {
+            self.wrap_formatting(start, end, name);
+        }
+        for name in names {
+            self.dom.merge_adjacent_formatting(name);
+        }
+    }
+
+    /**
+     * Apply all of formats to the selection as one edit, undone in one
+     * step, nested in the order of Format whatever order they are given
+     * in. Unlike bold and the like, formats already on all of the
+     * selection are kept rather than removed, and formats whose feature is
+     * disabled are left out. If the selection is empty, the formats not
+     * already pending become pending.
+     */
+    pub fn apply_formats(&mut self, formats: &[Format]) -> ComposerUpdate<C> {
+        if self.plain_text_mode {
+            return self.with_menu(ComposerUpdate::keep());
+        }
+        let mut formats: Vec<Format> = formats
+            .iter()
+            .copied()
+            .filter(|format| {
+                format
+                    .feature()
+                    .map_or(true, |feature| self.config.is_enabled(feature))
+            })
+            .collect();
+        formats.sort();
+        formats.dedup();
+        let (s, e) = self.safe_selection();
+        let existing = FormatSet::around(&self.dom, s, e);
+        let to_apply: Vec<&str> = formats
+            .iter()
+            .map(Format::element_name)
+            .filter(|name| !existing.contains(name))
+            .collect();
+        if s == e {
+            self.pending_removals
+                .retain(|p| !formats.iter().any(|f| f.element_name() == p));
+            // Pending formats nest in the order they were added
+            for name in to_apply {
+                if !self.pending_formats.iter().any(|p| p == name) {
+                    self.pending_formats.push(name.to_owned());
+                }
+            }
+            return self.with_menu(ComposerUpdate::keep());
+        }
+        if to_apply.is_empty() {
+            return self.unchanged();
+        }
+        let selection = self.begin_edit("apply_formats");
+        self.add_formatting(s, e, &to_apply);
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Replaces the current selection (usually empty) with a new link to
+     * `link` whose display text is `text`, leaving the cursor after the
+     * link so that anything typed next is not part of it. If text is the
+     * link itself, it is cut short to the config's max_link_text_length.
+     *
+     * Does nothing if links are disabled or link's scheme is not allowed.
+     */
+    pub fn set_link_with_text(
+        &mut self,
+        link: &[C],
+        text: &[C],
+    ) -> ComposerUpdate<C> {
+        let link = self.valid_input(link);
+        let text = self.valid_input(text);
+        if !self.config.is_enabled(Feature::Links)
+            || !self.config.is_allowed_link(&C::decode(&link))
+        {
+            return self.unchanged();
+        }
+        let text = match self.config.max_link_text_length {
+            Some(max) if text == link => shorten_url(&link, max),
+            _ => text.to_vec(),
+        };
+        let selection = self.begin_edit("set_link_with_text");
+        let (s, e) = self.safe_selection();
+        self.delete_selection(s, e);
+        let text_node = self.dom.create_text(text.to_vec());
+        let a = self.dom.create_container(
+            "a",
+            vec![(Atom::from("href"), link.to_vec())],
+            vec![text_node],
+        );
+        self.dom.insert_node(s, a);
+        self.start = Location::from(s + text.len());
+        self.end = self.start;
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Turn the innermost list containing the start of the selection from
+     * ordered into unordered, or the other way round, keeping its items
+     * and nesting. Does nothing outside a list, or if the list type it
+     * would become is not an enabled feature.
+     */
+    pub fn toggle_list_type(&mut self) -> ComposerUpdate<C> {
+        let (s, _) = self.safe_selection();
+        let list = match self.dom.list_at(s) {
+            Some(list) => list,
+            None => return self.unchanged(),
+        };
+        let target = match self.dom.node(list) {
+            DomNode::Container(c) if c.name() == "ol" => Feature::UnorderedList,
+            _ => Feature::OrderedList,
+        };
+        if !self.config.is_enabled(target) {
+            return self.unchanged();
+        }
+        let selection = self.begin_edit("toggle_list_type");
+        self.dom.toggle_list_type(list);
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Removes the innermost list containing the start of the selection,
+     * putting the content of each of its items in a paragraph of its own
+     * with its formatting intact (see Dom::remove_list). The selection
+     * stays next to the same text.
+     */
+    pub fn remove_list(&mut self) -> ComposerUpdate<C> {
+        let (s, e) = self.safe_selection();
+        let list = match self.dom.list_at(s) {
+            Some(list) => list,
+            None => return self.unchanged(),
+        };
+        let old = self.dom.location_units();
+        let selection = self.begin_edit("remove_list");
+        self.dom.remove_list(list);
+        let new = self.dom.location_units();
+        let new_s = map_location(&old, &new, s);
+        let new_e = map_location(&old, &new, e).max(new_s);
+        if self.start <= self.end {
+            self.start = Location::from(new_s);
+            self.end = Location::from(new_e);
+        } else {
+            self.start = Location::from(new_e);
+            self.end = Location::from(new_s);
+        }
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * Handles Tab: inside a code block, inserts indentation in place of the
+     * selection; inside a list, makes the item at the cursor a sub-item of
+     * the one before it. Anywhere else nothing changes (the update is
+     * keep()) and the platform should move focus as it normally would.
+     */
+    pub fn tab(&mut self) -> ComposerUpdate<C> {
+        let (s, e) = self.safe_selection();
+        if self.code_block_at(s) {
+            let selection = self.begin_edit("tab");
+            let before = self.dom.clone();
+            self.do_replace_text_in(&C::encode(CODE_INDENT), s, e);
+            self.end_edit(selection);
+            return self.create_update_for_edit(&before);
+        }
+        match self.dom.element_at(s, "li") {
+            Some(li) => {
+                let selection = self.begin_edit("tab");
+                let changed = self.dom.indent_list_item(li);
+                self.end_edit(selection);
+                if changed {
+                    self.create_update_replace_all()
+                } else {
+                    self.unchanged()
+                }
+            }
+            None => self.unchanged(),
+        }
+    }
+
+    /**
+     * Handles Shift+Tab: inside a code block, removes one level of
+     * indentation from the start of the line containing the cursor; inside
+     * a nested list, moves the item at the cursor out one level. Anywhere
+     * else nothing changes, as for tab.
+     */
+    pub fn shift_tab(&mut self) -> ComposerUpdate<C> {
+        let (s, _) = self.safe_selection();
+        if self.code_block_at(s) {
+            let selection = self.begin_edit("shift_tab");
+            let before = self.dom.clone();
+            self.do_unindent_code_line(s);
+            self.end_edit(selection);
+            return self.create_update_for_edit(&before);
+        }
+        match self.dom.element_at(s, "li") {
+            Some(li) => {
+                let selection = self.begin_edit("shift_tab");
+                let changed = self.dom.outdent_list_item(li);
+                self.end_edit(selection);
+                if changed {
+                    self.create_update_replace_all()
+                } else {
+                    self.unchanged()
+                }
+            }
+            None => self.unchanged(),
+        }
+    }
+
+    /**
+     * Inside code, comment out the lines the selection touches by putting
+     * prefix (e.g. "//") and a space after their indentation, or uncomment
+     * them if they all start with prefix already. Blank lines are left
+     * alone unless all the lines are blank. Outside code nothing changes.
+     */
+    pub fn toggle_comment(&mut self, prefix: &[C]) -> ComposerUpdate<C> {
+        let prefix = self.valid_input(prefix).to_vec();
+        self.edit_code_lines("toggle_comment", |text, start, end| {
+            code_lines::toggle_comment(text, start, end, &prefix)
+        })
+    }
+
+    /**
+     * Inside code, copy the lines the selection touches to below them,
+     * moving the selection to the copy. Outside code nothing changes.
+     */
+    pub fn duplicate_line(&mut self) -> ComposerUpdate<C> {
+        self.edit_code_lines("duplicate_line", code_lines::duplicate_lines)
+    }
+
+    /**
+     * Inside code, swap the lines the selection touches with the line
+     * above them, moving the selection with them, e.g. for Alt+Up. Outside
+     * code, or on its first line, nothing changes.
+     */
+    pub fn move_line_up(&mut self) -> ComposerUpdate<C> {
+        self.edit_code_lines("move_line_up", code_lines::move_lines_up)
+    }
+
+    /**
+     * Inside code, swap the lines the selection touches with the line
+     * below them, as move_line_up does.
+     */
+    pub fn move_line_down(&mut self) -> ComposerUpdate<C> {
+        self.edit_code_lines("move_line_down", code_lines::move_lines_down)
+    }
+
+    /**
+     * Replace the text of the code at the start of the selection, and the
+     * selection in it, by what edit makes of them (see code_lines). The
+     * selection is cut short at the end of that text.
+     */
+    fn edit_code_lines(
+        &mut self,
+        label: &'static str,
+        edit: impl FnOnce(&[C], usize, usize) -> Option<(Vec<C>, usize, usize)>,
+    ) -> ComposerUpdate<C> {
+        let (s, e) = self.safe_selection();
+        if !self.code_block_at(s) {
+            return self.unchanged();
+        }
+        let (id, offset) = match self.dom.text_node_at(s) {
+            Some(found) => found,
+            None => return self.unchanged(),
+        };
+        let text = match self.dom.node(id) {
+            DomNode::Text(text) => text.data(),
+            DomNode::Container(_) => return self.unchanged(),
+        };
+        let node_start = s - offset;
+        let end = (e - node_start).min(text.len());
+        let (new_text, new_s, new_e) = match edit(text, offset, end) {
+            Some(edited) => edited,
+            None => return self.unchanged(),
+        };
+        let selection = self.begin_edit(label);
+        let before = self.dom.clone();
+        self.dom.set_text(id, new_text);
+        let (new_s, new_e) = (node_start + new_s, node_start + new_e);
+        if self.start <= self.end {
+            self.start = Location::from(new_s);
+            self.end = Location::from(new_e);
+        } else {
+            self.start = Location::from(new_e);
+            self.end = Location::from(new_s);
+        }
+        self.end_edit(selection);
+        self.create_update_for_edit(&before)
+    }
+
+    /**
+     * Swap the list item containing the selection with the one before it,
+     * e.g. for Alt+Up, keeping any lists nested in either with them. The
+     * selection moves with the item. Outside a list item, or in the first
+     * item of a list, nothing changes.
+     */
+    pub fn move_list_item_up(&mut self) -> ComposerUpdate<C> {
+        self.move_list_item("move_list_item_up", Dom::move_list_item_up)
+    }
+
+    /**
+     * Swap the list item containing the selection with the one after it,
+     * e.g. for Alt+Down, as move_list_item_up does.
+     */
+    pub fn move_list_item_down(&mut self) -> ComposerUpdate<C> {
+        self.move_list_item("move_list_item_down", Dom::move_list_item_down)
+    }
+
+    fn move_list_item(
+        &mut self,
+        label: &'static str,
+        move_item: fn(&mut Dom<C>, NodeId) -> bool,
+    ) -> ComposerUpdate<C> {
+        let (s, e) = self.safe_selection();
+        let li = match self.dom.list_item_at(s) {
+            Some(li) => li,
+            None => return self.unchanged(),
+        };
+        let old_start = self.dom.start_of(li);
+        if e > old_start + self.dom.node_len(li) {
+            return self.unchanged();
+        }
+        let selection = self.begin_edit(label);
+        let changed = move_item(&mut self.dom, li);
+        if changed {
+            let new_start = self.dom.start_of(li);
+            let follow = |location: usize| {
+                Location::from(new_start + location - old_start)
+            };
+            let (start, end) = if self.start <= self.end {
+                (s, e)
+            } else {
+                (e, s)
+            };
+            self.start = follow(start);
+            self.end = follow(end);
+        }
+        self.end_edit(selection);
+        if changed {
+            self.create_update_replace_all()
+        } else {
+            self.unchanged()
+        }
+    }
+
+    /**
+     * Complete suggestion by replacing the text it covers with a mention:
+     * a matrix.to link to mention's ID showing its display name, followed
+     * by a space, with the cursor after it. The mention's ID, kind and
+     * avatar are kept on the link, so that mentioned_user_ids can list it
+     * for the message's m.mentions; they are not sent in the message HTML.
+     * Nothing is done in plain text mode, for a mention with no display
+     * name, or if the mention and its space would go beyond max_length.
+     */
+    pub fn replace_text_at_suggestion(
+        &mut self,
+        suggestion: &SuggestionPattern<C>,
+        mention: &Mention<C>,
+    ) -> ComposerUpdate<C> {
+        if self.plain_text_mode {
+            return self.with_menu(ComposerUpdate::keep());
+        }
+        let mention = Mention {
+            id: self.valid_input(&mention.id).into_owned(),
+            display_name: self.valid_input(&mention.display_name).into_owned(),
+            avatar_url: mention
+                .avatar_url
+                .as_ref()
+                .map(|url| self.valid_input(url).into_owned()),
+        };
+        let (s, e) =
+            self.safe_range(suggestion.start.into(), suggestion.end.into());
+        let fits = self.config.max_length.map_or(true, |max_length| {
+            let remaining =
+                max_length.saturating_sub(self.dom.text_len() - (e - s));
+            mention.display_name.len() < remaining
+        });
+        if mention.display_name.is_empty() || !fits {
+            return self.unchanged();
+        }
+        let selection = self.begin_edit("replace_text_at_suggestion");
+        self.delete_selection(s, e);
+        let mut href = C::encode(MATRIX_TO_PREFIX);
+        href.extend_from_slice(&mention.id);
+        let mut attrs = vec![
+            (Atom::from("href"), href),
+            (Atom::from(MENTION_ID_ATTR), mention.id.clone()),
+        ];
+        if let Some(kind) = mention.kind() {
+            attrs.push((Atom::from(MENTION_KIND_ATTR), C::encode(kind.name())));
+        }
+        if let Some(avatar_url) = mention.avatar_url {
+            attrs.push((Atom::from(MENTION_AVATAR_ATTR), avatar_url));
+        }
+        let after = s + mention.display_name.len();
+        let text = self.dom.create_text(mention.display_name);
+        let link = self.dom.create_container("a", attrs, vec![text]);
+        self.dom.insert_node(s, link);
+        self.dom.insert_text(after, &C::encode(" "));
+        self.start = Location::from(after + 1);
+        self.end = self.start;
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * The IDs of the users mentioned with replace_text_at_suggestion whose
+     * mentions are still in the content, once each in the order they
+     * appear, for the user_ids of the message's m.mentions.
+     */
+    pub fn mentioned_user_ids(&self) -> Vec<Vec<C>> {
+        let mut ids: Vec<Vec<C>> = Vec::new();
+        for id in self.dom.descendants(self.dom.document()) {
+            if self.dom.mention_kind(id) != Some(MentionKind::User) {
+                continue;
+            }
+            if let DomNode::Container(c) = self.dom.node(id) {
+                if let Some(mentioned) = c.attr(MENTION_ID_ATTR) {
+                    if !ids.iter().any(|i| i == mentioned) {
+                        ids.push(mentioned.to_vec());
+                    }
+                }
+            }
+        }
+        ids
+    }
+
+    /**
+     * Show the current display name and avatar of each of mentions in the
+     * mentions of it already in the content, e.g. when the room's state
+     * says that a user has changed their name. This is not an edit, so is
+     * not undone, and the selection keeps its place in the text around it.
+     */
+    pub fn update_mentions(
+        &mut self,
+        mentions: &[Mention<C>],
+    ) -> ComposerUpdate<C> {
+        let old = self.dom.location_units();
+        let selection = self.safe_selection();
+        if !self.dom.update_mentions(mentions) {
+            return self.with_menu(ComposerUpdate::keep());
+        }
+        self.keep_selection(&old, selection);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * The link covering location, including at its edges, if there is one.
+     */
+    pub fn get_link_at(&self, location: Location) -> Option<LinkInfo<C>> {
+        let id = self.dom.element_at(location.into(), "a")?;
+        let url = match self.dom.node(id) {
+            DomNode::Container(a) => {
+                a.attr("href").unwrap_or_default().to_vec()
+            }
+            DomNode::Text(_) => return None,
+        };
+        let start = self.dom.start_of(id);
+        Some(LinkInfo {
+            url,
+            text: self.dom.text_content(id),
+            start: Location::from(start),
+            end: Location::from(start + self.dom.node_len(id)),
+        })
+    }
+
+    /**
+     * Change the link at the start of the selection (see get_link_at) to
+     * point to url and show text, leaving the cursor after it. Formatting
+     * around the link is kept, and the new text takes the formatting of the
+     * start of the old text. If text is empty, the link's text is kept.
+     */
+    pub fn update_link(&mut self, url: &[C], text: &[C]) -> ComposerUpdate<C> {
+        let url = self.valid_input(url);
+        let text = self.valid_input(text);
+        if !self.config.is_enabled(Feature::Links)
+            || !self.config.is_allowed_link(&C::decode(&url))
+        {
+            return self.unchanged();
+        }
+        let (s, _) = self.safe_selection();
+        let id = match self.dom.element_at(s, "a") {
+            Some(id) => id,
+            None => return self.unchanged(),
+        };
+        let selection = self.begin_edit("update_link");
+        self.dom.set_attr(id, "href", url.to_vec());
+        if !text.is_empty() {
+            self.dom.replace_content(id, &text);
+        }
+        self.start =
+            Location::from(self.dom.start_of(id) + self.dom.node_len(id));
+        self.end = self.start;
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    // Internal functions
+
+    /**
+     * Start recording an edit, so that it can be undone as a single step
+     * however many nodes it changes. Returns the selection to restore when
+     * it is undone.
+     */
+    fn begin_edit(&mut self, label: &'static str) -> (Location, Location) {
+        self.last_space = None;
+        self.edit_label = label;
+        self.edit_typing = None;
+        self.deadline = self.config.operation_budget_ms.and_then(|budget| {
+            match &self.clock {
+                Some(clock) => Some(Deadline::ClockMs(
+                    clock.now_ms().saturating_add(budget),
+                )),
+                None => Some(Deadline::Instant(
+                    now()? + Duration::from_millis(budget),
+                )),
+            }
+        });
+        self.edit_protected = self.dom.protected_contents();
+        self.dom.start_transaction();
+        (self.start, self.end)
+    }
+
+    /**
+     * Parse HTML given to the composer, with its empty lines and line
+     * breaks represented as the composer represents them (see
+     * Dom::canonicalize_empty_lines and Dom::canonicalize_trailing_breaks),
+     * or refuse it with ComposerError::TooDeep if it is nested more deeply
+     * than the config allows.
+     */
+    fn parse_input(&mut self, html: &[C]) -> Option<Dom<C>> {
+        let html = self.valid_input(html);
+        let result = parse_to_depth(&html, self.config.max_depth);
+        let too_deep = result
+            .warnings
+            .iter()
+            .any(|w| matches!(w, ParseWarning::TooDeep { .. }));
+        if too_deep {
+            self.error = Some(ComposerError::TooDeep);
+            None
+        } else {
+            let mut dom = result.dom;
+            dom.canonicalize_empty_lines();
+            dom.canonicalize_trailing_breaks();
+            Some(dom)
+        }
+    }
+
+    /**
+     * units given to the composer, with any invalid sequences (e.g. lone
+     * surrogates in UTF-16 from the platform) replaced by U+FFFD
+     * REPLACEMENT CHARACTER, so that they never reach the content. Anything
+     * replaced is reported as a diagnostic.
+     */
+    fn valid_input<'a>(&mut self, units: &'a [C]) -> Cow<'a, [C]> {
+        let units = replace_invalid(units);
+        if let Cow::Owned(_) = units {
+            self.diagnostics.push(String::from(
+                "Invalid code units in the input were replaced",
+            ));
+        }
+        units
+    }
+
+    /**
+     * Forget the formats toggled on or off while the selection was empty,
+     * as anything other than typing there does.
+     */
+    fn clear_pending_formats(&mut self) {
+        self.pending_formats.clear();
+        self.pending_removals.clear();
+    }
+
+    fn end_edit(&mut self, (start, end): (Location, Location)) {
+        self.clear_pending_formats();
+        self.cursor_block = None;
+        if self.out_of_time() {
+            self.deadline = None;
+            if let Some(transaction) = self.dom.end_transaction() {
+                self.dom.revert(transaction);
+            }
+            self.start = start;
+            self.end = end;
+            self.error = Some(ComposerError::BudgetExceeded);
+            return;
+        }
+        self.deadline = None;
+        let protected = self.edit_protected.take();
+        if let Some(protected) = protected {
+            if !self.dom.protected_unchanged(&protected) {
+                if let Some(transaction) = self.dom.end_transaction() {
+                    self.dom.revert(transaction);
+                }
+                self.start = start;
+                self.end = end;
+                self.error = Some(ComposerError::Protected);
+                return;
+            }
+        }
+        if let Some(transaction) = self.dom.end_transaction() {
+            self.history.record(HistoryEntry {
+                transaction,
+                start,
+                end,
+                after: (self.start, self.end),
+                item: HistoryItem {
+                    label: self.edit_label,
+                    timestamp_ms: match &self.clock {
+                        Some(clock) => Some(clock.now_ms()),
+                        None => wall_clock_ms(),
+                    },
+                    content_len: self.dom.text_len(),
+                },
+                typing: self.edit_typing.take(),
+            });
+        }
+        self.check_dom();
+    }
+
+    /**
+     * end_edit, but leaving the cursor in the block that an Enter during
+     * the edit moved it into, so that typing next goes there.
+     */
+    fn end_edit_keeping_cursor_block(
+        &mut self,
+        selection: (Location, Location),
+    ) {
+        let cursor_block = self.cursor_block;
+        self.end_edit(selection);
+        self.cursor_block = cursor_block;
+    }
+
+    /**
+     * Whether the edit in progress has used up its operation_budget_ms, so
+     * should stop at the end of the step it is on. end_edit then undoes
+     * it. Nothing checks within a step, so the budget is a soft limit.
+     */
+    fn out_of_time(&self) -> bool {
+        match (self.deadline, &self.clock) {
+            (Some(Deadline::ClockMs(deadline)), Some(clock)) => {
+                clock.now_ms() >= deadline
+            }
+            (Some(Deadline::Instant(deadline)), _) => {
+                now().map_or(false, |now| now >= deadline)
+            }
+            _ => false,
+        }
+    }
+
+    /**
+     * If the Dom has become malformed (which is a bug), replace it with a
+     * repaired copy rather than carry on editing it, keep the selection as
+     * close as possible, and record a diagnostic. The history refers to
+     * the old Dom, so it is lost.
+     */
+    fn check_dom(&mut self) {
+        if let Err(problem) = self.dom.check_invariants() {
+            self.dom = self.dom.repaired();
+            self.history.clear();
+            let (s, e) = self.safe_range(self.start.into(), self.end.into());
+            if self.start <= self.end {
+                self.start = Location::from(s);
+                self.end = Location::from(e);
+            } else {
+                self.start = Location::from(e);
+                self.end = Location::from(s);
+            }
+            self.diagnostics.push(format!(
+                "Repaired the content after finding a problem: {}",
+                problem
+            ));
+        }
+    }
+
+    /**
+     * Reverse entry, putting back the selection from before it, and return
+     * the entry that reverses that, which puts back the selection from
+     * after it.
+     */
+    fn revert(&mut self, entry: HistoryEntry<C>) -> HistoryEntry<C> {
+        self.clear_pending_formats();
+        self.cursor_block = None;
+        let inverse = HistoryEntry {
+            transaction: self.dom.revert(entry.transaction),
+            start: entry.after.0,
+            end: entry.after.1,
+            after: (entry.start, entry.end),
+            item: entry.item,
+            typing: entry.typing,
+        };
+        self.start = entry.start;
+        self.end = entry.end;
+        self.check_dom();
+        inverse
+    }
+
+    /**
+     * Replace the attachment placeholder with the given id by the
+     * sanitized html, keeping the selection on the same content.
+     */
+    fn resolve_attachment(
+        &mut self,
+        id: &str,
+        html: &[C],
+    ) -> ComposerUpdate<C> {
+        let placeholder = match self.dom.find_element(
+            ATTACHMENT_ELEMENT,
+            "data-id",
+            &C::encode(id),
+        ) {
+            Some(placeholder) => placeholder,
+            None => return self.unchanged(),
+        };
+        let mut fragment = match self.parse_input(html) {
+            Some(fragment) => fragment,
+            None => return self.create_update_replace_all(),
+        };
+        let report = fragment.sanitize(&self.config.allowed_link_schemes);
+        self.sanitize_report.extend(report);
+        let selection = self.begin_edit("action_response");
+        let location = self.dom.start_of(placeholder);
+        self.dom.remove(placeholder);
+        let len = self.dom.insert_dom(location, &fragment);
+        let moved = |l: Location| {
+            let l = usize::from(l);
+            if l > location {
+                Location::from(l - 1 + len)
+            } else {
+                Location::from(l)
+            }
+        };
+        self.start = moved(self.start);
+        self.end = moved(self.end);
+        self.end_edit(selection);
+        self.create_update_replace_all()
+    }
+
+    /**
+     * The states reached by reverting each of entries in turn from dom,
+     * starting at the end, stopping after max states.
+     */
+    fn states_along(
+        dom: &Dom<C>,
+        entries: &[HistoryEntry<C>],
+        max: usize,
+    ) -> Vec<SnapshotState<C>> {
+        let mut dom = dom.clone();
+        entries
+            .iter()
+            .rev()
+            .take(max)
+            .map(|entry| {
+                dom.revert(entry.transaction.clone());
+                SnapshotState {
+                    html: dom.to_html(HtmlDialect::MatrixMessage),
+                    start: entry.start,
+                    end: entry.end,
+                }
+            })
+            .collect()
+    }
+
+    /**
+     * Move to state, whose HTML parsed as dom, as a single edit that can be
+     * undone.
+     */
+    fn restore_state(&mut self, state: &SnapshotState<C>, dom: Dom<C>) {
+        let before = self.begin_edit("restore_snapshot");
+        // Restoring a snapshot may replace protected content too
+        self.edit_protected = None;
+        self.dom.replace_all(&dom);
+        self.start = state.start;
+        self.end = state.end;
+        self.end_edit(before);
+    }
+
+    /**
+     * Type new_text if it is a single printable ASCII character typed at
+     * the cursor inside a text node, which is by far the most common edit,
+     * going straight to that node rather than through do_replace_text.
+     * Such text needs no normalization or truncation at a character
+     * boundary, and cannot start a new line. Returns None, without
+     * changing anything, for any other edit, including ones that apply
+     * pending formats or markdown shortcuts.
+     */
+    fn type_ascii(&mut self, new_text: &[C]) -> Option<ComposerUpdate<C>> {
+        let is_printable_ascii = new_text.len() == 1
+            && C::decode(new_text)
+                .chars()
+                .all(|c| c.is_ascii_graphic() || c == ' ');
+        let (s, e) = self.safe_selection();
+        let room = self
+            .config
+            .max_length
+            .map_or(true, |max| self.dom.text_len() < max);
+        if !is_printable_ascii
+            || s != e
+            || !room
+            || !self.pending_formats.is_empty()
+            || !self.pending_removals.is_empty()
+            || self.config.markdown_shortcuts
+            || self.cursor_block_at(Location::from(s)).is_some()
+        {
+            return None;
+        }
+        let (id, offset) = self.dom.text_node_at(s)?;
+        let selection = self.begin_edit("replace_text");
+        self.edit_typing = Typing::of(new_text);
+        self.dom.insert_into_text_node(id, offset, new_text);
+        self.start = Location::from(s + 1);
+        self.end = self.start;
+        self.end_edit(selection);
+        if !self.diagnostics.is_empty()
+            || self.error.is_some()
+            || self.dom.get(id).is_none()
+        {
+            return Some(self.create_update_replace_all());
+        }
+        let new_text = match self.dom.node(id) {
+            DomNode::Text(text) => text.data().to_vec(),
+            DomNode::Container(_) => {
+                return Some(self.create_update_replace_all())
+            }
+        };
+        Some(self.with_menu(ComposerUpdate::update_text_node(
+            self.dom.handle_of(id),
+            new_text,
+            self.start,
+            self.end,
+        )))
+    }
+
+    fn do_replace_text(&mut self, new_text: &[C]) {
+        // Soft keyboards report Enter as a typed newline, so treat it as
+        // Enter would be treated.
+        if self.config.enter_behaviour == EnterBehaviour::Send
+            && is_single_newline(new_text)
+        {
+            return;
+        }
+        let (s, e) = self.safe_selection();
+        let pending = std::mem::take(&mut self.pending_formats);
+        let removals = std::mem::take(&mut self.pending_removals);
+        let existing = FormatSet::around(&self.dom, s, s);
+        self.do_replace_text_in(new_text, s, e);
+        let end: usize = self.start.into();
+        if end > s {
+            for name in &removals {
+                self.dom.unwrap_inline_range(s, end, name);
+            }
+            // Innermost first, so that each wraps the ones toggled before it
+            for name in pending.iter().rev() {
+                if !existing.contains(name) {
+                    self.dom.wrap_inline_range(s, end, name);
+                }
+            }
+        }
+        if self.config.markdown_shortcuts && !self.plain_text_mode {
+            self.apply_markdown_shortcut();
+        }
+    }
+
+    /**
+     * Make fragment, about to be inserted without replacing anything, fit
+     * the config as typed text is made to: formatting whose feature is
+     * disabled is replaced by its content (lists by a line per item), and
+     * whatever would go beyond max_length is cut off without splitting a
+     * character.
+     */
+    fn fit_to_config(&self, fragment: &mut Dom<C>) {
+        for id in fragment.descendants(fragment.document()) {
+            let (is_list, feature) = match fragment.get(id) {
+                Some(DomNode::Container(c))
+                    if c.attr(MENTION_ID_ATTR).is_none() =>
+                {
+                    match element_spec(c.name()).and_then(|spec| spec.feature) {
+                        Some(feature) => {
+                            (c.name() == "ol" || c.name() == "ul", feature)
+                        }
+                        None => continue,
+                    }
+                }
+                _ => continue,
+            };
+            if self.config.is_enabled(feature) {
+                continue;
+            }
+            if is_list {
+                fragment.remove_list(id);
+            } else {
+                fragment.unwrap(id);
+            }
+        }
+        let remaining = match self.config.max_length {
+            Some(max_length) => max_length.saturating_sub(self.dom.text_len()),
+            None => return,
+        };
+        let units = fragment.location_units();
+        if units.len() <= remaining {
+            return;
+        }
+        let mut cut = 0;
+        while cut < units.len() {
+            let run: Vec<C> = units[cut..]
+                .iter()
+                .take(4)
+                .map_while(|&unit| unit)
+                .collect();
+            let len = C::decode(&run)
+                .chars()
+                .next()
+                .map_or(1, |c| C::encode(c.encode_utf8(&mut [0; 4])).len());
+            if cut + len > remaining {
+                break;
+            }
+            cut += len;
+        }
+        fragment.delete_range(cut, units.len());
+    }
+
+    fn do_replace_text_in(&mut self, new_text: &[C], start: usize, end: usize) {
+        let (start, end) = self.safe_range(start, end);
+        let new_text = self.valid_input(new_text);
+        let normalized;
+        let new_text = if self.config.normalize_input {
+            normalized = C::encode(&nfc(&C::decode(&new_text)));
+            normalized.as_slice()
+        } else {
+            &new_text
+        };
+        let new_text = match self.config.max_length {
+            Some(max_length) => {
+                let remaining = max_length
+                    .saturating_sub(self.dom.text_len() - (end - start));
+                truncate_to_chars(new_text, remaining)
+            }
+            None => new_text,
+        };
+        let mut lines = split_lines(new_text).into_iter();
+        let first = lines.next().unwrap_or_default();
+        match self.cursor_block_at(Location::from(start)) {
+            Some(block) if start == end => {
+                self.dom.insert_text_at_start(block, first)
+            }
+            _ => self.dom.replace_text_in(first, start, end),
+        }
+        if start < end {
+            // Formatting whose text was all replaced must not catch what is
+            // typed next, nor a mention's link be left behind without it
+            self.remove_emptied_elements();
+        }
+        let mut pos = start + first.len();
+        for line in lines {
+            if self.out_of_time() {
+                break;
+            }
+            let br = self.dom.create_container("br", Vec::new(), Vec::new());
+            self.dom.insert_node(pos, br);
+            self.dom.insert_text(pos + 1, line);
+            pos += 1 + line.len();
+        }
+
+        self.start = Location::from(pos);
+        self.end = self.start;
+    }
+
+    /**
+     * Delete start..end, the selection about to be replaced, along with
+     * any formatting left empty, as do_replace_text_in does, so that it
+     * does not catch what replaces the selection.
+     */
+    fn delete_selection(&mut self, start: usize, end: usize) {
+        self.dom.delete_range(start, end);
+        if start < end {
+            self.remove_emptied_elements();
+        }
+    }
+
+    /**
+     * Remove the formatting and paragraphs that deleting a range left with
+     * nothing in them, which the cursor could no longer reach.
+     */
+    fn remove_emptied_elements(&mut self) {
+        self.dom.remove_empty_formatting();
+        self.dom.remove_blank_lines();
+    }
+
+    /**
+     * The block that enter moved the cursor into, if it is still at
+     * location.
+     */
+    fn cursor_block_at(&self, location: Location) -> Option<NodeId> {
+        self.cursor_block.filter(|&block| {
+            self.dom.get(block).is_some()
+                && self.dom.start_of(block) == usize::from(location)
+        })
+    }
+
+    fn do_backspace(&mut self) {
+        if self.start == self.end {
+            // Go back 1 from the current location
+            self.start -= 1;
+        }
+
+        self.do_replace_text(&[]);
+    }
+
+    fn do_delete_in(&mut self, start: usize, end: usize) {
+        self.end = Location::from(start);
+        self.do_replace_text_in(&[], start, end);
+    }
+
+    fn do_delete(&mut self) {
+        if self.start == self.end {
+            // Go forward 1 from the current location
+            self.end += 1;
+        }
+
+        self.do_replace_text(&[]);
+    }
+
+    /**
+     * If the text just before the cursor is **text** or *text*, replace it
+     * with text in bold or italic, leaving the cursor after it.
+     */
+    fn apply_markdown_shortcut(&mut self) {
+        let cursor: usize = self.start.into();
+        let (id, offset) = match self.dom.text_node_at(cursor) {
+            Some(found) => found,
+            None => return,
+        };
+        let data = match self.dom.node(id) {
+            DomNode::Text(text) => &text.data()[..offset],
+            DomNode::Container(_) => return,
+        };
+        let (open, marker_len) = match find_markdown_emphasis(data) {
+            Some(found) => found,
+            None => return,
+        };
+        let name = if marker_len == 2 { "strong" } else { "em" };
+        let open = cursor - offset + open;
+        let close = cursor - marker_len;
+        self.dom.delete_range(close, cursor);
+        self.dom.delete_range(open, open + marker_len);
+        self.dom.wrap_inline_range(open, close - marker_len, name);
+        self.start = Location::from(close - marker_len);
+        self.end = self.start;
+    }
+
+    /**
+     * If the cursor follows a word and two spaces, replace the first space
+     * with a full stop, as a separate edit. Returns true if it did.
+     */
+    fn apply_double_space_full_stop(&mut self) -> bool {
+        let cursor: usize = self.start.into();
+        let (id, offset) = match self.dom.text_node_at(cursor) {
+            Some(found) => found,
+            None => return false,
+        };
+        let data = match self.dom.node(id) {
+            DomNode::Text(text) => &text.data()[..offset],
+            DomNode::Container(_) => return false,
+        };
+        let follows_word = offset >= 3
+            && data[offset - 2..] == *C::encode("  ")
+            && C::decode(&data[..offset - 2])
+                .chars()
+                .last()
+                .map_or(false, char::is_alphanumeric);
+        if !follows_word {
+            return false;
+        }
+        let selection = self.begin_edit("replace_text");
+        self.dom
+            .replace_text_in(&C::encode("."), cursor - 2, cursor - 1);
+        self.end_edit(selection);
+        true
+    }
+
+    fn code_block_at(&self, location: usize) -> bool {
+        self.dom.element_at(location, "pre").is_some()
+            || self.dom.element_at(location, "code").is_some()
+    }
+
+    /**
+     * Remove up to CODE_INDENT's worth of spaces, or one tab, from the
+     * start of the line of code containing location.
+     */
+    fn do_unindent_code_line(&mut self, location: usize) {
+        let (id, offset) = match self.dom.text_node_at(location) {
+            Some(found) => found,
+            None => return,
+        };
+        let data = match self.dom.node(id) {
+            DomNode::Text(text) => text.data(),
+            DomNode::Container(_) => return,
+        };
+        let is = |c: &C, ascii: u8| c.to_ascii() == Some(ascii);
+        let line_start = data[..offset]
+            .iter()
+            .rposition(|c| is(c, b'\n'))
+            .map_or(0, |i| i + 1);
+        let line = &data[line_start..];
+        let len = if line.first().map_or(false, |c| is(c, b'\t')) {
+            1
+        } else {
+            line.iter()
+                .take(CODE_INDENT.len())
+                .take_while(|c| is(c, b' '))
+                .count()
+        };
+        if len == 0 {
+            return;
+        }
+        let start = location - offset + line_start;
+        let (s, e) = self.safe_selection();
+        self.dom.delete_range(start, start + len);
+        let shift = |x: usize| {
+            if x >= start + len {
+                x - len
+            } else {
+                x.min(start)
+            }
+        };
+        let (s, e) = (shift(s), shift(e));
+        if self.start <= self.end {
+            self.start = Location::from(s);
+            self.end = Location::from(e);
+        } else {
+            self.start = Location::from(e);
+            self.end = Location::from(s);
+        }
+    }
+
+    pub(crate) fn message_dom(&self) -> Dom<C> {
+        self.to_message_dom(self.dom.clone())
+    }
+
+    /**
+     * A copy of the content for plain text: rooms and events mentioned by
+     * their IDs or permalinks (see Dom::use_mention_fallbacks) if
+     * mention_fallbacks is true, and shortened links showing their whole
+     * URLs (see Dom::use_full_link_urls).
+     */
+    pub(crate) fn plain_text_dom(&self, mention_fallbacks: bool) -> Dom<C> {
+        let mut dom = self.dom.clone();
+        if mention_fallbacks {
+            dom.use_mention_fallbacks();
+        }
+        dom.use_full_link_urls();
+        dom
+    }
+
+    /**
+     * dom, a version of the content, as message_dom would make it.
+     */
+    pub(crate) fn to_message_dom(&self, dom: Dom<C>) -> Dom<C> {
+        let mut dom = if self.plain_text_mode {
+            let markdown = C::decode(&dom.to_plain_text(false));
+            let html = markdown_to_html(&markdown, self.config.max_depth);
+            parse_to_depth(&C::encode(&html), self.config.max_depth).dom
+        } else {
+            dom
+        };
+        dom.strip_matrix_violations(&self.config.allowed_link_schemes);
+        dom.drop_edge_formatting();
+        dom
+    }
+
+    fn create_update_replace_all(&mut self) -> ComposerUpdate<C> {
+        self.with_menu(ComposerUpdate::replace_all(
+            self.get_html(),
+            self.start,
+            self.end,
+        ))
+    }
+
+    /**
+     * Fill in the menu state and action of update for the current content
+     * and selection, and the diagnostics waiting to be reported. The menu
+     * state is MenuState::None unless it differs from the last one
+     * reported.
+     */
+    fn with_menu(
+        &mut self,
+        mut update: ComposerUpdate<C>,
+    ) -> ComposerUpdate<C> {
+        update.menu_state = self.menu_state_update();
+        update.menu_action = self.compute_menu_action();
+        update.diagnostics = std::mem::take(&mut self.diagnostics);
+        update.sanitize_report = std::mem::take(&mut self.sanitize_report);
+        update.error = self.error.take();
+        update.revision = self.revision();
+        self.notify_observers();
+        update
+    }
+
+    /**
+     * An update saying that nothing changed.
+     */
+    fn unchanged(&self) -> ComposerUpdate<C> {
+        let mut update = ComposerUpdate::keep();
+        update.revision = self.revision();
+        update
+    }
+
+    fn notify_observers(&mut self) {
+        let observed = match &self.observed {
+            Some(observed) => observed,
+            None => return,
+        };
+        let events = self.dom.events_since(observed);
+        if events.is_empty() {
+            return;
+        }
+        for observer in &mut self.observers {
+            for event in &events {
+                observer.on_event(event);
+            }
+        }
+        self.observed = Some(self.dom.clone());
+    }
+
+    fn menu_state_update(&mut self) -> MenuState {
+        let key = self.menu_key();
+        if let Some((Some(last_key), _)) = &self.last_menu {
+            if key.as_ref() == Some(last_key) {
+                return MenuState::None;
+            }
+        }
+        let menu_state = self.compute_menu_state();
+        let changed = !matches!(
+            &self.last_menu,
+            Some((_, last)) if *last == menu_state
+        );
+        self.last_menu = Some((key, menu_state.clone()));
+        if changed {
+            menu_state
+        } else {
+            MenuState::None
+        }
+    }
+
+    /**
+     * What the MenuState depends on, if the selection is empty. Typing or
+     * moving within the same text node leaves this unchanged.
+     */
+    fn menu_key(&self) -> Option<MenuKey> {
+        let (s, e) = self.safe_selection();
+        if s != e {
+            return None;
+        }
+        let list = self.dom.list_at(s).map(|list| match self.dom.node(list) {
+            DomNode::Container(c) => (list, String::from(c.name())),
+            _ => (list, String::new()),
+        });
+        Some(MenuKey {
+            ancestry: self
+                .dom
+                .ancestry_at(s)
+                .into_iter()
+                .map(|(id, name)| (id, String::from(name)))
+                .collect(),
+            list,
+            pending_formats: self.pending_formats.clone(),
+            pending_removals: self.pending_removals.clone(),
+            can_undo_and_redo: self.can_undo_and_redo(),
+        })
+    }
+
+    fn compute_menu_state(&self) -> MenuState {
+        let (s, e) = self.safe_selection();
+        let formats = FormatSet::around(&self.dom, s, e);
+        let partial_formats = FormatSet::within(&self.dom, s, e);
+        let inline_features = [Feature::Bold, Feature::Italic, Feature::Links];
+        let mut active_features: Vec<Feature> = inline_features
+            .into_iter()
+            .filter(|&feature| {
+                let name = element_name(feature);
+                (formats.contains(name)
+                    || self.pending_formats.iter().any(|p| p == name))
+                    && !self.pending_removals.iter().any(|p| p == name)
+            })
+            .collect();
+        let partially_active_features = inline_features
+            .into_iter()
+            .filter(|&feature| {
+                let name = element_name(feature);
+                partial_formats.contains(name) && !formats.contains(name)
+            })
+            .collect();
+        // Only the innermost list counts, as that is the one toggled
+        if let Some(list) = self.dom.list_at(s) {
+            active_features.push(match self.dom.node(list) {
+                DomNode::Container(c) if c.name() == "ol" => {
+                    Feature::OrderedList
+                }
+                _ => Feature::UnorderedList,
+            });
+        }
+        let (can_undo, can_redo) = self.can_undo_and_redo();
+        MenuState::Update {
+            active_features,
+            partially_active_features,
+            can_undo,
+            can_redo,
+        }
+    }
+
+    fn can_undo_and_redo(&self) -> (bool, bool) {
+        (
+            !self.history.undo_entries().is_empty(),
+            !self.history.redo_entries().is_empty(),
+        )
+    }
+
+    /**
+     * If the cursor is at the end of a word starting with a pattern key
+     * (e.g. "@al"), suggest completing it.
+     */
+    fn compute_menu_action(&self) -> MenuAction<C> {
+        let (s, e) = self.safe_selection();
+        if s != e {
+            return MenuAction::None;
+        }
+        let (id, offset) = match self.dom.text_node_at(s) {
+            Some(found) => found,
+            None => return MenuAction::None,
+        };
+        let data = match self.dom.node(id) {
+            DomNode::Text(text) => &text.data()[..offset],
+            DomNode::Container(_) => return MenuAction::None,
+        };
+        let word_start = data
+            .iter()
+            .rposition(|c| {
+                c.to_ascii().map_or(false, |c| c.is_ascii_whitespace())
+            })
+            .map_or(0, |i| i + 1);
+        let start = s - offset + word_start;
+        let key = data
+            .get(word_start)
+            .and_then(|c| c.to_ascii())
+            .and_then(PatternKey::from_ascii);
+        match key {
+            Some(PatternKey::Slash) if start != 0 => MenuAction::None,
+            Some(key) => MenuAction::Suggestion(SuggestionPattern {
+                key,
+                text: data[word_start + 1..].to_vec(),
+                start: Location::from(start),
+                end: Location::from(s),
+            }),
+            None => MenuAction::None,
+        }
+    }
+
+    /**
+     * Describe an edit made since the content was before. If only the text
+     * of one text node changed, the platform can patch just that node,
+     * which preserves its own caret and IME state.
+     */
+    fn create_update_for_edit(&mut self, before: &Dom<C>) -> ComposerUpdate<C> {
+        if !self.diagnostics.is_empty() || self.error.is_some() {
+            return self.create_update_replace_all();
+        }
+        if self.dom.revision() == before.revision() {
+            return self.unchanged();
+        }
+        if let Some(id) = self.dom.changed_text_node(before) {
+            if let DomNode::Text(text) = self.dom.node(id) {
+                return self.with_menu(ComposerUpdate::update_text_node(
+                    self.dom.handle_of(id),
+                    text.data().to_vec(),
+                    self.start,
+                    self.end,
+                ));
+            }
+        }
+        self.create_update_replace_all()
+    }
+}
+
+/**
+ * The element that applies feature to its content.
+ */
+fn element_name(feature: Feature) -> &'static str {
+    ELEMENTS
+        .iter()
+        .find(|spec| spec.feature == Some(feature))
+        .map(|spec| spec.name)
+        .expect("Every feature has an element")
+}
+
+/**
+ * What Tab inserts in a code block.
+ */
+const CODE_INDENT: &str = "    ";
+
+/**
+ * The longest prefix of text that is at most max_len code units long and
+ * does not split a character.
+ */
+fn truncate_to_chars<C>(text: &[C], max_len: usize) -> &[C]
+where
+    C: CodeUnit,
+{
+    if text.len() <= max_len {
+        return text;
+    }
+    let mut len = 0;
+    for ch in C::decode(text).chars() {
+        let ch_len = C::encode(ch.encode_utf8(&mut [0; 4])).len();
+        if len + ch_len > max_len {
+            break;
+        }
+        len += ch_len;
+    }
+    &text[..len]
+}
+
+/**
+ * Where old and new differ, as the length of the start they have in common
+ * and the ends of the parts after that which differ, in old and then new,
+ * leaving out the end they have in common. Characters are never split.
+ */
+fn changed_range<C>(old: &[C], new: &[C]) -> (usize, usize, usize)
+where
+    C: CodeUnit,
+{
+    let char_lens = |text: &[C]| -> Vec<usize> {
+        C::decode(text)
+            .chars()
+            .map(|ch| C::encode(ch.encode_utf8(&mut [0; 4])).len())
+            .collect()
+    };
+    let (old_lens, new_lens) = (char_lens(old), char_lens(new));
+    let mut prefix = 0;
+    for (&old_len, &new_len) in old_lens.iter().zip(&new_lens) {
+        if old_len != new_len
+            || old[prefix..prefix + old_len] != new[prefix..prefix + new_len]
+        {
+            break;
+        }
+        prefix += old_len;
+    }
+    let (mut old_end, mut new_end) = (old.len(), new.len());
+    for (&old_len, &new_len) in old_lens.iter().rev().zip(new_lens.iter().rev())
+    {
+        if old_end - old_len < prefix
+            || new_end - new_len < prefix
+            || old_len != new_len
+            || old[old_end - old_len..old_end]
+                != new[new_end - new_len..new_end]
+        {
+            break;
+        }
+        old_end -= old_len;
+        new_end -= new_len;
+    }
+    (prefix, old_end, new_end)
+}
+
+/**
+ * Split text at each newline ("\n", "\r\n" or "\r"), which is not
+ * included in the pieces.
+ */
+fn split_lines<C>(text: &[C]) -> Vec<&[C]>
+where
+    C: CodeUnit,
+{
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    let mut i = 0;
+    while i < text.len() {
+        match text[i].to_ascii() {
+            Some(b'\n') => {
+                lines.push(&text[line_start..i]);
+                line_start = i + 1;
+            }
+            Some(b'\r') => {
+                lines.push(&text[line_start..i]);
+                if text.get(i + 1).and_then(|c| c.to_ascii()) == Some(b'\n') {
+                    i += 1;
+                }
+                line_start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    lines.push(&text[line_start..]);
+    lines
+}
+
+fn is_single_newline<C>(text: &[C]) -> bool
+where
+    C: CodeUnit,
+{
+    let lines = split_lines(text);
+    lines.len() == 2 && lines.iter().all(|line| line.is_empty())
+}
+
+/**
+ * If text ends with markdown emphasis (**strong** or *em*), return the
+ * offset of its opening marker and the length of its markers.
+ */
+fn find_markdown_emphasis<C>(text: &[C]) -> Option<(usize, usize)>
+where
+    C: CodeUnit,
+{
+    let ascii: Vec<Option<u8>> = text.iter().map(|c| c.to_ascii()).collect();
+    let is_star = |i: usize| ascii[i] == Some(b'*');
+    let is_space =
+        |i: usize| ascii[i].map_or(false, |c| c.is_ascii_whitespace());
+    let len = ascii.len();
+    if len < 3 || !is_star(len - 1) {
+        return None;
+    }
+    let marker_len = if is_star(len - 2) { 2 } else { 1 };
+    let close = len - marker_len;
+    // The emphasised text must not be empty, or start or end with a space
+    if close == 0 || is_star(close - 1) || is_space(close - 1) {
+        return None;
+    }
+    let open = (0..close - 1).rev().find(|&i| {
+        (0..marker_len).all(|j| is_star(i + j))
+            && !(i > 0 && is_star(i - 1))
+            && !is_star(i + marker_len)
+    })?;
+    let inner = open + marker_len..close;
+    if inner.is_empty() || is_space(inner.start) || inner.clone().any(is_star) {
+        return None;
+    }
+    Some((open, marker_len))
+}
+
+/**
+ * The current time, if there is a clock to tell it. There is none in
+ * WebAssembly, where Instant::now panics.
+ */
+fn now() -> Option<Instant> {
+    if cfg!(target_arch = "wasm32") {
+        None
+    } else {
+        Some(Instant::now())
+    }
+}
+
+/**
+ * The time in milliseconds since the Unix epoch, or None where there is no
+ * clock (wasm).
+ */
+fn wall_clock_ms() -> Option<u64> {
+    if cfg!(target_arch = "wasm32") {
+        None
+    } else {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        u64::try_from(since_epoch.as_millis()).ok()
+    }
+}
+
+impl<C> Default for ComposerModel<C>
+where
+    C: CodeUnit,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use speculoos::{prelude::*, AssertionFailure, Spec};
+
+    use crate::dom::{parse_message_html, Dom, DomNode, NodeId, ELEMENTS};
+    use crate::{
+        ActionRequest, ActionResponse, AnchoredLocation, Clock,
+        ComposerCommand, ComposerEffect, ComposerError, ComposerModelBuilder,
+        Coverage, DomEvent, DomHandle, DomObserver, EnterBehaviour, Feature,
+        Format, HtmlDialect, HtmlViolation, InputEvent, LinkFormatting,
+        Location, MarkdownStyleKind, Mention, MentionFormatting, MenuAction,
+        MenuState, PatternKey, RemovalSeverity, RestoreOptions,
+        SuggestionPattern, TextUpdate, UndoCoalescing, WordDictionary,
+    };
+
+    use super::ComposerModel;
+
+    #[test]
+    fn typing_a_character_into_an_empty_box_appends_it() {
+        let mut model = cm("|");
+        replace_text(&mut model, "v");
+        assert_eq!(tx(&model), "v|");
+    }
+
+    #[test]
+    fn typing_a_character_at_the_end_appends_it() {
+        let mut model = cm("abc|");
+        replace_text(&mut model, "d");
+        assert_eq!(tx(&model), "abcd|");
+    }
+
+    #[test]
+    fn typing_a_character_in_the_middle_inserts_it() {
+        let mut model = cm("|abc");
+        replace_text(&mut model, "Z");
+        assert_eq!(tx(&model), "Z|abc");
+    }
+
+    #[test]
+    fn selecting_past_the_end_is_harmless() {
+        let mut model = cm("|");
+        model.select(Location::from(7), Location::from(7));
+        replace_text(&mut model, "Z");
+        assert_eq!(tx(&model), "Z|");
+    }
+
+    #[test]
+    fn replacing_a_selection_with_a_character() {
+        let mut model = cm("abc{def}|ghi");
+        replace_text(&mut model, "Z");
+        assert_eq!(tx(&model), "abcZ|ghi");
+    }
+
+    #[test]
+    fn replacing_a_backwards_selection_with_a_character() {
+        let mut model = cm("abc|{def}ghi");
+        replace_text(&mut model, "Z");
+        assert_eq!(tx(&model), "abcZ|ghi");
+    }
+
+    #[test]
+    fn typing_a_character_after_a_multi_codepoint_character() {
+        // Woman Astronaut:
+        // Woman+Dark Skin Tone+Zero Width Joiner+Rocket
+        let mut model = cm("\u{1F469}\u{1F3FF}\u{200D}\u{1F680}|");
+        replace_text(&mut model, "Z");
+        assert_eq!(tx(&model), "\u{1F469}\u{1F3FF}\u{200D}\u{1F680}Z|");
+    }
+
+    #[test]
+    fn typing_a_character_in_a_range_inserts_it() {
+        let mut model = cm("0123456789|");
+        let new_text = "654".encode_utf16().collect::<Vec<u16>>();
+        model.replace_text_in(&new_text, 4, 7);
+        assert_eq!(tx(&model), "0123654|789");
+    }
+
+    #[test]
+    fn backspacing_a_character_at_the_end_deletes_it() {
+        let mut model = cm("abc|");
+        model.backspace();
+        assert_eq!(tx(&model), "ab|");
+    }
+
+    #[test]
+    fn backspacing_a_character_at_the_beginning_does_nothing() {
+        let mut model = cm("|abc");
+        model.backspace();
+        assert_eq!(tx(&model), "|abc");
+    }
+
+    #[test]
+    fn backspacing_a_character_in_the_middle_deletes_it() {
+        let mut model = cm("ab|c");
+        model.backspace();
+        assert_eq!(tx(&model), "a|c");
+    }
+
+    #[test]
+    fn backspacing_a_selection_deletes_it() {
+        let mut model = cm("a{bc}|");
+        model.backspace();
+        assert_eq!(tx(&model), "a|");
+    }
+
+    #[test]
+    fn backspacing_a_backwards_selection_deletes_it() {
+        let mut model = cm("a|{bc}");
+        model.backspace();
+        assert_eq!(tx(&model), "a|");
+    }
+
+    #[test]
+    fn deleting_a_character_at_the_end_does_nothing() {
+        let mut model = cm("abc|");
+        model.delete();
+        assert_eq!(tx(&model), "abc|");
+    }
+
+    #[test]
+    fn deleting_a_character_at_the_beginning_deletes_it() {
+        let mut model = cm("|abc");
+        model.delete();
+        assert_eq!(tx(&model), "|bc");
+    }
+
+    #[test]
+    fn deleting_a_character_in_the_middle_deletes_it() {
+        let mut model = cm("a|bc");
+        model.delete();
+        assert_eq!(tx(&model), "a|c");
+    }
+
+    #[test]
+    fn deleting_a_selection_deletes_it() {
+        let mut model = cm("a{bc}|");
+        model.delete();
+        assert_eq!(tx(&model), "a|");
+    }
+
+    #[test]
+    fn deleting_a_backwards_selection_deletes_it() {
+        let mut model = cm("a|{bc}");
+        model.delete();
+        assert_eq!(tx(&model), "a|");
+    }
+
+    #[test]
+    fn deleting_a_range_removes_it() {
+        let mut model = cm("abcd|");
+        model.delete_in(1, 3);
+        assert_eq!(tx(&model), "a|d");
+    }
+
+    #[test]
+    fn selecting_ascii_characters() {
+        let mut model = cm("abcdefgh|");
+        model.select(Location::from(0), Location::from(1));
+        assert_eq!(tx(&model), "{a}|bcdefgh");
+
+        model.select(Location::from(1), Location::from(3));
+        assert_eq!(tx(&model), "a{bc}|defgh");
+
+        model.select(Location::from(4), Location::from(8));
+        assert_eq!(tx(&model), "abcd{efgh}|");
+
+        model.select(Location::from(4), Location::from(9));
+        assert_eq!(tx(&model), "abcd{efgh}|");
+    }
+
+    #[test]
+    fn selecting_single_utf16_code_unit_characters() {
+        let mut model = cm("\u{03A9}\u{03A9}\u{03A9}|");
+
+        model.select(Location::from(0), Location::from(1));
+        assert_eq!(tx(&model), "{\u{03A9}}|\u{03A9}\u{03A9}");
+
+        model.select(Location::from(0), Location::from(3));
+        assert_eq!(tx(&model), "{\u{03A9}\u{03A9}\u{03A9}}|");
+
+        model.select(Location::from(1), Location::from(2));
+        assert_eq!(tx(&model), "\u{03A9}{\u{03A9}}|\u{03A9}");
+    }
+
+    #[test]
+    fn selecting_multiple_utf16_code_unit_characters() {
+        let mut model = cm("\u{1F4A9}\u{1F4A9}\u{1F4A9}|");
+
+        model.select(Location::from(0), Location::from(2));
+        assert_eq!(tx(&model), "{\u{1F4A9}}|\u{1F4A9}\u{1F4A9}");
+
+        model.select(Location::from(0), Location::from(6));
+        assert_eq!(tx(&model), "{\u{1F4A9}\u{1F4A9}\u{1F4A9}}|");
+
+        model.select(Location::from(2), Location::from(4));
+        assert_eq!(tx(&model), "\u{1F4A9}{\u{1F4A9}}|\u{1F4A9}");
+    }
+
+    #[test]
+    fn selecting_complex_characters() {
+        let mut model =
+            cm("aaa\u{03A9}bbb\u{1F469}\u{1F3FF}\u{200D}\u{1F680}ccc|");
+
+        model.select(Location::from(0), Location::from(3));
+        assert_eq!(
+            tx(&model),
+            "{aaa}|\u{03A9}bbb\u{1F469}\u{1F3FF}\u{200D}\u{1F680}ccc"
+        );
+
+        model.select(Location::from(0), Location::from(4));
+        assert_eq!(
+            tx(&model),
+            "{aaa\u{03A9}}|bbb\u{1F469}\u{1F3FF}\u{200D}\u{1F680}ccc"
+        );
+
+        model.select(Location::from(7), Location::from(14));
+        assert_eq!(
+            tx(&model),
+            "aaa\u{03A9}bbb{\u{1F469}\u{1F3FF}\u{200D}\u{1F680}}|ccc"
+        );
+
+        model.select(Location::from(7), Location::from(15));
+        assert_eq!(
+            tx(&model),
+            "aaa\u{03A9}bbb{\u{1F469}\u{1F3FF}\u{200D}\u{1F680}c}|cc"
+        );
+    }
+
+    #[test]
+    fn bolding_ascii_adds_strong_tags() {
+        let mut model = cm("aa{bb}|cc");
+        model.bold();
+        assert_eq!(tx(&model), "aa{<strong>bb}|</strong>cc");
+
+        let mut model = cm("aa|{bb}cc");
+        model.bold();
+        assert_eq!(tx(&model), "aa|{<strong>bb}</strong>cc");
+    }
+
+    #[test]
+    fn copied_formatting_is_applied_to_another_selection() {
+        let mut model = cm("<strong><em>a|</em></strong> b c");
+        model.copy_formatting();
+        model.select(Location::from(2), Location::from(3));
+        model.apply_copied_formatting();
+        assert_eq!(
+            tx(&model),
+            "<strong><em>a</em></strong> {<strong><em>b}|</em></strong> c"
+        );
+    }
+
+    #[test]
+    fn applying_copied_formatting_keeps_existing_formatting() {
+        let mut model = cm("<u>a|</u> <em>b</em>");
+        model.copy_formatting();
+        model.select(Location::from(2), Location::from(3));
+        model.apply_copied_formatting();
+        assert_eq!(tx(&model), "<u>a</u> {<u><em>b}|</em></u>");
+    }
+
+    #[test]
+    fn copied_formatting_is_not_nested_in_itself() {
+        let mut model = cm("<strong>x|</strong> a<strong>bc</strong>de");
+        model.copy_formatting();
+        model.select(Location::from(2), Location::from(7));
+        model.apply_copied_formatting();
+        assert_eq!(tx(&model), "<strong>x</strong> {<strong>abcde}|</strong>");
+    }
+
+    #[test]
+    fn a_range_can_be_asked_how_much_of_it_has_a_format() {
+        let model = cm("a<u>b|c</u>");
+        let coverage = |start, end, format| {
+            model.is_range_formatted(
+                Location::from(start),
+                Location::from(end),
+                format,
+            )
+        };
+        assert_eq!(coverage(1, 3, Format::Underline), Coverage::Full);
+        assert_eq!(coverage(3, 0, Format::Underline), Coverage::Partial);
+        assert_eq!(coverage(0, 1, Format::Underline), Coverage::None);
+        assert_eq!(coverage(1, 3, Format::Bold), Coverage::None);
+    }
+
+    #[test]
+    fn several_formats_are_applied_in_one_edit_in_their_order() {
+        let mut model = cm("a{b}|<em>c</em>");
+        model.apply_formats(&[
+            Format::InlineCode,
+            Format::Underline,
+            Format::Italic,
+            Format::Bold,
+            Format::Underline,
+        ]);
+        assert_eq!(
+            tx(&model),
+            "a{<strong><em><u><code>b}|</code></u></em></strong><em>c</em>"
+        );
+        model.undo();
+        assert_eq!(tx(&model), "a{b}|<em>c</em>");
+    }
+
+    #[test]
+    fn formats_on_part_of_the_selection_are_not_nested_in_themselves() {
+        let mut model = cm("{a<strong>bc</strong>de}|");
+        model.apply_formats(&[Format::Bold]);
+        assert_eq!(tx(&model), "<strong>{abcde}|</strong>");
+    }
+
+    #[test]
+    fn applying_formats_keeps_those_already_applied() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().features(vec![Feature::Italic]),
+            "<em>{a}|</em>",
+        );
+        model.apply_formats(&[Format::Bold, Format::Italic, Format::Underline]);
+        assert_eq!(tx(&model), "<u><em>{a}|</em></u>");
+        model.select(Location::from(1), Location::from(1));
+        model.apply_formats(&[Format::Strikethrough, Format::Underline]);
+        replace_text(&mut model, "b");
+        assert_eq!(tx(&model), "<u><em>a<del>b|</del></em></u>");
+    }
+
+    #[test]
+    fn links_are_not_copied_as_formatting() {
+        let mut model = cm("<a href=\"https://x.org\">a|</a> b");
+        model.copy_formatting();
+        model.select(Location::from(2), Location::from(3));
+        let update = model.apply_copied_formatting();
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+    }
+
+    #[test]
+    fn bolding_across_formatting_wraps_each_part() {
+        let mut model = cm("a<em>b{c</em>d}|e");
+        model.bold();
+        assert_eq!(
+            tx(&model),
+            "a<em>b{<strong>c</strong></em><strong>d}|</strong>e"
+        );
+    }
+
+    #[test]
+    fn bolding_part_of_a_link_formats_inside_it_by_default() {
+        let mut model = cm("{a<a href=\"x\">b}|c</a>");
+        model.bold();
+        assert_eq!(
+            tx(&model),
+            "<strong>{a</strong><a href=\"x\"><strong>b}|</strong>c</a>"
+        );
+    }
+
+    #[test]
+    fn bolding_part_of_a_link_can_split_the_link() {
+        let mut model = with_config(
+            ComposerModelBuilder::new()
+                .link_formatting(LinkFormatting::SplitLink),
+            "{a<a href=\"x\">b}|c</a>",
+        );
+        model.bold();
+        assert_eq!(
+            tx(&model),
+            "<strong>{a<a href=\"x\">b</a>}|</strong><a href=\"x\">c</a>"
+        );
+    }
+
+    #[test]
+    fn typing_after_bolding_continues_in_bold() {
+        let mut model = cm("aa{bb}|cc");
+        model.bold();
+        model.select(Location::from(4), Location::from(4));
+        replace_text(&mut model, "X");
+        assert_eq!(tx(&model), "aa<strong>bbX|</strong>cc");
+    }
+
+    #[test]
+    fn bolding_an_empty_composer_makes_typed_text_bold() {
+        let mut model = cm("|");
+        let update = model.bold();
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(
+            update.menu_state,
+            MenuState::Update {
+                active_features: vec![Feature::Bold],
+                partially_active_features: vec![],
+                can_undo: false,
+                can_redo: false,
+            }
+        );
+        assert_eq!(tx(&model), "|");
+        replace_text(&mut model, "typed");
+        assert_eq!(tx(&model), "<strong>typed|</strong>");
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_message_html()).unwrap(),
+            "<strong>typed</strong>"
+        );
+    }
+
+    #[test]
+    fn every_inline_format_can_be_pending() {
+        for name in ELEMENTS.iter().filter(|s| s.format).map(|s| s.name) {
+            let mut model = cm("|");
+            model.format(name);
+            assert_eq!(tx(&model), "|");
+            replace_text(&mut model, "typed");
+            assert_eq!(tx(&model), format!("<{0}>typed|</{0}>", name));
+        }
+    }
+
+    #[test]
+    fn pending_formats_nest_in_the_order_they_were_toggled() {
+        let mut model = cm("a|");
+        model.format("em");
+        model.bold();
+        replace_text(&mut model, "b");
+        assert_eq!(tx(&model), "a<em><strong>b|</strong></em>");
+    }
+
+    #[test]
+    fn bolding_twice_with_an_empty_selection_cancels_it() {
+        let mut model = cm("|");
+        model.bold();
+        let update = model.bold();
+        assert_eq!(
+            update.menu_state,
+            MenuState::Update {
+                active_features: vec![],
+                partially_active_features: vec![],
+                can_undo: false,
+                can_redo: false,
+            }
+        );
+        replace_text(&mut model, "a");
+        assert_eq!(tx(&model), "a|");
+    }
+
+    #[test]
+    fn pending_formats_are_dropped_by_moving_or_other_edits() {
+        let mut model = cm("ab|");
+        model.bold();
+        model.select(Location::from(1), Location::from(1));
+        replace_text(&mut model, "x");
+        assert_eq!(tx(&model), "ax|b");
+
+        let mut model = cm("ab|");
+        model.bold();
+        model.backspace();
+        replace_text(&mut model, "x");
+        assert_eq!(tx(&model), "ax|");
+    }
+
+    #[test]
+    fn formats_at_the_cursor_can_be_turned_off_for_what_is_typed_next() {
+        let mut model = cm("<strong>a|b</strong>");
+        let update = model.bold();
+        assert_eq!(
+            update.menu_state,
+            MenuState::Update {
+                active_features: vec![],
+                partially_active_features: vec![],
+                can_undo: false,
+                can_redo: false,
+            }
+        );
+        replace_text(&mut model, "x");
+        replace_text(&mut model, "y");
+        assert_eq!(tx(&model), "<strong>a</strong>xy|<strong>b</strong>");
+
+        let mut model = cm("<strong>a|</strong>");
+        model.bold();
+        model.bold();
+        replace_text(&mut model, "b");
+        assert_eq!(tx(&model), "<strong>ab|</strong>");
+    }
+
+    #[test]
+    fn enter_in_a_list_item_carries_the_rest_into_a_new_item() {
+        let mut model = with_config(
+            ComposerModelBuilder::new()
+                .enter_behaviour(EnterBehaviour::NewLine),
+            "<ul><li>a<strong>b|c</strong>d</li></ul>",
+        );
+        model.enter();
+        assert_eq!(
+            tx(&model),
+            "<ul><li>a<strong>b|</strong></li><li><strong>c</strong>d</li></ul>"
+        );
+        replace_text(&mut model, "x");
+        assert_eq!(
+            tx(&model),
+            "<ul><li>a<strong>b</strong></li><li><strong>x|c</strong>d</li></ul>"
+        );
+    }
+
+    #[test]
+    fn enter_at_the_end_of_a_list_item_starts_an_empty_one() {
+        let mut model = with_config(
+            ComposerModelBuilder::new()
+                .enter_behaviour(EnterBehaviour::NewLine),
+            "<ol><li>a|</li></ol>",
+        );
+        model.enter();
+        assert_eq!(tx(&model), "<ol><li>a|</li><li></li></ol>");
+        replace_text(&mut model, "b");
+        assert_eq!(tx(&model), "<ol><li>a</li><li>b|</li></ol>");
+        model.enter();
+        model.enter();
+        replace_text(&mut model, "c");
+        assert_eq!(
+            tx(&model),
+            "<ol><li>a</li><li>b</li><li></li><li>c|</li></ol>"
+        );
+    }
+
+    #[test]
+    fn enter_in_a_list_item_replaces_the_selection() {
+        let mut model = with_config(
+            ComposerModelBuilder::new()
+                .enter_behaviour(EnterBehaviour::NewLine),
+            "<ul><li>a{bc}|d</li></ul>",
+        );
+        model.enter();
+        assert_eq!(tx(&model), "<ul><li>a|</li><li>d</li></ul>");
+        model.undo();
+        assert_eq!(tx(&model), "<ul><li>a{bc}|d</li></ul>");
+    }
+
+    #[test]
+    fn typed_html_is_escaped() {
+        let mut model = cm("|");
+        replace_text(&mut model, "<b>&");
+        assert_eq!(tx(&model), "&lt;b&gt;&amp;|");
+        assert_eq!(model.dom.text_len(), 4);
+    }
+
+    #[test]
+    fn setting_content_from_html_parses_it() {
+        let mut model = cm("|");
+        model.set_content_from_html(&utf16("a<b>b</b>c"));
+        assert_eq!(tx(&model), "|a<strong>b</strong>c");
+    }
+
+    #[test]
+    fn setting_content_can_keep_the_selection_on_the_same_text() {
+        let mut model = cm("Hello {world}|, how are you?");
+        model.set_content_from_html_keeping_selection(&utf16(
+            "Oh! Hello <em>world</em>, how are you today?",
+        ));
+        assert_eq!(
+            tx(&model),
+            "Oh! Hello {<em>world}|</em>, how are you today?"
+        );
+        assert_eq!(model.history.take_undo().map(|_| ()), None);
+    }
+
+    #[test]
+    fn setting_content_keeps_a_backwards_selection_backwards() {
+        let mut model = cm("ab|{cd}e");
+        model.set_content_from_html_keeping_selection(&utf16("xxabcde"));
+        assert_eq!(tx(&model), "xxab|{cd}e");
+    }
+
+    #[test]
+    fn content_can_be_retrieved_in_each_dialect() {
+        let model = cm("a<strong>b</strong>c<em></em>|");
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_message_html()).unwrap(),
+            "a<strong>b</strong>c"
+        );
+        assert_eq!(
+            String::from_utf16(&model.get_html()).unwrap(),
+            "a<strong>b</strong>c<em>\u{200B}</em>"
+        );
+        assert_eq!(
+            String::from_utf16(
+                &model.get_content_as_html(HtmlDialect::Preview)
+            )
+            .unwrap(),
+            "a<b>b</b>c<i></i>"
+        );
+    }
+
+    #[test]
+    fn editor_html_can_be_loaded_again_without_its_placeholders() {
+        let mut model = cm("a<del>Q</del><strong></strong><em></em>d|");
+        let html = model.get_html();
+        model.set_content_from_html(&html);
+        assert_eq!(model.get_content_as_plain_text(false), utf16("aQd"));
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_message_html()).unwrap(),
+            "a<del>Q</del><strong></strong><em></em>d"
+        );
+        assert_eq!(model.get_html(), html);
+    }
+
+    #[test]
+    fn visible_length_counts_characters_not_code_units() {
+        let model = cm("<strong>e\u{301}</strong>\u{1F469}\u{1F3FF}<br />x|");
+        assert_eq!(model.visible_length(), 4);
+        assert_eq!(
+            model.html_length(),
+            "<strong>e\u{301}</strong>\u{1F469}\u{1F3FF}<br />x"
+                .encode_utf16()
+                .count()
+        );
+    }
+
+    #[test]
+    fn decomposed_input_is_composed_when_configured() {
+        let mut model =
+            with_config(ComposerModelBuilder::new().normalize_input(true), "|");
+        replace_text(&mut model, "cafe\u{301}!");
+        assert_eq!(tx(&model), "caf\u{e9}!|");
+        assert_eq!(
+            model.get_selection(),
+            (Location::from(5), Location::from(5))
+        );
+    }
+
+    #[test]
+    fn input_is_not_normalized_by_default() {
+        let mut model = cm("|");
+        replace_text(&mut model, "e\u{301}");
+        assert_eq!(
+            model.get_selection(),
+            (Location::from(2), Location::from(2))
+        );
+    }
+
+    #[test]
+    fn two_quick_spaces_after_a_word_become_a_full_stop() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().double_space_full_stop(500),
+            "hi|",
+        );
+        replace_text_at_time(&mut model, " ", 1000);
+        replace_text_at_time(&mut model, " ", 1300);
+        assert_eq!(tx(&model), "hi. |");
+        model.undo();
+        assert_eq!(tx(&model), "hi  |");
+        model.undo();
+        assert_eq!(tx(&model), "hi |");
+    }
+
+    #[test]
+    fn a_full_stop_is_reported_after_the_space_it_replaces() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().double_space_full_stop(500),
+            "hi|",
+        );
+        model.replace_text_at_time(&utf16(" "), 1000);
+        let update = model.replace_text_at_time(&utf16(" "), 1300);
+        let texts: Vec<String> = update
+            .effects()
+            .into_iter()
+            .filter_map(|effect| match effect {
+                ComposerEffect::TextUpdated(TextUpdate::UpdateTextNode(u)) => {
+                    Some(String::from_utf16(&u.new_text).unwrap())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts, ["hi  ", "hi. "]);
+        match update.text_update {
+            TextUpdate::UpdateTextNode(u) => {
+                assert_eq!(String::from_utf16(&u.new_text).unwrap(), "hi. ")
+            }
+            _ => panic!("Expected the full stop to patch the text node"),
+        }
+    }
+
+    #[test]
+    fn each_effect_of_an_update_is_listed_once() {
+        let mut model = cm("|");
+        let update = model.paste_html(&utf16("<em>a</em><marquee>b</marquee>"));
+        let effects = update.effects();
+        assert!(matches!(effects[0], ComposerEffect::TextUpdated(_)));
+        assert_eq!(
+            effects
+                .iter()
+                .filter(|e| matches!(e, ComposerEffect::Sanitized(_)))
+                .count(),
+            1
+        );
+        assert!(effects
+            .iter()
+            .any(|e| matches!(e, ComposerEffect::MenuActionChanged(_))));
+    }
+
+    #[test]
+    fn slow_spaces_are_left_alone() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().double_space_full_stop(500),
+            "hi|",
+        );
+        replace_text_at_time(&mut model, " ", 1000);
+        replace_text_at_time(&mut model, " ", 1600);
+        assert_eq!(tx(&model), "hi  |");
+    }
+
+    #[test]
+    fn spaces_not_after_a_word_are_left_alone() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().double_space_full_stop(500),
+            "hi.|",
+        );
+        replace_text_at_time(&mut model, " ", 1000);
+        replace_text_at_time(&mut model, " ", 1100);
+        replace_text_at_time(&mut model, " ", 1200);
+        assert_eq!(tx(&model), "hi.   |");
+    }
+
+    #[test]
+    fn double_spaces_are_left_alone_unless_configured() {
+        let mut model = cm("hi|");
+        replace_text_at_time(&mut model, " ", 1000);
+        replace_text_at_time(&mut model, " ", 1100);
+        assert_eq!(tx(&model), "hi  |");
+    }
+
+    #[test]
+    fn moving_the_cursor_between_spaces_prevents_a_full_stop() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().double_space_full_stop(500),
+            "ab|",
+        );
+        replace_text_at_time(&mut model, " ", 1000);
+        model.select(Location::from(3), Location::from(3));
+        replace_text_at_time(&mut model, " ", 1100);
+        assert_eq!(tx(&model), "ab  |");
+    }
+
+    #[test]
+    fn typing_a_newline_when_enter_adds_lines_inserts_a_line_break() {
+        let mut model = with_config(
+            ComposerModelBuilder::new()
+                .enter_behaviour(EnterBehaviour::NewLine),
+            "ab|",
+        );
+        replace_text(&mut model, "\n");
+        assert_eq!(tx(&model), "ab<br />|");
+    }
+
+    #[test]
+    fn typing_a_newline_when_enter_sends_changes_nothing() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().enter_behaviour(EnterBehaviour::Send),
+            "ab|",
+        );
+        replace_text(&mut model, "\r\n");
+        assert_eq!(tx(&model), "ab|");
+        replace_text(&mut model, "c\nd");
+        assert_eq!(tx(&model), "abc<br />d|");
+    }
+
+    #[test]
+    fn pasting_lines_inserts_line_breaks_between_them() {
+        let mut model = cm("a|b");
+        replace_text(&mut model, "x\r\ny\nz\rw");
+        assert_eq!(tx(&model), "ax<br />y<br />z<br />w|b");
+    }
+
+    #[test]
+    fn blank_lines_become_consecutive_line_breaks() {
+        let mut model = cm("<strong>a{b}|</strong>");
+        replace_text(&mut model, "\r\n\r\nc");
+        assert_eq!(tx(&model), "<strong>a<br /><br />c|</strong>");
+    }
+
+    #[test]
+    fn inserting_at_a_location_keeps_the_selection_on_its_content() {
+        let mut model = cm("ab{cd}|ef");
+        insert_at(&mut model, 1, "<em>x</em>");
+        assert_eq!(tx(&model), "a<em>x</em>b{cd}|ef");
+        insert_at(&mut model, 6, "y");
+        assert_eq!(tx(&model), "a<em>x</em>b{cd}|eyf");
+    }
+
+    #[test]
+    fn inserting_at_the_cursor_moves_it_after_the_content() {
+        let mut model = cm("ab|");
+        insert_at(&mut model, 2, "cd");
+        assert_eq!(tx(&model), "abcd|");
+    }
+
+    #[test]
+    fn inserting_inside_the_selection_inserts_after_it() {
+        let mut model = cm("a{bcd}|e");
+        insert_at(&mut model, 2, "x");
+        assert_eq!(tx(&model), "a{bcd}|xe");
+        model.delete();
+        assert_eq!(tx(&model), "a|xe");
+    }
+
+    #[test]
+    fn inserted_content_is_sanitized() {
+        let mut model = cm("a|");
+        insert_at(&mut model, 0, "<marquee onclick=\"x\">b</marquee>");
+        assert_eq!(tx(&model), "ba|");
+    }
+
+    #[test]
+    fn inserted_content_loses_formatting_whose_feature_is_disabled() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().features(vec![Feature::Italic]),
+            "a|",
+        );
+        insert_at(
+            &mut model,
+            1,
+            "<strong>b<em>c</em></strong><ol><li>d</li><li>e</li></ol>",
+        );
+        assert_eq!(tx(&model), "ab<em>c</em><p>d</p><p>e|</p>");
+    }
+
+    #[test]
+    fn inserted_content_is_cut_off_at_the_max_length() {
+        let mut model =
+            with_config(ComposerModelBuilder::new().max_length(4), "a|");
+        insert_at(&mut model, 0, "<strong>b</strong>c\u{1F600}");
+        assert_eq!(tx(&model), "<strong>b</strong>ca|");
+        insert_at(&mut model, 0, "xy");
+        assert_eq!(tx(&model), "<strong>xb</strong>ca|");
+    }
+
+    #[test]
+    fn pasting_reports_what_was_removed() {
+        let mut model = cm("|");
+        let update =
+            model.paste_html(&utf16("<p class=\"x\">a<script>b</script></p>"));
+        assert_eq!(tx(&model), "<p>a|</p>");
+        assert_eq!(
+            update.sanitize_report.severity(),
+            Some(RemovalSeverity::Dangerous)
+        );
+        assert_eq!(update.sanitize_report.removals.len(), 2);
+
+        let update = model.paste_html(&utf16("<section>c</section>"));
+        assert!(!update.sanitize_report.is_meaningful());
+        let update = model.replace_text(&utf16("d"));
+        assert_eq!(update.sanitize_report.severity(), None);
+    }
+
+    #[test]
+    fn pasting_html_replaces_the_selection_with_cleaned_content() {
+        let mut model = cm("a{b}|c");
+        model.paste_html(&utf16(
+            "<b style=\"font-weight:normal\" id=\"docs-internal-guid-1\">\
+            <span style=\"font-weight:700\">x</span>y</b>",
+        ));
+        assert_eq!(tx(&model), "a<strong>x</strong>y|c");
+        model.undo();
+        assert_eq!(tx(&model), "a{b}|c");
+    }
+
+    #[test]
+    fn message_html_omits_what_matrix_does_not_allow() {
+        let model = cm("a<marquee>b</marquee><u style=\"x\">c</u>|");
+        assert_eq!(
+            model.validate_message_html(),
+            vec![
+                HtmlViolation::Element {
+                    name: String::from("marquee")
+                },
+                HtmlViolation::Attribute {
+                    element: String::from("u"),
+                    attribute: String::from("style"),
+                },
+            ]
+        );
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_message_html()).unwrap(),
+            "ab<u>c</u>"
+        );
+        assert_eq!(tx(&model), "a<marquee>b</marquee><u style=\"x\">c|</u>");
+    }
+
+    #[test]
+    fn trimmed_content_leaves_the_editing_buffer_alone() {
+        let model = cm("<br /><p> hi</p><br />|");
+        assert_eq!(
+            String::from_utf16(&model.get_content_trimmed()).unwrap(),
+            "<p>hi</p>"
+        );
+        assert_eq!(tx(&model), "<br /><p> hi</p><br />|");
+    }
+
+    #[test]
+    fn text_in_a_range_is_the_visible_text() {
+        let model = cm("<p>a<strong>b{c</strong></p><p>d}|e</p>");
+        assert_eq!(
+            String::from_utf16(
+                &model.text_in_range(Location::from(2), Location::from(4))
+            )
+            .unwrap(),
+            "c\nd"
+        );
+        assert_eq!(
+            String::from_utf16(
+                &model.text_in_range(Location::from(9), Location::from(3))
+            )
+            .unwrap(),
+            "de"
+        );
+    }
+
+    #[test]
+    fn char_at_returns_whole_characters_only() {
+        let model = cm("a\u{1F469}<br />b|");
+        assert_eq!(model.char_at(Location::from(0)), Some('a'));
+        assert_eq!(model.char_at(Location::from(1)), Some('\u{1F469}'));
+        assert_eq!(model.char_at(Location::from(2)), None);
+        assert_eq!(model.char_at(Location::from(3)), None);
+        assert_eq!(model.char_at(Location::from(4)), Some('b'));
+        assert_eq!(model.char_at(Location::from(5)), None);
+    }
+
+    #[test]
+    fn rendered_html_follows_every_kind_of_change() {
+        let mut model = cm("a|");
+        assert_eq!(model.get_html(), utf16("a"));
+        replace_text(&mut model, "b");
+        assert_eq!(model.get_html(), utf16("ab"));
+        model.undo();
+        assert_eq!(model.get_html(), utf16("a"));
+        model.set_content_from_html(&utf16("c"));
+        assert_eq!(model.get_html(), utf16("c"));
+        model.dom.insert_text(1, &utf16("d"));
+        assert_eq!(model.get_html(), utf16("cd"));
+    }
+
+    #[test]
+    fn serializing_into_a_buffer_reuses_it() {
+        let mut model = cm("<strong>abc</strong> é|");
+        let mut units = Vec::with_capacity(64);
+        let mut string = String::with_capacity(64);
+        let (units_at, string_at) = (units.as_ptr(), string.as_ptr());
+        for typed in ["d", "e", "f"] {
+            replace_text(&mut model, typed);
+            model.serialize_into(&mut units);
+            model.serialize_into_string(&mut string);
+            assert_eq!(units, model.get_html());
+            assert_eq!(utf16(&string), model.get_html());
+        }
+        assert_eq!(string, "<strong>abc</strong> édef");
+        assert_eq!((units.as_ptr(), string.as_ptr()), (units_at, string_at));
+        let rendered_at = model.rendered_html().as_ptr();
+        model.backspace();
+        assert_eq!(model.rendered_html().as_ptr(), rendered_at);
+    }
+
+    #[test]
+    fn edits_that_change_nothing_keep_the_content() {
+        let mut model = cm("|ab");
+        assert!(matches!(model.backspace().text_update, TextUpdate::Keep));
+        let mut model = cm("ab|");
+        assert!(matches!(model.delete().text_update, TextUpdate::Keep));
+        assert!(matches!(
+            model.delete_in(1, 1).text_update,
+            TextUpdate::Keep
+        ));
+        assert!(matches!(
+            model.replace_text(&[]).text_update,
+            TextUpdate::Keep
+        ));
+        let mut model = cm("|");
+        assert!(matches!(model.backspace().text_update, TextUpdate::Keep));
+        assert!(matches!(model.delete().text_update, TextUpdate::Keep));
+        assert_eq!(model.history.take_undo().map(|_| ()), None);
+    }
+
+    #[test]
+    fn the_word_around_the_cursor_can_be_selected() {
+        let mut model = cm("one tw|o, three");
+        model.select_word_at_cursor();
+        assert_eq!(tx(&model), "one {two}|, three");
+
+        let mut model = cm("one|   two");
+        model.select_word_at_cursor();
+        assert_eq!(tx(&model), "{one}|   two");
+    }
+
+    #[test]
+    fn selected_words_stop_at_line_breaks_and_formatting_is_ignored() {
+        let mut model = cm("ab<br />c<strong>|d</strong>e f");
+        model.select_word_at_cursor();
+        assert_eq!(tx(&model), "ab<br />{c<strong>d</strong>e}| f");
+    }
+
+    #[test]
+    fn the_selection_can_be_extended_to_the_ends_of_a_line() {
+        let mut model = cm("ab<br />c{d}|e<br />f");
+        model.select_to_end_of_block();
+        assert_eq!(tx(&model), "ab<br />c{de}|<br />f");
+        model.select_to_start_of_block();
+        assert_eq!(tx(&model), "ab<br />|{c}de<br />f");
+
+        // The location between the blocks is the end of the first
+        let mut model = cm("<p>ab</p><p>c{d}|e</p>");
+        model.select_to_start_of_block();
+        assert_eq!(
+            model.get_selection(),
+            (Location::from(3), Location::from(2))
+        );
+    }
+
+    #[test]
+    fn the_selection_can_be_extended_to_the_ends_of_the_content() {
+        let mut model = cm("<p>ab</p><p>c{d}|e</p>");
+        model.select_to_end_of_document();
+        assert_eq!(tx(&model), "<p>ab</p><p>c{de}|</p>");
+        model.select_to_start_of_document();
+        assert_eq!(
+            model.get_selection(),
+            (Location::from(3), Location::from(0))
+        );
+    }
+
+    #[test]
+    fn the_revision_goes_up_once_for_each_change_reported() {
+        let mut model = cm("a|");
+        let first = model.revision();
+        assert_eq!(model.revision(), first);
+        assert_eq!(model.replace_text(&utf16("b")).revision, first + 1);
+        assert_eq!(model.bold().revision, first + 1);
+        model.select(Location::from(0), Location::from(0));
+        model.select(Location::from(1), Location::from(1));
+        assert_eq!(model.enter().revision, first + 2);
+        assert_eq!(model.undo().revision, first + 3);
+        assert_eq!(model.fork().revision(), first + 3);
+    }
+
+    struct FirstTwo;
+
+    impl WordDictionary for FirstTwo {
+        fn words(&self, run: &str, locale: Option<&str>) -> Vec<usize> {
+            assert_eq!(locale, Some("ja"));
+            vec![2, run.chars().count() - 2]
+        }
+    }
+
+    #[test]
+    fn a_word_dictionary_finds_words_without_spaces() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().word_locale("ja"),
+            "日|本語です",
+        );
+        model.select_word_at_cursor();
+        assert_eq!(tx(&model), "日{本}|語です");
+
+        model.set_word_dictionary(Arc::new(FirstTwo));
+        model.select(Location::from(1), Location::from(1));
+        model.select_word_at_cursor();
+        assert_eq!(tx(&model), "{日本}|語です");
+    }
+
+    #[test]
+    fn the_placeholder_is_shown_only_while_the_content_is_empty() {
+        let mut model = cm("|");
+        assert_eq!(model.placeholder(), None);
+        model.set_placeholder(&utf16("Message #room"));
+        assert_eq!(
+            model.placeholder(),
+            Some(utf16("Message #room").as_slice())
+        );
+
+        replace_text(&mut model, "a");
+        assert_eq!(model.placeholder(), None);
+        assert_eq!(model.get_html(), utf16("a"));
+        assert_eq!(model.get_content_as_plain_text(true), utf16("a"));
+
+        model.backspace();
+        assert_eq!(model.get_html(), utf16(""));
+        assert_eq!(
+            model.placeholder(),
+            Some(utf16("Message #room").as_slice())
+        );
+
+        model.set_placeholder(&[]);
+        assert_eq!(model.placeholder(), None);
+    }
+
+    #[test]
+    fn deleting_all_of_some_formatted_text_removes_the_formatting() {
+        let mut model = cm("a<strong>{bc}|</strong>d");
+        model.backspace();
+        assert_eq!(tx(&model), "a|d");
+        replace_text(&mut model, "x");
+        assert_eq!(tx(&model), "ax|d");
+
+        let mut model = cm("a<strong><em>b|</em></strong>c");
+        model.backspace();
+        replace_text(&mut model, "x");
+        assert_eq!(tx(&model), "ax|c");
+    }
+
+    #[test]
+    fn deleting_across_formatting_leaves_no_empty_tags() {
+        let mut model = cm("<del>{a</del><em>b}|</em>");
+        model.delete();
+        assert_eq!(model.get_html(), utf16(""));
+        replace_text(&mut model, "x");
+        assert_eq!(tx(&model), "x|");
+    }
+
+    #[test]
+    fn typing_over_formatting_leaves_no_empty_tags() {
+        let mut model = cm("a<del>b</del><em>c</em>d|");
+        model.select(Location::from(1), Location::from(3));
+        replace_text(&mut model, "é");
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_message_html()).unwrap(),
+            "aéd"
+        );
+    }
+
+    #[test]
+    fn deleting_a_paragraphs_text_removes_the_paragraph() {
+        let mut model = ComposerModel::new();
+        model.set_content_from_html(&utf16("<p>one</p><p>two</p>"));
+        model.select(Location::from(3), Location::from(6));
+        model.backspace();
+        assert_eq!(tx(&model), "<p>one|</p>");
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_message_html()).unwrap(),
+            "<p>one</p>"
+        );
+        let html = model.get_content_as_html(HtmlDialect::Editor);
+        model.set_content_from_html(&html);
+        assert_eq!(model.get_content_as_plain_text(false), utf16("one"));
+    }
+
+    #[test]
+    fn pasting_over_formatting_leaves_no_empty_tags() {
+        let mut model = cm("a<del>b</del><em>c</em>d|");
+        model.select(Location::from(1), Location::from(3));
+        model.paste_html(&utf16("z"));
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_message_html()).unwrap(),
+            "azd"
+        );
+    }
+
+    #[test]
+    fn formatting_stays_pending_after_deleting_formatted_text() {
+        let mut model = cm("a<strong>b|</strong>");
+        model.backspace();
+        model.bold();
+        replace_text(&mut model, "x");
+        assert_eq!(tx(&model), "a<strong>x|</strong>");
+    }
+
+    #[test]
+    fn visual_lines_count_wrapped_lines() {
+        let mut model = cm("|");
+        assert_eq!(model.count_visual_lines(5), 1);
+        replace_text(&mut model, "hello world\nbye");
+        assert_eq!(model.count_visual_lines(5), 3);
+        assert_eq!(model.count_visual_lines(20), 2);
+    }
+
+    #[test]
+    fn edits_over_budget_are_abandoned() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().operation_budget_ms(0),
+            "a<strong>{b}|</strong>c",
+        );
+        let before = tx(&model);
+        let update = model.replace_text(&utf16("x\ny\nz"));
+        assert_eq!(update.error, Some(ComposerError::BudgetExceeded));
+        assert!(matches!(update.text_update, TextUpdate::ReplaceAll(_)));
+        assert_eq!(tx(&model), before);
+        assert!(model.undo().error.is_none());
+        assert_eq!(tx(&model), before);
+
+        let update = model.apply_events(vec![InputEvent::Backspace]);
+        assert_eq!(update.error, Some(ComposerError::BudgetExceeded));
+        assert_eq!(tx(&model), before);
+    }
+
+    #[test]
+    fn edits_within_budget_succeed() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().operation_budget_ms(60_000),
+            "a|",
+        );
+        let update = model.replace_text(&utf16("b"));
+        assert_eq!(update.error, None);
+        assert_eq!(tx(&model), "ab|");
+    }
+
+    #[test]
+    fn custom_emotes_are_inserted_as_images() {
+        let mut model = cm("a{b}|c");
+        model.insert_custom_emote("mxc://example.org/cat", &utf16("cat"));
+        assert_eq!(
+            tx(&model),
+            "a<img data-mx-emoticon=\"\" src=\"mxc://example.org/cat\" \
+            alt=\":cat:\" />|c"
+        );
+        assert_eq!(
+            model.get_content_as_message_html(),
+            model.get_content_as_html(HtmlDialect::Editor)
+        );
+        assert_eq!(model.get_content_as_plain_text(false), utf16("a:cat:c"));
+        replace_text(&mut model, "x");
+        assert_eq!(model.get_content_as_plain_text(false), utf16("a:cat:xc"));
+    }
+
+    #[test]
+    fn custom_emotes_need_an_mxc_url() {
+        let mut model = cm("a|");
+        let update = model
+            .insert_custom_emote("https://example.org/cat", &utf16(":cat:"));
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "a|");
+    }
+
+    #[test]
+    fn html_nested_too_deeply_is_refused() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().max_depth(2),
+            "a<em>b|</em>",
+        );
+        let update = model.set_content_from_html(&utf16(
+            "<em><strong><u>c</u></strong></em>",
+        ));
+        assert_eq!(update.error, Some(ComposerError::TooDeep));
+        assert_eq!(tx(&model), "a<em>b|</em>");
+        let update =
+            model.paste_html(&utf16("<em><strong><u>c</u></strong></em>"));
+        assert_eq!(update.error, Some(ComposerError::TooDeep));
+        assert_eq!(tx(&model), "a<em>b|</em>");
+        let update = model.paste_html(&utf16("<strong><u>c</u></strong>"));
+        assert_eq!(update.error, None);
+    }
+
+    #[test]
+    fn a_received_message_can_be_quoted() {
+        let mut model = cm("a{b}|");
+        let fragment = parse_message_html(&utf16(
+            "<mx-reply>x</mx-reply><em>quoted</em><img src=\"mxc://y\" onerror=\"z\">",
+        ));
+        model.insert_fragment(&fragment.quoted());
+        assert_eq!(
+            tx(&model),
+            "a<blockquote><em>quoted</em><img src=\"mxc://y\" />|</blockquote>"
+        );
+    }
+
+    #[test]
+    fn bold_inside_a_word_can_format_the_whole_word() {
+        let builder = ComposerModelBuilder::new().format_word_at_cursor(true);
+        let mut model = with_config(builder.clone(), "one tw|o three");
+        model.bold();
+        assert_eq!(tx(&model), "one <strong>tw|o</strong> three");
+        replace_text(&mut model, "x");
+        assert_eq!(tx(&model), "one <strong>twx|o</strong> three");
+    }
+
+    #[test]
+    fn bold_at_the_edge_of_a_word_is_pending() {
+        let builder = ComposerModelBuilder::new().format_word_at_cursor(true);
+        let mut model = with_config(builder, "one two| three");
+        model.bold();
+        replace_text(&mut model, "x");
+        assert_eq!(tx(&model), "one two<strong>x|</strong> three");
+    }
+
+    #[test]
+    fn bold_inside_a_word_is_pending_by_default() {
+        let mut model = cm("one tw|o three");
+        model.bold();
+        replace_text(&mut model, "x");
+        assert_eq!(tx(&model), "one tw<strong>x|</strong>o three");
+    }
+
+    #[test]
+    fn a_suggestion_can_be_completed_with_a_mention() {
+        let mut model = cm("hi @al|");
+        let suggestion = match model.replace_text(&utf16("i")).menu_action {
+            MenuAction::Suggestion(suggestion) => suggestion,
+            _ => panic!("Expected a suggestion"),
+        };
+        model.replace_text_at_suggestion(
+            &suggestion,
+            &Mention {
+                id: utf16("@alice:matrix.org"),
+                display_name: utf16("Alice"),
+                avatar_url: Some(utf16("mxc://matrix.org/a")),
+            },
+        );
+        assert_eq!(
+            tx(&model),
+            "hi <a href=\"https://matrix.to/#/@alice:matrix.org\" \
+            data-mention-id=\"@alice:matrix.org\" data-mention-type=\"user\" \
+            data-mention-avatar=\"mxc://matrix.org/a\">Alice</a> |"
+        );
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_message_html()).unwrap(),
+            "hi <a href=\"https://matrix.to/#/@alice:matrix.org\">Alice</a> "
+        );
+        assert_eq!(
+            model.mentioned_user_ids(),
+            vec![utf16("@alice:matrix.org")]
+        );
+    }
+
+    #[test]
+    fn a_mention_is_completed_only_within_the_max_length() {
+        let alice = Mention {
+            id: utf16("@alice:matrix.org"),
+            display_name: utf16("Alice"),
+            avatar_url: None,
+        };
+        let mut model =
+            with_config(ComposerModelBuilder::new().max_length(5), "@al|");
+        let update =
+            model.replace_text_at_suggestion(&suggestion(0, 3), &alice);
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "@al|");
+
+        let mut model =
+            with_config(ComposerModelBuilder::new().max_length(6), "@al|");
+        model.replace_text_at_suggestion(&suggestion(0, 3), &alice);
+        assert_eq!(model.dom.text_len(), 6);
+    }
+
+    #[test]
+    fn a_mention_is_not_completed_in_plain_text_mode() {
+        let mut model = cm("@al|");
+        model.set_plain_text_mode(true);
+        let update = model.replace_text_at_suggestion(
+            &suggestion(0, 3),
+            &Mention {
+                id: utf16("@alice:matrix.org"),
+                display_name: utf16("Alice"),
+                avatar_url: None,
+            },
+        );
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "@al|");
+    }
+
+    #[test]
+    fn a_mention_without_a_display_name_is_not_completed() {
+        let mut model = cm("@al|");
+        let update = model.replace_text_at_suggestion(
+            &suggestion(0, 3),
+            &Mention {
+                id: utf16("@alice:matrix.org"),
+                display_name: Vec::new(),
+                avatar_url: None,
+            },
+        );
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "@al|");
+    }
+
+    #[test]
+    fn invalid_code_units_in_a_mention_are_replaced() {
+        let mut model = cm("@al|");
+        let mut id = utf16("@a");
+        id.push(0xD800);
+        model.replace_text_at_suggestion(
+            &suggestion(0, 3),
+            &Mention {
+                id,
+                display_name: vec![0xD800],
+                avatar_url: Some(vec![0xDC00]),
+            },
+        );
+        assert_eq!(
+            tx(&model),
+            "<a href=\"https://matrix.to/#/@a\u{FFFD}\" \
+            data-mention-id=\"@a\u{FFFD}\" data-mention-type=\"user\" \
+            data-mention-avatar=\"\u{FFFD}\">\u{FFFD}</a> |"
+        );
+    }
+
+    #[test]
+    fn only_users_still_mentioned_are_listed() {
+        let mut model = cm("<a href=\"https://matrix.to/#/@a:b\" data-mention-id=\"@a:b\">A</a> \
+            <a href=\"https://matrix.to/#/#r:b\" data-mention-id=\"#r:b\">R</a> \
+            <a href=\"https://matrix.to/#/@c:d\" data-mention-id=\"@c:d\">C</a> \
+            <a href=\"https://matrix.to/#/@a:b\" data-mention-id=\"@a:b\">A</a>|");
+        assert_eq!(
+            model.mentioned_user_ids(),
+            vec![utf16("@a:b"), utf16("@c:d")]
+        );
+        model.select(Location::from(0), Location::from(2));
+        model.delete();
+        assert_eq!(
+            model.mentioned_user_ids(),
+            vec![utf16("@c:d"), utf16("@a:b")]
+        );
+    }
+
+    #[test]
+    fn rooms_and_events_are_mentioned_by_id_in_the_plain_text() {
+        let mut model = cm("|");
+        for (id, name) in [("#r:b", "Room"), ("!r:b/$e", "Msg"), ("@a:b", "A")]
+        {
+            let suggestion = SuggestionPattern {
+                key: PatternKey::At,
+                text: Vec::new(),
+                start: model.get_selection().1,
+                end: model.get_selection().1,
+            };
+            model.replace_text_at_suggestion(
+                &suggestion,
+                &Mention {
+                    id: utf16(id),
+                    display_name: utf16(name),
+                    avatar_url: None,
+                },
+            );
+        }
+        assert!(tx(&model).contains("data-mention-type=\"event\">Msg</a>"));
+        assert_eq!(
+            model.get_content_as_plain_text(false),
+            utf16("Room Msg A ")
+        );
+        assert_eq!(
+            model.get_content_as_plain_text(true),
+            utf16("#r:b https://matrix.to/#/!r:b/$e A ")
+        );
+        assert_eq!(model.mentioned_user_ids(), vec![utf16("@a:b")]);
+    }
+
+    #[test]
+    fn mentions_can_show_new_display_names() {
+        let mut model = cm("<a href=\"https://matrix.to/#/@a:b\" data-mention-id=\"@a:b\">A</a> \
+            x{y}| <a href=\"https://matrix.to/#/@a:b\" data-mention-id=\"@a:b\" \
+            data-mention-avatar=\"mxc://a\">A</a>");
+        let alice = Mention {
+            id: utf16("@a:b"),
+            display_name: utf16("Alice"),
+            avatar_url: None,
+        };
+        model.update_mentions(std::slice::from_ref(&alice));
+        assert_eq!(
+            tx(&model),
+            "<a href=\"https://matrix.to/#/@a:b\" data-mention-id=\"@a:b\">Alice</a> \
+            x{y}| <a href=\"https://matrix.to/#/@a:b\" data-mention-id=\"@a:b\">Alice</a>"
+        );
+        assert!(matches!(
+            model.update_mentions(&[alice]).text_update,
+            TextUpdate::Keep
+        ));
+        assert_eq!(model.history.take_undo().map(|_| ()), None);
+    }
+
+    #[test]
+    fn up_in_an_empty_composer_navigates_history() {
+        let mut model = cm("|");
+        assert!(model.can_recall_previous());
+        assert_eq!(model.up().menu_action, MenuAction::NavigateHistory);
+        let mut model = cm("<p>|</p>");
+        assert_eq!(model.up().menu_action, MenuAction::NavigateHistory);
+    }
+
+    #[test]
+    fn up_with_content_moves_the_cursor() {
+        let mut model = cm("|a");
+        assert!(!model.can_recall_previous());
+        let update = model.up();
+        assert_eq!(update.menu_action, MenuAction::None);
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+    }
+
+    #[test]
+    fn backspace_removes_one_empty_line_at_a_time() {
+        let mut model = cm("<p>a</p><p></p><p><br /></p><p>|b</p>");
+        assert_eq!(tx(&model), "<p>a</p><br /><br /><p>|b</p>");
+        model.backspace();
+        assert_eq!(tx(&model), "<p>a</p><br /><p>|b</p>");
+        model.backspace();
+        assert_eq!(tx(&model), "<p>a|</p><p>b</p>");
+    }
+
+    #[test]
+    fn message_html_with_empty_lines_round_trips() {
+        let model = cm("<p>a</p><p></p><p>b|</p>");
+        let html = model.get_content_as_message_html();
+        let mut copy = ComposerModel::new();
+        copy.set_content_from_html(&html);
+        assert_eq!(copy.get_content_as_message_html(), html);
+    }
+
+    #[test]
+    fn the_undo_history_lists_each_edit_with_its_content_length() {
+        let mut model = ComposerModel::new();
+        model.replace_text(&utf16("abc"));
+        model.select(Location::from(0), Location::from(3));
+        model.bold();
+        model.backspace();
+        let items: Vec<_> = model.undo_history().cloned().collect();
+        let labels: Vec<_> = items.iter().map(|item| item.label).collect();
+        assert_eq!(labels, vec!["replace_text", "format", "backspace"]);
+        let lens: Vec<_> = items.iter().map(|item| item.content_len).collect();
+        assert_eq!(lens, vec![3, 3, 0]);
+        assert!(items.iter().all(|item| item.timestamp_ms.is_some()));
+        assert_eq!(model.redo_history().count(), 0);
+    }
+
+    #[test]
+    fn undone_edits_move_to_the_redo_history_unchanged() {
+        let mut model = ComposerModel::new();
+        model.replace_text(&utf16("ab"));
+        model.backspace();
+        let deleted = model.undo_history().last().cloned().unwrap();
+        model.undo();
+        assert_eq!(model.undo_history().count(), 1);
+        let redo: Vec<_> = model.redo_history().cloned().collect();
+        assert_eq!(redo, vec![deleted]);
+        model.redo();
+        assert_eq!(model.undo_history().last().unwrap().label, "backspace");
+        assert_eq!(model.redo_history().count(), 0);
+    }
+
+    #[test]
+    fn only_the_most_recent_edits_can_be_undone_past_the_limit() {
+        let mut model = ComposerModelBuilder::new().max_undo_entries(2).build();
+        model.replace_text(&utf16("a"));
+        model.replace_text(&utf16("b"));
+        model.replace_text(&utf16("c"));
+        assert_eq!(model.undo_history().count(), 2);
+        model.undo();
+        model.undo();
+        model.undo();
+        assert_eq!(tx(&model), "a|");
+    }
+
+    #[test]
+    fn the_oldest_edits_are_forgotten_when_the_history_gets_too_big() {
+        let mut model = ComposerModel::new();
+        model.replace_text(&utf16(&"a".repeat(1000)));
+        model.replace_text(&utf16("b"));
+        model.replace_text(&utf16("c"));
+        // Each of the last two edits records the previous 1000 or so
+        // characters of the text node it changed
+        model.set_history_limits(None, Some(3000));
+        assert_eq!(model.undo_history().count(), 1);
+        model.undo();
+        model.undo();
+        assert_eq!(tx(&model), format!("{}b|", "a".repeat(1000)));
+
+        model.set_history_limits(None, Some(0));
+        assert_eq!(model.undo_history().count(), 0);
+    }
+
+    #[test]
+    fn a_sent_message_can_be_restored_for_editing() {
+        let mut model = cm("|");
+        let restored = model.set_content_from_message_html(
+            &utf16(
+                "<mx-reply>quoted</mx-reply><b>hi</b> \
+                <a href=\"https://matrix.to/#/@alice:matrix.org\">Alice</a>",
+            ),
+            &RestoreOptions::default(),
+        );
+        assert_eq!(restored.changes.len(), 2);
+        assert_eq!(
+            tx(&model),
+            "<strong>|hi</strong> \
+            <a href=\"https://matrix.to/#/@alice:matrix.org\" \
+            data-mention-id=\"@alice:matrix.org\" \
+            data-mention-type=\"user\">Alice</a>"
+        );
+        assert_eq!(
+            model.mentioned_user_ids(),
+            vec![utf16("@alice:matrix.org")]
+        );
+    }
+
+    #[test]
+    fn typing_ascii_updates_only_the_text_node_it_goes_into() {
+        let mut model = cm("<p>a</p><p>b<em>c|</em>d</p>");
+        let update = model.replace_text(&utf16("x"));
+        assert_eq!(tx(&model), "<p>a</p><p>b<em>cx|</em>d</p>");
+        match update.text_update {
+            TextUpdate::UpdateTextNode(u) => {
+                assert_eq!(u.new_text, utf16("cx"));
+                assert_eq!(u.start, Location::from(4));
+            }
+            _ => panic!("Expected UpdateTextNode"),
+        }
+        model.undo();
+        assert_eq!(tx(&model), "<p>a</p><p>b<em>c|</em>d</p>");
+    }
+
+    #[test]
+    fn typing_ascii_has_the_same_result_as_replacing_text() {
+        for html in ["a|b", "<a href=\"x\">a|</a>b", "a<br />|b", "|"] {
+            for typed in ["x", " ", "é"] {
+                let mut typing = cm(html);
+                typing.replace_text(&utf16(typed));
+                let mut replacing = cm(html);
+                let (s, e) = replacing.safe_selection();
+                replacing.replace_text_in(&utf16(typed), s, e);
+                assert_eq!(tx(&typing), tx(&replacing), "{} {}", html, typed);
+            }
+        }
+    }
+
+    #[test]
+    fn typing_ascii_respects_the_max_length() {
+        let mut model = ComposerModelBuilder::new().max_length(2).build();
+        model.replace_text(&utf16("ab"));
+        model.replace_text(&utf16("c"));
+        assert_eq!(tx(&model), "ab|");
+    }
+
+    #[test]
+    fn a_plain_text_edit_keeps_the_formatting_around_it() {
+        let mut model = cm("a<strong>bc|</strong>d");
+        model.apply_plain_text_edit(&utf16("abcd"), &utf16("abXcd"), 3);
+        assert_eq!(tx(&model), "a<strong>bX|c</strong>d");
+        model.undo();
+        assert_eq!(tx(&model), "a<strong>bc|</strong>d");
+    }
+
+    #[test]
+    fn a_plain_text_edit_can_delete_and_add_lines() {
+        let mut model = cm("a<br />b|cd");
+        model.apply_plain_text_edit(&utf16("a\nbcd"), &utf16("abc\nxd"), 5);
+        assert_eq!(tx(&model), "abc<br />x|d");
+    }
+
+    #[test]
+    fn a_plain_text_edit_does_not_split_characters() {
+        let mut model = cm("a😀|");
+        model.apply_plain_text_edit(&utf16("a😀"), &utf16("a😁"), 3);
+        assert_eq!(tx(&model), "a😁|");
+    }
+
+    #[test]
+    fn a_plain_text_edit_of_stale_text_is_worked_out_from_the_content() {
+        let mut model = cm("abc|");
+        let update =
+            model.apply_plain_text_edit(&utf16("xyz"), &utf16("abcd"), 4);
+        assert_eq!(tx(&model), "abcd|");
+        assert_eq!(update.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn plain_text_mode_shows_the_content_as_markdown() {
+        let mut model = cm("a<strong>b</strong><br />c|");
+        model.set_plain_text_mode(true);
+        assert!(model.is_plain_text_mode());
+        assert_eq!(tx(&model), "a**b**<br />c|");
+        model.bold();
+        assert_eq!(tx(&model), "a**b**<br />c|");
+        assert_eq!(
+            model.get_content_as_message_html(),
+            utf16("a<strong>b</strong><br />c")
+        );
+    }
+
+    #[test]
+    fn leaving_plain_text_mode_parses_the_markdown() {
+        let mut model = cm("|");
+        model.set_plain_text_mode(true);
+        replace_text(&mut model, "> a *b*");
+        model.set_plain_text_mode(false);
+        assert!(!model.is_plain_text_mode());
+        assert_eq!(tx(&model), "<blockquote>a <em>b|</em></blockquote>");
+    }
+
+    #[test]
+    fn leaving_plain_text_mode_removes_what_matrix_does_not_allow() {
+        let mut model = cm("|");
+        model.set_plain_text_mode(true);
+        replace_text(
+            &mut model,
+            "<a href=\"javascript:alert(1)\">x</a> [y](javascript:alert(2)) \
+            <img src=x onerror=alert(3)>",
+        );
+        let update = model.set_plain_text_mode(false);
+        assert_eq!(tx(&model), "<a>x</a> <a>y</a> <img />|");
+        assert_eq!(
+            update.sanitize_report.severity(),
+            Some(RemovalSeverity::Dangerous)
+        );
+    }
+
+    #[test]
+    fn deeply_quoted_markdown_is_sent_without_overflowing() {
+        let mut model =
+            with_config(ComposerModelBuilder::new().max_depth(3), "|");
+        model.set_plain_text_mode(true);
+        replace_text(&mut model, ">>>>> a");
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_message_html()).unwrap(),
+            "<blockquote><blockquote><blockquote>&gt;&gt; a\
+            </blockquote></blockquote></blockquote>"
+        );
+        let mut model = cm("|");
+        model.set_plain_text_mode(true);
+        replace_text(&mut model, &">".repeat(20_000));
+        model.get_content_as_message_html();
+        model.set_plain_text_mode(false);
+    }
+
+    #[test]
+    fn deeply_quoted_markdown_can_be_set_and_styled() {
+        let mut model = cm("|");
+        let quotes = utf16(&">".repeat(100_000));
+        model.set_content_from_markdown(&quotes);
+        model.set_plain_text_mode(true);
+        model.set_content_from_markdown(&quotes);
+        assert_eq!(model.get_markdown_styles().len(), model.config.max_depth);
+    }
+
+    #[test]
+    fn content_can_be_set_from_markdown() {
+        let mut model = cm("a|");
+        let update = model.set_content_from_markdown(&utf16(
+            "**b** [c](https://x.org)\n- d\n- <button>e</button>",
+        ));
+        assert_eq!(
+            tx(&model),
+            "<strong>|b</strong> <a href=\"https://x.org\">c</a>\
+            <ul><li>d</li><li>e</li></ul>"
+        );
+        assert_eq!(update.sanitize_report.removals.len(), 1);
+        assert_eq!(model.history.take_undo().map(|_| ()), None);
+    }
+
+    #[test]
+    fn markdown_set_in_plain_text_mode_is_shown_as_it_is() {
+        let mut model = cm("|");
+        model.set_plain_text_mode(true);
+        model.set_content_from_markdown(&utf16("*a*\n<b>"));
+        assert_eq!(tx(&model), "|*a*<br />&lt;b&gt;");
+    }
+
+    #[test]
+    fn lone_surrogates_in_input_are_replaced() {
+        let mut model = cm("|");
+        let update = model.replace_text(&[0x61, 0xD800]);
+        assert_eq!(tx(&model), "a\u{FFFD}|");
+        assert_eq!(update.diagnostics.len(), 1);
+        model.set_content_from_html(&[0xDFFF, 0x3C, 0x62, 0x72, 0x3E]);
+        assert_eq!(tx(&model), "|\u{FFFD}<br />");
+        model.set_link_with_text(&utf16("https://x.org"), &[0xDBFF]);
+        assert_eq!(
+            tx(&model),
+            "<a href=\"https://x.org\">\u{FFFD}</a>|\u{FFFD}<br />"
+        );
+    }
+
+    #[test]
+    fn models_with_the_same_content_and_selection_have_the_same_state() {
+        let a = cm("<a href=\"x\" title=\"y\">b|</a>");
+        let mut b = cm("<a title=\"y\" href=\"x\">b|</a>");
+        b.bold();
+        assert!(a.has_same_state(&b));
+        assert_eq!(a.state_hash(), b.state_hash());
+        b.select(Location::from(0), Location::from(0));
+        assert!(!a.has_same_state(&b));
+        assert_ne!(a.state_hash(), b.state_hash());
+        assert_eq!(a.content_hash(), b.content_hash());
+    }
+
+    #[test]
+    fn the_state_hash_does_not_depend_on_the_code_unit() {
+        let html = "a\u{1F600}<br />b";
+        let mut utf16_model = ComposerModel::<u16>::new();
+        utf16_model.set_content_from_html(&utf16(html));
+        utf16_model.select(Location::from(3), Location::from(4));
+        let mut utf8_model = ComposerModel::<u8>::new();
+        utf8_model.set_content_from_html(html.as_bytes());
+        utf8_model.select(Location::from(5), Location::from(6));
+        assert_eq!(utf16_model.state_hash(), utf8_model.state_hash());
+    }
+
+    #[test]
+    fn markdown_in_plain_text_mode_is_styled() {
+        let mut model = cm("|");
+        assert_eq!(model.get_markdown_styles(), Vec::new());
+        model.set_plain_text_mode(true);
+        model.set_content_from_markdown(&utf16("\u{1F600}\n**a**"));
+        let styles: Vec<(MarkdownStyleKind, usize, usize)> = model
+            .get_markdown_styles()
+            .into_iter()
+            .map(|s| (s.kind, s.start.into(), s.end.into()))
+            .collect();
+        assert_eq!(
+            styles,
+            vec![
+                (MarkdownStyleKind::BoldMarker, 3, 5),
+                (MarkdownStyleKind::Bold, 5, 6),
+                (MarkdownStyleKind::BoldMarker, 6, 8),
+            ]
+        );
+    }
+
+    #[test]
+    fn switching_to_plain_text_mode_and_back_keeps_the_content() {
+        let html = "<ul><li>a<strong>b</strong></li><li>c</li></ul>\
+            <a href=\"https://x.org\">d</a>|";
+        let mut model = cm(html);
+        model.set_plain_text_mode(true);
+        model.set_plain_text_mode(false);
+        assert_eq!(tx(&model), html);
+        model.undo();
+        assert_eq!(tx(&model), html);
+    }
+
+    #[test]
+    fn commands_do_what_their_methods_do() {
+        let mut model = cm("a{b}|c");
+        let html = |model: &ComposerModel<u16>| {
+            String::from_utf16(&model.get_content_as_message_html()).unwrap()
+        };
+        model.apply(ComposerCommand::Italic);
+        assert_eq!(html(&model), "a<em>b</em>c");
+        model.apply(ComposerCommand::Bold);
+        assert_eq!(html(&model), "a<strong><em>b</em></strong>c");
+        model.apply(ComposerCommand::Undo);
+        assert_eq!(html(&model), "a<em>b</em>c");
+        model.apply(ComposerCommand::Redo);
+        assert_eq!(html(&model), "a<strong><em>b</em></strong>c");
+        let mut model = cm("a|");
+        model.apply(ComposerCommand::SetLinkWithText {
+            link: utf16("https://x.org"),
+            text: utf16("d"),
+        });
+        assert_eq!(html(&model), "a<a href=\"https://x.org\">d</a>");
+    }
+
+    #[test]
+    fn italic_is_reported_in_the_menu_state() {
+        let mut model = cm("a{b}|c");
+        let update = model.italic();
+        assert_eq!(
+            update.menu_state,
+            MenuState::Update {
+                active_features: vec![Feature::Italic],
+                partially_active_features: Vec::new(),
+                can_undo: true,
+                can_redo: false,
+            }
+        );
+    }
+
+    const MENTION: &str = "<a href=\"https://matrix.to/#/@b:c\" \
+        data-mention-id=\"@b:c\">Bob</a>";
+
+    #[test]
+    fn deleting_part_of_a_mention_deletes_all_of_it() {
+        let mut model = cm(&format!("a{}d|", MENTION));
+        model.select(Location::from(2), Location::from(5));
+        model.backspace();
+        assert_eq!(tx(&model), "a|");
+
+        let mut model = cm(&format!("a{}|", MENTION));
+        model.backspace();
+        assert_eq!(tx(&model), "a|");
+    }
+
+    #[test]
+    fn typing_over_part_of_a_mention_replaces_all_of_it() {
+        let mut model = cm(&format!("a{}d|", MENTION));
+        model.select(Location::from(0), Location::from(2));
+        replace_text(&mut model, "x");
+        assert_eq!(tx(&model), "x|d");
+    }
+
+    #[test]
+    fn selected_text_includes_whole_mentions() {
+        let model = cm(&format!("a{}d|", MENTION));
+        assert_eq!(
+            model.text_in_range(Location::from(3), Location::from(5)),
+            utf16("Bobd")
+        );
+    }
+
+    #[test]
+    fn formatting_skips_mentions_by_default() {
+        let mut model = cm(&format!("a{}d|", MENTION));
+        model.select(Location::from(0), Location::from(5));
+        model.bold();
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_html(HtmlDialect::Editor))
+                .unwrap(),
+            format!("<strong>a</strong>{}<strong>d</strong>", MENTION)
+        );
+    }
+
+    #[test]
+    fn formatting_can_style_the_text_of_mentions() {
+        let mut model = ComposerModelBuilder::new()
+            .mention_formatting(MentionFormatting::StyleText)
+            .build();
+        model.set_content_from_html(&utf16(&format!("a{}d", MENTION)));
+        model.select(Location::from(2), Location::from(5));
+        model.bold();
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_html(HtmlDialect::Editor))
+                .unwrap(),
+            format!("a<strong>{}d</strong>", MENTION)
+        );
+    }
+
+    #[test]
+    fn trailing_breaks_from_the_editor_are_not_lines() {
+        let mut model = cm("|");
+        model.set_content_from_html(&utf16("<p>a<br></p><p>b<br><br></p>"));
+        assert_eq!(model.dom.text_len(), 3);
+        let html = model.get_html();
+        assert_eq!(html, utf16("<p>a</p><p>b<br /><br /></p>"));
+        model.set_content_from_html(&html);
+        assert_eq!(model.get_html(), html);
+    }
+
+    #[test]
+    fn an_unchanged_menu_state_is_not_reported_again() {
+        let mut model = cm("a<strong>b|</strong>");
+        assert_eq!(
+            model.replace_text(&utf16("c")).menu_state,
+            MenuState::Update {
+                active_features: vec![Feature::Bold],
+                partially_active_features: vec![],
+                can_undo: true,
+                can_redo: false,
+            }
+        );
+        assert_eq!(model.replace_text(&utf16("d")).menu_state, MenuState::None);
+        model.select(Location::from(1), Location::from(1));
+        assert_eq!(
+            model.replace_text(&utf16("e")).menu_state,
+            MenuState::Update {
+                active_features: vec![],
+                partially_active_features: vec![],
+                can_undo: true,
+                can_redo: false,
+            }
+        );
+        model.select(Location::from(0), Location::from(0));
+        assert_eq!(model.replace_text(&utf16("f")).menu_state, MenuState::None);
+    }
+
+    #[test]
+    fn the_menu_state_changes_when_undo_or_redo_becomes_possible() {
+        let menu = |can_undo, can_redo| MenuState::Update {
+            active_features: vec![],
+            partially_active_features: vec![],
+            can_undo,
+            can_redo,
+        };
+        let mut model = cm("|");
+        assert_eq!(
+            model.replace_text(&utf16("a")).menu_state,
+            menu(true, false)
+        );
+        assert_eq!(model.undo().menu_state, menu(false, true));
+        assert_eq!(model.redo().menu_state, menu(true, false));
+        assert_eq!(model.replace_text(&utf16("b")).menu_state, MenuState::None);
+        assert_eq!(
+            model.set_content_from_html(&utf16("c")).menu_state,
+            menu(false, false)
+        );
+    }
+
+    #[test]
+    fn a_fork_reports_its_menu_state_afresh() {
+        let mut model = cm("a<strong>b|</strong>");
+        model.replace_text(&utf16("c"));
+        let mut fork = model.fork();
+        assert!(matches!(
+            fork.replace_text(&utf16("d")).menu_state,
+            MenuState::Update { .. }
+        ));
+    }
+
+    /**
+     * A clock that starts at start_ms and moves on by step_ms each time it
+     * is read, or when told to.
+     */
+    struct TestClock {
+        ms: AtomicU64,
+        step_ms: u64,
+    }
+
+    impl TestClock {
+        fn new(start_ms: u64, step_ms: u64) -> Arc<Self> {
+            Arc::new(Self {
+                ms: AtomicU64::new(start_ms),
+                step_ms,
+            })
+        }
+
+        fn advance(&self, ms: u64) {
+            self.ms.fetch_add(ms, Ordering::SeqCst);
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now_ms(&self) -> u64 {
+            self.ms.fetch_add(self.step_ms, Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn text_typed_with_a_clock_is_timed_by_it() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().double_space_full_stop(500),
+            "hi|",
+        );
+        let clock = TestClock::new(1000, 0);
+        model.set_clock(clock.clone());
+        replace_text(&mut model, " ");
+        clock.advance(300);
+        replace_text(&mut model, " ");
+        assert_eq!(tx(&model), "hi. |");
+        replace_text(&mut model, "yo");
+        replace_text(&mut model, " ");
+        clock.advance(600);
+        replace_text(&mut model, " ");
+        assert_eq!(tx(&model), "hi. yo  |");
+    }
+
+    #[test]
+    fn history_items_are_timestamped_by_the_clock() {
+        let mut model = cm("|");
+        model.set_clock(TestClock::new(1000, 0));
+        replace_text(&mut model, "a");
+        let items: Vec<_> = model.undo_history().cloned().collect();
+        assert_eq!(items[0].timestamp_ms, Some(1000));
+    }
+
+    fn type_chars(model: &mut ComposerModel<u16>, text: &str) {
+        for c in text.chars() {
+            replace_text(model, &c.to_string());
+        }
+    }
+
+    #[test]
+    fn typing_can_be_undone_a_word_at_a_time() {
+        let mut model = ComposerModelBuilder::new()
+            .undo_coalescing(UndoCoalescing::Words)
+            .build();
+        type_chars(&mut model, "hi there");
+        model.backspace();
+        type_chars(&mut model, "e");
+        assert_eq!(model.undo_history().count(), 4);
+        model.undo();
+        model.undo();
+        assert_eq!(tx(&model), "hi there|");
+        model.undo();
+        assert_eq!(tx(&model), "hi |");
+        model.undo();
+        assert_eq!(tx(&model), "|");
+        model.redo();
+        assert_eq!(tx(&model), "hi |");
+    }
+
+    #[test]
+    fn typing_can_be_undone_a_few_characters_at_a_time() {
+        let mut model = ComposerModelBuilder::new()
+            .undo_coalescing(UndoCoalescing::Characters { max: 3 })
+            .build();
+        type_chars(&mut model, "abcde");
+        model.undo();
+        assert_eq!(tx(&model), "abc|");
+        model.undo();
+        assert_eq!(tx(&model), "|");
+    }
+
+    #[test]
+    fn typing_can_be_undone_back_to_the_last_pause() {
+        let mut model = ComposerModelBuilder::new()
+            .undo_coalescing(UndoCoalescing::Pauses { max_pause_ms: 500 })
+            .build();
+        let clock = TestClock::new(1000, 100);
+        model.set_clock(clock.clone());
+        type_chars(&mut model, "ab");
+        clock.advance(1000);
+        type_chars(&mut model, "cd");
+        model.undo();
+        assert_eq!(tx(&model), "ab|");
+    }
+
+    #[test]
+    fn moving_the_cursor_ends_a_run_of_typing() {
+        let mut model = ComposerModelBuilder::new()
+            .undo_coalescing(UndoCoalescing::Words)
+            .build();
+        type_chars(&mut model, "ab");
+        model.select(Location::from(0), Location::from(0));
+        type_chars(&mut model, "c");
+        model.undo();
+        assert_eq!(tx(&model), "|ab");
+        model.undo();
+        assert_eq!(tx(&model), "|");
+    }
+
+    #[test]
+    fn edits_that_would_change_protected_content_are_refused() {
+        let mut model = cm("a<span data-protected=\"\">bc</span>|d");
+        let update = model.backspace();
+        assert_eq!(update.error, Some(ComposerError::Protected));
+        assert_eq!(tx(&model), "a<span data-protected=\"\">bc</span>|d");
+        model.select(Location::from(0), Location::from(4));
+        let update = model.replace_text(&utf16("x"));
+        assert_eq!(update.error, Some(ComposerError::Protected));
+        let update = model.delete();
+        assert_eq!(update.error, Some(ComposerError::Protected));
+        model.select(Location::from(3), Location::from(3));
+        let update = model.replace_text(&utf16("x"));
+        assert_eq!(update.error, None);
+        assert_eq!(tx(&model), "a<span data-protected=\"\">bc</span>x|d");
+        assert_eq!(
+            model.get_content_as_message_html(),
+            utf16("a<span>bc</span>xd")
+        );
+    }
+
+    #[test]
+    fn the_selection_snaps_out_of_protected_content() {
+        let mut model = cm("|a<span data-protected=\"\">bcd</span>e");
+        model.select(Location::from(2), Location::from(2));
+        assert_eq!(
+            model.get_selection(),
+            (Location::from(1), Location::from(1))
+        );
+        model.select(Location::from(5), Location::from(3));
+        assert_eq!(
+            model.get_selection(),
+            (Location::from(5), Location::from(1))
+        );
+    }
+
+    #[test]
+    fn protection_can_be_set_and_removed_by_handle() {
+        let mut model = cm("<p>Forwarded</p><p>a|</p>");
+        let first = DomHandle::from_raw(vec![0]);
+        model.set_protected(&first, true);
+        model.select(Location::from(0), Location::from(3));
+        assert_eq!(
+            model.replace_text(&utf16("x")).error,
+            Some(ComposerError::Protected)
+        );
+        model.set_protected(&first, false);
+        model.select(Location::from(0), Location::from(3));
+        model.replace_text(&utf16("x"));
+        assert_eq!(tx(&model), "<p>x|warded</p><p>a</p>");
+    }
+
+    #[test]
+    fn the_operation_budget_is_measured_by_the_clock() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().operation_budget_ms(5),
+            "a|",
+        );
+        model.set_clock(TestClock::new(0, 10));
+        let update = model.replace_text(&utf16("b"));
+        assert_eq!(update.error, Some(ComposerError::BudgetExceeded));
+        model.set_clock(TestClock::new(0, 0));
+        let update = model.replace_text(&utf16("b"));
+        assert_eq!(update.error, None);
+        assert_eq!(tx(&model), "ab|");
+    }
+
+    #[test]
+    fn a_pinned_node_is_followed_through_edits() {
+        let mut model = cm("a<strong>b</strong>c|");
+        let pinned = model.pin(&DomHandle::from_raw(vec![2])).unwrap();
+        model.insert_at(Location::from(0), &utf16("<em>z</em>"));
+        assert_eq!(tx(&model), "<em>z</em>a<strong>b</strong>c|");
+        assert_eq!(
+            model.handle_of_pinned(&pinned),
+            Some(DomHandle::from_raw(vec![3]))
+        );
+        model.set_content_from_html(&utf16("a<strong>b</strong>c"));
+        assert_eq!(model.handle_of_pinned(&pinned), None);
+    }
+
+    #[test]
+    fn the_selection_can_be_anchored_beside_a_mention() {
+        let mut model = cm("ab<a href=\"https://matrix.to/#/@c:d\" \
+            data-mention-id=\"@c:d\">Cat</a>|");
+        let after_mention = AnchoredLocation {
+            handle: DomHandle::from_raw(vec![]),
+            offset: 2,
+        };
+        assert_eq!(
+            model.get_anchored_selection(),
+            (after_mention.clone(), after_mention.clone())
+        );
+        let in_text = AnchoredLocation {
+            handle: DomHandle::from_raw(vec![0]),
+            offset: 1,
+        };
+        assert!(model.select_anchored(&after_mention, &in_text));
+        assert_eq!(
+            model.get_selection(),
+            (Location::from(5), Location::from(1))
+        );
+        assert_eq!(model.get_anchored_selection(), (after_mention, in_text));
+        let missing = AnchoredLocation {
+            handle: DomHandle::from_raw(vec![3]),
+            offset: 0,
+        };
+        assert!(!model.select_anchored(&missing, &missing));
+        assert_eq!(
+            model.get_selection(),
+            (Location::from(5), Location::from(1))
+        );
+    }
+
+    #[test]
+    fn list_items_can_be_moved_with_the_cursor_in_them() {
+        let mut model =
+            cm("<ul><li>a</li><li>b{c}|<ul><li>d</li></ul></li></ul>");
+        model.move_list_item_up();
+        assert_eq!(
+            tx(&model),
+            "<ul><li>b{c}|<ul><li>d</li></ul></li><li>a</li></ul>"
+        );
+        model.apply(ComposerCommand::MoveListItemDown);
+        assert_eq!(
+            tx(&model),
+            "<ul><li>a</li><li>b{c}|<ul><li>d</li></ul></li></ul>"
+        );
+        assert!(matches!(
+            model.move_list_item_down().text_update,
+            TextUpdate::Keep
+        ));
+        model.undo();
+        assert_eq!(
+            tx(&model),
+            "<ul><li>b{c}|<ul><li>d</li></ul></li><li>a</li></ul>"
+        );
+    }
+
+    #[test]
+    fn removing_a_list_leaves_no_empty_formatting_behind() {
+        let html = "<ul><li>a|</li><li><strong><em></em></strong></li>\
+                    <li><em>b</em></li></ul>";
+        let mut model = cm(html);
+        model.apply(ComposerCommand::RemoveList);
+        assert_eq!(tx(&model), "<p>a|</p><br /><p><em>b</em></p>");
+        model.undo();
+        assert_eq!(tx(&model), html);
+    }
+
+    #[test]
+    fn list_items_do_not_move_outside_a_list() {
+        let mut model = cm("a|");
+        assert!(matches!(
+            model.move_list_item_up().text_update,
+            TextUpdate::Keep
+        ));
+    }
+
+    #[test]
+    fn a_fork_can_be_edited_without_changing_the_original() {
+        let mut model = cm("a|");
+        replace_text(&mut model, "b");
+        let mut fork = model.fork();
+        replace_text(&mut fork, "c");
+        assert_eq!(tx(&fork), "abc|");
+        assert_eq!(tx(&model), "ab|");
+        fork.undo();
+        assert_eq!(tx(&fork), "ab|");
+        fork.undo();
+        assert_eq!(tx(&fork), "ab|");
+    }
+
+    #[test]
+    fn a_clone_keeps_the_undo_history() {
+        let mut model = cm("a|");
+        replace_text(&mut model, "b");
+        let mut clone = model.clone();
+        clone.undo();
+        assert_eq!(tx(&clone), "a|");
+        assert_eq!(tx(&model), "ab|");
+    }
+
+    #[test]
+    fn forks_are_not_observed() {
+        let mut model = cm("a|");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        model.subscribe(Box::new(Recorder(Arc::clone(&events))));
+        let mut fork = model.fork();
+        replace_text(&mut fork, "b");
+        assert_eq!(*events.lock().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn observers_are_told_about_each_change() {
+        let mut model = cm("a<strong>b|</strong>");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        model.subscribe(Box::new(Recorder(Arc::clone(&events))));
+        replace_text(&mut model, "c");
+        model.select(Location::from(0), Location::from(1));
+        model.bold();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                DomEvent::TextChanged {
+                    handle: DomHandle::from_raw(vec![1, 0]),
+                    new_text: utf16("bc"),
+                },
+                DomEvent::NodeRemoved {
+                    handle: DomHandle::from_raw(vec![1]),
+                },
+                DomEvent::NodeRemoved {
+                    handle: DomHandle::from_raw(vec![0]),
+                },
+                DomEvent::NodeInserted {
+                    handle: DomHandle::from_raw(vec![0]),
+                    html: utf16("<strong>abc</strong>"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn observers_are_not_told_about_selection_changes() {
+        let mut model = cm("ab|");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        model.subscribe(Box::new(Recorder(Arc::clone(&events))));
+        model.select(Location::from(0), Location::from(1));
+        model.undo();
+        assert_eq!(*events.lock().unwrap(), vec![]);
+        replace_text(&mut model, "x");
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![DomEvent::TextChanged {
+                handle: DomHandle::from_raw(vec![0]),
+                new_text: utf16("xb"),
+            }]
+        );
+    }
+
+    #[test]
+    fn a_malformed_dom_is_repaired_and_reported() {
+        let mut model = cm("a<br />bc|");
+        let children = model.dom.children(model.dom.document()).to_vec();
+        model.dom.append_child(children[1], children[2]);
+        let update = model.replace_text(&utf16("d"));
+        assert_eq!(update.diagnostics.len(), 1);
+        assert!(matches!(update.text_update, TextUpdate::ReplaceAll(_)));
+        assert_eq!(model.dom.check_invariants(), Ok(()));
+        assert_eq!(tx(&model), "a<br />d|");
+    }
+
+    #[test]
+    fn edits_normally_report_no_diagnostics() {
+        let mut model = cm("a|");
+        assert_eq!(
+            model.replace_text(&utf16("b")).diagnostics,
+            Vec::<String>::new()
+        );
+        assert_eq!(model.undo().diagnostics, Vec::<String>::new());
+    }
+
+    #[test]
+    fn tab_in_a_code_block_inserts_indentation() {
+        let mut model = cm("<pre>a\n{b}|</pre>");
+        model.tab();
+        assert_eq!(tx(&model), "<pre>a\n    |</pre>");
+    }
+
+    #[test]
+    fn shift_tab_in_a_code_block_unindents_the_line() {
+        let mut model = cm("<pre>a\n      b|c</pre>");
+        model.shift_tab();
+        assert_eq!(tx(&model), "<pre>a\n  b|c</pre>");
+        model.shift_tab();
+        assert_eq!(tx(&model), "<pre>a\nb|c</pre>");
+        model.shift_tab();
+        assert_eq!(tx(&model), "<pre>a\nb|c</pre>");
+    }
+
+    #[test]
+    fn lines_of_code_can_be_commented_duplicated_and_moved() {
+        let mut model = cm("<pre>a\n  b|\nc</pre>");
+        model.toggle_comment(&utf16("#"));
+        assert_eq!(tx(&model), "<pre>a\n  # b|\nc</pre>");
+        model.duplicate_line();
+        assert_eq!(tx(&model), "<pre>a\n  # b\n  # b|\nc</pre>");
+        model.move_line_down();
+        assert_eq!(tx(&model), "<pre>a\n  # b\nc\n  # b|</pre>");
+        model.move_line_down();
+        assert_eq!(tx(&model), "<pre>a\n  # b\nc\n  # b|</pre>");
+        model.select(Location::from(0), Location::from(7));
+        model.move_line_up();
+        assert_eq!(
+            model.get_selection(),
+            (Location::from(0), Location::from(7))
+        );
+        model.toggle_comment(&utf16("#"));
+        assert_eq!(tx(&model), "<pre>{# a\n  # # b}|\nc\n  # b</pre>");
+        model.undo();
+        assert_eq!(tx(&model), "<pre>{a\n  # b}|\nc\n  # b</pre>");
+    }
+
+    #[test]
+    fn line_operations_outside_code_change_nothing() {
+        let mut model = cm("a\nb|");
+        model.move_line_up();
+        model.duplicate_line();
+        model.toggle_comment(&utf16("//"));
+        assert_eq!(tx(&model), "a\nb|");
+    }
+
+    #[test]
+    fn shift_tab_in_code_removes_a_tab() {
+        let mut model = cm("<code>\t\tx|</code>");
+        model.shift_tab();
+        assert_eq!(tx(&model), "<code>\tx|</code>");
+    }
+
+    #[test]
+    fn toggling_the_list_type_converts_the_innermost_list() {
+        let mut model = cm("<ul><li>a<ol><li>b|</li></ol></li></ul>");
+        model.toggle_list_type();
+        assert_eq!(tx(&model), "<ul><li>a<ul><li>b|</li></ul></li></ul>");
+        model.undo();
+        assert_eq!(tx(&model), "<ul><li>a<ol><li>b|</li></ol></li></ul>");
+    }
+
+    #[test]
+    fn toggling_the_list_type_outside_a_list_changes_nothing() {
+        let mut model = cm("a|");
+        let update = model.toggle_list_type();
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+    }
+
+    #[test]
+    fn toggling_to_a_list_type_that_is_not_enabled_changes_nothing() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().features(vec![Feature::OrderedList]),
+            "<ol><li>a|</li></ol>",
+        );
+        model.toggle_list_type();
+        assert_eq!(tx(&model), "<ol><li>a|</li></ol>");
+    }
+
+    #[test]
+    fn the_menu_reports_formatting_on_part_of_the_selection() {
+        let mut model = cm("{a<strong>b</strong>c}|");
+        assert_eq!(
+            model.compute_menu_state(),
+            MenuState::Update {
+                active_features: vec![],
+                partially_active_features: vec![Feature::Bold],
+                can_undo: false,
+                can_redo: false,
+            }
+        );
+        model.select(Location::from(1), Location::from(2));
+        assert_eq!(
+            model.compute_menu_state(),
+            MenuState::Update {
+                active_features: vec![Feature::Bold],
+                partially_active_features: vec![],
+                can_undo: false,
+                can_redo: false,
+            }
+        );
+    }
+
+    #[test]
+    fn the_menu_reports_the_type_of_the_innermost_list() {
+        let mut model = cm("<ol><li>a<ul><li>b|</li></ul></li></ol>");
+        assert_eq!(
+            model.replace_text(&utf16("c")).menu_state,
+            MenuState::Update {
+                active_features: vec![Feature::UnorderedList],
+                partially_active_features: vec![],
+                can_undo: true,
+                can_redo: false,
+            }
+        );
+    }
+
+    #[test]
+    fn tab_in_a_list_indents_the_item() {
+        let mut model = cm("<ul><li>a</li><li>b|</li></ul>");
+        model.tab();
+        assert_eq!(tx(&model), "<ul><li>a<ul><li>b|</li></ul></li></ul>");
+        model.shift_tab();
+        assert_eq!(tx(&model), "<ul><li>a</li><li>b|</li></ul>");
+    }
+
+    #[test]
+    fn tab_elsewhere_changes_nothing() {
+        let mut model = cm("ab|");
+        assert!(matches!(model.tab().text_update, TextUpdate::Keep));
+        assert!(matches!(model.shift_tab().text_update, TextUpdate::Keep));
+        let mut model = cm("<ul><li>a|</li></ul>");
+        assert!(matches!(model.tab().text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "<ul><li>a|</li></ul>");
+    }
+
+    #[test]
+    fn the_link_at_a_location_is_found_including_at_its_edges() {
+        let model = cm("a<a href=\"https://x.org\">b<em>c</em></a>d|");
+        let link = model.get_link_at(Location::from(1)).unwrap();
+        assert_eq!(String::from_utf16(&link.url).unwrap(), "https://x.org");
+        assert_eq!(String::from_utf16(&link.text).unwrap(), "bc");
+        assert_eq!(
+            (link.start, link.end),
+            (Location::from(1), Location::from(3))
+        );
+        assert_eq!(model.get_link_at(Location::from(3)), Some(link));
+        assert_eq!(model.get_link_at(Location::from(4)), None);
+        assert_eq!(cm("ab|").get_link_at(Location::from(1)), None);
+    }
+
+    #[test]
+    fn updating_a_link_replaces_its_url_and_text() {
+        let mut model =
+            cm("<strong>a<a href=\"https://x.org\">b|c</a>d</strong>");
+        update_link(&mut model, "https://y.org", "new");
+        assert_eq!(
+            tx(&model),
+            "<strong>a<a href=\"https://y.org\">new</a>|d</strong>"
+        );
+    }
+
+    #[test]
+    fn updated_link_text_takes_the_formatting_of_its_start() {
+        let mut model =
+            cm("<a href=\"https://x.org\"><em>b|</em>c<strong>d</strong></a>");
+        update_link(&mut model, "https://x.org", "new");
+        assert_eq!(tx(&model), "<a href=\"https://x.org\"><em>new</em></a>|");
+    }
+
+    #[test]
+    fn updating_a_link_with_no_text_keeps_its_text() {
+        let mut model = cm("<a href=\"https://x.org\">b|c</a>");
+        update_link(&mut model, "https://y.org", "");
+        assert_eq!(tx(&model), "<a href=\"https://y.org\">bc</a>|");
+    }
+
+    #[test]
+    fn updating_a_link_to_a_disallowed_scheme_does_nothing() {
+        let mut model = cm("<a href=\"https://x.org\">b|c</a>");
+        update_link(&mut model, "javascript:x", "new");
+        assert_eq!(tx(&model), "<a href=\"https://x.org\">b|c</a>");
+    }
+
+    #[test]
+    fn updating_a_link_can_be_undone() {
+        let mut model = cm("<a href=\"https://x.org\">b|c</a>");
+        update_link(&mut model, "https://y.org", "new");
+        model.undo();
+        assert_eq!(tx(&model), "<a href=\"https://x.org\">b|c</a>");
+    }
+
+    #[test]
+    fn setting_a_link_with_text_at_the_cursor_inserts_it() {
+        let mut model = cm("aa|bb");
+        set_link_with_text(&mut model, "https://matrix.org", "link");
+        assert_eq!(tx(&model), "aa<a href=\"https://matrix.org\">link</a>|bb");
+    }
+
+    #[test]
+    fn setting_a_link_with_text_in_an_empty_model_inserts_it() {
+        let mut model = cm("|");
+        set_link_with_text(&mut model, "https://matrix.org", "link");
+        assert_eq!(tx(&model), "<a href=\"https://matrix.org\">link</a>|");
+    }
+
+    #[test]
+    fn typing_after_setting_a_link_with_text_is_not_linkified() {
+        let mut model = cm("|");
+        set_link_with_text(&mut model, "https://matrix.org", "link");
+        replace_text(&mut model, " more");
+        assert_eq!(tx(&model), "<a href=\"https://matrix.org\">link</a> more|");
+    }
+
+    #[test]
+    fn long_urls_given_as_link_text_are_shortened() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().max_link_text_length(12),
+            "|",
+        );
+        let url = "https://matrix.org/docs";
+        set_link_with_text(&mut model, url, url);
+        set_link_with_text(&mut model, url, " docs are at matrix.org/docs");
+        assert_eq!(
+            tx(&model),
+            "<a href=\"https://matrix.org/docs\">https://mat…</a>\
+             <a href=\"https://matrix.org/docs\"> docs are at matrix.org/docs</a>|"
+        );
+        assert_eq!(
+            model.get_content_as_plain_text(false),
+            utf16("https://matrix.org/docs docs are at matrix.org/docs")
+        );
+    }
+
+    #[test]
+    fn setting_a_link_with_text_replaces_the_selection() {
+        let mut model = cm("a{bc}|d");
+        set_link_with_text(&mut model, "https://matrix.org", "link");
+        assert_eq!(tx(&model), "a<a href=\"https://matrix.org\">link</a>|d");
+    }
+
+    #[test]
+    fn setting_a_link_with_text_escapes_the_link_and_text() {
+        let mut model = cm("|");
+        set_link_with_text(&mut model, "https://x.org/?a=\"1\"&b", "<b>");
+        assert_eq!(
+            tx(&model),
+            "<a href=\"https://x.org/?a=&quot;1&quot;&amp;b\">&lt;b&gt;</a>|"
+        );
+    }
+
+    #[test]
+    fn applying_a_batch_of_events_applies_them_in_order() {
+        let mut model = cm("abc|");
+        model.apply_events(vec![
+            InputEvent::Backspace,
+            InputEvent::ReplaceText {
+                new_text: utf16("XY"),
+            },
+            InputEvent::Select {
+                start: Location::from(0),
+                end: Location::from(1),
+            },
+            InputEvent::Delete,
+        ]);
+        assert_eq!(tx(&model), "|bXY");
+    }
+
+    #[test]
+    fn applying_a_batch_of_events_returns_one_update() {
+        let mut model = cm("abc|");
+        let update = model.apply_events(vec![
+            InputEvent::ReplaceTextIn {
+                new_text: utf16("Z"),
+                start: 0,
+                end: 1,
+            },
+            InputEvent::DeleteIn { start: 2, end: 3 },
+        ]);
+        if let TextUpdate::UpdateTextNode(u) = update.text_update {
+            assert_eq!(String::from_utf16(&u.new_text).unwrap(), "Zb");
+            assert_eq!(u.start, 2);
+            assert_eq!(u.end, 2);
+        } else {
+            panic!("Expected to receive an UpdateTextNode response");
+        }
+    }
+
+    #[test]
+    fn typing_inside_a_text_node_updates_only_that_node() {
+        let mut model = cm("a<strong>b|</strong>");
+        let update = model.replace_text(&utf16("c"));
+        if let TextUpdate::UpdateTextNode(u) = update.text_update {
+            assert_eq!(u.node_handle.raw(), &[1, 0]);
+            assert_eq!(String::from_utf16(&u.new_text).unwrap(), "bc");
+            assert_eq!(u.start, 3);
+            assert_eq!(u.end, 3);
+        } else {
+            panic!("Expected to receive an UpdateTextNode response");
+        }
+    }
+
+    #[test]
+    fn deleting_a_whole_text_node_replaces_all() {
+        let mut model = cm("a<strong>b|</strong>");
+        let update = model.backspace();
+        assert!(matches!(update.text_update, TextUpdate::ReplaceAll(_)));
+        assert_eq!(tx(&model), "a|");
+    }
+
+    #[test]
+    fn typing_where_there_is_no_text_node_replaces_all() {
+        let mut model = cm("|");
+        let update = model.replace_text(&utf16("a"));
+        assert!(matches!(update.text_update, TextUpdate::ReplaceAll(_)));
+    }
+
+    #[test]
+    fn enter_in_a_batch_of_events_adds_a_line_as_enter_does() {
+        let mut model = with_config(
+            ComposerModelBuilder::new()
+                .enter_behaviour(EnterBehaviour::NewLine),
+            "ab|",
+        );
+        model.apply_events(vec![
+            InputEvent::Enter,
+            InputEvent::ReplaceText {
+                new_text: utf16("c"),
+            },
+        ]);
+        assert_eq!(tx(&model), "ab<br />c|");
+        model.undo();
+        assert_eq!(tx(&model), "ab|");
+
+        let mut model = with_config(
+            ComposerModelBuilder::new()
+                .enter_behaviour(EnterBehaviour::NewLine),
+            "<ol><li>a|</li></ol>",
+        );
+        model.apply_events(vec![InputEvent::Enter]);
+        replace_text(&mut model, "b");
+        assert_eq!(tx(&model), "<ol><li>a</li><li>b|</li></ol>");
+    }
+
+    #[test]
+    fn enter_in_a_batch_of_events_does_nothing_when_enter_sends() {
+        let mut model = cm("ab|");
+        let update = model.apply_events(vec![InputEvent::Enter]);
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "ab|");
+    }
+
+    #[test]
+    fn applying_only_selection_events_keeps_the_text() {
+        let mut model = cm("abc|");
+        let update = model.apply_events(vec![InputEvent::Select {
+            start: Location::from(1),
+            end: Location::from(2),
+        }]);
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "a{b}|c");
+    }
+
+    #[test]
+    fn applying_an_empty_batch_of_events_changes_nothing() {
+        let mut model = cm("a|bc");
+        let update = model.apply_events(Vec::new());
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "a|bc");
+    }
+
+    #[test]
+    fn plain_text_content_optionally_includes_fallback_formatting() {
+        let model = cm("<blockquote>a<strong>b</strong></blockquote>c|");
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_plain_text(false))
+                .unwrap(),
+            "ab\nc"
+        );
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_plain_text(true)).unwrap(),
+            "> a**b**\nc"
+        );
+    }
+
+    #[test]
+    fn offset_of_finds_text_ignoring_tags() {
+        let model = cm("a<strong>b|c</strong>d bcd");
+        assert_eq!(
+            model.offset_of(&utf16("bcd"), Location::from(0)),
+            Some(Location::from(1))
+        );
+        assert_eq!(
+            model.offset_of(&utf16("bcd"), Location::from(2)),
+            Some(Location::from(5))
+        );
+        assert_eq!(model.offset_of(&utf16("e"), Location::from(0)), None);
+    }
+
+    #[test]
+    fn locations_map_to_lines_split_by_line_breaks() {
+        let model = cm("ab<br />cd|");
+        assert_eq!(model.location_to_line_col(Location::from(1)), (0, 1));
+        assert_eq!(model.location_to_line_col(Location::from(2)), (0, 2));
+        assert_eq!(model.location_to_line_col(Location::from(3)), (1, 0));
+        assert_eq!(model.location_to_line_col(Location::from(5)), (1, 2));
+    }
+
+    #[test]
+    fn a_location_between_blocks_is_at_the_end_of_the_first() {
+        let model = cm("<p>ab</p><p>cd|</p>");
+        assert_eq!(model.location_to_line_col(Location::from(2)), (0, 2));
+        assert_eq!(model.location_to_line_col(Location::from(3)), (1, 1));
+    }
+
+    #[test]
+    fn locations_past_the_end_map_to_the_end_of_the_last_line() {
+        let model = cm("ab<br />cd|");
+        assert_eq!(model.location_to_line_col(Location::from(99)), (1, 2));
+    }
+
+    #[test]
+    fn lines_and_columns_map_to_locations() {
+        let model = cm("<p>abc</p><p>d|</p>");
+        assert_eq!(model.line_col_to_location(0, 2), Location::from(2));
+        assert_eq!(model.line_col_to_location(1, 0), Location::from(3));
+        assert_eq!(model.line_col_to_location(1, 1), Location::from(4));
+    }
+
+    #[test]
+    fn lines_and_columns_past_the_end_are_clamped() {
+        let model = cm("abc<br />d|");
+        assert_eq!(model.line_col_to_location(1, 3), Location::from(5));
+        assert_eq!(model.line_col_to_location(7, 0), Location::from(4));
+    }
+
+    #[test]
+    fn builder_configures_the_model() {
+        let model: ComposerModel<u16> = ComposerModelBuilder::new()
+            .enter_behaviour(EnterBehaviour::NewLine)
+            .max_length(10)
+            .build();
+        assert_eq!(model.config().enter_behaviour, EnterBehaviour::NewLine);
+        assert_eq!(model.config().max_length, Some(10));
+        assert!(model.config().is_enabled(Feature::Bold));
+    }
+
+    #[test]
+    fn enter_does_nothing_by_default() {
+        let mut model = cm("a|b");
+        let update = model.enter();
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "a|b");
+    }
+
+    #[test]
+    fn enter_can_insert_a_line_break() {
+        let mut model = with_config(
+            ComposerModelBuilder::new()
+                .enter_behaviour(EnterBehaviour::NewLine),
+            "a{b}|c",
+        );
+        model.enter();
+        assert_eq!(tx(&model), "a<br />|c");
+    }
+
+    #[test]
+    fn disabled_features_leave_the_content_unchanged() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().features(vec![Feature::Links]),
+            "a{b}|c",
+        );
+        let update = model.bold();
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "a{b}|c");
+    }
+
+    #[test]
+    fn links_with_disallowed_schemes_are_ignored() {
+        let mut model = cm("a|");
+        set_link_with_text(&mut model, "javascript:alert(1)", "x");
+        assert_eq!(tx(&model), "a|");
+    }
+
+    #[test]
+    fn allowed_link_schemes_can_be_configured() {
+        let mut model = with_config(
+            ComposerModelBuilder::new()
+                .allowed_link_schemes(vec![String::from("gopher")]),
+            "a|",
+        );
+        set_link_with_text(&mut model, "https://matrix.org", "x");
+        assert_eq!(tx(&model), "a|");
+        set_link_with_text(&mut model, "gopher://x", "x");
+        assert_eq!(tx(&model), "a<a href=\"gopher://x\">x</a>|");
+    }
+
+    #[test]
+    fn links_with_configured_schemes_are_kept_in_messages() {
+        let model = cm("<a href=\"matrix:r/room:example.org\">room</a>|");
+        assert_eq!(model.validate_message_html(), vec![]);
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_message_html()).unwrap(),
+            "<a href=\"matrix:r/room:example.org\">room</a>"
+        );
+
+        let mut model = with_config(
+            ComposerModelBuilder::new()
+                .allowed_link_schemes(vec![String::from("gopher")]),
+            "|",
+        );
+        model.paste_html(&utf16(
+            "<a href=\"gopher://x\">x</a><a href=\"https://y.org\">y</a>",
+        ));
+        assert_eq!(
+            String::from_utf16(&model.get_content_as_message_html()).unwrap(),
+            "<a href=\"gopher://x\">x</a><a>y</a>"
+        );
+    }
+
+    #[test]
+    fn typing_beyond_the_max_length_is_truncated() {
+        let mut model =
+            with_config(ComposerModelBuilder::new().max_length(4), "ab|");
+        replace_text(&mut model, "cde");
+        assert_eq!(tx(&model), "abcd|");
+        replace_text(&mut model, "f");
+        assert_eq!(tx(&model), "abcd|");
     }
 
-    pub fn get_selection(&self) -> (Location, Location) {
-        (self.start, self.end)
+    #[test]
+    fn truncation_does_not_split_characters() {
+        let mut model =
+            with_config(ComposerModelBuilder::new().max_length(4), "ab|");
+        replace_text(&mut model, "c\u{1F4A9}");
+        assert_eq!(tx(&model), "abc|");
     }
 
-    // Internal functions
-
-    fn create_update_replace_all(&self) -> ComposerUpdate<C> {
-        ComposerUpdate::replace_all(self.html.clone(), self.start, self.end)
+    #[test]
+    fn replacing_a_selection_may_reach_the_max_length() {
+        let mut model =
+            with_config(ComposerModelBuilder::new().max_length(4), "a{bcd}|");
+        replace_text(&mut model, "xyzw");
+        assert_eq!(tx(&model), "axyz|");
     }
-}
-
-impl ComposerModel<u16> {
-    pub fn bold(&mut self) -> ComposerUpdate<u16> {
-        let (s, e) = self.safe_selection();
 
-        // TODO: not a real AST
-        let mut new_html = self.html[..s].to_vec();
-        new_html.extend("<strong>".encode_utf16().collect::<Vec<_>>());
-        new_html.extend_from_slice(&self.html[s..e]);
-        new_html.extend("</strong>".encode_utf16().collect::<Vec<_>>());
-        new_html.extend_from_slice(&self.html[e..]);
-        self.html = new_html;
-
-        /*
-        TODO: probably requires a real AST
-        let start_b = ByteLocation::from(range[0]);
-        let end_b = ByteLocation::from(range[1] + "<strong></strong>".len());
-
-        self.selection_start_codepoint = start_b.codepoint(&self.html);
-        self.selection_end_codepoint = end_b.codepoint(&self.html);
-        */
-
-        self.create_update_replace_all()
+    #[test]
+    fn markdown_shortcuts_are_off_by_default() {
+        let mut model = cm("**a*|");
+        replace_text(&mut model, "*");
+        assert_eq!(tx(&model), "**a**|");
     }
-}
 
-#[cfg(test)]
-mod test {
-    use speculoos::{prelude::*, AssertionFailure, Spec};
+    #[test]
+    fn typing_markdown_bold_formats_it() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().markdown_shortcuts(true),
+            "x **ab*|",
+        );
+        replace_text(&mut model, "*");
+        assert_eq!(tx(&model), "x <strong>ab|</strong>");
+    }
 
-    use crate::Location;
+    #[test]
+    fn typing_markdown_italic_formats_it() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().markdown_shortcuts(true),
+            "*ab|",
+        );
+        replace_text(&mut model, "*");
+        assert_eq!(tx(&model), "<em>ab|</em>");
+    }
 
-    use super::ComposerModel;
+    #[test]
+    fn unbalanced_or_spaced_markdown_is_left_alone() {
+        let mut model = with_config(
+            ComposerModelBuilder::new().markdown_shortcuts(true),
+            "**ab|",
+        );
+        replace_text(&mut model, "*");
+        assert_eq!(tx(&model), "**ab*|");
+        let mut model = with_config(
+            ComposerModelBuilder::new().markdown_shortcuts(true),
+            "* a |",
+        );
+        replace_text(&mut model, "*");
+        assert_eq!(tx(&model), "* a *|");
+    }
 
     #[test]
-    fn typing_a_character_into_an_empty_box_appends_it() {
-        let mut model = cm("|");
-        replace_text(&mut model, "v");
-        assert_eq!(tx(&model), "v|");
+    fn menu_state_shows_the_formatting_at_the_cursor() {
+        let mut model = cm("a<strong>b|</strong>");
+        let update = model.replace_text(&utf16("c"));
+        assert_eq!(
+            update.menu_state,
+            MenuState::Update {
+                active_features: vec![Feature::Bold],
+                partially_active_features: vec![],
+                can_undo: true,
+                can_redo: false,
+            }
+        );
     }
 
     #[test]
-    fn typing_a_character_at_the_end_appends_it() {
-        let mut model = cm("abc|");
-        replace_text(&mut model, "d");
-        assert_eq!(tx(&model), "abcd|");
+    fn menu_state_shows_formatting_shared_by_the_whole_selection() {
+        let mut model = cm("{a<strong>b</strong>}|");
+        let update = model.bold();
+        assert_eq!(
+            update.menu_state,
+            MenuState::Update {
+                active_features: vec![Feature::Bold],
+                partially_active_features: vec![],
+                can_undo: true,
+                can_redo: false,
+            }
+        );
     }
 
     #[test]
-    fn typing_a_character_in_the_middle_inserts_it() {
-        let mut model = cm("|abc");
-        replace_text(&mut model, "Z");
-        assert_eq!(tx(&model), "Z|abc");
+    fn typing_a_mention_suggests_completing_it() {
+        let mut model = cm("hi @a|");
+        let update = model.replace_text(&utf16("l"));
+        assert_eq!(
+            update.menu_action,
+            MenuAction::Suggestion(SuggestionPattern {
+                key: PatternKey::At,
+                text: utf16("al"),
+                start: Location::from(3),
+                end: Location::from(6),
+            })
+        );
     }
 
     #[test]
-    fn selecting_past_the_end_is_harmless() {
+    fn a_room_pattern_is_suggested_from_its_key() {
         let mut model = cm("|");
-        model.select(Location::from(7), Location::from(7));
-        replace_text(&mut model, "Z");
-        assert_eq!(tx(&model), "Z|");
+        let update = model.replace_text(&utf16("#"));
+        assert!(matches!(
+            update.menu_action,
+            MenuAction::Suggestion(SuggestionPattern {
+                key: PatternKey::Hash,
+                ..
+            })
+        ));
     }
 
     #[test]
-    fn replacing_a_selection_with_a_character() {
-        let mut model = cm("abc{def}|ghi");
-        replace_text(&mut model, "Z");
-        assert_eq!(tx(&model), "abcZ|ghi");
+    fn commands_are_only_suggested_at_the_start() {
+        let mut model = cm("/jo|");
+        let update = model.replace_text(&utf16("i"));
+        assert!(matches!(
+            update.menu_action,
+            MenuAction::Suggestion(SuggestionPattern {
+                key: PatternKey::Slash,
+                ..
+            })
+        ));
+        let mut model = cm("a /jo|");
+        let update = model.replace_text(&utf16("i"));
+        assert_eq!(update.menu_action, MenuAction::None);
     }
 
     #[test]
-    fn replacing_a_backwards_selection_with_a_character() {
-        let mut model = cm("abc|{def}ghi");
-        replace_text(&mut model, "Z");
-        assert_eq!(tx(&model), "abcZ|ghi");
+    fn finishing_a_word_ends_the_suggestion() {
+        let mut model = cm("@al|");
+        let update = model.replace_text(&utf16(" "));
+        assert_eq!(update.menu_action, MenuAction::None);
     }
 
     #[test]
-    fn typing_a_character_after_a_multi_codepoint_character() {
-        // Woman Astronaut:
-        // Woman+Dark Skin Tone+Zero Width Joiner+Rocket
-        let mut model = cm("\u{1F469}\u{1F3FF}\u{200D}\u{1F680}|");
-        replace_text(&mut model, "Z");
-        assert_eq!(tx(&model), "\u{1F469}\u{1F3FF}\u{200D}\u{1F680}Z|");
+    fn inserting_an_attachment_placeholder_requests_its_resolution() {
+        let mut model = cm("a{b}|c");
+        let update = model.insert_attachment_placeholder("f1", &utf16("x"));
+        assert_eq!(
+            tx(&model),
+            "a<mx-attachment data-id=\"f1\" data-label=\"x\" />|c"
+        );
+        assert_eq!(update.actions.len(), 1);
+        assert_eq!(update.actions[0].action_id, "f1");
+        assert!(matches!(
+            update.actions[0].action,
+            ActionRequest::ResolveAttachment
+        ));
     }
 
     #[test]
-    fn typing_a_character_in_a_range_inserts_it() {
-        let mut model = cm("0123456789|");
-        let new_text = "654".encode_utf16().collect::<Vec<u16>>();
-        model.replace_text_in(&new_text, 4, 7);
-        assert_eq!(tx(&model), "0123654|789");
+    fn attachment_placeholders_are_left_out_of_messages() {
+        let mut model = cm("a|");
+        model.insert_attachment_placeholder("f1", &utf16("x"));
+        assert_eq!(model.get_content_as_message_html(), utf16("a"));
     }
 
     #[test]
-    fn backspacing_a_character_at_the_end_deletes_it() {
-        let mut model = cm("abc|");
-        model.backspace();
-        assert_eq!(tx(&model), "ab|");
+    fn a_ready_attachment_replaces_its_placeholder() {
+        let mut model = cm("a|");
+        model.insert_attachment_placeholder("f1", &utf16("x"));
+        replace_text(&mut model, "b");
+        model.action_response(
+            String::from("f1"),
+            ActionResponse::AttachmentReady {
+                html: String::from("<img src=\"mxc://s/m\" />"),
+            },
+        );
+        assert_eq!(tx(&model), "a<img src=\"mxc://s/m\" />b|");
     }
 
     #[test]
-    fn backspacing_a_character_at_the_beginning_does_nothing() {
-        let mut model = cm("|abc");
-        model.backspace();
-        assert_eq!(tx(&model), "|abc");
+    fn a_removed_attachment_removes_only_its_placeholder() {
+        let mut model = cm("a|");
+        model.insert_attachment_placeholder("f1", &utf16("x"));
+        model.insert_attachment_placeholder("f2", &utf16("y"));
+        model.action_response(
+            String::from("f1"),
+            ActionResponse::AttachmentRemoved,
+        );
+        assert_eq!(
+            tx(&model),
+            "a<mx-attachment data-id=\"f2\" data-label=\"y\" />|"
+        );
     }
 
     #[test]
-    fn backspacing_a_character_in_the_middle_deletes_it() {
-        let mut model = cm("ab|c");
-        model.backspace();
-        assert_eq!(tx(&model), "a|c");
+    fn responding_about_an_unknown_attachment_changes_nothing() {
+        let mut model = cm("a|");
+        let update = model.action_response(
+            String::from("f1"),
+            ActionResponse::AttachmentRemoved,
+        );
+        assert!(matches!(update.text_update, TextUpdate::Keep));
     }
 
     #[test]
-    fn backspacing_a_selection_deletes_it() {
-        let mut model = cm("a{bc}|");
-        model.backspace();
-        assert_eq!(tx(&model), "a|");
+    fn a_restored_snapshot_keeps_its_content_and_selection() {
+        let model = cm("a<strong>b{c</strong>d}|");
+        let snapshot = model.snapshot(10);
+        let mut restored = ComposerModel::new();
+        restored.restore_snapshot(&snapshot);
+        assert_eq!(tx(&restored), "a<strong>b{c</strong>d}|");
     }
 
     #[test]
-    fn backspacing_a_backwards_selection_deletes_it() {
-        let mut model = cm("a|{bc}");
-        model.backspace();
-        assert_eq!(tx(&model), "a|");
+    fn a_restored_snapshot_can_be_undone_and_redone() {
+        let mut model = cm("|");
+        model.replace_text(&utf16("a"));
+        model.replace_text(&utf16("b"));
+        model.replace_text(&utf16("c"));
+        model.undo();
+        let snapshot = model.snapshot(10);
+
+        let mut restored = ComposerModel::new();
+        restored.restore_snapshot(&snapshot);
+        assert_eq!(tx(&restored), "ab|");
+        restored.undo();
+        assert_eq!(tx(&restored), "a|");
+        restored.undo();
+        assert_eq!(tx(&restored), "|");
+        assert!(matches!(restored.undo().text_update, TextUpdate::Keep));
+        restored.redo();
+        restored.redo();
+        restored.redo();
+        assert_eq!(tx(&restored), "abc|");
     }
 
     #[test]
-    fn deleting_a_character_at_the_end_does_nothing() {
-        let mut model = cm("abc|");
-        model.delete();
-        assert_eq!(tx(&model), "abc|");
+    fn a_snapshot_nested_too_deeply_is_not_restored() {
+        let mut model = cm("|");
+        model.replace_text(&utf16("a"));
+        let mut snapshot = model.snapshot(10);
+        snapshot.undo[0].html =
+            utf16(&format!("{}b{}", "<em>".repeat(50), "</em>".repeat(50)));
+
+        let mut restored =
+            with_config(ComposerModelBuilder::new().max_depth(5), "c|");
+        let update = restored.restore_snapshot(&snapshot);
+        assert_eq!(update.error, Some(ComposerError::TooDeep));
+        assert_eq!(tx(&restored), "c|");
+        assert!(matches!(restored.undo().text_update, TextUpdate::Keep));
     }
 
     #[test]
-    fn deleting_a_character_at_the_beginning_deletes_it() {
-        let mut model = cm("|abc");
-        model.delete();
-        assert_eq!(tx(&model), "|bc");
+    fn content_nested_thousands_deep_can_be_edited() {
+        let mut model =
+            with_config(ComposerModelBuilder::new().max_depth(usize::MAX), "|");
+        for (open, close) in
+            [("<em>", "</em>"), ("<blockquote>", "</blockquote>")]
+        {
+            let html = open.repeat(20_000) + "abc" + &close.repeat(20_000);
+            let update = model.set_content_from_html(&utf16(&html));
+            assert_eq!(update.error, None);
+            assert_eq!(model.get_content_as_plain_text(false), utf16("abc"));
+            model.select(Location::from(1), Location::from(2));
+            model.bold();
+            model.replace_text(&utf16("x"));
+            model.enter();
+            model.backspace();
+            model.undo();
+            model.redo();
+            model.set_plain_text_mode(true);
+            model.set_plain_text_mode(false);
+            model.paste_html(&utf16(&html));
+            let snapshot = model.snapshot(10);
+            model.restore_snapshot(&snapshot);
+            let message = model.get_content_as_message_html();
+            assert!(String::from_utf16(&message).unwrap().contains("abc"));
+        }
     }
 
     #[test]
-    fn deleting_a_character_in_the_middle_deletes_it() {
-        let mut model = cm("a|bc");
-        model.delete();
-        assert_eq!(tx(&model), "a|c");
+    fn a_snapshot_keeps_only_the_most_recent_history() {
+        let mut model = cm("|");
+        model.replace_text(&utf16("a"));
+        model.replace_text(&utf16("b"));
+        model.replace_text(&utf16("c"));
+        let snapshot = model.snapshot(2);
+        assert_eq!(snapshot.undo.len(), 2);
+
+        let mut restored = ComposerModel::new();
+        restored.restore_snapshot(&snapshot);
+        restored.undo();
+        restored.undo();
+        assert_eq!(tx(&restored), "a|");
+        assert!(matches!(restored.undo().text_update, TextUpdate::Keep));
     }
 
     #[test]
-    fn deleting_a_selection_deletes_it() {
-        let mut model = cm("a{bc}|");
-        model.delete();
-        assert_eq!(tx(&model), "a|");
+    fn bolding_bold_text_makes_it_not_bold() {
+        let mut model = cm("<strong>a{bc}|</strong>d");
+        model.bold();
+        assert_eq!(tx(&model), "<strong>a{</strong>bc}|d");
+        model.bold();
+        assert_eq!(tx(&model), "<strong>a{bc}|</strong>d");
     }
 
     #[test]
-    fn deleting_a_backwards_selection_deletes_it() {
-        let mut model = cm("a|{bc}");
-        model.delete();
-        assert_eq!(tx(&model), "a|");
+    fn bolding_part_bold_text_bolds_it_without_nesting() {
+        let mut model = cm("{a<strong>b}|c</strong>");
+        model.bold();
+        assert_eq!(tx(&model), "<strong>{ab}|c</strong>");
     }
 
     #[test]
-    fn deleting_a_range_removes_it() {
-        let mut model = cm("abcd|");
-        model.delete_in(1, 3);
-        assert_eq!(tx(&model), "a|d");
+    fn undoing_bold_across_nodes_restores_the_content_in_one_step() {
+        let mut model = cm("a{b<em>c</em><a href=\"x\">d</a>e}|f");
+        model.bold();
+        model.undo();
+        assert_eq!(tx(&model), "a{b<em>c</em><a href=\"x\">d</a>e}|f");
+        assert_eq!(model.history.take_undo().map(|_| ()), None);
     }
 
     #[test]
-    fn selecting_ascii_characters() {
-        let mut model = cm("abcdefgh|");
-        model.select(Location::from(0), Location::from(1));
-        assert_eq!(tx(&model), "{a}|bcdefgh");
-
-        model.select(Location::from(1), Location::from(3));
-        assert_eq!(tx(&model), "a{bc}|defgh");
+    fn redoing_bold_reapplies_it() {
+        let mut model = cm("a{b<em>c</em>}|d");
+        model.bold();
+        let bolded = tx(&model);
+        model.undo();
+        model.redo();
+        assert_eq!(tx(&model), bolded);
+    }
 
-        model.select(Location::from(4), Location::from(8));
-        assert_eq!(tx(&model), "abcd{efgh}|");
+    #[test]
+    fn undoing_typing_restores_the_previous_text_and_cursor() {
+        let mut model = cm("ab|");
+        replace_text(&mut model, "c");
+        replace_text(&mut model, "d");
+        model.undo();
+        assert_eq!(tx(&model), "abc|");
+        model.undo();
+        assert_eq!(tx(&model), "ab|");
+        model.redo();
+        model.redo();
+        assert_eq!(tx(&model), "abcd|");
+    }
 
-        model.select(Location::from(4), Location::from(9));
-        assert_eq!(tx(&model), "abcd{efgh}|");
+    #[test]
+    fn undoing_restores_a_selection_spanning_several_nodes() {
+        let mut model = cm("a{b<em>c</em><strong>d</strong>e}|f");
+        replace_text(&mut model, "X");
+        model.undo();
+        assert_eq!(tx(&model), "a{b<em>c</em><strong>d</strong>e}|f");
     }
 
     #[test]
-    fn selecting_single_utf16_code_unit_characters() {
-        let mut model = cm("\u{03A9}\u{03A9}\u{03A9}|");
+    fn undoing_restores_a_backwards_selection() {
+        let mut model = cm("a|{b<em>c</em>d}e");
+        model.delete();
+        model.undo();
+        assert_eq!(tx(&model), "a|{b<em>c</em>d}e");
+    }
 
-        model.select(Location::from(0), Location::from(1));
-        assert_eq!(tx(&model), "{\u{03A9}}|\u{03A9}\u{03A9}");
+    #[test]
+    fn redoing_restores_the_selection_after_the_edit() {
+        let mut model = cm("a{b<em>c</em>d}|e");
+        model.bold();
+        let bolded = tx(&model);
+        model.select(Location::from(0), Location::from(0));
+        model.undo();
+        assert_eq!(tx(&model), "a{b<em>c</em>d}|e");
+        model.select(Location::from(4), Location::from(4));
+        model.redo();
+        assert_eq!(tx(&model), bolded);
+    }
 
-        model.select(Location::from(0), Location::from(3));
-        assert_eq!(tx(&model), "{\u{03A9}\u{03A9}\u{03A9}}|");
+    #[test]
+    fn undoing_and_redoing_typing_ignores_where_the_cursor_moved_since() {
+        let mut model = cm("ab|");
+        replace_text(&mut model, "c");
+        model.select(Location::from(0), Location::from(0));
+        model.undo();
+        assert_eq!(tx(&model), "ab|");
+        model.select(Location::from(1), Location::from(1));
+        model.redo();
+        assert_eq!(tx(&model), "abc|");
+    }
 
-        model.select(Location::from(1), Location::from(2));
-        assert_eq!(tx(&model), "\u{03A9}{\u{03A9}}|\u{03A9}");
+    #[test]
+    fn a_new_edit_discards_undone_edits() {
+        let mut model = cm("a|");
+        replace_text(&mut model, "b");
+        model.undo();
+        replace_text(&mut model, "c");
+        let update = model.redo();
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "ac|");
     }
 
     #[test]
-    fn selecting_multiple_utf16_code_unit_characters() {
-        let mut model = cm("\u{1F4A9}\u{1F4A9}\u{1F4A9}|");
+    fn undoing_with_no_history_changes_nothing() {
+        let mut model = cm("a|");
+        let update = model.undo();
+        assert!(matches!(update.text_update, TextUpdate::Keep));
+        assert_eq!(tx(&model), "a|");
+    }
 
-        model.select(Location::from(0), Location::from(2));
-        assert_eq!(tx(&model), "{\u{1F4A9}}|\u{1F4A9}\u{1F4A9}");
+    #[test]
+    fn setting_the_content_clears_the_history() {
+        let mut model = cm("a|");
+        replace_text(&mut model, "b");
+        model.set_content_from_html(&utf16("x"));
+        model.undo();
+        assert_eq!(tx(&model), "|x");
+    }
 
-        model.select(Location::from(0), Location::from(6));
-        assert_eq!(tx(&model), "{\u{1F4A9}\u{1F4A9}\u{1F4A9}}|");
+    // Test utils
 
-        model.select(Location::from(2), Location::from(4));
-        assert_eq!(tx(&model), "\u{1F4A9}{\u{1F4A9}}|\u{1F4A9}");
+    /**
+     * Create a ComposerModel from builder, with content as described for
+     * cm().
+     */
+    fn suggestion(start: usize, end: usize) -> SuggestionPattern<u16> {
+        SuggestionPattern {
+            key: PatternKey::At,
+            text: Vec::new(),
+            start: Location::from(start),
+            end: Location::from(end),
+        }
     }
 
-    #[test]
-    fn selecting_complex_characters() {
-        let mut model =
-            cm("aaa\u{03A9}bbb\u{1F469}\u{1F3FF}\u{200D}\u{1F680}ccc|");
+    fn with_config(
+        builder: ComposerModelBuilder,
+        text: &str,
+    ) -> ComposerModel<u16> {
+        let mut model = builder.build();
+        let content = cm(text);
+        model.dom = content.dom;
+        model.start = content.start;
+        model.end = content.end;
+        model
+    }
 
-        model.select(Location::from(0), Location::from(3));
-        assert_eq!(
-            tx(&model),
-            "{aaa}|\u{03A9}bbb\u{1F469}\u{1F3FF}\u{200D}\u{1F680}ccc"
-        );
+    fn utf16(text: &str) -> Vec<u16> {
+        text.encode_utf16().collect()
+    }
 
-        model.select(Location::from(0), Location::from(4));
-        assert_eq!(
-            tx(&model),
-            "{aaa\u{03A9}}|bbb\u{1F469}\u{1F3FF}\u{200D}\u{1F680}ccc"
+    fn set_link_with_text(
+        model: &mut ComposerModel<u16>,
+        link: &str,
+        text: &str,
+    ) {
+        model.set_link_with_text(
+            &link.encode_utf16().collect::<Vec<u16>>(),
+            &text.encode_utf16().collect::<Vec<u16>>(),
         );
+    }
 
-        model.select(Location::from(7), Location::from(14));
-        assert_eq!(
-            tx(&model),
-            "aaa\u{03A9}bbb{\u{1F469}\u{1F3FF}\u{200D}\u{1F680}}|ccc"
+    fn insert_at(model: &mut ComposerModel<u16>, location: usize, html: &str) {
+        model.insert_at(
+            Location::from(location),
+            &html.encode_utf16().collect::<Vec<u16>>(),
         );
+    }
 
-        model.select(Location::from(7), Location::from(15));
-        assert_eq!(
-            tx(&model),
-            "aaa\u{03A9}bbb{\u{1F469}\u{1F3FF}\u{200D}\u{1F680}c}|cc"
+    fn replace_text_at_time(
+        model: &mut ComposerModel<u16>,
+        new_text: &str,
+        time_ms: u64,
+    ) {
+        model.replace_text_at_time(
+            &new_text.encode_utf16().collect::<Vec<u16>>(),
+            time_ms,
         );
     }
 
-    #[test]
-    fn bolding_ascii_adds_strong_tags() {
-        let mut model = cm("aa{bb}|cc");
-        model.bold();
-        // TODO: because it's not an AST
-        assert_eq!(tx(&model), "aa{<s}|trong>bb</strong>cc");
-
-        let mut model = cm("aa|{bb}cc");
-        model.bold();
-        assert_eq!(tx(&model), "aa|{<s}trong>bb</strong>cc");
+    fn update_link(model: &mut ComposerModel<u16>, url: &str, text: &str) {
+        model.update_link(
+            &url.encode_utf16().collect::<Vec<u16>>(),
+            &text.encode_utf16().collect::<Vec<u16>>(),
+        );
     }
 
-    // Test utils
-
     fn replace_text(model: &mut ComposerModel<u16>, new_text: &str) {
         model.replace_text(&new_text.encode_utf16().collect::<Vec<u16>>());
     }
@@ -431,49 +6893,54 @@ mod test {
     }
 
     /**
-     * Create a ComposerModel from a text representation.
+     * Create a ComposerModel from a text representation: some HTML
+     * containing a cursor ('|') and optionally a selection ('{..}').
      */
     fn cm(text: &str) -> ComposerModel<u16> {
-        let text: Vec<u16> = text.encode_utf16().collect();
+        let mut model = ComposerModel::new();
+        model.set_content_from_html(&utf16(text));
 
-        fn find(haystack: &[u16], needle: &str) -> Option<usize> {
-            let needle = needle.encode_utf16().collect::<Vec<u16>>()[0];
-            for (i, &ch) in haystack.iter().enumerate() {
-                if ch == needle {
-                    return Some(i);
+        fn collect_text(dom: &Dom<u16>, id: NodeId, out: &mut Vec<u16>) {
+            for &child in dom.children(id) {
+                match dom.node(child) {
+                    DomNode::Text(t) => out.extend_from_slice(t.data()),
+                    DomNode::Container(c) if c.is_void() => out.push(0),
+                    DomNode::Container(_) => collect_text(dom, child, out),
                 }
             }
-            None
         }
+        let mut content = Vec::new();
+        collect_text(&model.dom, model.dom.document(), &mut content);
 
-        let curs = find(&text, "|").expect(&format!(
-            "ComposerModel text did not contain a '|' symbol: '{}'",
-            String::from_utf16(&text)
-                .expect("ComposerModel text was not UTF-16"),
-        ));
+        fn find(haystack: &[u16], needle: char) -> Option<usize> {
+            haystack
+                .iter()
+                .position(|&ch| u32::from(ch) == needle as u32)
+        }
 
-        let s = find(&text, "{");
-        let e = find(&text, "}");
+        let curs = find(&content, '|').unwrap_or_else(|| {
+            panic!(
+                "ComposerModel text did not contain a '|' symbol: '{}'",
+                text,
+            )
+        });
 
-        let mut ret = ComposerModel::new();
+        let s = find(&content, '{');
+        let e = find(&content, '}');
 
+        let mut markers = vec![curs];
         if let (Some(s), Some(e)) = (s, e) {
+            markers.extend([s, e]);
             if curs == e + 1 {
                 // Cursor after end: foo{bar}|baz
                 // The { made an extra codeunit - move the end back 1
-                ret.start = Location::from(s);
-                ret.end = Location::from(e - 1);
-                ret.html = text[..s].to_vec();
-                ret.html.extend_from_slice(&text[s + 1..e]);
-                ret.html.extend_from_slice(&text[curs + 1..]);
-            } else if curs == s - 1 {
+                model.start = Location::from(s);
+                model.end = Location::from(e - 1);
+            } else if curs + 1 == s {
                 // Cursor before beginning: foo|{bar}baz
                 // The |{ made an extra 2 codeunits - move the end back 2
-                ret.start = Location::from(e - 2);
-                ret.end = Location::from(curs);
-                ret.html = text[..curs].to_vec();
-                ret.html.extend_from_slice(&text[s + 1..e]);
-                ret.html.extend_from_slice(&text[e + 1..]);
+                model.start = Location::from(e - 2);
+                model.end = Location::from(curs);
             } else {
                 panic!(
                     "The cursor ('|') must always be directly before or after \
@@ -482,73 +6949,72 @@ mod test {
                 )
             }
         } else {
-            ret.start = Location::from(curs);
-            ret.end = Location::from(curs);
-            ret.html = text[..curs].to_vec();
-            ret.html.extend_from_slice(&text[curs + 1..]);
+            model.start = Location::from(curs);
+            model.end = Location::from(curs);
+        }
+
+        markers.sort_unstable();
+        for marker in markers.into_iter().rev() {
+            model.dom.delete_range(marker, marker + 1);
         }
 
-        ret
+        model
     }
 
     /**
      * Convert a ComposerModel to a text representation.
      */
     fn tx(model: &ComposerModel<u16>) -> String {
-        let mut ret;
-        if model.start == model.end {
-            ret =
-                String::from_utf16(&model.html[..model.start.into()]).unwrap();
-            ret.push('|');
-            ret +=
-                &String::from_utf16(&model.html[model.start.into()..]).unwrap();
+        let mut dom = model.dom.clone();
+        let (s, e) = model.safe_selection();
+        if s == e {
+            dom.insert_text(s, &utf16("|"));
+        } else if model.start < model.end {
+            dom.insert_text(e, &utf16("}|"));
+            dom.insert_text(s, &utf16("{"));
         } else {
-            let (s, e) = model.safe_selection();
-
-            ret = String::from_utf16(&model.html[..s]).unwrap();
-            if model.start < model.end {
-                ret.push('{');
-            } else {
-                ret += "|{";
-            }
-            ret += &String::from_utf16(&model.html[s..e]).unwrap();
-            if model.start < model.end {
-                ret += "}|";
-            } else {
-                ret.push('}');
-            }
-            ret += &String::from_utf16(&model.html[e..]).unwrap()
+            dom.insert_text(e, &utf16("}"));
+            dom.insert_text(s, &utf16("|{"));
         }
-        ret
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
     }
 
     #[test]
     fn cm_creates_correct_component_model() {
         assert_eq!(cm("|").start, 0);
         assert_eq!(cm("|").end, 0);
-        assert_eq!(cm("|").html, &[]);
+        assert_eq!(cm("|").get_html(), Vec::<u16>::new());
 
         assert_eq!(cm("a|").start, 1);
         assert_eq!(cm("a|").end, 1);
-        assert_eq!(cm("a|").html, "a".encode_utf16().collect::<Vec<_>>());
+        assert_eq!(cm("a|").get_html(), "a".encode_utf16().collect::<Vec<_>>());
 
         assert_eq!(cm("a|b").start, 1);
         assert_eq!(cm("a|b").end, 1);
-        assert_eq!(cm("a|b").html, "ab".encode_utf16().collect::<Vec<_>>());
+        assert_eq!(
+            cm("a|b").get_html(),
+            "ab".encode_utf16().collect::<Vec<_>>()
+        );
 
         assert_eq!(cm("|ab").start, 0);
         assert_eq!(cm("|ab").end, 0);
-        assert_eq!(cm("|ab").html, "ab".encode_utf16().collect::<Vec<_>>());
+        assert_eq!(
+            cm("|ab").get_html(),
+            "ab".encode_utf16().collect::<Vec<_>>()
+        );
 
         assert_eq!(cm("foo|").start, 3);
         assert_eq!(cm("foo|").end, 3);
-        assert_eq!(cm("foo|").html, "foo".encode_utf16().collect::<Vec<_>>());
+        assert_eq!(
+            cm("foo|").get_html(),
+            "foo".encode_utf16().collect::<Vec<_>>()
+        );
 
         let t1 = cm("foo|\u{1F4A9}bar");
         assert_eq!(t1.start, 3);
         assert_eq!(t1.end, 3);
         assert_eq!(
-            t1.html,
+            t1.get_html(),
             "foo\u{1F4A9}bar".encode_utf16().collect::<Vec<_>>()
         );
 
@@ -556,57 +7022,63 @@ mod test {
         assert_eq!(t2.start, 5);
         assert_eq!(t2.end, 5);
         assert_eq!(
-            t2.html,
+            t2.get_html(),
             "foo\u{1F4A9}bar".encode_utf16().collect::<Vec<_>>()
         );
 
         assert_eq!(cm("foo|\u{1F4A9}").start, 3);
         assert_eq!(cm("foo|\u{1F4A9}").end, 3);
         assert_eq!(
-            cm("foo|\u{1F4A9}").html,
+            cm("foo|\u{1F4A9}").get_html(),
             "foo\u{1F4A9}".encode_utf16().collect::<Vec<_>>()
         );
 
         assert_eq!(cm("foo\u{1F4A9}|").start, 5);
         assert_eq!(cm("foo\u{1F4A9}|").end, 5);
         assert_eq!(
-            cm("foo\u{1F4A9}|").html,
+            cm("foo\u{1F4A9}|").get_html(),
             "foo\u{1F4A9}".encode_utf16().collect::<Vec<_>>()
         );
 
         assert_eq!(cm("|\u{1F4A9}bar").start, 0);
         assert_eq!(cm("|\u{1F4A9}bar").end, 0);
         assert_eq!(
-            cm("|\u{1F4A9}bar").html,
+            cm("|\u{1F4A9}bar").get_html(),
             "\u{1F4A9}bar".encode_utf16().collect::<Vec<_>>()
         );
 
         assert_eq!(cm("\u{1F4A9}|bar").start, 2);
         assert_eq!(cm("\u{1F4A9}|bar").end, 2);
         assert_eq!(
-            cm("\u{1F4A9}|bar").html,
+            cm("\u{1F4A9}|bar").get_html(),
             "\u{1F4A9}bar".encode_utf16().collect::<Vec<_>>()
         );
 
         assert_eq!(cm("{a}|").start, 0);
         assert_eq!(cm("{a}|").end, 1);
-        assert_eq!(cm("{a}|").html, "a".encode_utf16().collect::<Vec<_>>());
+        assert_eq!(
+            cm("{a}|").get_html(),
+            "a".encode_utf16().collect::<Vec<_>>()
+        );
 
         assert_eq!(cm("|{a}").start, 1);
         assert_eq!(cm("|{a}").end, 0);
-        assert_eq!(cm("|{a}").html, "a".encode_utf16().collect::<Vec<_>>());
+        assert_eq!(
+            cm("|{a}").get_html(),
+            "a".encode_utf16().collect::<Vec<_>>()
+        );
 
         assert_eq!(cm("abc{def}|ghi").start, 3);
         assert_eq!(cm("abc{def}|ghi").end, 6);
         assert_eq!(
-            cm("abc{def}|ghi").html,
+            cm("abc{def}|ghi").get_html(),
             "abcdefghi".encode_utf16().collect::<Vec<_>>()
         );
 
         assert_eq!(cm("abc|{def}ghi").start, 6);
         assert_eq!(cm("abc|{def}ghi").end, 3);
         assert_eq!(
-            cm("abc|{def}ghi").html,
+            cm("abc|{def}ghi").get_html(),
             "abcdefghi".encode_utf16().collect::<Vec<_>>()
         );
 
@@ -614,7 +7086,7 @@ mod test {
         assert_eq!(t3.start, 2);
         assert_eq!(t3.end, 5);
         assert_eq!(
-            t3.html,
+            t3.get_html(),
             "\u{1F4A9}defghi".encode_utf16().collect::<Vec<_>>()
         );
 
@@ -622,7 +7094,7 @@ mod test {
         assert_eq!(t4.start, 5);
         assert_eq!(t4.end, 2);
         assert_eq!(
-            t4.html,
+            t4.get_html(),
             "\u{1F4A9}defghi".encode_utf16().collect::<Vec<_>>()
         );
 
@@ -630,7 +7102,7 @@ mod test {
         assert_eq!(t5.start, 3);
         assert_eq!(t5.end, 7);
         assert_eq!(
-            t5.html,
+            t5.get_html(),
             "abcd\u{1F4A9}fghi".encode_utf16().collect::<Vec<_>>()
         );
 
@@ -638,7 +7110,7 @@ mod test {
         assert_eq!(t6.start, 7);
         assert_eq!(t6.end, 3);
         assert_eq!(
-            t6.html,
+            t6.get_html(),
             "abcd\u{1F4A9}fghi".encode_utf16().collect::<Vec<_>>()
         );
 
@@ -646,7 +7118,7 @@ mod test {
         assert_eq!(t7.start, 3);
         assert_eq!(t7.end, 6);
         assert_eq!(
-            t7.html,
+            t7.get_html(),
             "abcdef\u{1F4A9}ghi".encode_utf16().collect::<Vec<_>>()
         );
 
@@ -654,7 +7126,7 @@ mod test {
         assert_eq!(t8.start, 6);
         assert_eq!(t8.end, 3);
         assert_eq!(
-            t8.html,
+            t8.get_html(),
             "abcdef\u{1F4A9}ghi".encode_utf16().collect::<Vec<_>>()
         );
     }
@@ -682,4 +7154,12 @@ mod test {
         assert_that!("abc{def}|\u{1F4A9}ghi").roundtrips();
         assert_that!("abc|{def}\u{1F4A9}ghi").roundtrips();
     }
+
+    struct Recorder(Arc<Mutex<Vec<DomEvent<u16>>>>);
+
+    impl DomObserver<u16> for Recorder {
+        fn on_event(&mut self, event: &DomEvent<u16>) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
 }
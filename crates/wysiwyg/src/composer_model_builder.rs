@@ -0,0 +1,132 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    CodeUnit, ComposerConfig, ComposerModel, EnterBehaviour, Feature,
+    LinkFormatting, MentionFormatting, UndoCoalescing,
+};
+
+/**
+ * Creates a ComposerModel configured with non-default options. Each method
+ * sets one field of the ComposerConfig, leaving the others at their
+ * defaults.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct ComposerModelBuilder {
+    config: ComposerConfig,
+}
+
+impl ComposerModelBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn features(mut self, features: Vec<Feature>) -> Self {
+        self.config.features = features;
+        self
+    }
+
+    pub fn enter_behaviour(mut self, enter_behaviour: EnterBehaviour) -> Self {
+        self.config.enter_behaviour = enter_behaviour;
+        self
+    }
+
+    pub fn markdown_shortcuts(mut self, markdown_shortcuts: bool) -> Self {
+        self.config.markdown_shortcuts = markdown_shortcuts;
+        self
+    }
+
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.config.max_length = Some(max_length);
+        self
+    }
+
+    pub fn allowed_link_schemes(mut self, schemes: Vec<String>) -> Self {
+        self.config.allowed_link_schemes = schemes;
+        self
+    }
+
+    pub fn double_space_full_stop(mut self, max_interval_ms: u64) -> Self {
+        self.config.double_space_full_stop = Some(max_interval_ms);
+        self
+    }
+
+    pub fn normalize_input(mut self, normalize_input: bool) -> Self {
+        self.config.normalize_input = normalize_input;
+        self
+    }
+
+    pub fn link_formatting(mut self, link_formatting: LinkFormatting) -> Self {
+        self.config.link_formatting = link_formatting;
+        self
+    }
+
+    pub fn mention_formatting(
+        mut self,
+        mention_formatting: MentionFormatting,
+    ) -> Self {
+        self.config.mention_formatting = mention_formatting;
+        self
+    }
+
+    pub fn word_locale(mut self, word_locale: &str) -> Self {
+        self.config.word_locale = Some(String::from(word_locale));
+        self
+    }
+
+    pub fn operation_budget_ms(mut self, operation_budget_ms: u64) -> Self {
+        self.config.operation_budget_ms = Some(operation_budget_ms);
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.config.max_depth = max_depth;
+        self
+    }
+
+    pub fn format_word_at_cursor(
+        mut self,
+        format_word_at_cursor: bool,
+    ) -> Self {
+        self.config.format_word_at_cursor = format_word_at_cursor;
+        self
+    }
+
+    pub fn max_undo_entries(mut self, max_undo_entries: usize) -> Self {
+        self.config.max_undo_entries = Some(max_undo_entries);
+        self
+    }
+
+    pub fn max_link_text_length(mut self, max_link_text_length: usize) -> Self {
+        self.config.max_link_text_length = Some(max_link_text_length);
+        self
+    }
+
+    pub fn max_undo_bytes(mut self, max_undo_bytes: usize) -> Self {
+        self.config.max_undo_bytes = Some(max_undo_bytes);
+        self
+    }
+
+    pub fn undo_coalescing(mut self, undo_coalescing: UndoCoalescing) -> Self {
+        self.config.undo_coalescing = undo_coalescing;
+        self
+    }
+
+    pub fn build<C>(self) -> ComposerModel<C>
+    where
+        C: CodeUnit,
+    {
+        ComposerModel::new_with_config(self.config)
+    }
+}
@@ -0,0 +1,43 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Location;
+
+/**
+ * Everything needed to recreate a composer, including the edits that can
+ * be undone and redone, in a form that platforms can store with a draft
+ * and pass back to ComposerModel::restore_snapshot after a restart.
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ComposerSnapshot<C> {
+    pub current: SnapshotState<C>,
+    /**
+     * The states that undo steps back through, oldest first.
+     */
+    pub undo: Vec<SnapshotState<C>>,
+    /**
+     * The states that redo steps forward through, nearest first.
+     */
+    pub redo: Vec<SnapshotState<C>>,
+}
+
+/**
+ * The content and selection of the composer at one point in its history.
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SnapshotState<C> {
+    pub html: Vec<C>,
+    pub start: Location,
+    pub end: Location,
+}
@@ -12,13 +12,43 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{ComposerAction, Location, MenuState, ReplaceAll, TextUpdate};
+use crate::{
+    ComposerAction, ComposerEffect, ComposerError, DomHandle, Location,
+    MenuAction, MenuState, ReplaceAll, SanitizeReport, TextUpdate,
+    UpdateTextNode,
+};
 
 #[derive(Debug, Clone)]
 pub struct ComposerUpdate<C> {
     pub text_update: TextUpdate<C>,
     pub menu_state: MenuState,
+    pub menu_action: MenuAction<C>,
     pub actions: Vec<ComposerAction>,
+    /**
+     * Problems the model found in its own state and recovered from, for
+     * the platform to log or report.
+     */
+    pub diagnostics: Vec<String>,
+    /**
+     * Set if the operation failed, in which case the content and selection
+     * are as they were before it, and text_update describes them.
+     */
+    pub error: Option<ComposerError>,
+    /**
+     * What was removed from HTML given to the operation, e.g. pasted, to
+     * keep to what the Matrix specification allows.
+     */
+    pub sanitize_report: SanitizeReport,
+    /**
+     * The model's revision after the operation (see
+     * ComposerModel::revision).
+     */
+    pub revision: u64,
+    /**
+     * Everything the updates this was chained from did, in order, if it
+     * was made by then.
+     */
+    chained: Option<Vec<ComposerEffect<C>>>,
 }
 
 impl<C> ComposerUpdate<C> {
@@ -26,7 +56,13 @@ impl<C> ComposerUpdate<C> {
         Self {
             text_update: TextUpdate::<C>::Keep,
             menu_state: MenuState::None,
+            menu_action: MenuAction::None,
             actions: Vec::new(),
+            diagnostics: Vec::new(),
+            error: None,
+            sanitize_report: SanitizeReport::default(),
+            revision: 0,
+            chained: None,
         }
     }
 
@@ -42,7 +78,120 @@ impl<C> ComposerUpdate<C> {
                 end,
             }),
             menu_state: MenuState::None,
+            menu_action: MenuAction::None,
             actions: Vec::new(),
+            diagnostics: Vec::new(),
+            error: None,
+            sanitize_report: SanitizeReport::default(),
+            revision: 0,
+            chained: None,
+        }
+    }
+
+    /**
+     * Combine this with next, the update from a later step of the same
+     * operation, so that the platform can act on both from one update.
+     * The fields describe the net result: the latest text_update,
+     * menu_state and menu_action that change anything, and everything
+     * else from both. If both steps patched different text nodes, only
+     * effects lists both patches.
+     */
+    pub fn then(self, next: ComposerUpdate<C>) -> Self
+    where
+        C: Clone,
+    {
+        let mut effects = self.effects();
+        effects.extend(next.effects());
+        let mut actions = self.actions;
+        actions.extend(next.actions);
+        let mut diagnostics = self.diagnostics;
+        diagnostics.extend(next.diagnostics);
+        let mut sanitize_report = self.sanitize_report;
+        sanitize_report.extend(next.sanitize_report);
+        Self {
+            text_update: match next.text_update {
+                TextUpdate::Keep => self.text_update,
+                text_update => text_update,
+            },
+            menu_state: match next.menu_state {
+                MenuState::None => self.menu_state,
+                menu_state => menu_state,
+            },
+            menu_action: next.menu_action,
+            actions,
+            diagnostics,
+            error: next.error.or(self.error),
+            sanitize_report,
+            revision: next.revision,
+            chained: Some(effects),
+        }
+    }
+
+    /**
+     * Everything this update asks of the platform, in the order to act on
+     * it: for an update made by then, the effects of each step in turn.
+     * Keep and MenuState::None are left out, since they ask for nothing,
+     * but MenuAction::None is not, since it dismisses any suggestion.
+     */
+    pub fn effects(&self) -> Vec<ComposerEffect<C>>
+    where
+        C: Clone,
+    {
+        if let Some(chained) = &self.chained {
+            return chained.clone();
+        }
+        let mut effects = Vec::new();
+        if !matches!(self.text_update, TextUpdate::Keep) {
+            effects.push(ComposerEffect::TextUpdated(self.text_update.clone()));
+        }
+        if !matches!(self.menu_state, MenuState::None) {
+            effects.push(ComposerEffect::MenuStateChanged(
+                self.menu_state.clone(),
+            ));
+        }
+        effects
+            .push(ComposerEffect::MenuActionChanged(self.menu_action.clone()));
+        effects.extend(
+            self.actions
+                .iter()
+                .cloned()
+                .map(ComposerEffect::ActionRequested),
+        );
+        if !self.sanitize_report.removals.is_empty() {
+            effects
+                .push(ComposerEffect::Sanitized(self.sanitize_report.clone()));
+        }
+        effects.extend(
+            self.diagnostics
+                .iter()
+                .cloned()
+                .map(ComposerEffect::Diagnostic),
+        );
+        effects.extend(self.error.map(ComposerEffect::Failed));
+        effects
+    }
+
+    pub fn update_text_node(
+        node_handle: DomHandle,
+        new_text: Vec<C>,
+        start: Location,
+        end: Location,
+    ) -> Self {
+        Self {
+            text_update: TextUpdate::UpdateTextNode(UpdateTextNode {
+                node_handle,
+                new_text,
+                start,
+                end,
+            }),
+            menu_state: MenuState::None,
+            menu_action: MenuAction::None,
+            actions: Vec::new(),
+            diagnostics: Vec::new(),
+            error: None,
+            sanitize_report: SanitizeReport::default(),
+            revision: 0,
+            chained: None,
         }
     }
 }
@@ -0,0 +1,91 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::Dom;
+use crate::{CodeUnit, FormatSet};
+
+/**
+ * How much of a range a format covers (see
+ * ComposerModel::is_range_formatted).
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Coverage {
+    /**
+     * All the text in the range has the format, or text typed at an empty
+     * range would.
+     */
+    Full,
+    /**
+     * Some of the text in the range has the format, but not all of it.
+     */
+    Partial,
+    None,
+}
+
+impl Coverage {
+    /**
+     * How much of the text in start..end is inside an element called name.
+     */
+    pub(crate) fn of<C>(
+        dom: &Dom<C>,
+        start: usize,
+        end: usize,
+        name: &str,
+    ) -> Self
+    where
+        C: CodeUnit,
+    {
+        if FormatSet::around(dom, start, end).contains(name) {
+            Self::Full
+        } else if start < end
+            && FormatSet::within(dom, start, end).contains(name)
+        {
+            Self::Partial
+        } else {
+            Self::None
+        }
+    }
+
+    /**
+     * A name for the coverage, e.g. "partial", for platforms that cannot
+     * be given the enum itself.
+     */
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Partial => "partial",
+            Self::None => "none",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Coverage;
+    use crate::dom::parse;
+
+    #[test]
+    fn coverage_says_whether_some_or_all_of_a_range_is_formatted() {
+        let dom = parse(
+            &"a<em>b<strong>c</strong></em>"
+                .encode_utf16()
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(Coverage::of(&dom, 1, 3, "em"), Coverage::Full);
+        assert_eq!(Coverage::of(&dom, 0, 3, "em"), Coverage::Partial);
+        assert_eq!(Coverage::of(&dom, 1, 2, "strong"), Coverage::None);
+        assert_eq!(Coverage::of(&dom, 3, 3, "strong"), Coverage::Full);
+        assert_eq!(Coverage::of(&dom, 0, 0, "em"), Coverage::None);
+    }
+}
@@ -0,0 +1,1735 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod anchored_location;
+mod atom;
+mod builder;
+mod diff;
+mod dom_handle;
+mod elements;
+mod empty_lines;
+mod equality;
+mod events;
+mod fragment;
+mod invariants;
+mod lines;
+mod links;
+mod lists;
+mod markdown;
+mod matrix_subset;
+mod mentions;
+mod parser;
+mod paste;
+mod plain_text;
+mod protected;
+mod restore;
+mod serializer;
+mod split;
+mod transaction;
+mod trim;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::CodeUnit;
+
+pub use anchored_location::AnchoredLocation;
+pub use atom::Atom;
+pub use builder::DomBuilder;
+pub use diff::{diff_html, DiffHtml};
+pub use dom_handle::{DomHandle, PinnedNode};
+pub(crate) use elements::{element_spec, ElementSpec, ELEMENTS};
+pub(crate) use equality::StableHasher;
+pub use fragment::{parse_message_html, DomFragment};
+pub(crate) use links::shorten_url;
+pub use markdown::parse_markdown;
+#[cfg(feature = "legacy-drafts")]
+pub(crate) use markdown::{escape_text, escape_url};
+pub(crate) use markdown::{
+    markdown_styles, markdown_to_html, plain_text_to_html,
+};
+pub use matrix_subset::MATRIX_LINK_SCHEMES;
+pub use parser::{
+    parse, parse_to_depth, parse_with_warnings, ParseResult, DEFAULT_MAX_DEPTH,
+};
+pub use paste::PasteSource;
+pub use protected::PROTECTED_ATTR;
+pub(crate) use transaction::Transaction;
+
+use transaction::Journal;
+
+/**
+ * The element that stands in for an attachment that is still being
+ * prepared. It has a data-id and a data-label, and no children.
+ */
+pub const ATTACHMENT_ELEMENT: &str = "mx-attachment";
+
+/**
+ * The source of Dom revisions, shared by all Doms so that no two have the
+ * same revision by chance.
+ */
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64 {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
+}
+
+/**
+ * A tree of HTML nodes representing the content of the composer.
+ *
+ * Nodes are stored contiguously in an arena and referred to by NodeId.
+ * When a node is removed its slot is reused, but NodeIds referring to the
+ * removed node are never confused with the new one because each slot
+ * carries a generation that is incremented on reuse.
+ *
+ * Locations within a Dom count the code units of its text, with each void
+ * element (e.g. a line break) counting as 1.
+ */
+#[derive(Clone, Debug)]
+pub struct Dom<C> {
+    slots: Vec<Slot<C>>,
+    free: Vec<u32>,
+    document: NodeId,
+    journal: Option<Journal<C>>,
+    /**
+     * Changes whenever the content does. See revision.
+     */
+    revision: u64,
+    /**
+     * Shared with copies of this Dom but no other, so that a PinnedNode is
+     * only looked up in the Dom it came from.
+     */
+    lineage: u64,
+}
+
+/**
+ * A handle to a node in a Dom. Only valid for the Dom that created it, and
+ * only until the node is removed.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Clone, Debug)]
+struct Slot<C> {
+    generation: u32,
+    entry: Option<Entry<C>>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry<C> {
+    parent: Option<NodeId>,
+    node: DomNode<C>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DomNode<C> {
+    Container(ContainerNode<C>),
+    Text(TextNode<C>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContainerNode<C> {
+    name: Atom,
+    attrs: Vec<(Atom, Vec<C>)>,
+    children: Vec<NodeId>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextNode<C> {
+    data: Vec<C>,
+}
+
+/**
+ * A text node, or an element that has no children, along with the location
+ * range it covers.
+ */
+struct Leaf {
+    id: NodeId,
+    start: usize,
+    len: usize,
+    is_text: bool,
+}
+
+impl Leaf {
+    fn end(&self) -> usize {
+        self.start + self.len
+    }
+}
+
+/**
+ * A node and the locations where it starts and ends.
+ */
+#[derive(Clone, Copy)]
+struct Span {
+    id: NodeId,
+    start: usize,
+    end: usize,
+}
+
+enum InsertionPoint {
+    InText { id: NodeId, offset: usize },
+    Between { parent: NodeId, index: usize },
+}
+
+impl<C> ContainerNode<C> {
+    /**
+     * The canonical (lower-case) tag name of this element.
+     */
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /**
+     * The attributes of this element, in the order they were given.
+     */
+    pub fn attrs(&self) -> &[(Atom, Vec<C>)] {
+        &self.attrs
+    }
+
+    /**
+     * The value of the attribute called name, if this element has one.
+     */
+    pub fn attr(&self, name: &str) -> Option<&[C]> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    /**
+     * The nodes inside this element, in order.
+     */
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    pub fn is_void(&self) -> bool {
+        self.name.element().map_or(false, |spec| spec.void)
+    }
+
+    pub fn is_block(&self) -> bool {
+        self.name.element().map_or(false, |spec| spec.block)
+    }
+
+    fn is_inclusive(&self) -> bool {
+        self.attr(PROTECTED_ATTR).is_none()
+            && self.name.element().map_or(true, |spec| spec.inclusive)
+    }
+}
+
+impl<C> TextNode<C> {
+    pub fn data(&self) -> &[C] {
+        &self.data
+    }
+}
+
+impl<C> Default for Dom<C>
+where
+    C: Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> Dom<C>
+where
+    C: Clone,
+{
+    pub fn new() -> Self {
+        let mut dom = Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            document: NodeId {
+                index: 0,
+                generation: 0,
+            },
+            journal: None,
+            revision: next_revision(),
+            lineage: next_revision(),
+        };
+        dom.document = dom.create_container("", Vec::new(), Vec::new());
+        dom
+    }
+
+    /**
+     * A number that changes whenever this Dom changes, and is never used
+     * by another Dom unless it is a clone with the same content, so that
+     * anything derived from a Dom can be cached until its revision
+     * changes.
+     */
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /**
+     * The container at the root of the document.
+     */
+    pub fn document(&self) -> NodeId {
+        self.document
+    }
+
+    /**
+     * The node id refers to, or None if it has been removed.
+     */
+    pub fn get(&self, id: NodeId) -> Option<&DomNode<C>> {
+        self.entry(id).map(|entry| &entry.node)
+    }
+
+    /**
+     * The node id refers to. Panics if it has been removed.
+     */
+    pub fn node(&self, id: NodeId) -> &DomNode<C> {
+        self.get(id).expect("NodeId refers to a removed node")
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.entry(id).and_then(|entry| entry.parent)
+    }
+
+    /**
+     * The children of id, which are empty if it is not a container.
+     */
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        match self.node(id) {
+            DomNode::Container(container) => &container.children,
+            DomNode::Text(_) => &[],
+        }
+    }
+
+    /**
+     * The nodes inside id, in document order (each element before its
+     * children), not counting anything inside void elements. This walks
+     * the tree with a stack of its own rather than by recursion, so it
+     * copes with any depth of nesting.
+     */
+    pub fn descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut found = Vec::new();
+        let mut pending: Vec<NodeId> =
+            self.children(id).iter().rev().cloned().collect();
+        while let Some(next) = pending.pop() {
+            found.push(next);
+            if !matches!(self.node(next), DomNode::Container(c) if c.is_void())
+            {
+                pending.extend(self.children(next).iter().rev());
+            }
+        }
+        found
+    }
+
+    /**
+     * The handle that currently identifies id.
+     */
+    pub fn handle_of(&self, id: NodeId) -> DomHandle {
+        let mut path = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.parent(current) {
+            let (_, index) = self.index_in_parent(current);
+            path.push(index);
+            current = parent;
+        }
+        path.reverse();
+        DomHandle::from_raw(path)
+    }
+
+    /**
+     * Create a new text node, not yet attached to the document.
+     */
+    pub(crate) fn create_text(&mut self, data: Vec<C>) -> NodeId {
+        self.alloc(DomNode::Text(TextNode { data }))
+    }
+
+    /**
+     * Create a new element called name with the supplied children, not yet
+     * attached to the document. The children are detached from any
+     * previous parent.
+     */
+    pub(crate) fn create_container(
+        &mut self,
+        name: &str,
+        attrs: Vec<(Atom, Vec<C>)>,
+        children: Vec<NodeId>,
+    ) -> NodeId {
+        let id = self.alloc(DomNode::Container(ContainerNode {
+            name: Atom::from(name),
+            attrs,
+            children: Vec::new(),
+        }));
+        self.set_children(id, children);
+        id
+    }
+
+    /**
+     * Attach child to parent, at position index among its children.
+     */
+    pub(crate) fn insert_child(
+        &mut self,
+        parent: NodeId,
+        index: usize,
+        child: NodeId,
+    ) {
+        self.detach(child);
+        self.container_mut(parent).children.insert(index, child);
+        self.entry_mut(child).parent = Some(parent);
+    }
+
+    pub(crate) fn append_child(&mut self, parent: NodeId, child: NodeId) {
+        let index = self.children(parent).len();
+        self.insert_child(parent, index, child);
+    }
+
+    /**
+     * Detach id from its parent and free it and all its descendants.
+     */
+    pub(crate) fn remove(&mut self, id: NodeId) {
+        self.detach(id);
+        self.free_subtree(id);
+    }
+
+    /**
+     * The number of code units covered by the whole document.
+     */
+    pub fn text_len(&self) -> usize {
+        self.node_len(self.document)
+    }
+
+    /**
+     * The text inside node id, ignoring tags.
+     */
+    pub fn text_content(&self, id: NodeId) -> Vec<C> {
+        std::iter::once(id)
+            .chain(self.descendants(id))
+            .flat_map(|node| match self.node(node) {
+                DomNode::Text(text) => text.data.clone(),
+                DomNode::Container(_) => Vec::new(),
+            })
+            .collect()
+    }
+
+    /**
+     * The content as one entry per location: a code unit of text, or None
+     * for a void element.
+     */
+    pub fn location_units(&self) -> Vec<Option<C>> {
+        let mut units = Vec::new();
+        for id in self.descendants(self.document) {
+            match self.node(id) {
+                DomNode::Text(text) => {
+                    units.extend(text.data.iter().cloned().map(Some))
+                }
+                DomNode::Container(c) if c.is_void() => units.push(None),
+                DomNode::Container(_) => {}
+            }
+        }
+        units
+    }
+
+    /**
+     * The innermost element called name that covers location, including
+     * at its edges. If several do, e.g. at the boundary between two list
+     * items, the first in document order is chosen.
+     */
+    pub fn element_at(&self, location: usize, name: &str) -> Option<NodeId> {
+        // Each element comes after the elements inside it, so the first
+        // found is the innermost
+        self.spans(self.document, 0)
+            .into_iter()
+            .find(|span| {
+                span.start <= location
+                    && location <= span.end
+                    && matches!(
+                        self.node(span.id),
+                        DomNode::Container(c) if c.name == name
+                    )
+            })
+            .map(|span| span.id)
+    }
+
+    /**
+     * The nodes inside id, which starts at offset, with where each starts
+     * and ends, each after the nodes inside it and otherwise in document
+     * order. Like descendants, this walks the tree with a stack of its own,
+     * and it finds every location in one pass rather than measuring each
+     * node separately.
+     */
+    fn spans(&self, id: NodeId, offset: usize) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let mut pos = offset;
+        // Each element being walked, where it starts and its next child
+        let mut open = vec![(id, offset, 0)];
+        while let Some(&(container, start, next)) = open.last() {
+            let child = match self.children(container).get(next) {
+                Some(&child) => child,
+                None => {
+                    open.pop();
+                    if container != id {
+                        spans.push(Span {
+                            id: container,
+                            start,
+                            end: pos,
+                        });
+                    }
+                    continue;
+                }
+            };
+            open.last_mut().unwrap().2 += 1;
+            let len = match self.node(child) {
+                DomNode::Text(text) => text.data.len(),
+                DomNode::Container(c) if c.is_void() => 1,
+                DomNode::Container(_) => {
+                    open.push((child, pos, 0));
+                    continue;
+                }
+            };
+            spans.push(Span {
+                id: child,
+                start: pos,
+                end: pos + len,
+            });
+            pos += len;
+        }
+        spans
+    }
+
+    /**
+     * Set the attribute called name on the element id to value, replacing
+     * any previous value.
+     */
+    pub(crate) fn set_attr(&mut self, id: NodeId, name: &str, value: Vec<C>) {
+        let attrs = &mut self.container_mut(id).attrs;
+        match attrs.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value,
+            None => attrs.push((Atom::from(name), value)),
+        }
+    }
+
+    /**
+     * Remove the attribute called name from the element id, if it has one.
+     */
+    pub(crate) fn remove_attr(&mut self, id: NodeId, name: &str) {
+        self.container_mut(id).attrs.retain(|(n, _)| n != name);
+    }
+
+    /**
+     * The number of code units covered by node id.
+     */
+    pub fn node_len(&self, id: NodeId) -> usize {
+        match self.node(id) {
+            DomNode::Text(text) => text.data.len(),
+            DomNode::Container(container) if container.is_void() => 1,
+            DomNode::Container(_) => self
+                .descendants(id)
+                .into_iter()
+                .map(|node| match self.node(node) {
+                    DomNode::Text(text) => text.data.len(),
+                    DomNode::Container(c) if c.is_void() => 1,
+                    DomNode::Container(_) => 0,
+                })
+                .sum(),
+        }
+    }
+
+    fn alloc(&mut self, node: DomNode<C>) -> NodeId {
+        let entry = Some(Entry { parent: None, node });
+        if let Some(index) = self.free.pop() {
+            self.touch(index);
+            let slot = &mut self.slots[index as usize];
+            slot.entry = entry;
+            NodeId {
+                index,
+                generation: slot.generation,
+            }
+        } else {
+            let index =
+                u32::try_from(self.slots.len()).expect("Too many nodes in Dom");
+            self.touch(index);
+            self.slots.push(Slot {
+                generation: 0,
+                entry,
+            });
+            NodeId {
+                index,
+                generation: 0,
+            }
+        }
+    }
+
+    fn free_subtree(&mut self, id: NodeId) {
+        let mut pending = vec![id];
+        while let Some(node) = pending.pop() {
+            pending.extend(self.children(node));
+            self.touch(node.index);
+            let slot = &mut self.slots[node.index as usize];
+            slot.entry = None;
+            slot.generation += 1;
+            self.free.push(node.index);
+        }
+    }
+
+    /**
+     * Remove id from its parent's children, leaving it without a parent.
+     */
+    fn detach(&mut self, id: NodeId) {
+        if let Some(parent) = self.entry_mut(id).parent.take() {
+            self.container_mut(parent).children.retain(|&c| c != id);
+        }
+    }
+
+    /**
+     * Replace the children of parent with children, detaching them from any
+     * previous parent.
+     */
+    fn set_children(&mut self, parent: NodeId, children: Vec<NodeId>) {
+        for &child in &children {
+            if self.parent(child) != Some(parent) {
+                self.detach(child);
+                self.entry_mut(child).parent = Some(parent);
+            }
+        }
+        self.container_mut(parent).children = children;
+    }
+
+    fn index_in_parent(&self, id: NodeId) -> (NodeId, usize) {
+        let parent = self.parent(id).expect("Node has no parent");
+        let index = self
+            .children(parent)
+            .iter()
+            .position(|&c| c == id)
+            .expect("Node is not among its parent's children");
+        (parent, index)
+    }
+
+    /**
+     * Create a detached copy of the node id from other, and its subtree.
+     */
+    fn import(&mut self, other: &Dom<C>, id: NodeId) -> NodeId {
+        if let DomNode::Text(text) = other.node(id) {
+            return self.create_text(text.data.clone());
+        }
+        // Each element being copied, with the copies of its children so far
+        let mut open: Vec<(NodeId, Vec<NodeId>)> = vec![(id, Vec::new())];
+        loop {
+            let (element, copies) = open.last().unwrap();
+            match other.children(*element).get(copies.len()) {
+                Some(&child) => match other.node(child) {
+                    DomNode::Text(text) => {
+                        let copy = self.create_text(text.data.clone());
+                        open.last_mut().unwrap().1.push(copy);
+                    }
+                    DomNode::Container(_) => open.push((child, Vec::new())),
+                },
+                None => {
+                    let (element, copies) = open.pop().unwrap();
+                    let c = other.container(element);
+                    let copy =
+                        self.create_container(&c.name, c.attrs.clone(), copies);
+                    match open.last_mut() {
+                        Some((_, parent_copies)) => parent_copies.push(copy),
+                        None => return copy,
+                    }
+                }
+            }
+        }
+    }
+
+    fn entry(&self, id: NodeId) -> Option<&Entry<C>> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.entry.as_ref())
+    }
+
+    fn entry_mut(&mut self, id: NodeId) -> &mut Entry<C> {
+        self.touch(id.index);
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.entry.as_mut())
+            .expect("NodeId refers to a removed node")
+    }
+
+    fn container(&self, id: NodeId) -> &ContainerNode<C> {
+        match self.node(id) {
+            DomNode::Container(container) => container,
+            DomNode::Text(_) => panic!("NodeId does not refer to a container"),
+        }
+    }
+
+    fn container_mut(&mut self, id: NodeId) -> &mut ContainerNode<C> {
+        match &mut self.entry_mut(id).node {
+            DomNode::Container(container) => container,
+            DomNode::Text(_) => panic!("NodeId does not refer to a container"),
+        }
+    }
+
+    fn text_mut(&mut self, id: NodeId) -> &mut TextNode<C> {
+        match &mut self.entry_mut(id).node {
+            DomNode::Text(text) => text,
+            DomNode::Container(_) => panic!("NodeId does not refer to text"),
+        }
+    }
+}
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * The first element in document order called name whose attribute
+     * attr is value.
+     */
+    pub fn find_element(
+        &self,
+        name: &str,
+        attr: &str,
+        value: &[C],
+    ) -> Option<NodeId> {
+        self.descendants(self.document).into_iter().find(|&id| {
+            matches!(
+                self.node(id),
+                DomNode::Container(c)
+                    if c.name == name && c.attr(attr) == Some(value)
+            )
+        })
+    }
+
+    /**
+     * Replace the range start..end with new_text.
+     */
+    pub(crate) fn replace_text_in(
+        &mut self,
+        new_text: &[C],
+        start: usize,
+        end: usize,
+    ) {
+        self.delete_range(start, end);
+        self.insert_text(start, new_text);
+    }
+
+    /**
+     * Insert new_text at location, the way typed text would be: where
+     * location is on the boundary between two nodes, the text goes into the
+     * text node ending there, unless that would extend a non-inclusive
+     * element such as a link or a protected element. If there is no suitable text node, a new one
+     * is created.
+     */
+    pub(crate) fn insert_text(&mut self, location: usize, new_text: &[C]) {
+        if new_text.is_empty() {
+            return;
+        }
+        match self.insertion_point(location) {
+            InsertionPoint::InText { id, offset } => {
+                self.insert_into_text_node(id, offset, new_text)
+            }
+            InsertionPoint::Between { parent, index } => {
+                let text = self.create_text(new_text.to_vec());
+                self.insert_child(parent, index, text);
+            }
+        }
+    }
+
+    /**
+     * Replace the text of the text node id with data.
+     */
+    pub(crate) fn set_text(&mut self, id: NodeId, data: Vec<C>) {
+        self.text_mut(id).data = data;
+    }
+
+    /**
+     * Insert new_text into the text node id, offset code units into it.
+     */
+    pub(crate) fn insert_into_text_node(
+        &mut self,
+        id: NodeId,
+        offset: usize,
+        new_text: &[C],
+    ) {
+        let text = self.text_mut(id);
+        let tail = text.data.split_off(offset);
+        text.data.extend_from_slice(new_text);
+        text.data.extend(tail);
+    }
+
+    /**
+     * The location of the first occurrence of needle in the text of the
+     * document at or after from, ignoring tags. Void elements (e.g. line
+     * breaks) never match.
+     */
+    pub fn find_text(&self, needle: &[C], from: usize) -> Option<usize> {
+        let mut text = vec![None; self.text_len()];
+        for leaf in self.leaves() {
+            if let DomNode::Text(t) = self.node(leaf.id) {
+                for (i, &c) in t.data.iter().enumerate() {
+                    text[leaf.start + i] = Some(c);
+                }
+            }
+        }
+        if from > text.len() {
+            return None;
+        }
+        if needle.is_empty() {
+            return Some(from);
+        }
+        text[from..]
+            .windows(needle.len())
+            .position(|window| {
+                window.iter().zip(needle).all(|(c, n)| *c == Some(*n))
+            })
+            .map(|i| from + i)
+    }
+
+    /**
+     * The text node that text typed at location would go into, and the
+     * offset of location within it, if there is one.
+     */
+    pub fn text_node_at(&self, location: usize) -> Option<(NodeId, usize)> {
+        match self.insertion_point(location) {
+            InsertionPoint::InText { id, offset } => Some((id, offset)),
+            InsertionPoint::Between { .. } => None,
+        }
+    }
+
+    /**
+     * The elements that text typed at location would be inside, innermost
+     * first, with their names. While these stay the same, so do the
+     * elements_around a cursor at location.
+     */
+    pub fn ancestry_at(&self, location: usize) -> Vec<(NodeId, &str)> {
+        let mut current = match self.insertion_point(location) {
+            InsertionPoint::InText { id, .. } => self.parent(id),
+            InsertionPoint::Between { parent, .. } => Some(parent),
+        };
+        let mut ancestry = Vec::new();
+        while let Some(id) = current {
+            if id != self.document {
+                ancestry.push((id, self.container(id).name.as_str()));
+            }
+            current = self.parent(id);
+        }
+        ancestry
+    }
+
+    /**
+     * The names of the elements that all the text in start..end is inside.
+     * If the range is empty, these are the elements that text typed at
+     * start would be inside.
+     */
+    pub fn elements_around(&self, start: usize, end: usize) -> Vec<&str> {
+        if start == end {
+            let container = match self.insertion_point(start) {
+                InsertionPoint::InText { id, .. } => self.parent(id),
+                InsertionPoint::Between { parent, .. } => Some(parent),
+            };
+            return container.map_or_else(Vec::new, |c| self.names_from(c));
+        }
+        let mut names: Option<Vec<&str>> = None;
+        for leaf in self.leaves() {
+            if !leaf.is_text || leaf.end() <= start || end <= leaf.start {
+                continue;
+            }
+            let leaf_names = match self.parent(leaf.id) {
+                Some(parent) => self.names_from(parent),
+                None => Vec::new(),
+            };
+            names = Some(match names {
+                Some(names) => names
+                    .into_iter()
+                    .filter(|name| leaf_names.contains(name))
+                    .collect(),
+                None => leaf_names,
+            });
+        }
+        names.unwrap_or_default()
+    }
+
+    /**
+     * The names of the elements that any of the text in start..end is
+     * inside, or the same as elements_around if the range is empty.
+     */
+    pub fn elements_within(&self, start: usize, end: usize) -> Vec<&str> {
+        if start == end {
+            return self.elements_around(start, end);
+        }
+        let mut names: Vec<&str> = Vec::new();
+        for leaf in self.leaves() {
+            if !leaf.is_text || leaf.end() <= start || end <= leaf.start {
+                continue;
+            }
+            if let Some(parent) = self.parent(leaf.id) {
+                for name in self.names_from(parent) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    /**
+     * The names of container and its ancestors, excluding the document.
+     */
+    fn names_from(&self, container: NodeId) -> Vec<&str> {
+        let mut names = Vec::new();
+        let mut current = Some(container);
+        while let Some(id) = current {
+            if id != self.document {
+                names.push(self.container(id).name.as_str());
+            }
+            current = self.parent(id);
+        }
+        names
+    }
+
+    /**
+     * Attach the detached node id at location, splitting a text node if
+     * location is inside one.
+     */
+    pub(crate) fn insert_node(&mut self, location: usize, id: NodeId) {
+        let (parent, index) = match self.insertion_point(location) {
+            InsertionPoint::InText {
+                id: text_id,
+                offset,
+            } => {
+                let (parent, index) = self.index_in_parent(text_id);
+                let text = self.text_mut(text_id);
+                if offset == 0 {
+                    (parent, index)
+                } else if offset == text.data.len() {
+                    (parent, index + 1)
+                } else {
+                    let tail = text.data.split_off(offset);
+                    let tail = self.create_text(tail);
+                    self.insert_child(parent, index + 1, tail);
+                    (parent, index + 1)
+                }
+            }
+            InsertionPoint::Between { parent, index } => (parent, index),
+        };
+        self.insert_child(parent, index, id);
+    }
+
+    /**
+     * Insert a copy of the content of fragment at location, returning the
+     * number of code units it covers. Only the first node is placed by
+     * location: the rest follow it as siblings, so that they do not join
+     * any formatting it ends with.
+     */
+    pub(crate) fn insert_dom(
+        &mut self,
+        location: usize,
+        fragment: &Dom<C>,
+    ) -> usize {
+        let mut len = 0;
+        let mut previous = None;
+        for &child in fragment.children(fragment.document) {
+            let id = self.import(fragment, child);
+            match previous {
+                None => self.insert_node(location, id),
+                Some(previous) => {
+                    let (parent, index) = self.index_in_parent(previous);
+                    self.insert_child(parent, index + 1, id);
+                }
+            }
+            len += self.node_len(id);
+            previous = Some(id);
+        }
+        self.normalize(self.document);
+        len
+    }
+
+    /**
+     * Replace all the content with a copy of the content of other.
+     */
+    pub(crate) fn replace_all(&mut self, other: &Dom<C>) {
+        for child in self.children(self.document).to_vec() {
+            self.remove(child);
+        }
+        self.insert_dom(0, other);
+    }
+
+    /**
+     * Replace the text inside the element id with new_text, which takes
+     * the formatting of the start of the element's content.
+     */
+    pub(crate) fn replace_content(&mut self, id: NodeId, new_text: &[C]) {
+        let first_text = self
+            .leaves_in(id)
+            .into_iter()
+            .find(|&leaf| matches!(self.node(leaf), DomNode::Text(_)));
+        let first_text = match first_text {
+            Some(first_text) if !new_text.is_empty() => first_text,
+            _ => {
+                for child in self.children(id).to_vec() {
+                    self.remove(child);
+                }
+                if !new_text.is_empty() {
+                    let text = self.create_text(new_text.to_vec());
+                    self.append_child(id, text);
+                }
+                return;
+            }
+        };
+        self.text_mut(first_text).data = new_text.to_vec();
+        let start = self.start_of(id);
+        let end = start + self.node_len(id);
+        let text_start = self.start_of(first_text);
+        self.delete_in(id, start, text_start + new_text.len(), end);
+        self.delete_in(id, start, start, text_start);
+        self.remove_empty_containers(id);
+        self.normalize(id);
+    }
+
+    /**
+     * The text nodes and childless elements inside id, in document order.
+     */
+    fn leaves_in(&self, id: NodeId) -> Vec<NodeId> {
+        if self.children(id).is_empty() {
+            return vec![id];
+        }
+        self.descendants(id)
+            .into_iter()
+            .filter(|&node| self.children(node).is_empty())
+            .collect()
+    }
+
+    fn remove_empty_containers(&mut self, container: NodeId) {
+        // Each element after those inside it, which may leave it empty
+        for id in self.descendants(container).into_iter().rev() {
+            let empty = matches!(self.node(id), DomNode::Container(c) if !c.is_void())
+                && self.children(id).is_empty();
+            if empty {
+                self.remove(id);
+            }
+        }
+    }
+
+    /**
+     * Remove the content in the range start..end. Void elements are only
+     * removed if they lie entirely inside the range.
+     */
+    pub(crate) fn delete_range(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.delete_in(self.document, 0, start, end);
+            self.normalize(self.document);
+        }
+    }
+
+    /**
+     * Wrap all inline content in the range start..end in new elements
+     * called name, splitting text nodes where necessary.
+     */
+    pub(crate) fn wrap_inline_range(
+        &mut self,
+        start: usize,
+        end: usize,
+        name: &str,
+    ) {
+        if start >= end {
+            return;
+        }
+        self.split_text_at(start);
+        self.split_text_at(end);
+        self.wrap_in(start, end, name);
+        self.normalize(self.document);
+    }
+
+    /**
+     * Merge each element called name with an element called name just
+     * before it, if both have no attributes, so that formatting next to
+     * the same formatting is one element, e.g. after a format is removed
+     * from part of an element and then applied again.
+     */
+    pub(crate) fn merge_adjacent_formatting(&mut self, name: &str) {
+        for container in self.containers_innermost_first(self.document) {
+            self.merge_adjacent(container, name);
+        }
+        self.normalize(self.document);
+    }
+
+    /**
+     * Remove the inline format name from the range start..end, splitting
+     * the elements called name that cross its edges so that the content
+     * outside it keeps the format.
+     */
+    pub(crate) fn unwrap_inline_range(
+        &mut self,
+        start: usize,
+        end: usize,
+        name: &str,
+    ) {
+        if start >= end {
+            return;
+        }
+        self.split_elements_at(start, name);
+        self.split_elements_at(end, name);
+        for id in self.descendants(self.document) {
+            let is_format = match self.get(id) {
+                Some(DomNode::Container(c)) => c.name == name,
+                _ => false,
+            };
+            if is_format {
+                let id_start = self.start_of(id);
+                if start <= id_start && id_start + self.node_len(id) <= end {
+                    self.unwrap(id);
+                }
+            }
+        }
+        self.normalize(self.document);
+    }
+
+    /**
+     * Merge each child of container called name into the one before it,
+     * as merge_adjacent_formatting does, leaving their children alone.
+     */
+    fn merge_adjacent(&mut self, container: NodeId, name: &str) {
+        let mut previous: Option<NodeId> = None;
+        for child in self.children(container).to_vec() {
+            let mergeable = match self.node(child) {
+                DomNode::Container(c) => c.name == name && c.attrs.is_empty(),
+                DomNode::Text(_) => false,
+            };
+            match previous {
+                Some(previous) if mergeable => {
+                    for grandchild in self.children(child).to_vec() {
+                        self.append_child(previous, grandchild);
+                    }
+                    self.remove(child);
+                }
+                _ => previous = Some(child).filter(|_| mergeable),
+            }
+        }
+    }
+
+    /**
+     * If the only difference between before (an earlier clone of this Dom)
+     * and this Dom is the text of a single text node, return that node.
+     */
+    pub fn changed_text_node(&self, before: &Dom<C>) -> Option<NodeId> {
+        let mut changed = Vec::new();
+        let mut pending = vec![self.document];
+        while let Some(id) = pending.pop() {
+            match (self.get(id), before.get(id)) {
+                (Some(DomNode::Text(now)), Some(DomNode::Text(then))) => {
+                    if now.data != then.data {
+                        changed.push(id);
+                    }
+                }
+                (
+                    Some(DomNode::Container(now)),
+                    Some(DomNode::Container(then)),
+                ) if now.name == then.name
+                    && now.attrs == then.attrs
+                    && now.children == then.children =>
+                {
+                    pending.extend(now.children.iter().rev());
+                }
+                _ => return None,
+            }
+        }
+        if changed.len() == 1 {
+            Some(changed[0])
+        } else {
+            None
+        }
+    }
+
+    /**
+     * The elements inside container, and then container itself, each
+     * after all of the elements inside it, so that a change made to each
+     * in turn has already been made to everything inside it.
+     */
+    fn containers_innermost_first(&self, container: NodeId) -> Vec<NodeId> {
+        let mut containers: Vec<NodeId> = self
+            .descendants(container)
+            .into_iter()
+            .filter(|&id| matches!(self.node(id), DomNode::Container(_)))
+            .collect();
+        containers.reverse();
+        containers.push(container);
+        containers
+    }
+
+    /**
+     * Merge adjacent text nodes inside container, so that each run of text
+     * is a single node, as it would be in a platform DOM parsed from our
+     * HTML. Adjacent lists of the same type are merged too, unless the
+     * second is an ordered list with its own start.
+     */
+    pub(crate) fn normalize(&mut self, container: NodeId) {
+        for id in self.containers_innermost_first(container) {
+            self.normalize_children(id);
+        }
+    }
+
+    /**
+     * Merge the adjacent text nodes and lists among the children of
+     * container, as normalize does, leaving their children alone.
+     */
+    fn normalize_children(&mut self, container: NodeId) {
+        let mut previous_text: Option<NodeId> = None;
+        let mut previous_list: Option<NodeId> = None;
+        for child in self.children(container).to_vec() {
+            match &self.node(child) {
+                DomNode::Text(text) => {
+                    previous_list = None;
+                    if let Some(previous) = previous_text {
+                        let data = text.data.clone();
+                        self.text_mut(previous).data.extend(data);
+                        self.remove(child);
+                    } else {
+                        previous_text = Some(child);
+                    }
+                }
+                DomNode::Container(c) => {
+                    previous_text = None;
+                    let is_list = matches!(c.name.as_str(), "ol" | "ul");
+                    let continues = previous_list.filter(|&previous| {
+                        is_list
+                            && self.container(previous).name == c.name
+                            && c.attr("start").is_none()
+                    });
+                    match continues {
+                        Some(previous) => {
+                            for item in self.children(child).to_vec() {
+                                self.append_child(previous, item);
+                            }
+                            self.remove(child);
+                        }
+                        None => {
+                            previous_list = Some(child).filter(|_| is_list);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /**
+     * If location is strictly inside a text node, split it into two text
+     * nodes at that location.
+     */
+    fn split_text_at(&mut self, location: usize) {
+        let leaf = self.leaves().into_iter().find(|leaf| {
+            leaf.is_text && leaf.start < location && location < leaf.end()
+        });
+        if let Some(leaf) = leaf {
+            let (parent, index) = self.index_in_parent(leaf.id);
+            let tail =
+                self.text_mut(leaf.id).data.split_off(location - leaf.start);
+            let tail = self.create_text(tail);
+            self.insert_child(parent, index + 1, tail);
+        }
+    }
+
+    /**
+     * Remove the content in start..end from inside container, which starts
+     * at offset, as delete_range does, leaving the elements that held it.
+     */
+    fn delete_in(
+        &mut self,
+        container: NodeId,
+        offset: usize,
+        start: usize,
+        end: usize,
+    ) {
+        for span in self.spans(container, offset) {
+            if span.end <= start || end <= span.start {
+                continue;
+            }
+            let remove = match &mut self.entry_mut(span.id).node {
+                DomNode::Text(text) => {
+                    text.data.drain(
+                        start.max(span.start) - span.start
+                            ..end.min(span.end) - span.start,
+                    );
+                    text.data.is_empty()
+                }
+                DomNode::Container(c) if c.is_void() => {
+                    start <= span.start && span.end <= end
+                }
+                DomNode::Container(_) => false,
+            };
+            if remove {
+                self.remove(span.id);
+            }
+        }
+    }
+
+    /**
+     * Wrap the inline content in start..end in elements called name, as
+     * wrap_inline_range does, once its text nodes are split at start and
+     * end.
+     */
+    fn wrap_in(&mut self, start: usize, end: usize, name: &str) {
+        // Wrapping moves nodes but not their locations
+        let spans: HashMap<NodeId, (usize, usize)> = self
+            .spans(self.document, 0)
+            .into_iter()
+            .map(|span| (span.id, (span.start, span.end)))
+            .collect();
+        let mut pending = vec![self.document];
+        while let Some(container) = pending.pop() {
+            self.wrap_children(
+                container,
+                &spans,
+                start,
+                end,
+                name,
+                &mut pending,
+            );
+        }
+    }
+
+    /**
+     * Wrap the children of container that are inside start..end in
+     * elements called name, grouping those next to each other, and add to
+     * pending the children only partly inside it, whose own children must
+     * be wrapped in turn.
+     */
+    fn wrap_children(
+        &mut self,
+        container: NodeId,
+        spans: &HashMap<NodeId, (usize, usize)>,
+        start: usize,
+        end: usize,
+        name: &str,
+        pending: &mut Vec<NodeId>,
+    ) {
+        let mut group = Vec::new();
+        let mut new_children = Vec::new();
+        for child in self.children(container).to_vec() {
+            let (child_start, child_end) = spans[&child];
+            let len = child_end - child_start;
+
+            let (is_block, is_void) = match self.node(child) {
+                DomNode::Container(c) => (c.is_block(), c.is_void()),
+                DomNode::Text(_) => (false, true),
+            };
+            let covered = start <= child_start
+                && child_end <= end
+                && (len > 0 || (start < child_start && child_end < end));
+            if covered && !is_block {
+                group.push(child);
+            } else {
+                if !group.is_empty() {
+                    let group = std::mem::take(&mut group);
+                    new_children.push(self.create_container(
+                        name,
+                        Vec::new(),
+                        group,
+                    ));
+                }
+                if !is_void && child_start < end && start < child_end {
+                    pending.push(child);
+                }
+                new_children.push(child);
+            }
+        }
+        if !group.is_empty() {
+            new_children.push(self.create_container(name, Vec::new(), group));
+        }
+        if self.children(container) != new_children.as_slice() {
+            self.set_children(container, new_children);
+        }
+    }
+
+    fn insertion_point(&self, location: usize) -> InsertionPoint {
+        let leaves = self.leaves();
+        let left = leaves
+            .iter()
+            .rev()
+            .find(|leaf| leaf.start < location && location <= leaf.end());
+        let right = leaves
+            .iter()
+            .find(|leaf| leaf.start <= location && location < leaf.end());
+
+        let in_text = left
+            .filter(|leaf| {
+                leaf.is_text && self.closing_ancestor(leaf, location).is_none()
+            })
+            .or_else(|| {
+                right.filter(|leaf| {
+                    leaf.is_text
+                        && self.opening_ancestor(leaf, location).is_none()
+                })
+            });
+        if let Some(leaf) = in_text {
+            return InsertionPoint::InText {
+                id: leaf.id,
+                offset: location - leaf.start,
+            };
+        }
+
+        if let Some(leaf) = left {
+            let anchor =
+                self.closing_ancestor(leaf, location).unwrap_or(leaf.id);
+            let (parent, index) = self.index_in_parent(anchor);
+            InsertionPoint::Between {
+                parent,
+                index: index + 1,
+            }
+        } else if let Some(leaf) = right {
+            let anchor =
+                self.opening_ancestor(leaf, location).unwrap_or(leaf.id);
+            let (parent, index) = self.index_in_parent(anchor);
+            InsertionPoint::Between { parent, index }
+        } else {
+            InsertionPoint::Between {
+                parent: self.document,
+                index: self.children(self.document).len(),
+            }
+        }
+    }
+
+    /**
+     * The outermost non-inclusive ancestor of leaf that ends at location,
+     * if any.
+     */
+    fn closing_ancestor(&self, leaf: &Leaf, location: usize) -> Option<NodeId> {
+        self.non_inclusive_ancestors(leaf.id)
+            .into_iter()
+            .find(|&id| self.start_of(id) + self.node_len(id) == location)
+    }
+
+    /**
+     * The outermost non-inclusive ancestor of leaf that starts at location,
+     * if any.
+     */
+    fn opening_ancestor(&self, leaf: &Leaf, location: usize) -> Option<NodeId> {
+        self.non_inclusive_ancestors(leaf.id)
+            .into_iter()
+            .find(|&id| self.start_of(id) == location)
+    }
+
+    /**
+     * The non-inclusive ancestors of id, outermost first.
+     */
+    fn non_inclusive_ancestors(&self, id: NodeId) -> Vec<NodeId> {
+        let mut ancestors = Vec::new();
+        let mut current = self.parent(id);
+        while let Some(ancestor) = current {
+            if !self.container(ancestor).is_inclusive() {
+                ancestors.push(ancestor);
+            }
+            current = self.parent(ancestor);
+        }
+        ancestors.reverse();
+        ancestors
+    }
+
+    /**
+     * The location where node id starts.
+     */
+    pub fn start_of(&self, id: NodeId) -> usize {
+        let mut offset = 0;
+        let mut current = id;
+        while let Some(parent) = self.parent(current) {
+            offset += self
+                .children(parent)
+                .iter()
+                .take_while(|&&c| c != current)
+                .map(|&c| self.node_len(c))
+                .sum::<usize>();
+            current = parent;
+        }
+        offset
+    }
+
+    /**
+     * All the leaves of the document, in document order.
+     */
+    fn leaves(&self) -> Vec<Leaf> {
+        let mut leaves = Vec::new();
+        let mut offset = 0;
+        for id in self.descendants(self.document) {
+            let len = match self.node(id) {
+                DomNode::Text(text) => text.data.len(),
+                DomNode::Container(c) if c.is_void() => 1,
+                DomNode::Container(_) => continue,
+            };
+            leaves.push(Leaf {
+                id,
+                start: offset,
+                len,
+                is_text: matches!(self.node(id), DomNode::Text(_)),
+            });
+            offset += len;
+        }
+        leaves
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, parse_to_depth, Dom, DomNode, MATRIX_LINK_SCHEMES};
+    use crate::HtmlDialect;
+
+    fn dom(html: &str) -> Dom<u16> {
+        parse(&utf16(html))
+    }
+
+    fn html(dom: &Dom<u16>) -> String {
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    fn utf16(text: &str) -> Vec<u16> {
+        text.encode_utf16().collect()
+    }
+
+    #[test]
+    fn thousands_of_nested_elements_can_be_edited() {
+        let html = "<em>".repeat(50_000) + "abc" + &"</em>".repeat(50_000);
+        let mut dom = parse_to_depth(&utf16(&html), usize::MAX).dom;
+        assert!(dom.element_at(1, "em").is_some());
+        dom.wrap_inline_range(0, 2, "strong");
+        dom.merge_adjacent_formatting("strong");
+        dom.delete_range(0, 1);
+        assert_eq!(dom.text_len(), 2);
+        assert_eq!(dom.changed_text_node(&dom.clone()), None);
+        let mut copy = Dom::new();
+        copy.replace_all(&dom);
+        assert!(copy == dom);
+        let outer = dom.children(dom.document())[0];
+        dom.replace_content(outer, &utf16("d"));
+        assert_eq!(dom.text_len(), 1);
+    }
+
+    #[test]
+    fn thousands_of_nested_elements_can_be_written_out() {
+        let html = "<blockquote><em>".repeat(25_000)
+            + "abc"
+            + &"</em></blockquote>".repeat(25_000);
+        let mut dom = parse_to_depth(&utf16(&html), usize::MAX).dom;
+        assert_eq!(dom.to_html(HtmlDialect::MatrixMessage), utf16(&html));
+        assert_eq!(dom.to_plain_text(false), utf16("abc"));
+        assert!(dom.to_markdown().starts_with(&utf16("> _")));
+        assert_eq!(dom.lines().len(), 1);
+        dom.sanitize(MATRIX_LINK_SCHEMES);
+        dom.clean_pasted(MATRIX_LINK_SCHEMES);
+        assert_eq!(dom.text_len(), 3);
+    }
+
+    #[test]
+    fn text_len_counts_text_and_void_elements() {
+        assert_eq!(dom("").text_len(), 0);
+        assert_eq!(dom("abc").text_len(), 3);
+        assert_eq!(dom("a<strong>bc</strong>d").text_len(), 4);
+        assert_eq!(dom("a<br />b").text_len(), 3);
+        assert_eq!(dom("\u{1F4A9}").text_len(), 2);
+    }
+
+    #[test]
+    fn typing_at_a_boundary_extends_the_preceding_formatting() {
+        let mut d = dom("a<strong>b</strong>c");
+        d.insert_text(2, &utf16("X"));
+        assert_eq!(html(&d), "a<strong>bX</strong>c");
+    }
+
+    #[test]
+    fn typing_at_the_end_of_a_link_does_not_extend_it() {
+        let mut d = dom("<a href=\"x\">b</a>");
+        d.insert_text(1, &utf16("X"));
+        assert_eq!(html(&d), "<a href=\"x\">b</a>X");
+    }
+
+    #[test]
+    fn typing_at_the_start_of_a_link_does_not_extend_it() {
+        let mut d = dom("<a href=\"x\">b</a>");
+        d.insert_text(0, &utf16("X"));
+        assert_eq!(html(&d), "X<a href=\"x\">b</a>");
+    }
+
+    #[test]
+    fn typing_between_void_elements_creates_a_text_node() {
+        let mut d = dom("<br /><br />");
+        d.insert_text(1, &utf16("X"));
+        assert_eq!(html(&d), "<br />X<br />");
+    }
+
+    #[test]
+    fn deleting_across_nodes_removes_text_from_each() {
+        let mut d = dom("ab<strong>cd</strong>ef");
+        d.delete_range(1, 5);
+        assert_eq!(html(&d), "a<strong></strong>f");
+    }
+
+    #[test]
+    fn deleting_a_range_containing_a_line_break_removes_it() {
+        let mut d = dom("a<br />b");
+        d.delete_range(0, 2);
+        assert_eq!(html(&d), "b");
+    }
+
+    #[test]
+    fn inserting_a_node_splits_the_text_node() {
+        let mut d = dom("abcd");
+        let br = d.create_container("br", vec![], vec![]);
+        d.insert_node(2, br);
+        assert_eq!(html(&d), "ab<br />cd");
+    }
+
+    #[test]
+    fn wrapping_a_range_across_formatting_wraps_each_part() {
+        let mut d = dom("a<em>bc</em>d");
+        d.wrap_inline_range(2, 4, "strong");
+        assert_eq!(html(&d), "a<em>b<strong>c</strong></em><strong>d</strong>");
+    }
+
+    #[test]
+    fn wrapping_a_range_inside_blocks_does_not_wrap_the_blocks() {
+        let mut d = dom("<p>ab</p><p>cd</p>");
+        d.wrap_inline_range(0, 4, "strong");
+        assert_eq!(
+            html(&d),
+            "<p><strong>ab</strong></p><p><strong>cd</strong></p>"
+        );
+    }
+
+    #[test]
+    fn inserting_a_fragment_copies_its_nodes_into_place() {
+        let mut d = dom("ab<em>cd</em>");
+        let fragment = dom("x<strong>y</strong>");
+        assert_eq!(d.insert_dom(1, &fragment), 2);
+        assert_eq!(html(&d), "ax<strong>y</strong>b<em>cd</em>");
+    }
+
+    #[test]
+    fn deleting_a_line_break_merges_the_text_around_it() {
+        let mut d = dom("a<br />b");
+        d.delete_range(1, 2);
+        assert_eq!(d.children(d.document()).len(), 1);
+        assert_eq!(html(&d), "ab");
+    }
+
+    #[test]
+    fn handles_are_paths_from_the_root() {
+        let d = dom("a<em>b<strong>c</strong></em>");
+        let em = d.children(d.document())[1];
+        let strong = d.children(em)[1];
+        let c = d.children(strong)[0];
+        assert_eq!(d.handle_of(c).raw(), &[1, 1, 0]);
+        assert_eq!(d.handle_of(d.document()).raw(), &[] as &[usize]);
+    }
+
+    #[test]
+    fn typing_into_a_text_node_is_a_text_only_change() {
+        let mut d = dom("a<strong>bc</strong>");
+        let before = d.clone();
+        d.insert_text(2, &utf16("X"));
+        let strong = d.children(d.document())[1];
+        assert_eq!(d.changed_text_node(&before), Some(d.children(strong)[0]));
+    }
+
+    #[test]
+    fn creating_a_text_node_is_not_a_text_only_change() {
+        let mut d = dom("<br />");
+        let before = d.clone();
+        d.insert_text(1, &utf16("X"));
+        assert_eq!(d.changed_text_node(&before), None);
+    }
+
+    #[test]
+    fn changing_two_text_nodes_is_not_a_text_only_change() {
+        let mut d = dom("ab<strong>cd</strong>");
+        let before = d.clone();
+        d.delete_range(1, 3);
+        assert_eq!(d.changed_text_node(&before), None);
+    }
+
+    #[test]
+    fn ancestry_at_a_cursor_names_the_elements_it_is_inside() {
+        let d = dom("<a href=\"x\">a<strong>bc</strong></a>");
+        let names: Vec<&str> =
+            d.ancestry_at(2).into_iter().map(|(_, name)| name).collect();
+        assert_eq!(names, vec!["strong", "a"]);
+        assert_eq!(d.ancestry_at(1), vec![(d.children(d.document())[0], "a")]);
+        assert_eq!(d.ancestry_at(3), Vec::new());
+    }
+
+    #[test]
+    fn elements_around_a_cursor_are_its_ancestors() {
+        let d = dom("<a href=\"x\">a<strong>bc</strong></a>");
+        assert_eq!(d.elements_around(2, 2), vec!["strong", "a"]);
+        assert_eq!(d.elements_around(1, 1), vec!["a"]);
+    }
+
+    #[test]
+    fn elements_around_a_range_are_shared_by_all_its_text() {
+        let d = dom("<em>a<strong>b</strong></em><strong>c</strong>");
+        assert_eq!(d.elements_around(1, 2), vec!["strong", "em"]);
+        assert_eq!(d.elements_around(0, 2), vec!["em"]);
+        assert_eq!(d.elements_around(1, 3), vec!["strong"]);
+    }
+
+    #[test]
+    fn elements_within_a_range_are_those_around_any_of_its_text() {
+        let d = dom("<em>a<strong>b</strong></em><u>c</u>");
+        assert_eq!(d.elements_within(0, 3), vec!["em", "strong", "u"]);
+        assert_eq!(d.elements_within(2, 2), vec!["strong", "em"]);
+    }
+
+    #[test]
+    fn text_can_be_found_across_formatting() {
+        let d = dom("ab<strong>c</strong>d abcd");
+        assert_eq!(d.find_text(&utf16("bcd"), 0), Some(1));
+        assert_eq!(d.find_text(&utf16("bcd"), 2), Some(6));
+        assert_eq!(d.find_text(&utf16("x"), 0), None);
+        assert_eq!(d.find_text(&utf16(""), 3), Some(3));
+        assert_eq!(d.find_text(&utf16("a"), 99), None);
+    }
+
+    #[test]
+    fn line_breaks_do_not_match_text() {
+        let d = dom("a<br />b");
+        assert_eq!(d.find_text(&utf16("ab"), 0), None);
+        assert_eq!(d.find_text(&utf16("b"), 0), Some(2));
+    }
+
+    #[test]
+    fn removed_nodes_cannot_be_looked_up() {
+        let mut d = dom("ab");
+        let text = d.children(d.document())[0];
+        d.remove(text);
+        assert!(d.get(text).is_none());
+        assert_eq!(html(&d), "");
+    }
+
+    #[test]
+    fn reused_slots_do_not_revive_stale_ids() {
+        let mut d: Dom<u16> = Dom::new();
+        let old = d.create_text(utf16("a"));
+        d.remove(old);
+        let new = d.create_text(utf16("b"));
+        assert_ne!(old, new);
+        assert!(d.get(old).is_none());
+        assert_eq!(
+            d.node(new),
+            &DomNode::Text(super::TextNode { data: utf16("b") })
+        );
+    }
+
+    #[test]
+    fn nodes_know_their_parents() {
+        let d = dom("a<strong>b</strong>");
+        let strong = d.children(d.document())[1];
+        let b = d.children(strong)[0];
+        assert_eq!(d.parent(b), Some(strong));
+        assert_eq!(d.parent(strong), Some(d.document()));
+        assert_eq!(d.parent(d.document()), None);
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Dom, DomHandle, DomNode, InsertionPoint, NodeId};
+use crate::mention::MENTION_ID_ATTR;
+use crate::CodeUnit;
+
+/**
+ * A location given as a node and an offset into it, as in a DOM Range:
+ * code units into a text node, or the number of children before it in an
+ * element. Unlike a Location this does not count the width of the nodes
+ * before it, so a platform that renders mentions as widgets of its own
+ * can still place a selection next to them exactly.
+ */
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct AnchoredLocation {
+    pub handle: DomHandle,
+    pub offset: usize,
+}
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * location as a node and an offset. A location inside a mention, which
+     * is edited as a single unit, is anchored just before or after it,
+     * whichever is nearer.
+     */
+    pub fn anchor_at(&self, location: usize) -> AnchoredLocation {
+        let (parent, index) = match self.insertion_point(location) {
+            InsertionPoint::InText { id, offset } => {
+                match self.enclosing_mention(id) {
+                    Some(mention) => {
+                        let (parent, index) = self.index_in_parent(mention);
+                        let into = location - self.start_of(mention);
+                        if into * 2 < self.node_len(mention) {
+                            (parent, index)
+                        } else {
+                            (parent, index + 1)
+                        }
+                    }
+                    None => {
+                        return AnchoredLocation {
+                            handle: self.handle_of(id),
+                            offset,
+                        }
+                    }
+                }
+            }
+            InsertionPoint::Between { parent, index } => (parent, index),
+        };
+        AnchoredLocation {
+            handle: self.handle_of(parent),
+            offset: index,
+        }
+    }
+
+    /**
+     * The location that anchor refers to, or None if its handle identifies
+     * no node. Offsets past the end of the node count as its end.
+     */
+    pub fn location_of_anchor(
+        &self,
+        anchor: &AnchoredLocation,
+    ) -> Option<usize> {
+        let id = self.node_at_handle(&anchor.handle)?;
+        let into = match self.node(id) {
+            DomNode::Text(text) => anchor.offset.min(text.data().len()),
+            DomNode::Container(_) => self
+                .children(id)
+                .iter()
+                .take(anchor.offset)
+                .map(|&child| self.node_len(child))
+                .sum(),
+        };
+        Some(self.start_of(id) + into)
+    }
+
+    fn enclosing_mention(&self, id: NodeId) -> Option<NodeId> {
+        let mut current = self.parent(id);
+        while let Some(ancestor) = current {
+            let c = self.container(ancestor);
+            if c.name == "a" && c.attr(MENTION_ID_ATTR).is_some() {
+                return Some(ancestor);
+            }
+            current = self.parent(ancestor);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, AnchoredLocation, Dom, DomHandle};
+
+    fn dom(html: &str) -> Dom<u16> {
+        parse(&html.encode_utf16().collect::<Vec<_>>())
+    }
+
+    fn anchor(path: &[usize], offset: usize) -> AnchoredLocation {
+        AnchoredLocation {
+            handle: DomHandle::from_raw(path.to_vec()),
+            offset,
+        }
+    }
+
+    const HTML: &str = "ab<a href=\"https://matrix.to/#/@c:d\" \
+        data-mention-id=\"@c:d\">Cat</a><em>e</em>";
+
+    #[test]
+    fn locations_in_text_are_anchored_in_the_text_node() {
+        let d = dom(HTML);
+        assert_eq!(d.anchor_at(1), anchor(&[0], 1));
+        assert_eq!(d.anchor_at(6), anchor(&[2, 0], 1));
+    }
+
+    #[test]
+    fn locations_by_a_mention_are_anchored_beside_it() {
+        let d = dom(HTML);
+        assert_eq!(d.anchor_at(3), anchor(&[], 1));
+        assert_eq!(d.anchor_at(4), anchor(&[], 2));
+        assert_eq!(d.anchor_at(5), anchor(&[2, 0], 0));
+        let d = dom("ab<a href=\"https://matrix.to/#/@c:d\" \
+            data-mention-id=\"@c:d\">Cat</a>");
+        assert_eq!(d.anchor_at(5), anchor(&[], 2));
+    }
+
+    #[test]
+    fn anchors_give_back_their_locations() {
+        let d = dom(HTML);
+        for location in [0, 1, 2, 5, 6] {
+            assert_eq!(
+                d.location_of_anchor(&d.anchor_at(location)),
+                Some(location)
+            );
+        }
+        assert_eq!(d.location_of_anchor(&anchor(&[], 9)), Some(6));
+        assert_eq!(d.location_of_anchor(&anchor(&[4], 0)), None);
+    }
+}
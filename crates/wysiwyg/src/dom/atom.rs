@@ -0,0 +1,173 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::ops::Deref;
+
+use crate::dom::{ElementSpec, ELEMENTS};
+
+/**
+ * The attribute names common enough to be worth interning, along with the
+ * names of ELEMENTS: most elements in a typical document use only these.
+ */
+const KNOWN_ATTRIBUTES: &[&str] = &[
+    "alt",
+    "class",
+    "color",
+    "data-id",
+    "data-label",
+    "data-mention-avatar",
+    "data-mention-id",
+    "data-mx-bg-color",
+    "data-mx-color",
+    "data-mx-emoticon",
+    "data-mx-spoiler",
+    "height",
+    "href",
+    "name",
+    "src",
+    "start",
+    "target",
+    "title",
+    "type",
+    "width",
+];
+
+/**
+ * A tag or attribute name. Common names are interned, so that they need
+ * no allocation of their own and compare as integers; others are stored
+ * as given.
+ */
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Atom(Repr);
+
+/**
+ * Names are interned whenever they are known, so two atoms of the same
+ * name always have the same representation and the derived comparisons
+ * are correct.
+ */
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Repr {
+    Known(u8),
+    Other(Box<str>),
+}
+
+impl Atom {
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            Repr::Known(index) => {
+                let index = usize::from(*index);
+                match ELEMENTS.get(index) {
+                    Some(spec) => spec.name,
+                    None => KNOWN_ATTRIBUTES[index - ELEMENTS.len()],
+                }
+            }
+            Repr::Other(name) => name,
+        }
+    }
+
+    /**
+     * The row of ELEMENTS for the element with this name, if there is one.
+     */
+    pub(crate) fn element(&self) -> Option<&'static ElementSpec> {
+        match &self.0 {
+            Repr::Known(index) => ELEMENTS.get(usize::from(*index)),
+            Repr::Other(_) => None,
+        }
+    }
+}
+
+/**
+ * Where name is among the names of ELEMENTS followed by KNOWN_ATTRIBUTES.
+ */
+fn known_index(name: &str) -> Option<usize> {
+    ELEMENTS
+        .iter()
+        .map(|spec| spec.name)
+        .chain(KNOWN_ATTRIBUTES.iter().copied())
+        .position(|known| known == name)
+}
+
+impl From<&str> for Atom {
+    fn from(name: &str) -> Self {
+        match known_index(name) {
+            Some(index) => Self(Repr::Known(
+                u8::try_from(index).expect("Too many known atoms"),
+            )),
+            None => Self(Repr::Other(Box::from(name))),
+        }
+    }
+}
+
+impl From<String> for Atom {
+    fn from(name: String) -> Self {
+        match known_index(&name) {
+            Some(_) => Self::from(name.as_str()),
+            None => Self(Repr::Other(name.into_boxed_str())),
+        }
+    }
+}
+
+impl Deref for Atom {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq<str> for Atom {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Atom {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Atom, Repr};
+
+    #[test]
+    fn known_names_are_interned() {
+        assert!(matches!(Atom::from("strong").0, Repr::Known(_)));
+        assert!(matches!(Atom::from(String::from("href")).0, Repr::Known(_)));
+        assert!(matches!(Atom::from("marquee").0, Repr::Other(_)));
+    }
+
+    #[test]
+    fn atoms_compare_by_name() {
+        assert_eq!(Atom::from("em"), Atom::from(String::from("em")));
+        assert_ne!(Atom::from("em"), Atom::from("strong"));
+        assert_eq!(Atom::from("marquee"), Atom::from(String::from("marquee")));
+        assert_eq!(Atom::from("li"), "li");
+        assert_eq!(Atom::from("x-y").as_str(), "x-y");
+    }
+}
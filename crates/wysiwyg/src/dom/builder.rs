@@ -0,0 +1,219 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Atom, Dom, NodeId};
+use crate::CodeUnit;
+
+/**
+ * Builds a Dom directly, without writing and parsing HTML: for example,
+ * DomBuilder::paragraph().text("a ").bold(|b| b.text("b")).build() makes
+ * the Dom of <p>a <strong>b</strong></p>. Each builder describes one
+ * element, or with new() a whole document, and its contents in order.
+ */
+#[derive(Clone, Debug)]
+pub struct DomBuilder<C> {
+    name: Option<Atom>,
+    attrs: Vec<(Atom, Vec<C>)>,
+    children: Vec<Child<C>>,
+}
+
+#[derive(Clone, Debug)]
+enum Child<C> {
+    Text(Vec<C>),
+    Element(DomBuilder<C>),
+}
+
+impl<C> Default for DomBuilder<C> {
+    fn default() -> Self {
+        Self {
+            name: None,
+            attrs: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+}
+
+impl<C> DomBuilder<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * A builder for a whole document, to which top-level content is added.
+     */
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /**
+     * A builder for an element called name.
+     */
+    pub fn element(name: &str) -> Self {
+        Self {
+            name: Some(Atom::from(name)),
+            ..Self::default()
+        }
+    }
+
+    pub fn paragraph() -> Self {
+        Self::element("p")
+    }
+
+    /**
+     * Give this element the attribute name, with value.
+     */
+    pub fn attr(mut self, name: &str, value: &str) -> Self {
+        self.attrs.push((Atom::from(name), C::encode(value)));
+        self
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.children.push(Child::Text(C::encode(text)));
+        self
+    }
+
+    /**
+     * Add the element described by child, e.g. another paragraph of a
+     * document.
+     */
+    pub fn append(mut self, child: DomBuilder<C>) -> Self {
+        self.children.push(Child::Element(child));
+        self
+    }
+
+    /**
+     * Add an element called name, whose contents are added by build.
+     */
+    pub fn child(
+        self,
+        name: &str,
+        build: impl FnOnce(DomBuilder<C>) -> DomBuilder<C>,
+    ) -> Self {
+        self.append(build(Self::element(name)))
+    }
+
+    pub fn bold(
+        self,
+        build: impl FnOnce(DomBuilder<C>) -> DomBuilder<C>,
+    ) -> Self {
+        self.child("strong", build)
+    }
+
+    pub fn italic(
+        self,
+        build: impl FnOnce(DomBuilder<C>) -> DomBuilder<C>,
+    ) -> Self {
+        self.child("em", build)
+    }
+
+    pub fn underline(
+        self,
+        build: impl FnOnce(DomBuilder<C>) -> DomBuilder<C>,
+    ) -> Self {
+        self.child("u", build)
+    }
+
+    pub fn link(
+        self,
+        url: &str,
+        build: impl FnOnce(DomBuilder<C>) -> DomBuilder<C>,
+    ) -> Self {
+        self.append(build(Self::element("a").attr("href", url)))
+    }
+
+    pub fn line_break(self) -> Self {
+        self.append(Self::element("br"))
+    }
+
+    /**
+     * The Dom described, normalized as parsing would leave it. A builder
+     * for an element makes a document containing just that element.
+     */
+    pub fn build(self) -> Dom<C> {
+        let mut dom = Dom::new();
+        let document = dom.document();
+        let children = self.build_in(&mut dom);
+        for child in children {
+            dom.append_child(document, child);
+        }
+        dom.normalize(document);
+        dom
+    }
+
+    /**
+     * Create the nodes described in dom, returning those that belong at
+     * the top: the element, or a document's contents.
+     */
+    fn build_in(self, dom: &mut Dom<C>) -> Vec<NodeId> {
+        let children = self
+            .children
+            .into_iter()
+            .flat_map(|child| match child {
+                Child::Text(text) => vec![dom.create_text(text)],
+                Child::Element(element) => element.build_in(dom),
+            })
+            .collect();
+        match self.name {
+            Some(name) => {
+                vec![dom.create_container(&name, self.attrs, children)]
+            }
+            None => children,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DomBuilder;
+    use crate::dom::{parse, Dom};
+    use crate::HtmlDialect;
+
+    fn html(dom: &Dom<u16>) -> String {
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    #[test]
+    fn elements_can_be_built_with_nested_formatting() {
+        let dom = DomBuilder::paragraph()
+            .text("a ")
+            .bold(|b| b.text("b").italic(|i| i.text("c")))
+            .line_break()
+            .link("https://matrix.org", |a| a.text("d"))
+            .build();
+        assert_eq!(
+            html(&dom),
+            "<p>a <strong>b<em>c</em></strong><br />\
+            <a href=\"https://matrix.org\">d</a></p>"
+        );
+    }
+
+    #[test]
+    fn documents_can_have_several_top_level_nodes() {
+        let dom = DomBuilder::new()
+            .append(DomBuilder::paragraph().text("a"))
+            .text("b")
+            .child("ol", |ol| {
+                ol.attr("start", "3").child("li", |li| li.text("c"))
+            })
+            .build();
+        assert_eq!(html(&dom), "<p>a</p>b<ol start=\"3\"><li>c</li></ol>");
+    }
+
+    #[test]
+    fn built_doms_are_normalized_like_parsed_ones() {
+        let built = DomBuilder::new().text("a").text("b").build();
+        let parsed = parse(&"ab".encode_utf16().collect::<Vec<_>>());
+        assert_eq!(built.children(built.document()).len(), 1);
+        assert_eq!(html(&built), html(&parsed));
+    }
+}
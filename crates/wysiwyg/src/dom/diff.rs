@@ -0,0 +1,474 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use crate::dom::{parse, Dom, DomNode, NodeId, MATRIX_LINK_SCHEMES};
+use crate::{CodeUnit, HtmlDialect};
+
+/**
+ * The difference between two versions of a message, rendered as HTML.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DiffHtml<C> {
+    /**
+     * The new version, with the words removed from the old version put
+     * back inside <del> and the words added inside <ins>. A word whose
+     * formatting changed is both removed and added.
+     */
+    pub html: Vec<C>,
+    /**
+     * Whether the versions differ at all.
+     */
+    pub has_changes: bool,
+}
+
+/**
+ * Compare two versions of a message, e.g. an event's formatted_body and
+ * the composer's message HTML when editing it, word by word, so that a
+ * client can preview what an edit changes before sending it.
+ *
+ * Each version is reduced to what the Matrix specification allows before
+ * comparing. Words, runs of whitespace and void elements such as line
+ * breaks are compared along with the formatting around them.
+ */
+pub fn diff_html<C>(old_html: &[C], new_html: &[C]) -> DiffHtml<C>
+where
+    C: CodeUnit,
+{
+    let mut old = parse(old_html);
+    old.strip_matrix_violations(MATRIX_LINK_SCHEMES);
+    let mut new = parse(new_html);
+    new.strip_matrix_violations(MATRIX_LINK_SCHEMES);
+    let old_tokens = tokens(&old);
+    let new_tokens = tokens(&new);
+
+    let mut out = Dom::new();
+    let mut writer = DiffWriter {
+        stack: Vec::new(),
+        matched: HashMap::new(),
+    };
+    let mut has_changes = false;
+    for step in diff(&old, &old_tokens, &new, &new_tokens) {
+        match step {
+            Step::Same(o, n) => {
+                writer.match_ancestors(
+                    &old,
+                    &old_tokens[o],
+                    &new,
+                    &new_tokens[n],
+                );
+                writer.write(&mut out, &new, &new_tokens[n], Side::New, None);
+            }
+            Step::Removed(o) => {
+                has_changes = true;
+                let token = &old_tokens[o];
+                writer.write(&mut out, &old, token, Side::Old, Some("del"));
+            }
+            Step::Added(n) => {
+                has_changes = true;
+                let token = &new_tokens[n];
+                writer.write(&mut out, &new, token, Side::New, Some("ins"));
+            }
+        }
+    }
+    let document = out.document();
+    out.normalize(document);
+    DiffHtml {
+        html: out.to_html(HtmlDialect::MatrixMessage),
+        has_changes,
+    }
+}
+
+/**
+ * A word, a run of whitespace or a void element, and the elements it is
+ * inside, outermost first.
+ */
+struct Token<C> {
+    content: TokenContent<C>,
+    ancestors: Vec<NodeId>,
+}
+
+enum TokenContent<C> {
+    Text(Vec<C>),
+    Void(NodeId),
+}
+
+fn tokens<C>(dom: &Dom<C>) -> Vec<Token<C>>
+where
+    C: CodeUnit,
+{
+    let mut found = Vec::new();
+    let mut ancestors = Vec::new();
+    // The nodes still to visit, with None marking where an element ends
+    let mut pending: Vec<Option<NodeId>> = dom
+        .children(dom.document())
+        .iter()
+        .rev()
+        .map(|&c| Some(c))
+        .collect();
+    while let Some(next) = pending.pop() {
+        let child = match next {
+            Some(child) => child,
+            None => {
+                ancestors.pop();
+                continue;
+            }
+        };
+        match dom.node(child) {
+            DomNode::Text(text) => {
+                let mut word: Vec<C> = Vec::new();
+                let mut in_space = false;
+                for &c in text.data() {
+                    let is_space =
+                        c.to_ascii().map_or(false, |a| a.is_ascii_whitespace());
+                    if !word.is_empty() && is_space != in_space {
+                        found.push(Token {
+                            content: TokenContent::Text(std::mem::take(
+                                &mut word,
+                            )),
+                            ancestors: ancestors.clone(),
+                        });
+                    }
+                    in_space = is_space;
+                    word.push(c);
+                }
+                if !word.is_empty() {
+                    found.push(Token {
+                        content: TokenContent::Text(word),
+                        ancestors: ancestors.clone(),
+                    });
+                }
+            }
+            DomNode::Container(c) if c.is_void() => found.push(Token {
+                content: TokenContent::Void(child),
+                ancestors: ancestors.clone(),
+            }),
+            DomNode::Container(_) => {
+                ancestors.push(child);
+                pending.push(None);
+                pending
+                    .extend(dom.children(child).iter().rev().map(|&c| Some(c)));
+            }
+        }
+    }
+    found
+}
+
+/**
+ * Whether a and b, from the Doms old and new, have the same content and
+ * formatting.
+ */
+fn same_token<C>(old: &Dom<C>, a: &Token<C>, new: &Dom<C>, b: &Token<C>) -> bool
+where
+    C: CodeUnit,
+{
+    let same_content = match (&a.content, &b.content) {
+        (TokenContent::Text(x), TokenContent::Text(y)) => x == y,
+        (TokenContent::Void(x), TokenContent::Void(y)) => {
+            same_element(old, *x, new, *y)
+        }
+        _ => false,
+    };
+    same_content
+        && a.ancestors.len() == b.ancestors.len()
+        && a.ancestors
+            .iter()
+            .zip(&b.ancestors)
+            .all(|(&x, &y)| same_element(old, x, new, y))
+}
+
+fn same_element<C>(old: &Dom<C>, a: NodeId, new: &Dom<C>, b: NodeId) -> bool
+where
+    C: CodeUnit,
+{
+    match (old.node(a), new.node(b)) {
+        (DomNode::Container(x), DomNode::Container(y)) => {
+            x.name() == y.name() && x.attrs() == y.attrs()
+        }
+        _ => false,
+    }
+}
+
+enum Step {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/**
+ * The steps that turn old_tokens into new_tokens, keeping as many tokens
+ * as possible (a longest common subsequence).
+ */
+fn diff<C>(
+    old: &Dom<C>,
+    old_tokens: &[Token<C>],
+    new: &Dom<C>,
+    new_tokens: &[Token<C>],
+) -> Vec<Step>
+where
+    C: CodeUnit,
+{
+    let same = |o: usize, n: usize| {
+        same_token(old, &old_tokens[o], new, &new_tokens[n])
+    };
+    // Common starts and ends are cheap to find, and usually most of an edit
+    let mut prefix = 0;
+    while prefix < old_tokens.len()
+        && prefix < new_tokens.len()
+        && same(prefix, prefix)
+    {
+        prefix += 1;
+    }
+    let mut suffix = 0;
+    while suffix < old_tokens.len() - prefix
+        && suffix < new_tokens.len() - prefix
+        && same(old_tokens.len() - 1 - suffix, new_tokens.len() - 1 - suffix)
+    {
+        suffix += 1;
+    }
+    let (o_end, n_end) = (old_tokens.len() - suffix, new_tokens.len() - suffix);
+
+    // lengths[i][j] is the length of the longest common subsequence of the
+    // old tokens from prefix + i and the new tokens from prefix + j
+    let (rows, cols) = (o_end - prefix, n_end - prefix);
+    let mut lengths = vec![vec![0usize; cols + 1]; rows + 1];
+    for i in (0..rows).rev() {
+        for j in (0..cols).rev() {
+            lengths[i][j] = if same(prefix + i, prefix + j) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut steps: Vec<Step> = (0..prefix).map(|i| Step::Same(i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < rows || j < cols {
+        if i < rows && j < cols && same(prefix + i, prefix + j) {
+            steps.push(Step::Same(prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if j == cols
+            || (i < rows && lengths[i + 1][j] >= lengths[i][j + 1])
+        {
+            steps.push(Step::Removed(prefix + i));
+            i += 1;
+        } else {
+            steps.push(Step::Added(prefix + j));
+            j += 1;
+        }
+    }
+    steps.extend((0..suffix).map(|k| Step::Same(o_end + k, n_end + k)));
+    steps
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum Side {
+    Old,
+    New,
+}
+
+/**
+ * An element of the output: a copy of an element of one of the Doms, or
+ * a <del> or <ins>.
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Source {
+    Element(Side, NodeId),
+    Change(&'static str),
+}
+
+struct DiffWriter {
+    /**
+     * The elements the next token will be written inside unless it needs
+     * others, with what they were copied from.
+     */
+    stack: Vec<(Source, NodeId)>,
+    /**
+     * The elements of the new Dom that elements of the old one correspond
+     * to, having held the same tokens. Removed tokens are written inside
+     * these, so that e.g. a word removed from a paragraph stays inside it.
+     */
+    matched: HashMap<NodeId, NodeId>,
+}
+
+impl DiffWriter {
+    fn match_ancestors<C>(
+        &mut self,
+        old: &Dom<C>,
+        a: &Token<C>,
+        new: &Dom<C>,
+        b: &Token<C>,
+    ) where
+        C: CodeUnit,
+    {
+        for (&x, &y) in a.ancestors.iter().zip(&b.ancestors) {
+            if !same_element(old, x, new, y) {
+                break;
+            }
+            self.matched.entry(x).or_insert(y);
+        }
+    }
+
+    /**
+     * Append a copy of token, from dom, to out, inside copies of its
+     * ancestors and, if change is set, inside an element of that name.
+     */
+    fn write<C>(
+        &mut self,
+        out: &mut Dom<C>,
+        dom: &Dom<C>,
+        token: &Token<C>,
+        side: Side,
+        change: Option<&'static str>,
+    ) where
+        C: CodeUnit,
+    {
+        let mut wanted: Vec<(Source, NodeId)> = token
+            .ancestors
+            .iter()
+            .map(|&id| {
+                let source = match (side, self.matched.get(&id)) {
+                    (Side::Old, Some(&matched)) => {
+                        Source::Element(Side::New, matched)
+                    }
+                    _ => Source::Element(side, id),
+                };
+                (source, id)
+            })
+            .collect();
+        if let Some(change) = change {
+            wanted.push((Source::Change(change), dom.document()));
+        }
+
+        let kept = self
+            .stack
+            .iter()
+            .zip(&wanted)
+            .take_while(|((a, _), (b, _))| a == b)
+            .count();
+        self.stack.truncate(kept);
+        for &(source, id) in &wanted[kept..] {
+            let copy = match (source, dom.node(id)) {
+                (Source::Element(..), DomNode::Container(c)) => out
+                    .create_container(c.name(), c.attrs().to_vec(), Vec::new()),
+                _ => out.create_container(
+                    change.unwrap_or_default(),
+                    Vec::new(),
+                    Vec::new(),
+                ),
+            };
+            let parent = self.parent(out);
+            out.append_child(parent, copy);
+            self.stack.push((source, copy));
+        }
+
+        let node = match &token.content {
+            TokenContent::Text(text) => out.create_text(text.clone()),
+            TokenContent::Void(id) => match dom.node(*id) {
+                DomNode::Container(c) => out.create_container(
+                    c.name(),
+                    c.attrs().to_vec(),
+                    Vec::new(),
+                ),
+                DomNode::Text(_) => unreachable!("Void tokens are elements"),
+            },
+        };
+        let parent = self.parent(out);
+        out.append_child(parent, node);
+    }
+
+    fn parent<C>(&self, out: &Dom<C>) -> NodeId
+    where
+        C: CodeUnit,
+    {
+        self.stack.last().map_or(out.document(), |&(_, id)| id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::diff_html;
+
+    fn diff(old: &str, new: &str) -> (String, bool) {
+        let result = diff_html(
+            &old.encode_utf16().collect::<Vec<_>>(),
+            &new.encode_utf16().collect::<Vec<_>>(),
+        );
+        (
+            String::from_utf16(&result.html).unwrap(),
+            result.has_changes,
+        )
+    }
+
+    #[test]
+    fn identical_messages_have_no_changes() {
+        assert_eq!(
+            diff("a <em>b</em>", "a <em>b</em>"),
+            (String::from("a <em>b</em>"), false)
+        );
+    }
+
+    #[test]
+    fn changed_words_are_removed_and_added() {
+        assert_eq!(
+            diff("the quick fox", "the slow fox"),
+            (
+                String::from("the <del>quick</del><ins>slow</ins> fox"),
+                true
+            )
+        );
+        assert_eq!(
+            diff("a b", "a b c"),
+            (String::from("a b<ins> c</ins>"), true)
+        );
+    }
+
+    #[test]
+    fn words_whose_formatting_changed_are_removed_and_added() {
+        assert_eq!(
+            diff("a b", "a <strong>b</strong>"),
+            (
+                String::from("a <del>b</del><strong><ins>b</ins></strong>"),
+                true
+            )
+        );
+    }
+
+    #[test]
+    fn removed_words_stay_inside_their_blocks() {
+        assert_eq!(
+            diff("<p>a b</p><p>c</p>", "<p>a</p><p>c</p>"),
+            (String::from("<p>a<del> b</del></p><p>c</p>"), true)
+        );
+    }
+
+    #[test]
+    fn line_breaks_are_compared_too() {
+        assert_eq!(
+            diff("a<br />b", "a b"),
+            (String::from("a<del><br /></del><ins> </ins>b"), true)
+        );
+    }
+
+    #[test]
+    fn markup_outside_the_matrix_subset_is_ignored() {
+        assert_eq!(
+            diff("<script>x</script>a", "a<ins></ins>"),
+            (String::from("a"), false)
+        );
+    }
+}
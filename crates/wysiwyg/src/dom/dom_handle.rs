@@ -0,0 +1,153 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Dom, NodeId};
+
+/**
+ * Identifies a node by the indices of the children to follow to reach it
+ * from the document root.
+ *
+ * Because the path counts every node the model serializes, it also
+ * identifies the matching node in a platform DOM built from the Editor
+ * HTML the model last returned. A DomHandle is only valid until the
+ * structure of the Dom changes; to keep track of a node across changes,
+ * pin it and ask for its handle again afterwards.
+ */
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DomHandle {
+    path: Vec<usize>,
+}
+
+impl DomHandle {
+    pub fn from_raw(path: Vec<usize>) -> Self {
+        Self { path }
+    }
+
+    pub fn raw(&self) -> &[usize] {
+        &self.path
+    }
+}
+
+/**
+ * A node pinned by Dom::pin, which stays the same node however the Dom
+ * around it changes, until the node itself is removed.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct PinnedNode {
+    lineage: u64,
+    id: NodeId,
+}
+
+impl<C> Dom<C>
+where
+    C: Clone,
+{
+    /**
+     * The node that handle identifies, or None if there is none.
+     */
+    pub fn node_at_handle(&self, handle: &DomHandle) -> Option<NodeId> {
+        let mut current = self.document;
+        for &index in handle.raw() {
+            current = *self.children(current).get(index)?;
+        }
+        Some(current)
+    }
+
+    /**
+     * Pin the node that handle identifies, so that its handle can be found
+     * again after the Dom changes. None if handle identifies no node.
+     */
+    pub fn pin(&self, handle: &DomHandle) -> Option<PinnedNode> {
+        Some(PinnedNode {
+            lineage: self.lineage,
+            id: self.node_at_handle(handle)?,
+        })
+    }
+
+    /**
+     * The handle that identifies pinned now, or None if it has been
+     * removed from the document, or was pinned in a Dom other than this
+     * one or an earlier copy of it. Unlike a stale DomHandle, this never
+     * silently identifies some other node.
+     */
+    pub fn handle_of_pinned(&self, pinned: &PinnedNode) -> Option<DomHandle> {
+        if pinned.lineage != self.lineage {
+            return None;
+        }
+        let mut current = pinned.id;
+        self.get(current)?;
+        while current != self.document {
+            current = self.parent(current)?;
+        }
+        Some(self.handle_of(pinned.id))
+    }
+
+    /**
+     * Where the node that handle identified in since, an earlier copy of
+     * this Dom, is now. None if there was no such node or it has since
+     * been removed.
+     */
+    pub fn rebase_handle(
+        &self,
+        handle: &DomHandle,
+        since: &Dom<C>,
+    ) -> Option<DomHandle> {
+        self.handle_of_pinned(&since.pin(handle)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, Dom, DomHandle};
+
+    fn dom(html: &str) -> Dom<u16> {
+        parse(&html.encode_utf16().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn a_pinned_node_is_found_after_nodes_before_it_are_removed() {
+        let mut d = dom("<p>a</p><p>b</p><p>c</p>");
+        let before = d.clone();
+        let handle = DomHandle::from_raw(vec![2, 0]);
+        let pinned = d.pin(&handle).unwrap();
+        let first = d.children(d.document())[0];
+        d.remove(first);
+        assert_eq!(
+            d.handle_of_pinned(&pinned),
+            Some(DomHandle::from_raw(vec![1, 0]))
+        );
+        assert_eq!(
+            d.rebase_handle(&handle, &before),
+            Some(DomHandle::from_raw(vec![1, 0]))
+        );
+    }
+
+    #[test]
+    fn a_removed_node_has_no_handle() {
+        let mut d = dom("<p>a</p><p>b</p>");
+        let pinned = d.pin(&DomHandle::from_raw(vec![1])).unwrap();
+        let second = d.children(d.document())[1];
+        d.remove(second);
+        assert_eq!(d.handle_of_pinned(&pinned), None);
+    }
+
+    #[test]
+    fn a_node_pinned_in_another_dom_has_no_handle() {
+        let d = dom("<p>a</p>");
+        let other = dom("<p>a</p>");
+        let pinned = other.pin(&DomHandle::from_raw(vec![0])).unwrap();
+        assert_eq!(d.handle_of_pinned(&pinned), None);
+        assert_eq!(d.pin(&DomHandle::from_raw(vec![1])), None);
+    }
+}
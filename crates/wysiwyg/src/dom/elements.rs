@@ -0,0 +1,375 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::ATTACHMENT_ELEMENT;
+use crate::{Feature, RemovalSeverity};
+
+/**
+ * How the composer treats one kind of element. The parser, sanitizer,
+ * serializer and menu state all read what they need to know about an
+ * element from its row in ELEMENTS, so that supporting a new element means
+ * adding or changing one row.
+ */
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ElementSpec {
+    pub name: &'static str,
+    /**
+     * Whether the Matrix specification allows it in formatted message
+     * bodies.
+     */
+    pub matrix: bool,
+    /**
+     * The attributes the Matrix specification allows on it.
+     */
+    pub attributes: &'static [&'static str],
+    /**
+     * Whether it never has children.
+     */
+    pub void: bool,
+    /**
+     * Whether it lays out its content as a block, rather than inline.
+     */
+    pub block: bool,
+    /**
+     * Whether HTML from elsewhere uses it for lines, including empty ones.
+     */
+    pub line: bool,
+    /**
+     * Whether it formats text without giving it any other meaning, and so
+     * can be copied from one piece of text to another.
+     */
+    pub format: bool,
+    /**
+     * Whether typing at its edge extends it. Typing just after a link, for
+     * example, does not make the new text part of the link.
+     */
+    pub inclusive: bool,
+    /**
+     * Whether whitespace inside it is content rather than layout.
+     */
+    pub preserves_whitespace: bool,
+    /**
+     * The feature that applies it, if it is one of the composer's own
+     * formats.
+     */
+    pub feature: Option<Feature>,
+    /**
+     * The element the parser stores it as, if it is a synonym.
+     */
+    pub parse_as: Option<&'static str>,
+    /**
+     * The element that replaces it when a message is restored for editing,
+     * if the composer cannot edit it.
+     */
+    pub restore_as: Option<&'static str>,
+    /**
+     * Whether restoring a message for editing replaces it by its content,
+     * because the composer cannot edit it.
+     */
+    pub restore_unwrapped: bool,
+    /**
+     * How serious removing it from a message is, if the Matrix
+     * specification does not allow it.
+     */
+    pub severity: RemovalSeverity,
+    /**
+     * Whether its content is removed along with it, rather than kept.
+     */
+    pub drops_content: bool,
+}
+
+const fn element(name: &'static str) -> ElementSpec {
+    ElementSpec {
+        name,
+        matrix: false,
+        attributes: &[],
+        void: false,
+        block: false,
+        line: false,
+        format: false,
+        inclusive: true,
+        preserves_whitespace: false,
+        feature: None,
+        parse_as: None,
+        restore_as: None,
+        restore_unwrapped: false,
+        severity: RemovalSeverity::Unsupported,
+        drops_content: false,
+    }
+}
+
+impl ElementSpec {
+    const fn matrix(self, attributes: &'static [&'static str]) -> Self {
+        Self {
+            matrix: true,
+            attributes,
+            ..self
+        }
+    }
+
+    const fn void(self) -> Self {
+        Self { void: true, ..self }
+    }
+
+    const fn block(self) -> Self {
+        Self {
+            block: true,
+            ..self
+        }
+    }
+
+    const fn line(self) -> Self {
+        Self { line: true, ..self }
+    }
+
+    const fn format(self) -> Self {
+        Self {
+            format: true,
+            ..self
+        }
+    }
+
+    const fn not_inclusive(self) -> Self {
+        Self {
+            inclusive: false,
+            ..self
+        }
+    }
+
+    const fn preserves_whitespace(self) -> Self {
+        Self {
+            preserves_whitespace: true,
+            ..self
+        }
+    }
+
+    const fn feature(self, feature: Feature) -> Self {
+        Self {
+            feature: Some(feature),
+            ..self
+        }
+    }
+
+    const fn parse_as(self, name: &'static str) -> Self {
+        Self {
+            parse_as: Some(name),
+            ..self
+        }
+    }
+
+    const fn restore_as(self, name: &'static str) -> Self {
+        Self {
+            restore_as: Some(name),
+            ..self
+        }
+    }
+
+    const fn restore_unwrapped(self) -> Self {
+        Self {
+            restore_unwrapped: true,
+            ..self
+        }
+    }
+
+    const fn dangerous(self) -> Self {
+        Self {
+            severity: RemovalSeverity::Dangerous,
+            ..self
+        }
+    }
+
+    const fn cosmetic(self) -> Self {
+        Self {
+            severity: RemovalSeverity::Cosmetic,
+            ..self
+        }
+    }
+
+    const fn drops_content(self) -> Self {
+        Self {
+            drops_content: true,
+            ..self
+        }
+    }
+}
+
+/**
+ * Every element the composer treats specially. Elements not listed are
+ * inline, not allowed in messages, and removed as Unsupported. Each name
+ * is interned as an Atom.
+ */
+pub(crate) const ELEMENTS: &[ElementSpec] = &[
+    element("a")
+        .matrix(&["name", "target", "href"])
+        .not_inclusive()
+        .feature(Feature::Links),
+    element("abbr").cosmetic(),
+    element("area").void(),
+    element("article").cosmetic(),
+    element("aside").cosmetic(),
+    element("b").matrix(&[]).parse_as("strong"),
+    element("base").void().dangerous(),
+    element("big").cosmetic(),
+    element("blockquote").matrix(&[]).block(),
+    element("br").matrix(&[]).void(),
+    element("caption").matrix(&[]),
+    element("center").cosmetic(),
+    element("cite").cosmetic(),
+    element("code")
+        .matrix(&["class"])
+        .format()
+        .preserves_whitespace(),
+    element("col").void(),
+    element("del").matrix(&[]).format(),
+    element("details").matrix(&[]),
+    element("div").matrix(&[]).block().line(),
+    element("em").matrix(&[]).format().feature(Feature::Italic),
+    element("embed").void().dangerous(),
+    element("font")
+        .matrix(&["data-mx-bg-color", "data-mx-color", "color"])
+        .restore_unwrapped(),
+    element("footer").cosmetic(),
+    element("form").dangerous(),
+    element("frame").dangerous(),
+    element("frameset").dangerous(),
+    element("h1").matrix(&[]).block().restore_as("p"),
+    element("h2").matrix(&[]).block().restore_as("p"),
+    element("h3").matrix(&[]).block().restore_as("p"),
+    element("h4").matrix(&[]).block().restore_as("p"),
+    element("h5").matrix(&[]).block().restore_as("p"),
+    element("h6").matrix(&[]).block().restore_as("p"),
+    element("header").cosmetic(),
+    element("hr").matrix(&[]).void(),
+    element("i").matrix(&[]).parse_as("em"),
+    element("iframe").dangerous(),
+    element("img")
+        .matrix(&["width", "height", "alt", "title", "src", "data-mx-emoticon"])
+        .void(),
+    element("input").void(),
+    element("ins").cosmetic(),
+    element("kbd").cosmetic(),
+    element("li").matrix(&[]).block(),
+    element("link").void().dangerous(),
+    element("main").cosmetic(),
+    element("mark").cosmetic(),
+    element("meta").void().dangerous(),
+    element(ATTACHMENT_ELEMENT).void(),
+    element("nav").cosmetic(),
+    element("object").dangerous(),
+    element("ol")
+        .matrix(&["start"])
+        .block()
+        .feature(Feature::OrderedList),
+    element("p").matrix(&[]).block().line(),
+    element("pre").matrix(&[]).block().preserves_whitespace(),
+    element("s").cosmetic(),
+    element("samp").cosmetic(),
+    element("script").dangerous().drops_content(),
+    element("section").cosmetic(),
+    element("small").cosmetic(),
+    element("source").void(),
+    element("span")
+        .matrix(&["data-mx-bg-color", "data-mx-color", "data-mx-spoiler"])
+        .restore_unwrapped(),
+    element("strike").matrix(&[]).format(),
+    element("strong")
+        .matrix(&[])
+        .format()
+        .feature(Feature::Bold),
+    element("style").dangerous().drops_content(),
+    element("sub").matrix(&[]).format(),
+    element("summary").matrix(&[]),
+    element("sup").matrix(&[]).format(),
+    element("table").matrix(&[]),
+    element("tbody").matrix(&[]),
+    element("td").matrix(&[]),
+    element("th").matrix(&[]),
+    element("thead").matrix(&[]),
+    element("time").cosmetic(),
+    element("tr").matrix(&[]),
+    element("track").void(),
+    element("u").matrix(&[]).format(),
+    element("ul")
+        .matrix(&[])
+        .block()
+        .feature(Feature::UnorderedList),
+    element("var").cosmetic(),
+    element("wbr").void(),
+];
+
+/**
+ * The row of ELEMENTS for the element called name, if it has one.
+ */
+pub(crate) fn element_spec(name: &str) -> Option<&'static ElementSpec> {
+    ELEMENTS.iter().find(|spec| spec.name == name)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::{element_spec, ELEMENTS};
+    use crate::{Feature, RemovalSeverity};
+
+    #[test]
+    fn each_element_has_one_row() {
+        let names: HashSet<_> = ELEMENTS.iter().map(|spec| spec.name).collect();
+        assert_eq!(names.len(), ELEMENTS.len());
+    }
+
+    #[test]
+    fn unlisted_elements_have_no_row() {
+        assert!(element_spec("marquee").is_none());
+        assert_eq!(
+            element_spec("ol").unwrap().feature,
+            Some(Feature::OrderedList)
+        );
+        assert_eq!(
+            element_spec("script").unwrap().severity,
+            RemovalSeverity::Dangerous
+        );
+    }
+
+    #[test]
+    fn synonyms_and_replacements_are_allowed_elements() {
+        let targets = ELEMENTS
+            .iter()
+            .flat_map(|spec| spec.parse_as.into_iter().chain(spec.restore_as));
+        for target in targets {
+            assert!(element_spec(target).unwrap().matrix, "{}", target);
+        }
+        assert!(ELEMENTS
+            .iter()
+            .filter(|spec| spec.format || spec.feature.is_some())
+            .all(|spec| spec.matrix));
+    }
+
+    #[test]
+    fn every_feature_has_one_element() {
+        let features = [
+            Feature::Bold,
+            Feature::Italic,
+            Feature::Links,
+            Feature::OrderedList,
+            Feature::UnorderedList,
+        ];
+        for feature in features {
+            let count = ELEMENTS
+                .iter()
+                .filter(|spec| spec.feature == Some(feature))
+                .count();
+            assert_eq!(count, 1, "{:?}", feature);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use crate::dom::{Dom, DomNode, NodeId};
+use crate::CodeUnit;
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Represent empty lines the one way the composer itself does, as line
+     * breaks: each empty paragraph, e.g. <p></p> or the <p><br></p> that
+     * browsers make, becomes a <br />. An empty paragraph takes up no
+     * location, so the cursor could not be placed on it nor backspace
+     * remove it, whereas a line break takes up one. If the content is
+     * nothing but empty paragraphs it becomes empty.
+     */
+    pub(crate) fn canonicalize_empty_lines(&mut self) {
+        let empty: Vec<NodeId> = self
+            .descendants(self.document())
+            .into_iter()
+            .filter(|&id| self.is_empty_line(id))
+            .collect();
+        if empty.is_empty() {
+            return;
+        }
+        let only_empty_lines = self
+            .children(self.document())
+            .iter()
+            .all(|child| empty.contains(child));
+        for id in empty {
+            if !only_empty_lines {
+                let br = self.create_container("br", Vec::new(), Vec::new());
+                let (parent, index) = self.index_in_parent(id);
+                self.insert_child(parent, index, br);
+            }
+            self.remove(id);
+        }
+        self.normalize(self.document());
+    }
+
+    /**
+     * Remove paragraphs left with nothing in them, e.g. by deleting all of
+     * their text. Like the empty paragraphs canonicalize_empty_lines
+     * replaces, they take up no location, so the cursor could never reach
+     * them, yet they would still be sent as empty lines. Run after
+     * remove_empty_formatting, so that formatting emptied along with them
+     * is already gone.
+     */
+    pub(crate) fn remove_blank_lines(&mut self) {
+        let blank: Vec<NodeId> = self
+            .descendants(self.document())
+            .into_iter()
+            .filter(|&id| self.is_blank_line(id))
+            .collect();
+        if blank.is_empty() {
+            return;
+        }
+        for id in blank {
+            self.remove(id);
+        }
+        self.normalize(self.document());
+    }
+
+    /**
+     * Remove the line break that contenteditable implementations leave at
+     * the end of a block with other content, e.g. <p>a<br></p>, which
+     * browsers do not show as a line of its own. What remains is the
+     * composer's own form, in which every line break is a line, so that
+     * locations are the same whichever platform the HTML came from. The
+     * Editor dialect adds the break back where a block really does end
+     * with an empty line (see ends_with_break).
+     */
+    pub(crate) fn canonicalize_trailing_breaks(&mut self) {
+        let lens: HashMap<NodeId, usize> = self
+            .spans(self.document(), 0)
+            .into_iter()
+            .map(|span| (span.id, span.end - span.start))
+            .collect();
+        let breaks: Vec<NodeId> = self
+            .descendants(self.document())
+            .into_iter()
+            .filter(|&id| {
+                matches!(self.node(id), DomNode::Container(c) if c.is_block())
+                    && lens[&id] > 1
+            })
+            .filter_map(|block| self.trailing_break(block))
+            .collect();
+        if breaks.is_empty() {
+            return;
+        }
+        for br in breaks {
+            self.remove(br);
+        }
+        self.normalize(self.document());
+    }
+
+    /**
+     * Whether the block id ends with a line break, which a browser would
+     * not show as a line unless another break follows it.
+     */
+    pub(crate) fn ends_with_break(&self, block: NodeId) -> bool {
+        self.trailing_break(block).is_some()
+    }
+
+    /**
+     * The line break that block ends with, if any, including one at the
+     * end of inline formatting at its end, but not one inside a nested
+     * block.
+     */
+    fn trailing_break(&self, block: NodeId) -> Option<NodeId> {
+        let mut current = block;
+        loop {
+            let last = *self.children(current).last()?;
+            match self.node(last) {
+                DomNode::Container(c) if c.name() == "br" => return Some(last),
+                DomNode::Container(c) if !c.is_void() && !c.is_block() => {
+                    current = last;
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    fn is_blank_line(&self, id: NodeId) -> bool {
+        let is_line = matches!(
+            self.node(id),
+            DomNode::Container(c) if c.name.element().map_or(false, |spec| spec.line)
+        );
+        is_line
+            && self.children(id).iter().all(|&child| {
+                matches!(
+                    self.node(child),
+                    DomNode::Text(text) if text.data().is_empty()
+                )
+            })
+    }
+
+    fn is_empty_line(&self, id: NodeId) -> bool {
+        let is_line = matches!(
+            self.node(id),
+            DomNode::Container(c) if c.name.element().map_or(false, |spec| spec.line)
+        );
+        is_line
+            && match self.children(id) {
+                [] => true,
+                [only] => matches!(
+                    self.node(*only),
+                    DomNode::Container(c) if c.name() == "br"
+                ),
+                _ => false,
+            }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::parse;
+    use crate::HtmlDialect;
+
+    fn canonical(html: &str) -> String {
+        let mut dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        dom.canonicalize_empty_lines();
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    #[test]
+    fn empty_paragraphs_become_line_breaks() {
+        assert_eq!(
+            canonical("<p>a</p><p></p><p><br></p><div></div><p>b</p>"),
+            "<p>a</p><br /><br /><br /><p>b</p>"
+        );
+        assert_eq!(
+            canonical("<ul><li><p></p></li></ul>"),
+            "<ul><li><br /></li></ul>"
+        );
+    }
+
+    #[test]
+    fn paragraphs_with_content_are_kept() {
+        assert_eq!(
+            canonical("<p> </p><p>a<br /></p><p><br /><br /></p>"),
+            "<p> </p><p>a<br /></p><p><br /><br /></p>"
+        );
+    }
+
+    #[test]
+    fn nothing_but_empty_paragraphs_is_empty() {
+        assert_eq!(canonical("<p></p><p><br /></p>"), "");
+    }
+
+    fn without_trailing_breaks(html: &str) -> String {
+        let mut dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        dom.canonicalize_trailing_breaks();
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    #[test]
+    fn trailing_breaks_in_blocks_are_removed() {
+        assert_eq!(
+            without_trailing_breaks(
+                "<p>a<br></p><ul><li><em>b<br></em></li></ul><p>c<br><br></p>"
+            ),
+            "<p>a</p><ul><li><em>b</em></li></ul><p>c<br /></p>"
+        );
+    }
+
+    #[test]
+    fn breaks_that_are_lines_are_kept() {
+        let html =
+            "<p><br /></p><blockquote><p>a<br /></p></blockquote>b<br />";
+        assert_eq!(
+            without_trailing_breaks(html),
+            "<p><br /></p><blockquote><p>a</p></blockquote>b<br />"
+        );
+    }
+
+    #[test]
+    fn the_editor_dialect_shows_a_trailing_empty_line() {
+        let mut dom =
+            parse(&"<p>a<br /><br /></p>".encode_utf16().collect::<Vec<_>>());
+        dom.canonicalize_trailing_breaks();
+        assert_eq!(
+            String::from_utf16(&dom.to_html(HtmlDialect::Editor)).unwrap(),
+            "<p>a<br /><br /></p>"
+        );
+        assert_eq!(
+            String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage))
+                .unwrap(),
+            "<p>a<br /></p>"
+        );
+    }
+
+    #[test]
+    fn canonical_content_round_trips() {
+        let html = canonical("<p>a</p><p></p><p>b</p>");
+        assert_eq!(canonical(&html), html);
+    }
+}
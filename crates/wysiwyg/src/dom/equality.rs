@@ -0,0 +1,199 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Atom, Dom, DomNode, NodeId};
+use crate::CodeUnit;
+
+/**
+ * A piece of the content, as it is compared and hashed: an element starting
+ * (with its attributes in order of name), a run of text, or the end of the
+ * element last started. Text is joined across text nodes, so how it is
+ * split into them makes no difference.
+ */
+#[derive(PartialEq)]
+enum Part<'a, C> {
+    Start(&'a Atom, Vec<(&'a Atom, &'a [C])>),
+    Text(Vec<C>),
+    End,
+}
+
+impl<C> PartialEq for Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Whether the two Doms have the same content, however they were made:
+     * the order of attributes, how text is split into nodes and where the
+     * nodes are stored are ignored.
+     */
+    fn eq(&self, other: &Self) -> bool {
+        self.parts() == other.parts()
+    }
+}
+
+impl<C> Eq for Dom<C> where C: CodeUnit {}
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * A hash of the content, which is the same for any two Doms that are
+     * equal. It does not depend on the version of this library, the
+     * platform or the code unit, so it can be stored, e.g. to only save a
+     * draft when its hash has changed.
+     */
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        for part in self.parts() {
+            match part {
+                Part::Start(name, attrs) => {
+                    hasher.write_u64(0);
+                    hasher.write_str(name);
+                    hasher.write_u64(attrs.len() as u64);
+                    for (name, value) in attrs {
+                        hasher.write_str(name);
+                        hasher.write_str(&C::decode(value));
+                    }
+                }
+                Part::Text(text) => {
+                    hasher.write_u64(1);
+                    hasher.write_str(&C::decode(&text));
+                }
+                Part::End => hasher.write_u64(2),
+            }
+        }
+        hasher.finish()
+    }
+
+    /**
+     * The parts of the content in order, found with a stack of the open
+     * elements rather than by recursion, so that any depth of nesting can
+     * be compared.
+     */
+    fn parts(&self) -> Vec<Part<'_, C>> {
+        let mut parts = Vec::new();
+        // Each open element and the index of its next child
+        let mut open: Vec<(NodeId, usize)> = vec![(self.document(), 0)];
+        while let Some(&(id, next)) = open.last() {
+            let child = match self.children(id).get(next) {
+                Some(&child) => child,
+                None => {
+                    open.pop();
+                    if !open.is_empty() {
+                        parts.push(Part::End);
+                    }
+                    continue;
+                }
+            };
+            open.last_mut().unwrap().1 += 1;
+            match self.node(child) {
+                DomNode::Text(t) if t.data.is_empty() => {}
+                DomNode::Text(t) => match parts.last_mut() {
+                    Some(Part::Text(text)) => text.extend_from_slice(&t.data),
+                    _ => parts.push(Part::Text(t.data.clone())),
+                },
+                DomNode::Container(c) => {
+                    let mut attrs: Vec<(&Atom, &[C])> = c
+                        .attrs
+                        .iter()
+                        .map(|(name, value)| (name, value.as_slice()))
+                        .collect();
+                    attrs.sort_by(|a, b| a.0.cmp(b.0));
+                    parts.push(Part::Start(&c.name, attrs));
+                    open.push((child, 0));
+                }
+            }
+        }
+        parts
+    }
+}
+
+/**
+ * 64-bit FNV-1a, which unlike the hashers in std is specified, so gives the
+ * same hash everywhere. Numbers are written little-endian, and strings as
+ * their length then their UTF-8.
+ */
+pub(crate) struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StableHasher {
+    pub(crate) fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    pub(crate) fn write_str(&mut self, s: &str) {
+        self.write_u64(s.len() as u64);
+        self.write(s.as_bytes());
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, Dom};
+    use crate::CodeUnit;
+
+    fn dom(html: &str) -> Dom<u16> {
+        parse(&u16::encode(html))
+    }
+
+    #[test]
+    fn attribute_order_and_text_nodes_make_no_difference() {
+        let a = dom("<a href=\"x\" title=\"y\">b</a>c");
+        let mut b = dom("<a title=\"y\" href=\"x\">b</a>");
+        let document = b.document();
+        for text in ["", "c"] {
+            let text = b.create_text(u16::encode(text));
+            b.append_child(document, text);
+        }
+        assert!(a == b);
+        assert_eq!(a.content_hash(), b.content_hash());
+    }
+
+    #[test]
+    fn different_content_is_not_equal() {
+        for (a, b) in [
+            ("<em>a</em>", "<strong>a</strong>"),
+            ("<em>a</em>b", "<em>ab</em>"),
+            ("<a href=\"x\">a</a>", "<a href=\"y\">a</a>"),
+            ("ab", "a b"),
+        ] {
+            assert!(dom(a) != dom(b), "{} == {}", a, b);
+            assert_ne!(dom(a).content_hash(), dom(b).content_hash());
+        }
+    }
+
+    #[test]
+    fn the_hash_is_the_same_for_any_code_unit() {
+        let html = "<p>a \u{1F600}</p>";
+        let utf8: Dom<u8> = parse(&u8::encode(html));
+        assert_eq!(dom(html).content_hash(), utf8.content_hash());
+        assert_eq!(dom("").content_hash(), 0xcbf2_9ce4_8422_2325);
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use crate::dom::{Dom, DomNode, NodeId};
+use crate::{CodeUnit, DomEvent, HtmlDialect};
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * The events that turn a platform DOM matching before (an earlier
+     * clone of this Dom) into one matching this Dom, in the order they
+     * must be applied:
+     *
+     * - the removals, last first, with handles in before, so that each
+     *   removal leaves the handles of the ones after it valid;
+     * - the insertions, first first, with handles in this Dom, so that the
+     *   siblings before each insertion are already in place;
+     * - the text changes of nodes that were neither removed nor inserted.
+     *
+     * A node that moved, or whose name or attributes changed, is removed
+     * and inserted again.
+     */
+    pub fn events_since(&self, before: &Dom<C>) -> Vec<DomEvent<C>> {
+        let mut kept = HashSet::new();
+        self.collect_kept(before, self.document, before.document, &mut kept);
+
+        let mut removals = Vec::new();
+        before.collect_removals(before.document, &kept, &mut removals);
+        removals.reverse();
+
+        let mut insertions = Vec::new();
+        let mut text_changes = Vec::new();
+        self.collect_insertions(
+            before,
+            self.document,
+            &kept,
+            &mut insertions,
+            &mut text_changes,
+        );
+
+        removals
+            .into_iter()
+            .chain(insertions)
+            .chain(text_changes)
+            .collect()
+    }
+
+    /**
+     * Add to kept the nodes under container that are still where they
+     * were under then_container in before, in the same order and with the
+     * same name and attributes.
+     */
+    fn collect_kept(
+        &self,
+        before: &Dom<C>,
+        container: NodeId,
+        then_container: NodeId,
+        kept: &mut HashSet<NodeId>,
+    ) {
+        let mut pending = vec![(container, then_container)];
+        while let Some((container, then_container)) = pending.pop() {
+            self.collect_kept_children(before, container, then_container, kept);
+            pending.extend(
+                self.children(container)
+                    .iter()
+                    .filter(|child| kept.contains(child))
+                    .map(|&child| (child, child)),
+            );
+        }
+    }
+
+    /**
+     * Add to kept the children of container that are kept, as collect_kept
+     * does, leaving their children alone.
+     */
+    fn collect_kept_children(
+        &self,
+        before: &Dom<C>,
+        container: NodeId,
+        then_container: NodeId,
+        kept: &mut HashSet<NodeId>,
+    ) {
+        let candidates: Vec<NodeId> = self
+            .children(container)
+            .iter()
+            .copied()
+            .filter(|&child| {
+                before.parent(child) == Some(then_container)
+                    && same_shape(self.node(child), before.node(child))
+            })
+            .collect();
+        let previous_order: Vec<NodeId> = before
+            .children(then_container)
+            .iter()
+            .copied()
+            .filter(|child| candidates.contains(child))
+            .collect();
+        if previous_order == candidates {
+            kept.extend(candidates);
+        }
+    }
+
+    /**
+     * Add to removals the nodes under container that were not kept, in
+     * document order, but not the nodes inside them.
+     */
+    fn collect_removals(
+        &self,
+        container: NodeId,
+        kept: &HashSet<NodeId>,
+        removals: &mut Vec<DomEvent<C>>,
+    ) {
+        let mut pending: Vec<NodeId> =
+            self.children(container).iter().rev().cloned().collect();
+        while let Some(child) = pending.pop() {
+            if kept.contains(&child) {
+                pending.extend(self.children(child).iter().rev());
+            } else {
+                removals.push(DomEvent::NodeRemoved {
+                    handle: self.handle_of(child),
+                });
+            }
+        }
+    }
+
+    /**
+     * Add to insertions the nodes under container that were not kept, and
+     * to text_changes the kept text nodes whose text changed, in document
+     * order.
+     */
+    fn collect_insertions(
+        &self,
+        before: &Dom<C>,
+        container: NodeId,
+        kept: &HashSet<NodeId>,
+        insertions: &mut Vec<DomEvent<C>>,
+        text_changes: &mut Vec<DomEvent<C>>,
+    ) {
+        let mut pending: Vec<NodeId> =
+            self.children(container).iter().rev().cloned().collect();
+        while let Some(child) = pending.pop() {
+            if !kept.contains(&child) {
+                insertions.push(DomEvent::NodeInserted {
+                    handle: self.handle_of(child),
+                    html: self.node_to_html(child, HtmlDialect::Editor),
+                });
+                continue;
+            }
+            match (self.node(child), before.node(child)) {
+                (DomNode::Text(now), DomNode::Text(then)) => {
+                    if now.data != then.data {
+                        text_changes.push(DomEvent::TextChanged {
+                            handle: self.handle_of(child),
+                            new_text: now.data.clone(),
+                        });
+                    }
+                }
+                _ => pending.extend(self.children(child).iter().rev()),
+            }
+        }
+    }
+}
+
+fn same_shape<C>(now: &DomNode<C>, then: &DomNode<C>) -> bool
+where
+    C: CodeUnit,
+{
+    match (now, then) {
+        (DomNode::Text(_), DomNode::Text(_)) => true,
+        (DomNode::Container(now), DomNode::Container(then)) => {
+            now.name == then.name && now.attrs == then.attrs
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, Dom, NodeId};
+    use crate::{DomEvent, DomHandle, HtmlDialect};
+
+    fn dom(html: &str) -> Dom<u16> {
+        parse(&utf16(html))
+    }
+
+    fn utf16(text: &str) -> Vec<u16> {
+        text.encode_utf16().collect()
+    }
+
+    fn handle(path: &[usize]) -> DomHandle {
+        DomHandle::from_raw(path.to_vec())
+    }
+
+    /**
+     * Apply events to platform the way a platform layer would, using only
+     * the handles and HTML they carry.
+     */
+    fn apply(platform: &mut Dom<u16>, events: Vec<DomEvent<u16>>) {
+        fn at(platform: &Dom<u16>, path: &[usize]) -> NodeId {
+            path.iter().fold(platform.document(), |id, &index| {
+                platform.children(id)[index]
+            })
+        }
+        for event in events {
+            match event {
+                DomEvent::NodeInserted { handle, html } => {
+                    let (index, parent) = handle.raw().split_last().unwrap();
+                    let parent = at(platform, parent);
+                    let fragment = parse(&html);
+                    let node = fragment.children(fragment.document())[0];
+                    let node = platform.import(&fragment, node);
+                    platform.insert_child(parent, *index, node);
+                }
+                DomEvent::NodeRemoved { handle } => {
+                    let node = at(platform, handle.raw());
+                    platform.remove(node);
+                }
+                DomEvent::TextChanged { handle, new_text } => {
+                    let node = at(platform, handle.raw());
+                    platform.text_mut(node).data = new_text;
+                }
+            }
+        }
+    }
+
+    fn assert_events_reproduce(before: &Dom<u16>, after: &Dom<u16>) {
+        let mut platform = dom(&String::from_utf16(
+            &before.to_html(HtmlDialect::MatrixMessage),
+        )
+        .unwrap());
+        apply(&mut platform, after.events_since(before));
+        assert_eq!(
+            platform.to_html(HtmlDialect::MatrixMessage),
+            after.to_html(HtmlDialect::MatrixMessage)
+        );
+    }
+
+    #[test]
+    fn an_unchanged_dom_has_no_events() {
+        let d = dom("a<strong>b</strong>");
+        assert_eq!(d.events_since(&d.clone()), vec![]);
+    }
+
+    #[test]
+    fn typing_changes_text() {
+        let before = dom("a<strong>b</strong>");
+        let mut after = before.clone();
+        after.insert_text(2, &utf16("c"));
+        assert_eq!(
+            after.events_since(&before),
+            vec![DomEvent::TextChanged {
+                handle: handle(&[1, 0]),
+                new_text: utf16("bc"),
+            }]
+        );
+    }
+
+    #[test]
+    fn wrapping_splits_the_text_and_inserts_the_wrapper() {
+        let before = dom("abc");
+        let mut after = before.clone();
+        after.wrap_inline_range(1, 2, "em");
+        assert_eq!(
+            after.events_since(&before),
+            vec![
+                DomEvent::NodeInserted {
+                    handle: handle(&[1]),
+                    html: utf16("<em>b</em>"),
+                },
+                DomEvent::NodeInserted {
+                    handle: handle(&[2]),
+                    html: utf16("c"),
+                },
+                DomEvent::TextChanged {
+                    handle: handle(&[0]),
+                    new_text: utf16("a"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn removals_come_last_first_and_insertions_first_first() {
+        let before = dom("<p>a</p><p>b</p><p>c</p>");
+        let mut after = before.clone();
+        let children = after.children(after.document()).to_vec();
+        after.remove(children[0]);
+        after.remove(children[2]);
+        let x = after.create_text(utf16("x"));
+        after.insert_child(after.document(), 0, x);
+        let y = after.create_text(utf16("y"));
+        after.append_child(after.document(), y);
+        assert_eq!(
+            after.events_since(&before),
+            vec![
+                DomEvent::NodeRemoved {
+                    handle: handle(&[2]),
+                },
+                DomEvent::NodeRemoved {
+                    handle: handle(&[0]),
+                },
+                DomEvent::NodeInserted {
+                    handle: handle(&[0]),
+                    html: utf16("x"),
+                },
+                DomEvent::NodeInserted {
+                    handle: handle(&[2]),
+                    html: utf16("y"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn changed_attributes_replace_the_node() {
+        let before = dom("<a href=\"x\">b</a>");
+        let mut after = before.clone();
+        let link = after.children(after.document())[0];
+        after.set_attr(link, "href", utf16("y"));
+        assert_eq!(
+            after.events_since(&before),
+            vec![
+                DomEvent::NodeRemoved {
+                    handle: handle(&[0]),
+                },
+                DomEvent::NodeInserted {
+                    handle: handle(&[0]),
+                    html: utf16("<a href=\"y\">b</a>"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn applying_the_events_reproduces_the_dom() {
+        let before = dom("<p>ab<em>cd</em></p><ul><li>e</li><li>f</li></ul>");
+
+        let mut after = before.clone();
+        after.delete_range(1, 4);
+        assert_events_reproduce(&before, &after);
+
+        let mut after = before.clone();
+        after.wrap_inline_range(0, 5, "strong");
+        assert_events_reproduce(&before, &after);
+
+        let mut after = before.clone();
+        let list = after.children(after.document())[1];
+        after.toggle_list_type(list);
+        after.insert_text(5, &utf16("g"));
+        assert_events_reproduce(&before, &after);
+
+        let after = dom("<p>x</p>");
+        assert_events_reproduce(&before, &after);
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{parse, Dom, DomNode, MATRIX_LINK_SCHEMES};
+use crate::{CodeUnit, HtmlDialect};
+
+/**
+ * Content from outside the composer, e.g. a received event's
+ * formatted_body, made safe to insert into it (see
+ * ComposerModel::insert_fragment).
+ */
+#[derive(Clone, Debug)]
+pub struct DomFragment<C> {
+    dom: Dom<C>,
+}
+
+/**
+ * Parse the HTML of a received message into a fragment that can be
+ * quoted or forwarded in the composer: anything the Matrix specification
+ * does not allow in messages is removed, as is the reply fallback
+ * (<mx-reply>) that the specification says clients should strip when
+ * showing a reply.
+ */
+pub fn parse_message_html<C>(html: &[C]) -> DomFragment<C>
+where
+    C: CodeUnit,
+{
+    let mut dom = parse(html);
+    for id in dom.descendants(dom.document()) {
+        let is_reply = matches!(
+            dom.get(id),
+            Some(DomNode::Container(c)) if c.name() == "mx-reply"
+        );
+        if is_reply {
+            dom.remove(id);
+        }
+    }
+    dom.strip_matrix_violations(MATRIX_LINK_SCHEMES);
+    DomFragment { dom }
+}
+
+impl<C> DomFragment<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * This fragment inside a blockquote, as for quoting a message.
+     */
+    pub fn quoted(&self) -> DomFragment<C> {
+        let mut dom = Dom::new();
+        let children = self
+            .dom
+            .children(self.dom.document())
+            .iter()
+            .map(|&child| dom.import(&self.dom, child))
+            .collect();
+        let quote = dom.create_container("blockquote", Vec::new(), children);
+        dom.append_child(dom.document(), quote);
+        DomFragment { dom }
+    }
+
+    pub fn dom(&self) -> &Dom<C> {
+        &self.dom
+    }
+
+    pub fn to_html(&self) -> Vec<C> {
+        self.dom.to_html(HtmlDialect::MatrixMessage)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_message_html;
+
+    fn fragment_html(html: &str, quoted: bool) -> String {
+        let fragment =
+            parse_message_html(&html.encode_utf16().collect::<Vec<_>>());
+        let fragment = if quoted { fragment.quoted() } else { fragment };
+        String::from_utf16(&fragment.to_html()).unwrap()
+    }
+
+    #[test]
+    fn messages_are_reduced_to_the_matrix_subset() {
+        assert_eq!(
+            fragment_html(
+                "<div onclick=\"x\">a<script>b</script></div>",
+                false
+            ),
+            "<div>a</div>"
+        );
+    }
+
+    #[test]
+    fn reply_fallbacks_are_dropped() {
+        assert_eq!(
+            fragment_html(
+                "<mx-reply><blockquote>quoted</blockquote></mx-reply>reply",
+                false
+            ),
+            "reply"
+        );
+    }
+
+    #[test]
+    fn fragments_can_be_quoted() {
+        assert_eq!(
+            fragment_html("a<strong>b</strong>", true),
+            "<blockquote>a<strong>b</strong></blockquote>"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use crate::dom::{parse, Dom, DomNode, NodeId};
+use crate::{CodeUnit, HtmlDialect};
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Check that the tree is well formed: every node reachable from the
+     * document is alive, appears only once, has the right parent, and is
+     * not a void element with children. Returns a description of the
+     * first problem found.
+     */
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let mut seen = HashSet::new();
+        seen.insert(self.document());
+        // Each node still to check, with the container it was found in
+        let mut pending: Vec<(NodeId, NodeId)> = self
+            .children(self.document())
+            .iter()
+            .rev()
+            .map(|&child| (self.document(), child))
+            .collect();
+        while let Some((container, child)) = pending.pop() {
+            if self.get(child).is_none() {
+                return Err(format!("{:?} has a removed child", container));
+            }
+            if !seen.insert(child) {
+                return Err(format!("{:?} appears more than once", child));
+            }
+            if self.parent(child) != Some(container) {
+                return Err(format!(
+                    "{:?} is a child of {:?} but has parent {:?}",
+                    child,
+                    container,
+                    self.parent(child)
+                ));
+            }
+            if let DomNode::Container(c) = self.node(child) {
+                if c.is_void() && !c.children.is_empty() {
+                    return Err(format!(
+                        "{:?} is a {} with children",
+                        child, c.name
+                    ));
+                }
+                pending.extend(
+                    c.children
+                        .iter()
+                        .rev()
+                        .map(|&grandchild| (child, grandchild)),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * A well-formed copy of this Dom, made by serializing whatever can be
+     * reached from the document (each node once, ignoring the children of
+     * void elements) and parsing the result.
+     */
+    pub fn repaired(&self) -> Dom<C> {
+        let mut copy = Dom::new();
+        let mut seen = HashSet::new();
+        seen.insert(self.document());
+        for child in self.copy_reachable(&mut seen, &mut copy) {
+            copy.append_child(copy.document(), child);
+        }
+        parse(&copy.to_html(HtmlDialect::MatrixMessage))
+    }
+
+    /**
+     * Copy the children of the document into copy, skipping any that are
+     * removed or have already been seen.
+     */
+    fn copy_reachable(
+        &self,
+        seen: &mut HashSet<NodeId>,
+        copy: &mut Dom<C>,
+    ) -> Vec<NodeId> {
+        // Each element being copied, the index of its next child, and the
+        // copies of its children so far
+        let mut open: Vec<(NodeId, usize, Vec<NodeId>)> =
+            vec![(self.document(), 0, Vec::new())];
+        loop {
+            let (container, next, _) = open.last_mut().unwrap();
+            let (container, index) = (*container, *next);
+            *next += 1;
+            let child = match self.children(container).get(index) {
+                Some(&child) => child,
+                None => {
+                    let (_, _, copied) = open.pop().unwrap();
+                    let parent = match open.last_mut() {
+                        Some(parent) => parent,
+                        None => return copied,
+                    };
+                    let c = self.container(container);
+                    let id =
+                        copy.create_container(&c.name, c.attrs.clone(), copied);
+                    parent.2.push(id);
+                    continue;
+                }
+            };
+            if self.get(child).is_none() || !seen.insert(child) {
+                continue;
+            }
+            let id = match self.node(child) {
+                DomNode::Text(text) => copy.create_text(text.data.clone()),
+                DomNode::Container(c) if c.is_void() => {
+                    copy.create_container(&c.name, c.attrs.clone(), Vec::new())
+                }
+                DomNode::Container(_) => {
+                    open.push((child, 0, Vec::new()));
+                    continue;
+                }
+            };
+            open.last_mut().unwrap().2.push(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, Dom};
+    use crate::HtmlDialect;
+
+    fn dom(html: &str) -> Dom<u16> {
+        parse(&html.encode_utf16().collect::<Vec<_>>())
+    }
+
+    fn html(dom: &Dom<u16>) -> String {
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    #[test]
+    fn parsed_content_is_well_formed() {
+        assert_eq!(
+            dom("a<strong>b<br />c</strong><ul><li>d</li></ul>")
+                .check_invariants(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn a_void_element_with_children_is_detected_and_repaired() {
+        let mut d = dom("a<br />b");
+        let children = d.children(d.document()).to_vec();
+        d.append_child(children[1], children[2]);
+        assert!(d.check_invariants().is_err());
+        let repaired = d.repaired();
+        assert_eq!(repaired.check_invariants(), Ok(()));
+        assert_eq!(html(&repaired), "a<br />");
+    }
+
+    #[test]
+    fn a_cycle_is_detected_and_cut_when_repairing() {
+        let mut d = dom("<em><strong>a</strong></em>b");
+        let em = d.children(d.document())[0];
+        let strong = d.children(em)[0];
+        d.container_mut(strong).children.push(em);
+        assert!(d.check_invariants().is_err());
+        let repaired = d.repaired();
+        assert_eq!(repaired.check_invariants(), Ok(()));
+        assert_eq!(html(&repaired), "<em><strong>a</strong></em>b");
+    }
+}
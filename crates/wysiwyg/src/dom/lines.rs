@@ -0,0 +1,220 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Dom, DomNode, NodeId};
+use crate::graphemes::grapheme_count;
+use crate::CodeUnit;
+
+impl<C> Dom<C>
+where
+    C: Clone,
+{
+    /**
+     * The range of locations covered by each line of the content, in order.
+     *
+     * Lines are ended by line breaks and by the boundaries of block
+     * elements. A location on the boundary between two blocks is included
+     * in both lines. There is always at least one line.
+     */
+    pub fn lines(&self) -> Vec<(usize, usize)> {
+        let mut lines = Lines {
+            ranges: Vec::new(),
+            current: Some(0),
+            pos: 0,
+        };
+        self.collect_lines(self.document(), &mut lines);
+        if let Some(start) = lines.current {
+            lines.ranges.push((start, lines.pos));
+        }
+        lines.ranges
+    }
+
+    fn collect_lines(&self, container: NodeId, lines: &mut Lines) {
+        // The nodes still to visit, with None marking where a block ends
+        let mut pending: Vec<Option<NodeId>> = self
+            .children(container)
+            .iter()
+            .rev()
+            .map(|&c| Some(c))
+            .collect();
+        while let Some(next) = pending.pop() {
+            let child = match next {
+                Some(child) => child,
+                None => {
+                    if let Some(start) = lines.current.take() {
+                        lines.ranges.push((start, lines.pos));
+                    }
+                    continue;
+                }
+            };
+            match self.node(child) {
+                DomNode::Text(text) => {
+                    lines.current.get_or_insert(lines.pos);
+                    lines.pos += text.data.len();
+                }
+                DomNode::Container(c) if c.is_void() => {
+                    let start = lines.current.unwrap_or(lines.pos);
+                    if c.name == "br" {
+                        lines.ranges.push((start, lines.pos));
+                        lines.current = Some(lines.pos + 1);
+                    } else {
+                        lines.current = Some(start);
+                    }
+                    lines.pos += 1;
+                }
+                DomNode::Container(c) if c.is_block() => {
+                    if let Some(start) = lines.current {
+                        if start < lines.pos {
+                            lines.ranges.push((start, lines.pos));
+                        }
+                    }
+                    lines.current = Some(lines.pos);
+                    pending.push(None);
+                    pending.extend(
+                        self.children(child).iter().rev().map(|&c| Some(c)),
+                    );
+                }
+                DomNode::Container(_) => {
+                    pending.extend(
+                        self.children(child).iter().rev().map(|&c| Some(c)),
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * An estimate of how many lines the content takes up when rendered in
+     * a box wrap_width characters wide: each of its lines, wrapped at
+     * spaces where it can be and mid-word where a word is wider than the
+     * box. A wrap_width of 0 means lines are never wrapped.
+     */
+    pub fn visual_line_count(&self, wrap_width: usize) -> usize {
+        self.lines()
+            .into_iter()
+            .map(|(start, end)| {
+                let text = C::decode(&self.text_in_range(start, end));
+                wrapped_line_count(&text, wrap_width)
+            })
+            .sum()
+    }
+}
+
+/**
+ * How many lines wrapping line to width characters, as visual_line_count
+ * does, gives.
+ */
+fn wrapped_line_count(line: &str, width: usize) -> usize {
+    if width == 0 {
+        return 1;
+    }
+    let mut count = 1;
+    let mut used = 0;
+    for word in line.split(' ') {
+        let len = grapheme_count(word);
+        let needed = if used == 0 { len } else { used + 1 + len };
+        if needed <= width {
+            used = needed;
+            continue;
+        }
+        if used > 0 {
+            count += 1;
+        }
+        let extra = (len.max(1) - 1) / width;
+        count += extra;
+        used = len - extra * width;
+    }
+    count
+}
+
+struct Lines {
+    ranges: Vec<(usize, usize)>,
+    /**
+     * Where the line we are in started, or None if we are between blocks.
+     */
+    current: Option<usize>,
+    pos: usize,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::parse;
+
+    fn lines(html: &str) -> Vec<(usize, usize)> {
+        parse(&html.encode_utf16().collect::<Vec<_>>()).lines()
+    }
+
+    #[test]
+    fn empty_content_has_one_empty_line() {
+        assert_eq!(lines(""), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn line_breaks_end_lines() {
+        assert_eq!(lines("ab<br />cd"), vec![(0, 2), (3, 5)]);
+        assert_eq!(lines("ab<br />"), vec![(0, 2), (3, 3)]);
+        assert_eq!(lines("<br /><br />"), vec![(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn blocks_are_separate_lines() {
+        assert_eq!(lines("<p>ab</p><p>cd</p>"), vec![(0, 2), (2, 4)]);
+        assert_eq!(lines("ab<p>cd</p>ef"), vec![(0, 2), (2, 4), (4, 6)]);
+        assert_eq!(lines("<p></p><p>a</p>"), vec![(0, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn nested_blocks_do_not_add_lines() {
+        assert_eq!(
+            lines("<ul><li>a</li><li>b<br />c</li></ul>"),
+            vec![(0, 1), (1, 2), (3, 4)]
+        );
+    }
+
+    fn visual_lines(html: &str, wrap_width: usize) -> usize {
+        parse(&html.encode_utf16().collect::<Vec<_>>())
+            .visual_line_count(wrap_width)
+    }
+
+    #[test]
+    fn each_line_is_at_least_one_visual_line() {
+        assert_eq!(visual_lines("", 10), 1);
+        assert_eq!(visual_lines("ab<br /><br />c<p>d</p>", 10), 4);
+        assert_eq!(visual_lines("a very long line indeed", 0), 1);
+    }
+
+    #[test]
+    fn long_lines_wrap_at_spaces() {
+        assert_eq!(visual_lines("aaa bbb ccc", 7), 2);
+        assert_eq!(visual_lines("aaa bbb ccc", 11), 1);
+        assert_eq!(visual_lines("<ul><li>aaa bbb</li><li>c</li></ul>", 4), 3);
+    }
+
+    #[test]
+    fn words_wider_than_the_line_are_broken() {
+        assert_eq!(visual_lines("abcdefghij", 4), 3);
+        assert_eq!(visual_lines("a abcdefgh", 4), 3);
+        assert_eq!(visual_lines("a\u{301}b\u{301}c\u{301}", 3), 1);
+    }
+
+    #[test]
+    fn inline_formatting_does_not_affect_lines() {
+        assert_eq!(lines("a<strong>b<br />c</strong>d"), vec![(0, 2), (3, 5)]);
+    }
+}
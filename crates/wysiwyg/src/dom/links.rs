@@ -0,0 +1,120 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Dom, DomNode};
+use crate::CodeUnit;
+
+const ELLIPSIS: &str = "…";
+
+/**
+ * url cut short to max_chars characters, the last of them an ellipsis, if
+ * it is longer than that, for showing as the text of a link to it (see
+ * ComposerConfig::max_link_text_length).
+ */
+pub fn shorten_url<C>(url: &[C], max_chars: usize) -> Vec<C>
+where
+    C: CodeUnit,
+{
+    let url = C::decode(url);
+    if url.chars().count() <= max_chars {
+        return C::encode(&url);
+    }
+    let mut shortened: String =
+        url.chars().take(max_chars.saturating_sub(1)).collect();
+    shortened.push_str(ELLIPSIS);
+    C::encode(&shortened)
+}
+
+/**
+ * Whether text is what shorten_url would make of href.
+ */
+fn is_shortened<C>(text: &[C], href: &[C]) -> bool
+where
+    C: CodeUnit,
+{
+    let text = C::decode(text);
+    match text.strip_suffix(ELLIPSIS) {
+        Some(prefix) => {
+            let href = C::decode(href);
+            href.len() > prefix.len() && href.starts_with(prefix)
+        }
+        None => false,
+    }
+}
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Show the whole URL of each link whose text is its URL cut short (see
+     * shorten_url), e.g. for a plain text body, where the text is all a
+     * reader gets.
+     */
+    pub(crate) fn use_full_link_urls(&mut self) {
+        for id in self.descendants(self.document()) {
+            let href = match self.get(id) {
+                Some(DomNode::Container(c)) if c.name == "a" => {
+                    c.attr("href").map(<[C]>::to_vec)
+                }
+                _ => None,
+            };
+            if let Some(href) = href {
+                if is_shortened(&self.text_content(id), &href) {
+                    self.replace_content(id, &href);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::shorten_url;
+    use crate::dom::{parse, Dom};
+
+    fn dom(html: &str) -> Dom<u16> {
+        parse(&html.encode_utf16().collect::<Vec<_>>())
+    }
+
+    fn shorten(url: &str, max_chars: usize) -> String {
+        String::from_utf16(&shorten_url(
+            &url.encode_utf16().collect::<Vec<_>>(),
+            max_chars,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn urls_longer_than_the_limit_are_cut_short_with_an_ellipsis() {
+        assert_eq!(shorten("https://matrix.org/a", 20), "https://matrix.org/a");
+        assert_eq!(
+            shorten("https://matrix.org/ab", 20),
+            "https://matrix.org/…"
+        );
+        assert_eq!(shorten("https://é.org", 10), "https://é…");
+    }
+
+    #[test]
+    fn shortened_links_show_their_full_urls() {
+        let mut dom =
+            dom("<a href=\"https://matrix.org/abc\">https://ma…</a> \
+            <a href=\"https://x.org\">elsewhere…</a>");
+        dom.use_full_link_urls();
+        assert_eq!(
+            String::from_utf16(&dom.to_plain_text(false)).unwrap(),
+            "https://matrix.org/abc elsewhere…"
+        );
+    }
+}
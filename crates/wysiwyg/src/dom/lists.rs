@@ -0,0 +1,439 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Atom, Dom, DomNode, NodeId};
+use crate::CodeUnit;
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * The innermost list item containing the text just before location,
+     * or just after it if there is none before, i.e. the item a caret at
+     * location is shown in.
+     */
+    pub fn list_item_at(&self, location: usize) -> Option<NodeId> {
+        let leaves = self.leaves();
+        let leaf = leaves
+            .iter()
+            .rev()
+            .find(|leaf| leaf.start < location && location <= leaf.end())
+            .or_else(|| {
+                leaves.iter().find(|leaf| {
+                    leaf.start <= location && location < leaf.end()
+                })
+            })?;
+        let mut current = self.parent(leaf.id);
+        while let Some(id) = current {
+            if self.name_of(id) == "li" {
+                return Some(id);
+            }
+            current = self.parent(id);
+        }
+        None
+    }
+
+    /**
+     * Make the list item li a child of the item before it, in a nested
+     * list of the same kind. Returns false, changing nothing, if li is the
+     * first item in its list.
+     */
+    pub(crate) fn indent_list_item(&mut self, li: NodeId) -> bool {
+        if self.parent(li).is_none() {
+            return false;
+        }
+        let (list, index) = self.index_in_parent(li);
+        if index == 0 {
+            return false;
+        }
+        let previous = self.children(list)[index - 1];
+        // Join a nested list already at the end of the previous item
+        let existing = self
+            .children(previous)
+            .last()
+            .copied()
+            .filter(|&last| self.name_of(last) == self.name_of(list));
+        match existing {
+            Some(nested) => self.append_child(nested, li),
+            None => {
+                let name = String::from(self.name_of(list));
+                let nested = self.create_container(&name, Vec::new(), vec![li]);
+                self.append_child(previous, nested);
+            }
+        }
+        true
+    }
+
+    /**
+     * Move the list item li out of the nested list containing it, to just
+     * after the item that list belongs to. The items after li in its list
+     * move with it, into a list nested inside it. Returns false, changing
+     * nothing, if li is not in a nested list.
+     */
+    pub(crate) fn outdent_list_item(&mut self, li: NodeId) -> bool {
+        let list = match self.parent(li) {
+            Some(list) => list,
+            None => return false,
+        };
+        let parent_li = match self.parent(list) {
+            Some(parent) if self.name_of(parent) == "li" => parent,
+            _ => return false,
+        };
+        let outer_list = self.parent(parent_li).expect("li is in a list");
+        let (_, index) = self.index_in_parent(li);
+        let following = self.children(list)[index + 1..].to_vec();
+        if !following.is_empty() {
+            let name = String::from(self.name_of(list));
+            let nested = self.create_container(&name, Vec::new(), following);
+            self.append_child(li, nested);
+        }
+        let (_, parent_index) = self.index_in_parent(parent_li);
+        self.insert_child(outer_list, parent_index + 1, li);
+        if self.children(list).is_empty() {
+            self.remove(list);
+        }
+        true
+    }
+
+    /**
+     * Swap the list item li with the item before it, taking any lists
+     * nested in either with them. Returns false, changing nothing, if li
+     * is the first item in its list.
+     */
+    pub(crate) fn move_list_item_up(&mut self, li: NodeId) -> bool {
+        if self.parent(li).is_none() {
+            return false;
+        }
+        let (list, index) = self.index_in_parent(li);
+        if index == 0 {
+            return false;
+        }
+        self.insert_child(list, index - 1, li);
+        true
+    }
+
+    /**
+     * Swap the list item li with the item after it, as move_list_item_up
+     * does. Returns false, changing nothing, if li is the last item in its
+     * list.
+     */
+    pub(crate) fn move_list_item_down(&mut self, li: NodeId) -> bool {
+        if self.parent(li).is_none() {
+            return false;
+        }
+        let (list, index) = self.index_in_parent(li);
+        if index + 1 >= self.children(list).len() {
+            return false;
+        }
+        self.insert_child(list, index + 1, li);
+        true
+    }
+
+    /**
+     * The innermost list, ordered or unordered, that covers location.
+     */
+    pub fn list_at(&self, location: usize) -> Option<NodeId> {
+        match (
+            self.element_at(location, "ol"),
+            self.element_at(location, "ul"),
+        ) {
+            (Some(ol), Some(ul)) => {
+                if self.is_inside(ol, ul) {
+                    Some(ol)
+                } else {
+                    Some(ul)
+                }
+            }
+            (ol, ul) => ol.or(ul),
+        }
+    }
+
+    /**
+     * Turn the list into an unordered list if it is ordered, and into an
+     * ordered list if not, keeping its items and any lists nested in them
+     * as they are. If that makes it the same type as a list next to it,
+     * they are merged.
+     */
+    pub(crate) fn toggle_list_type(&mut self, list: NodeId) {
+        let container = self.container_mut(list);
+        if container.name == "ol" {
+            container.name = Atom::from("ul");
+            container.attrs.retain(|(name, _)| name != "start");
+        } else {
+            container.name = Atom::from("ol");
+        }
+        if let Some(parent) = self.parent(list) {
+            self.normalize(parent);
+        }
+    }
+
+    /**
+     * Replace the list by a paragraph for each of its items, holding the
+     * item's content with the formatting inside it. Blocks in an item,
+     * such as a nested list, stay as blocks between the paragraphs rather
+     * than going inside them. Formatting left with nothing in it, e.g. in
+     * an empty item, is removed with the item rather than being kept as
+     * empty elements, and an empty item becomes an empty line (a line
+     * break, as canonicalize_empty_lines makes empty paragraphs).
+     */
+    pub(crate) fn remove_list(&mut self, list: NodeId) {
+        let (parent, index) = self.index_in_parent(list);
+        let mut content = Vec::new();
+        for li in self.children(list).to_vec() {
+            self.remove_empty_inline(li);
+            let children = self.children(li).to_vec();
+            if children.is_empty() {
+                content.push(self.create_container(
+                    "br",
+                    Vec::new(),
+                    Vec::new(),
+                ));
+            }
+            let mut inline = Vec::new();
+            for child in children {
+                let is_block = match self.node(child) {
+                    DomNode::Container(c) => c.is_block(),
+                    DomNode::Text(_) => false,
+                };
+                if is_block {
+                    self.push_paragraph(&mut inline, &mut content);
+                    content.push(child);
+                } else {
+                    inline.push(child);
+                }
+            }
+            self.push_paragraph(&mut inline, &mut content);
+        }
+        let mut children = self.children(parent).to_vec();
+        children.splice(index..=index, content);
+        self.set_children(parent, children);
+        self.remove(list);
+        self.normalize(parent);
+    }
+
+    /**
+     * Take the inline nodes out of inline and push a paragraph of them onto
+     * content, or the nodes themselves if they are just the line break of
+     * an empty line.
+     */
+    fn push_paragraph(
+        &mut self,
+        inline: &mut Vec<NodeId>,
+        content: &mut Vec<NodeId>,
+    ) {
+        let nodes = std::mem::take(inline);
+        let is_empty_line = nodes.len() == 1 && self.name_of(nodes[0]) == "br";
+        if is_empty_line {
+            content.extend(nodes);
+        } else if !nodes.is_empty() {
+            content.push(self.create_container("p", Vec::new(), nodes));
+        }
+    }
+
+    fn is_inside(&self, id: NodeId, ancestor: NodeId) -> bool {
+        let mut current = self.parent(id);
+        while let Some(parent) = current {
+            if parent == ancestor {
+                return true;
+            }
+            current = self.parent(parent);
+        }
+        false
+    }
+
+    fn name_of(&self, id: NodeId) -> &str {
+        match self.node(id) {
+            DomNode::Container(c) => c.name(),
+            DomNode::Text(_) => "",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, Dom};
+    use crate::HtmlDialect;
+
+    fn dom(html: &str) -> Dom<u16> {
+        parse(&html.encode_utf16().collect::<Vec<_>>())
+    }
+
+    fn html(dom: &Dom<u16>) -> String {
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    fn item(dom: &Dom<u16>, location: usize) -> crate::dom::NodeId {
+        dom.element_at(location, "li").unwrap()
+    }
+
+    #[test]
+    fn toggling_a_list_type_keeps_nested_lists() {
+        let mut d =
+            dom("<ol start=\"3\"><li>a<ol><li>b</li></ol></li><li>c</li></ol>");
+        let inner = d.list_at(1).unwrap();
+        d.toggle_list_type(inner);
+        assert_eq!(
+            html(&d),
+            "<ol start=\"3\"><li>a<ul><li>b</li></ul></li><li>c</li></ol>"
+        );
+        let outer = d.list_at(3).unwrap();
+        d.toggle_list_type(outer);
+        assert_eq!(
+            html(&d),
+            "<ul><li>a<ul><li>b</li></ul></li><li>c</li></ul>"
+        );
+    }
+
+    #[test]
+    fn toggling_a_list_next_to_one_of_the_new_type_merges_them() {
+        let mut d = dom("<ul><li>a</li></ul><ol><li>b</li></ol>");
+        let list = d.list_at(2).unwrap();
+        d.toggle_list_type(list);
+        assert_eq!(html(&d), "<ul><li>a</li><li>b</li></ul>");
+    }
+
+    #[test]
+    fn deleting_what_separates_two_lists_merges_them() {
+        let mut d = dom("<ul><li>a</li></ul>x<ul><li>b</li></ul>");
+        d.delete_range(1, 2);
+        assert_eq!(html(&d), "<ul><li>a</li><li>b</li></ul>");
+    }
+
+    #[test]
+    fn lists_of_different_types_are_not_merged() {
+        let d = dom("<ul><li>a</li></ul><ol><li>b</li></ol><ol start=\"5\"><li>c</li></ol>");
+        assert_eq!(
+            html(&d),
+            "<ul><li>a</li></ul><ol><li>b</li></ol><ol start=\"5\"><li>c</li></ol>"
+        );
+    }
+
+    #[test]
+    fn indenting_nests_an_item_in_the_one_before() {
+        let mut d = dom("<ul><li>a</li><li>b</li><li>c</li></ul>");
+        let b = item(&d, 2);
+        assert!(d.indent_list_item(b));
+        assert_eq!(
+            html(&d),
+            "<ul><li>a<ul><li>b</li></ul></li><li>c</li></ul>"
+        );
+        let c = item(&d, 3);
+        assert!(d.indent_list_item(c));
+        assert_eq!(
+            html(&d),
+            "<ul><li>a<ul><li>b</li><li>c</li></ul></li></ul>"
+        );
+    }
+
+    #[test]
+    fn the_first_item_cannot_be_indented() {
+        let mut d = dom("<ol><li>a</li><li>b</li></ol>");
+        let a = item(&d, 0);
+        assert!(!d.indent_list_item(a));
+        assert_eq!(html(&d), "<ol><li>a</li><li>b</li></ol>");
+    }
+
+    #[test]
+    fn outdenting_moves_an_item_after_its_parent() {
+        let mut d =
+            dom("<ul><li>a<ul><li>b</li><li>c</li></ul></li><li>d</li></ul>");
+        let b = item(&d, 2);
+        assert!(d.outdent_list_item(b));
+        assert_eq!(
+            html(&d),
+            "<ul><li>a</li><li>b<ul><li>c</li></ul></li><li>d</li></ul>"
+        );
+    }
+
+    #[test]
+    fn outdenting_the_only_nested_item_removes_the_nested_list() {
+        let mut d = dom("<ul><li>a<ul><li>b</li></ul></li></ul>");
+        let b = item(&d, 2);
+        assert!(d.outdent_list_item(b));
+        assert_eq!(html(&d), "<ul><li>a</li><li>b</li></ul>");
+    }
+
+    #[test]
+    fn moving_an_item_takes_its_nested_list_with_it() {
+        let mut d = dom("<ol><li>a</li><li>b<ul><li>c</li></ul></li></ol>");
+        let list = d.children(d.document())[0];
+        let b = d.children(list)[1];
+        assert!(d.move_list_item_up(b));
+        assert_eq!(
+            html(&d),
+            "<ol><li>b<ul><li>c</li></ul></li><li>a</li></ol>"
+        );
+        assert!(d.move_list_item_down(b));
+        assert_eq!(
+            html(&d),
+            "<ol><li>a</li><li>b<ul><li>c</li></ul></li></ol>"
+        );
+    }
+
+    #[test]
+    fn items_at_the_ends_of_a_list_cannot_move_past_them() {
+        let mut d = dom("<ul><li>a</li><li>b</li></ul>");
+        let a = item(&d, 0);
+        let b = item(&d, 2);
+        assert!(!d.move_list_item_up(a));
+        assert!(!d.move_list_item_down(b));
+        assert_eq!(html(&d), "<ul><li>a</li><li>b</li></ul>");
+    }
+
+    #[test]
+    fn removing_a_list_puts_each_item_on_its_own_line() {
+        let mut d = dom("<ul><li>a</li><li><strong>b</strong></li></ul>c");
+        let list = d.list_at(0).unwrap();
+        d.remove_list(list);
+        assert_eq!(html(&d), "<p>a</p><p><strong>b</strong></p>c");
+    }
+
+    #[test]
+    fn removing_a_list_drops_formatting_left_empty() {
+        let mut d = dom(
+            "<ul><li>a</li><li><strong><em></em></strong></li><li>b</li></ul>",
+        );
+        let list = d.list_at(0).unwrap();
+        d.remove_list(list);
+        assert_eq!(html(&d), "<p>a</p><br /><p>b</p>");
+    }
+
+    #[test]
+    fn removing_a_nested_list_keeps_the_outer_one() {
+        let mut d = dom("<ul><li>a<ol><li>b</li><li>c</li></ol></li></ul>");
+        let list = d.list_at(1).unwrap();
+        d.remove_list(list);
+        assert_eq!(html(&d), "<ul><li>a<p>b</p><p>c</p></li></ul>");
+    }
+
+    #[test]
+    fn removing_a_list_keeps_the_lists_nested_in_it_as_blocks() {
+        let mut d =
+            dom("<ul><li><em>a</em>b<ol><li>c</li></ol>d</li><li>e</li></ul>");
+        let list = d.list_at(0).unwrap();
+        d.remove_list(list);
+        assert_eq!(
+            html(&d),
+            "<p><em>a</em>b</p><ol><li>c</li></ol><p>d</p><p>e</p>"
+        );
+    }
+
+    #[test]
+    fn top_level_items_cannot_be_outdented() {
+        let mut d = dom("<ul><li>a</li></ul>");
+        let a = item(&d, 0);
+        assert!(!d.outdent_list_item(a));
+    }
+}
@@ -0,0 +1,1057 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{parse, Dom, DomNode, NodeId, DEFAULT_MAX_DEPTH};
+use crate::{CodeUnit, HtmlDialect, MarkdownStyleKind};
+
+/**
+ * The ASCII punctuation that a backslash escapes in markdown.
+ */
+const ESCAPABLE: &str = "\\`*_~[]()<>#+-.!&";
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Serialize this Dom as markdown, so that it can be edited as plain
+     * text and read back with parse_markdown. Line breaks, and the
+     * boundaries between paragraphs, become newlines. Bold, italic,
+     * strikethrough, inline code, links, quotes, lists and code blocks
+     * use the usual markdown syntax, and any other element is written as
+     * HTML, which markdown allows.
+     */
+    pub fn to_markdown(&self) -> Vec<C> {
+        C::encode(&self.block_markdown(self.document()).join("\n"))
+    }
+
+    /**
+     * The lines of markdown for the children of container.
+     */
+    fn block_markdown(&self, container: NodeId) -> Vec<String> {
+        let mut writer = MarkdownWriter {
+            lines: Vec::new(),
+            line: None,
+            prefixes: Vec::new(),
+        };
+        let mut pending: Vec<MarkdownStep> = self
+            .children(container)
+            .iter()
+            .rev()
+            .map(|&child| MarkdownStep::Node(child))
+            .collect();
+        while let Some(step) = pending.pop() {
+            let child = match step {
+                MarkdownStep::Node(child) => child,
+                MarkdownStep::Item(item, marker) => {
+                    let indent = " ".repeat(marker.len());
+                    writer.prefixes.push(LinePrefix {
+                        first: marker,
+                        rest: indent,
+                        quote: false,
+                        started: false,
+                    });
+                    match self.node(item) {
+                        DomNode::Container(i) if i.name == "li" => {
+                            pending.push(MarkdownStep::EndItem);
+                            pending.extend(
+                                self.children(item)
+                                    .iter()
+                                    .rev()
+                                    .map(|&child| MarkdownStep::Node(child)),
+                            );
+                        }
+                        _ => {
+                            let mut line = String::new();
+                            self.write_inline_markdown(item, &mut line);
+                            writer.write_line(line);
+                            writer.prefixes.pop();
+                        }
+                    }
+                    continue;
+                }
+                MarkdownStep::EndBlock => {
+                    writer.end_line();
+                    continue;
+                }
+                MarkdownStep::EndQuote => {
+                    writer.end_line();
+                    writer.prefixes.pop();
+                    continue;
+                }
+                MarkdownStep::EndItem => {
+                    writer.end_line();
+                    if !writer.prefixes.last().map_or(true, |p| p.started) {
+                        writer.write_line(String::new());
+                    }
+                    writer.prefixes.pop();
+                    continue;
+                }
+            };
+            match self.node(child) {
+                DomNode::Container(c) if c.name == "br" => {
+                    let line = writer.line.take().unwrap_or_default();
+                    writer.write_line(escape_line_start(line));
+                    writer.line = Some(String::new());
+                }
+                DomNode::Container(c) if c.is_block() => {
+                    writer.end_line();
+                    self.start_block_markdown(child, &mut writer, &mut pending);
+                }
+                _ => {
+                    let mut inline = String::new();
+                    self.write_inline_markdown(child, &mut inline);
+                    let mut pieces = inline.split('\n');
+                    writer
+                        .line
+                        .get_or_insert_with(String::new)
+                        .push_str(pieces.next().unwrap_or_default());
+                    for piece in pieces {
+                        let line = writer.line.take().unwrap_or_default();
+                        writer.write_line(escape_line_start(line));
+                        writer.line = Some(String::from(piece));
+                    }
+                }
+            }
+        }
+        writer.end_line();
+        writer.lines
+    }
+
+    /**
+     * Start writing the block element id: write what it can be written
+     * as straight away, and add the steps for its contents to pending.
+     */
+    fn start_block_markdown(
+        &self,
+        id: NodeId,
+        writer: &mut MarkdownWriter,
+        pending: &mut Vec<MarkdownStep>,
+    ) {
+        let c = self.container(id);
+        let children = self.children(id).iter().rev();
+        match c.name.as_str() {
+            "blockquote" => {
+                writer.prefixes.push(LinePrefix {
+                    first: String::from("> "),
+                    rest: String::from("> "),
+                    quote: true,
+                    started: false,
+                });
+                pending.push(MarkdownStep::EndQuote);
+                pending
+                    .extend(children.map(|&child| MarkdownStep::Node(child)));
+            }
+            "ul" | "ol" => {
+                let first: u32 = c
+                    .attr("start")
+                    .and_then(|start| C::decode(start).parse().ok())
+                    .unwrap_or(1);
+                let items: Vec<MarkdownStep> = (first..)
+                    .zip(self.children(id))
+                    .map(|(number, &item)| {
+                        let marker = if c.name == "ol" {
+                            format!("{}. ", number)
+                        } else {
+                            String::from("- ")
+                        };
+                        MarkdownStep::Item(item, marker)
+                    })
+                    .collect();
+                pending.extend(items.into_iter().rev());
+            }
+            "pre" => {
+                let code = C::decode(&self.text_content(id));
+                writer.write_line(String::from("```"));
+                for line in code.split('\n') {
+                    writer.write_line(String::from(line));
+                }
+                writer.write_line(String::from("```"));
+            }
+            _ => {
+                pending.push(MarkdownStep::EndBlock);
+                pending
+                    .extend(children.map(|&child| MarkdownStep::Node(child)));
+            }
+        }
+    }
+
+    /**
+     * Append the markdown for the inline node id to out. Line breaks
+     * become newlines.
+     */
+    fn write_inline_markdown(&self, id: NodeId, out: &mut String) {
+        // The nodes still to write, and what closes the elements open
+        let mut pending: Vec<Result<NodeId, String>> = vec![Ok(id)];
+        while let Some(step) = pending.pop() {
+            let id = match step {
+                Ok(id) => id,
+                Err(close) => {
+                    out.push_str(&close);
+                    continue;
+                }
+            };
+            let c = match self.node(id) {
+                DomNode::Text(text) => {
+                    out.push_str(&escape_text(&C::decode(&text.data)));
+                    continue;
+                }
+                DomNode::Container(c) => c,
+            };
+            let only_text = self
+                .children(id)
+                .iter()
+                .all(|&child| matches!(self.node(child), DomNode::Text(_)));
+            let (open, close) = match c.name.as_str() {
+                _ if self.children(id).is_empty() && !c.is_void() => continue,
+                "br" => {
+                    out.push('\n');
+                    continue;
+                }
+                "strong" | "b" => ("**", String::from("**")),
+                "em" | "i" => ("_", String::from("_")),
+                "del" | "strike" => ("~~", String::from("~~")),
+                "code" if only_text => {
+                    let code = C::decode(&self.text_content(id));
+                    if !code.contains('`') {
+                        out.push_str(&format!("`{}`", code));
+                        continue;
+                    }
+                    ("", String::new())
+                }
+                "a" if c.attrs.len() == 1 => match c.attr("href") {
+                    Some(href) => {
+                        let href = C::decode(href);
+                        ("[", format!("]({})", escape_url(&href)))
+                    }
+                    None => ("", String::new()),
+                },
+                _ => ("", String::new()),
+            };
+            if open.is_empty() {
+                out.push_str(&C::decode(
+                    &self.node_to_html(id, HtmlDialect::MatrixMessage),
+                ));
+            } else {
+                out.push_str(open);
+                pending.push(Err(close));
+                pending.extend(self.children(id).iter().rev().map(|&c| Ok(c)));
+            }
+        }
+    }
+}
+
+/**
+ * Parse markdown, as written by Dom::to_markdown, into a Dom. Newlines
+ * become line breaks, except where they end quotes, list items or code
+ * blocks. HTML in the markdown is kept. Markdown nested more than
+ * DEFAULT_MAX_DEPTH deep is kept as text (see markdown_to_html).
+ */
+pub fn parse_markdown<C>(markdown: &[C]) -> Dom<C>
+where
+    C: CodeUnit,
+{
+    let html = markdown_to_html(&C::decode(markdown), DEFAULT_MAX_DEPTH);
+    parse(&C::encode(&html))
+}
+
+/**
+ * The HTML for markdown (see parse_markdown), with at most max_depth
+ * elements, and never more than DEFAULT_MAX_DEPTH, nested inside each
+ * other. Quotes, lists and emphasis that would be nested any deeper are
+ * not read as markdown, but kept as the text they were written as, so
+ * that however deeply the markdown nests reading it, which recurses once
+ * for each, cannot overflow the stack.
+ */
+pub(crate) fn markdown_to_html(markdown: &str, max_depth: usize) -> String {
+    let max_depth = max_depth.min(DEFAULT_MAX_DEPTH);
+    let lines: Vec<&str> = markdown
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+    blocks_to_html(&lines, max_depth)
+}
+
+/**
+ * The parts of markdown to style when it is shown as plain text, as
+ * markdown_to_html reads it with max_depth: each kind with the byte range
+ * it covers, in order of their starts. Emphasis inside a quote or a list
+ * item is only found within one line.
+ */
+pub(crate) fn markdown_styles(
+    markdown: &str,
+    max_depth: usize,
+) -> Vec<(MarkdownStyleKind, usize, usize)> {
+    let max_depth = max_depth.min(DEFAULT_MAX_DEPTH);
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in markdown.split('\n') {
+        lines.push((offset, line.strip_suffix('\r').unwrap_or(line)));
+        offset += line.len() + 1;
+    }
+    let mut styles = Vec::new();
+    block_styles(markdown, &lines, max_depth, &mut styles);
+    styles.retain(|&(_, start, end)| start < end);
+    styles.sort_by_key(|&(_, start, end)| (start, usize::MAX - end));
+    styles
+}
+
+/**
+ * Add the styles of lines, each at its offset in markdown, to styles, as
+ * blocks_to_html reads them with depth.
+ */
+fn block_styles(
+    markdown: &str,
+    lines: &[(usize, &str)],
+    depth: usize,
+    styles: &mut Vec<(MarkdownStyleKind, usize, usize)>,
+) {
+    let is_block = |line: &str| {
+        line.starts_with("```")
+            || (depth > 0 && line.starts_with('>'))
+            || (depth > 1 && list_marker(line).is_some())
+    };
+    let mut i = 0;
+    while i < lines.len() {
+        let (offset, line) = lines[i];
+        if line.starts_with("```") {
+            styles.push((
+                MarkdownStyleKind::CodeBlockFence,
+                offset,
+                offset + line.len(),
+            ));
+            let end = lines[i + 1..]
+                .iter()
+                .position(|(_, l)| *l == "```")
+                .map_or(lines.len(), |end| i + 1 + end);
+            let code = &lines[i + 1..end];
+            if let (Some(first), Some(last)) = (code.first(), code.last()) {
+                styles.push((
+                    MarkdownStyleKind::CodeBlock,
+                    first.0,
+                    last.0 + last.1.len(),
+                ));
+            }
+            if let Some((offset, line)) = lines.get(end) {
+                styles.push((
+                    MarkdownStyleKind::CodeBlockFence,
+                    *offset,
+                    offset + line.len(),
+                ));
+            }
+            i = end + 1;
+        } else if depth > 0 && line.starts_with('>') {
+            let mut quoted = Vec::new();
+            while let Some(&(offset, line)) =
+                lines.get(i).filter(|(_, l)| l.starts_with('>'))
+            {
+                let marker = if line[1..].starts_with(' ') { 2 } else { 1 };
+                styles.push((
+                    MarkdownStyleKind::QuoteMarker,
+                    offset,
+                    offset + marker,
+                ));
+                quoted.push((offset + marker, &line[marker..]));
+                i += 1;
+            }
+            block_styles(markdown, &quoted, depth - 1, styles);
+        } else if let Some((_, _, width)) =
+            list_marker(line).filter(|_| depth > 1)
+        {
+            styles.push((
+                MarkdownStyleKind::ListMarker,
+                offset,
+                offset + width,
+            ));
+            inline_styles(&line[width..], offset + width, depth - 2, styles);
+            i += 1;
+        } else {
+            // A paragraph, read as one where its lines are contiguous
+            let mut end = offset + line.len();
+            i += 1;
+            while let Some(&(next, line)) = lines.get(i) {
+                if is_block(line) || next != end + 1 {
+                    break;
+                }
+                end = next + line.len();
+                i += 1;
+            }
+            inline_styles(&markdown[offset..end], offset, depth, styles);
+        }
+    }
+}
+
+/**
+ * Add the styles of inline markdown at offset to styles, as
+ * inline_to_html reads it with depth.
+ */
+fn inline_styles(
+    text: &str,
+    offset: usize,
+    depth: usize,
+    styles: &mut Vec<(MarkdownStyleKind, usize, usize)>,
+) {
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        let start = offset + i;
+        let emphasis = ["**", "~~", "*", "_"]
+            .iter()
+            .find(|d| depth > 0 && rest.starts_with(**d))
+            .and_then(|d| {
+                find_unescaped(&rest[d.len()..], d)
+                    .filter(|&end| end > 0)
+                    .map(|end| (*d, end))
+            });
+        if let Some((delimiter, end)) = emphasis {
+            let (marker, content) = match delimiter {
+                "**" => {
+                    (MarkdownStyleKind::BoldMarker, MarkdownStyleKind::Bold)
+                }
+                "~~" => (
+                    MarkdownStyleKind::StrikethroughMarker,
+                    MarkdownStyleKind::Strikethrough,
+                ),
+                _ => {
+                    (MarkdownStyleKind::ItalicMarker, MarkdownStyleKind::Italic)
+                }
+            };
+            let d = delimiter.len();
+            styles.push((marker, start, start + d));
+            styles.push((content, start + d, start + d + end));
+            styles.push((marker, start + d + end, start + 2 * d + end));
+            inline_styles(&rest[d..d + end], start + d, depth - 1, styles);
+            i += 2 * d + end;
+        } else if c == '\\'
+            && rest[1..].starts_with(|c: char| ESCAPABLE.contains(c))
+        {
+            styles.push((MarkdownStyleKind::Escape, start, start + 1));
+            i += 1 + rest[1..].chars().next().unwrap().len_utf8();
+        } else if let Some(end) =
+            (c == '`').then(|| rest[1..].find('`')).flatten()
+        {
+            styles.push((MarkdownStyleKind::CodeMarker, start, start + 1));
+            styles.push((MarkdownStyleKind::Code, start + 1, start + 1 + end));
+            styles.push((
+                MarkdownStyleKind::CodeMarker,
+                start + 1 + end,
+                start + 2 + end,
+            ));
+            i += end + 2;
+        } else if let Some((inner, _, len)) =
+            (c == '[' && depth > 0).then(|| link(rest)).flatten()
+        {
+            let close = start + 1 + inner.len();
+            let end = start + len;
+            styles.push((MarkdownStyleKind::LinkMarker, start, start + 1));
+            styles.push((MarkdownStyleKind::LinkText, start + 1, close));
+            styles.push((MarkdownStyleKind::LinkMarker, close, close + 2));
+            styles.push((MarkdownStyleKind::LinkUrl, close + 2, end - 1));
+            styles.push((MarkdownStyleKind::LinkMarker, end - 1, end));
+            inline_styles(inner, start + 1, depth - 1, styles);
+            i += len;
+        } else if c == '<'
+            && rest[1..].starts_with(|c: char| {
+                c.is_ascii_alphabetic() || c == '/' || c == '!'
+            })
+        {
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            styles.push((MarkdownStyleKind::Html, start, start + end));
+            i += end;
+        } else {
+            i += c.len_utf8();
+        }
+    }
+}
+
+/**
+ * The HTML for text shown as it is, with newlines as line breaks, e.g.
+ * markdown being edited as plain text.
+ */
+pub(crate) fn plain_text_to_html(text: &str) -> String {
+    escape_html(text).replace('\n', "<br />")
+}
+
+/**
+ * The HTML for lines of markdown, with at most depth elements nested
+ * inside each other: a quote takes one, a list two (the list and its
+ * item), and deeper quotes and lists are read as paragraphs.
+ */
+fn blocks_to_html(lines: &[&str], depth: usize) -> String {
+    let mut html = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let is_block = line.starts_with("```")
+            || (depth > 0 && line.starts_with('>'))
+            || (depth > 1 && list_marker(line).is_some());
+        if !is_block {
+            paragraph.push(line);
+            i += 1;
+            continue;
+        }
+        if !paragraph.is_empty() {
+            html.push_str(&inline_to_html(&paragraph.join("\n"), depth));
+            paragraph.clear();
+        }
+        if line.starts_with("```") {
+            let end = lines[i + 1..]
+                .iter()
+                .position(|l| *l == "```")
+                .map_or(lines.len(), |end| i + 1 + end);
+            let code = lines[i + 1..end].join("\n");
+            html.push_str("<pre><code>");
+            html.push_str(&escape_html(&code));
+            html.push_str("</code></pre>");
+            i = end + 1;
+        } else if line.starts_with('>') {
+            let mut quoted = Vec::new();
+            while let Some(rest) =
+                lines.get(i).and_then(|l| l.strip_prefix('>'))
+            {
+                quoted.push(rest.strip_prefix(' ').unwrap_or(rest));
+                i += 1;
+            }
+            html.push_str("<blockquote>");
+            html.push_str(&blocks_to_html(&quoted, depth - 1));
+            html.push_str("</blockquote>");
+        } else {
+            let (html_list, next) = list_to_html(lines, i, depth);
+            html.push_str(&html_list);
+            i = next;
+        }
+    }
+    if !paragraph.is_empty() {
+        html.push_str(&inline_to_html(&paragraph.join("\n"), depth));
+    }
+    html
+}
+
+/**
+ * The HTML for the list starting at lines[start], and the index of the
+ * line after it. The list and its items take two of depth.
+ */
+fn list_to_html(lines: &[&str], start: usize, depth: usize) -> (String, usize) {
+    let (ordered, first_number, _) =
+        list_marker(lines[start]).expect("Not the start of a list");
+    let mut items: Vec<Vec<&str>> = Vec::new();
+    let mut indent = 0;
+    let mut i = start;
+    while i < lines.len() {
+        let line = lines[i];
+        match list_marker(line) {
+            Some((o, _, width)) if o == ordered => {
+                items.push(vec![&line[width..]]);
+                indent = width;
+            }
+            _ if line.len() >= indent
+                && line.is_char_boundary(indent)
+                && line[..indent].trim().is_empty()
+                && !line.is_empty() =>
+            {
+                items.last_mut().unwrap().push(&line[indent..]);
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+    let mut html = String::from(match (ordered, first_number) {
+        (false, _) => "<ul>",
+        (true, 1) => "<ol>",
+        (true, _) => "",
+    });
+    if html.is_empty() {
+        html = format!("<ol start=\"{}\">", first_number);
+    }
+    for item in items {
+        html.push_str("<li>");
+        html.push_str(&blocks_to_html(&item, depth - 2));
+        html.push_str("</li>");
+    }
+    html.push_str(if ordered { "</ol>" } else { "</ul>" });
+    (html, i)
+}
+
+/**
+ * If line starts a list item, whether the list is ordered, the item's
+ * number, and the width of its marker (e.g. "- " or "12. ").
+ */
+fn list_marker(line: &str) -> Option<(bool, u32, usize)> {
+    if line.starts_with("- ") {
+        return Some((false, 1, 2));
+    }
+    let digits = line.len()
+        - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 && digits <= 9 && line[digits..].starts_with(". ") {
+        let number = line[..digits].parse().ok()?;
+        Some((true, number, digits + 2))
+    } else {
+        None
+    }
+}
+
+/**
+ * The HTML for inline markdown, e.g. the text of a paragraph, with at most
+ * depth elements nested inside each other. Emphasis and links any deeper
+ * are kept as text.
+ */
+fn inline_to_html(text: &str, depth: usize) -> String {
+    let mut html = String::new();
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        let emphasis = ["**", "~~", "*", "_"]
+            .iter()
+            .find(|d| depth > 0 && rest.starts_with(**d))
+            .and_then(|d| {
+                find_unescaped(&rest[d.len()..], d)
+                    .filter(|&end| end > 0)
+                    .map(|end| (*d, end))
+            });
+        if let Some((delimiter, end)) = emphasis {
+            let tag = match delimiter {
+                "**" => "strong",
+                "~~" => "del",
+                _ => "em",
+            };
+            let inner = &rest[delimiter.len()..delimiter.len() + end];
+            html.push_str(&format!(
+                "<{}>{}</{}>",
+                tag,
+                inline_to_html(inner, depth - 1),
+                tag
+            ));
+            i += 2 * delimiter.len() + end;
+        } else if c == '\\'
+            && rest[1..].starts_with(|c: char| ESCAPABLE.contains(c))
+        {
+            let escaped = rest[1..].chars().next().unwrap();
+            html.push_str(&escape_html(&escaped.to_string()));
+            i += 2;
+        } else if let Some(end) =
+            (c == '`').then(|| rest[1..].find('`')).flatten()
+        {
+            html.push_str("<code>");
+            html.push_str(&escape_html(&rest[1..1 + end]));
+            html.push_str("</code>");
+            i += end + 2;
+        } else if let Some((inner, url, len)) =
+            (c == '[' && depth > 0).then(|| link(rest)).flatten()
+        {
+            html.push_str(&format!(
+                "<a href=\"{}\">{}</a>",
+                escape_html(&url).replace('"', "&quot;"),
+                inline_to_html(inner, depth - 1)
+            ));
+            i += len;
+        } else if c == '<'
+            && rest[1..].starts_with(|c: char| {
+                c.is_ascii_alphabetic() || c == '/' || c == '!'
+            })
+        {
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            html.push_str(&rest[..end]);
+            i += end;
+        } else if c == '&' && is_entity(rest) {
+            html.push('&');
+            i += 1;
+        } else if c == '\n' {
+            html.push_str("<br />");
+            i += 1;
+        } else {
+            html.push_str(&escape_html(&c.to_string()));
+            i += c.len_utf8();
+        }
+    }
+    html
+}
+
+/**
+ * If text starts with a link, [inner](url), its inner markdown, its URL
+ * with escapes removed, and its length. Parentheses in the URL end it
+ * only where they are not balanced, e.g. in [a](https://x.org/a_(b)).
+ */
+fn link(text: &str) -> Option<(&str, String, usize)> {
+    let close = find_unescaped(&text[1..], "]")? + 1;
+    let after = text[close + 1..].strip_prefix('(')?;
+    let url_end = closing_parenthesis(after)?;
+    let mut url = String::new();
+    let mut chars = after[..url_end].chars();
+    while let Some(c) = chars.next() {
+        url.push(if c == '\\' { chars.next()? } else { c });
+    }
+    Some((&text[1..close], url, close + 2 + url_end + 1))
+}
+
+/**
+ * The offset of the first unescaped ")" in text that closes no unescaped
+ * "(" before it.
+ */
+fn closing_parenthesis(text: &str) -> Option<usize> {
+    let mut open = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with('\\') {
+            i += 1 + text[i + 1..].chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+        if text[i..].starts_with('(') {
+            open += 1;
+        } else if text[i..].starts_with(')') {
+            if open == 0 {
+                return Some(i);
+            }
+            open -= 1;
+        }
+        i += text[i..].chars().next().map_or(1, char::len_utf8);
+    }
+    None
+}
+
+/**
+ * The offset of the first occurrence of needle in text that is not
+ * escaped with a backslash.
+ */
+fn find_unescaped(text: &str, needle: &str) -> Option<usize> {
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with('\\') {
+            i += 1 + text[i + 1..].chars().next().map_or(0, char::len_utf8);
+        } else if text[i..].starts_with(needle) {
+            return Some(i);
+        } else {
+            i += text[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    None
+}
+
+/**
+ * Whether text starts with an HTML character reference such as &amp;.
+ */
+fn is_entity(text: &str) -> bool {
+    let name = &text[1..];
+    let len = name
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '#'))
+        .unwrap_or(name.len());
+    len > 0 && name[len..].starts_with(';')
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/**
+ * Text as markdown, escaping anything that would be read as markdown.
+ */
+/**
+ * What block_markdown does next: write a node, start a list item with
+ * its marker, or finish a block, quote or list item.
+ */
+enum MarkdownStep {
+    Node(NodeId),
+    Item(NodeId, String),
+    EndBlock,
+    EndQuote,
+    EndItem,
+}
+
+struct MarkdownWriter {
+    lines: Vec<String>,
+    /**
+     * The line being written, or None if we are between blocks.
+     */
+    line: Option<String>,
+    /**
+     * What goes before each line for the quotes and list items it is in,
+     * outermost first.
+     */
+    prefixes: Vec<LinePrefix>,
+}
+
+struct LinePrefix {
+    first: String,
+    rest: String,
+    /**
+     * Whether this is a quote, whose marker loses its trailing space
+     * on an empty line.
+     */
+    quote: bool,
+    /**
+     * Whether a line has been written, so that the next uses rest.
+     */
+    started: bool,
+}
+
+impl MarkdownWriter {
+    fn write_line(&mut self, content: String) {
+        let mut line = String::new();
+        let innermost = self.prefixes.len().saturating_sub(1);
+        for (i, prefix) in self.prefixes.iter_mut().enumerate() {
+            if prefix.quote && i == innermost && content.is_empty() {
+                line.push('>');
+            } else if prefix.started {
+                line.push_str(&prefix.rest);
+            } else {
+                line.push_str(&prefix.first);
+            }
+            prefix.started = true;
+        }
+        line.push_str(&content);
+        self.lines.push(line);
+    }
+
+    fn end_line(&mut self) {
+        if let Some(line) = self.line.take() {
+            self.write_line(escape_line_start(line));
+        }
+    }
+}
+
+pub(crate) fn escape_text(text: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in text.char_indices() {
+        let escape = match c {
+            '\\' | '`' | '*' | '_' | '~' | '[' | ']' | '<' => true,
+            '&' => is_entity(&text[i..]),
+            _ => false,
+        };
+        if escape {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/**
+ * The line, escaping anything at its start that would be read as the
+ * start of a quote, list or code block.
+ */
+fn escape_line_start(line: String) -> String {
+    if line.starts_with('>') || line.starts_with("- ") {
+        format!("\\{}", line)
+    } else if let Some((_, _, width)) = list_marker(&line) {
+        format!("{}\\{}", &line[..width - 2], &line[width - 2..])
+    } else {
+        line
+    }
+}
+
+pub(crate) fn escape_url(url: &str) -> String {
+    let mut out = String::new();
+    for c in url.chars() {
+        if matches!(c, '\\' | '(' | ')') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, DEFAULT_MAX_DEPTH};
+    use crate::HtmlDialect;
+
+    use super::{markdown_styles, markdown_to_html, parse_markdown};
+    use crate::MarkdownStyleKind::*;
+
+    fn markdown(html: &str) -> String {
+        let dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        String::from_utf16(&dom.to_markdown()).unwrap()
+    }
+
+    fn round_trip(html: &str) -> String {
+        let dom =
+            parse_markdown(&markdown(html).encode_utf16().collect::<Vec<_>>());
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    fn styled(markdown: &str) -> Vec<(&str, crate::MarkdownStyleKind)> {
+        markdown_styles(markdown, DEFAULT_MAX_DEPTH)
+            .into_iter()
+            .map(|(kind, start, end)| (&markdown[start..end], kind))
+            .collect()
+    }
+
+    #[test]
+    fn markers_and_content_are_styled() {
+        assert_eq!(
+            styled("a **b _c_** [d](e) \\*"),
+            vec![
+                ("**", BoldMarker),
+                ("b _c_", Bold),
+                ("_", ItalicMarker),
+                ("c", Italic),
+                ("_", ItalicMarker),
+                ("**", BoldMarker),
+                ("[", LinkMarker),
+                ("d", LinkText),
+                ("](", LinkMarker),
+                ("e", LinkUrl),
+                (")", LinkMarker),
+                ("\\", Escape),
+            ]
+        );
+    }
+
+    #[test]
+    fn block_markers_are_styled() {
+        assert_eq!(
+            styled("> - `a`\n```\nb\n```\n1. ~~c~~"),
+            vec![
+                ("> ", QuoteMarker),
+                ("- ", ListMarker),
+                ("`", CodeMarker),
+                ("a", Code),
+                ("`", CodeMarker),
+                ("```", CodeBlockFence),
+                ("b", CodeBlock),
+                ("```", CodeBlockFence),
+                ("1. ", ListMarker),
+                ("~~", StrikethroughMarker),
+                ("c", Strikethrough),
+                ("~~", StrikethroughMarker),
+            ]
+        );
+    }
+
+    #[test]
+    fn emphasis_can_span_the_lines_of_a_paragraph() {
+        assert_eq!(
+            styled("*a\nb* <u>"),
+            vec![
+                ("*", ItalicMarker),
+                ("a\nb", Italic),
+                ("*", ItalicMarker),
+                ("<u>", Html),
+            ]
+        );
+    }
+
+    #[test]
+    fn formatting_becomes_markdown() {
+        assert_eq!(
+            markdown(
+                "a<strong>b</strong><em>c</em><del>d</del><code>e</code>\
+                <a href=\"https://x.org\">f</a>"
+            ),
+            "a**b**_c_~~d~~`e`[f](https://x.org)"
+        );
+    }
+
+    #[test]
+    fn blocks_become_markdown() {
+        assert_eq!(
+            markdown(
+                "<blockquote>a<br />b</blockquote><ol start=\"3\"><li>c</li>\
+                <li>d<ul><li>e</li></ul></li></ol><pre><code>f\ng</code></pre>"
+            ),
+            "> a\n> b\n3. c\n4. d\n   - e\n```\nf\ng\n```"
+        );
+    }
+
+    #[test]
+    fn text_that_looks_like_markdown_is_escaped() {
+        assert_eq!(
+            markdown("- a *b* [c]<br />1. d &amp; e"),
+            "\\- a \\*b\\* \\[c\\]\n1\\. d & e"
+        );
+        assert_eq!(
+            round_trip("- a *b* [c]<br />1. d &amp;amp; e"),
+            "- a *b* [c]<br />1. d &amp;amp; e"
+        );
+    }
+
+    #[test]
+    fn other_elements_are_kept_as_html() {
+        assert_eq!(markdown("a<u>b</u>"), "a<u>b</u>");
+        assert_eq!(
+            round_trip("a<u>b</u><sup>c</sup>"),
+            "a<u>b</u><sup>c</sup>"
+        );
+    }
+
+    #[test]
+    fn markdown_becomes_html() {
+        assert_eq!(
+            markdown_to_html(
+                "a **b** *c* _d_\n\n> d\n- e\n  f\n- g\nh",
+                DEFAULT_MAX_DEPTH
+            ),
+            "a <strong>b</strong> <em>c</em> <em>d</em><br />\
+            <blockquote>d</blockquote><ul><li>e<br />f</li><li>g</li></ul>h"
+        );
+    }
+
+    #[test]
+    fn parentheses_in_link_urls_are_balanced() {
+        assert_eq!(
+            markdown_to_html("[a](https://x.org/a_(b)) c)", DEFAULT_MAX_DEPTH),
+            "<a href=\"https://x.org/a_(b)\">a</a> c)"
+        );
+        assert_eq!(
+            styled("[a](b\\(c)"),
+            vec![
+                ("[", LinkMarker),
+                ("a", LinkText),
+                ("](", LinkMarker),
+                ("b\\(c", LinkUrl),
+                (")", LinkMarker),
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown_nested_past_the_depth_is_kept_as_text() {
+        assert_eq!(
+            markdown_to_html("> > *a*\n- *b*", 1),
+            "<blockquote>&gt; *a*</blockquote>- <em>b</em>"
+        );
+        let html = markdown_to_html(&format!("{} a", ">".repeat(100_000)), 100);
+        assert_eq!(html.matches("<blockquote>").count(), 100);
+    }
+
+    #[test]
+    fn markdown_nested_past_the_depth_is_not_styled() {
+        assert_eq!(
+            markdown_styles("> > *a*\n- *b*", 1),
+            vec![
+                (QuoteMarker, 0, 2),
+                (ItalicMarker, 10, 11),
+                (Italic, 11, 12),
+                (ItalicMarker, 12, 13),
+            ]
+        );
+        let quotes = markdown_styles(&">".repeat(100_000), 100);
+        assert_eq!(quotes.len(), 100);
+    }
+
+    #[test]
+    fn content_survives_a_round_trip() {
+        for html in [
+            "a<strong>b<em>c</em></strong><br /><br />d",
+            "<blockquote>a<br /><strong>b</strong></blockquote>c",
+            "<ul><li>a</li><li>b<ol><li>c</li></ol></li></ul>",
+            "<a href=\"https://x.org/a_(b)\">x</a> <code>y*z</code>",
+            "<pre><code>a\n  *b*</code></pre>",
+        ] {
+            assert_eq!(round_trip(html), html);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{element_spec, Dom, DomNode, NodeId};
+use crate::{CodeUnit, HtmlViolation, SanitizeReport};
+
+/**
+ * The schemes the Matrix specification allows in links, for checking
+ * content against where no ComposerConfig::allowed_link_schemes applies.
+ */
+pub const MATRIX_LINK_SCHEMES: &[&str] =
+    &["https", "http", "ftp", "mailto", "magnet"];
+
+const ALLOWED_IMAGE_SCHEMES: &[&str] = &["mxc"];
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * The ways in which this Dom uses HTML that the Matrix specification
+     * does not allow in messages, in document order. Links are allowed
+     * only if their scheme is one of link_schemes (e.g.
+     * MATRIX_LINK_SCHEMES or ComposerConfig::allowed_link_schemes).
+     */
+    pub fn matrix_violations<S>(&self, link_schemes: &[S]) -> Vec<HtmlViolation>
+    where
+        S: AsRef<str>,
+    {
+        let mut violations = Vec::new();
+        for id in self.descendants(self.document()) {
+            if let DomNode::Container(c) = self.node(id) {
+                if !c.name.element().map_or(false, |spec| spec.matrix) {
+                    violations.push(HtmlViolation::Element {
+                        name: c.name.to_string(),
+                    });
+                }
+                for (attribute, value) in &c.attrs {
+                    if let Some(violation) = attribute_violation(
+                        &c.name,
+                        attribute,
+                        value,
+                        link_schemes,
+                    ) {
+                        violations.push(violation);
+                    }
+                }
+            }
+        }
+        violations
+    }
+
+    /**
+     * Remove everything that the Matrix specification does not allow in
+     * messages: disallowed attributes are removed, and disallowed elements
+     * are replaced by their content. Links are allowed as by
+     * matrix_violations.
+     */
+    pub fn strip_matrix_violations<S>(&mut self, link_schemes: &[S])
+    where
+        S: AsRef<str>,
+    {
+        for container in self.containers_innermost_first(self.document()) {
+            self.strip_in(container, link_schemes);
+        }
+        self.normalize(self.document());
+    }
+
+    /**
+     * Strip everything that the Matrix specification does not allow, as
+     * strip_matrix_violations does, and report what was removed.
+     */
+    pub fn sanitize<S>(&mut self, link_schemes: &[S]) -> SanitizeReport
+    where
+        S: AsRef<str>,
+    {
+        let removals = self.matrix_violations(link_schemes);
+        if !removals.is_empty() {
+            self.strip_matrix_violations(link_schemes);
+        }
+        SanitizeReport { removals }
+    }
+
+    /**
+     * Strip the children of container that are not allowed, once their
+     * own children have been stripped.
+     */
+    fn strip_in<S>(&mut self, container: NodeId, link_schemes: &[S])
+    where
+        S: AsRef<str>,
+    {
+        let mut new_children = Vec::new();
+        let mut removed = Vec::new();
+        for child in self.children(container).to_vec() {
+            let name = match self.node(child) {
+                DomNode::Container(c) => c.name.clone(),
+                DomNode::Text(_) => {
+                    new_children.push(child);
+                    continue;
+                }
+            };
+            let spec = name.element();
+            if spec.map_or(false, |spec| spec.drops_content) {
+                removed.push(child);
+            } else if spec.map_or(false, |spec| spec.matrix) {
+                self.strip_attributes(child, &name, link_schemes);
+                new_children.push(child);
+            } else {
+                new_children.extend_from_slice(self.children(child));
+                removed.push(child);
+            }
+        }
+        self.set_children(container, new_children);
+        for id in removed {
+            self.remove(id);
+        }
+    }
+
+    fn strip_attributes<S>(
+        &mut self,
+        id: NodeId,
+        name: &str,
+        link_schemes: &[S],
+    ) where
+        S: AsRef<str>,
+    {
+        let has_violation = self.container(id).attrs.iter().any(|(a, v)| {
+            attribute_violation(name, a, v, link_schemes).is_some()
+        });
+        if has_violation {
+            self.container_mut(id).attrs.retain(|(a, v)| {
+                attribute_violation(name, a, v, link_schemes).is_none()
+            });
+        }
+    }
+}
+
+/**
+ * Why attribute=value is not allowed on element, if it is not.
+ */
+fn attribute_violation<C, S>(
+    element: &str,
+    attribute: &str,
+    value: &[C],
+    link_schemes: &[S],
+) -> Option<HtmlViolation>
+where
+    C: CodeUnit,
+    S: AsRef<str>,
+{
+    let allowed = element_spec(element)
+        .map_or(false, |spec| spec.attributes.contains(&attribute));
+    if !allowed {
+        return Some(HtmlViolation::Attribute {
+            element: String::from(element),
+            attribute: String::from(attribute),
+        });
+    }
+    let value = C::decode(value);
+    let allowed_value = match (element, attribute) {
+        ("a", "href") => has_scheme(&value, link_schemes),
+        ("img", "src") => has_scheme(&value, ALLOWED_IMAGE_SCHEMES),
+        ("code", "class") => value.starts_with("language-"),
+        _ => true,
+    };
+    if allowed_value {
+        None
+    } else {
+        Some(HtmlViolation::AttributeValue {
+            element: String::from(element),
+            attribute: String::from(attribute),
+            value,
+        })
+    }
+}
+
+fn has_scheme<S>(url: &str, schemes: &[S]) -> bool
+where
+    S: AsRef<str>,
+{
+    url.split_once(':').map_or(false, |(scheme, _)| {
+        schemes
+            .iter()
+            .any(|s| s.as_ref().eq_ignore_ascii_case(scheme))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, MATRIX_LINK_SCHEMES};
+    use crate::{HtmlDialect, HtmlViolation};
+
+    fn stripped(html: &str) -> String {
+        let mut dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        dom.strip_matrix_violations(MATRIX_LINK_SCHEMES);
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    fn violations(html: &str) -> Vec<HtmlViolation> {
+        parse(&html.encode_utf16().collect::<Vec<_>>())
+            .matrix_violations(MATRIX_LINK_SCHEMES)
+    }
+
+    #[test]
+    fn allowed_html_has_no_violations() {
+        let html = "<p>a<strong>b</strong><a href=\"https://x.org\">c</a></p>\
+            <ol start=\"3\"><li><code class=\"language-rust\">d</code></li></ol>";
+        assert_eq!(violations(html), vec![]);
+        assert_eq!(stripped(html), html);
+    }
+
+    #[test]
+    fn disallowed_elements_are_reported_and_unwrapped() {
+        assert_eq!(
+            violations("a<marquee>b</marquee>"),
+            vec![HtmlViolation::Element {
+                name: String::from("marquee")
+            }]
+        );
+        assert_eq!(
+            stripped("a<marquee>b<em>c</em></marquee>d"),
+            "ab<em>c</em>d"
+        );
+    }
+
+    #[test]
+    fn scripts_are_removed_with_their_content() {
+        assert_eq!(stripped("a<script>alert(1)</script>b"), "ab");
+    }
+
+    #[test]
+    fn disallowed_attributes_are_reported_and_removed() {
+        assert_eq!(
+            violations("<p style=\"x\">a</p>"),
+            vec![HtmlViolation::Attribute {
+                element: String::from("p"),
+                attribute: String::from("style"),
+            }]
+        );
+        assert_eq!(stripped("<p style=\"x\">a</p>"), "<p>a</p>");
+    }
+
+    #[test]
+    fn links_and_images_must_use_allowed_schemes() {
+        assert_eq!(
+            violations("<a href=\"javascript:x\">a</a>"),
+            vec![HtmlViolation::AttributeValue {
+                element: String::from("a"),
+                attribute: String::from("href"),
+                value: String::from("javascript:x"),
+            }]
+        );
+        assert_eq!(stripped("<a href=\"javascript:x\">a</a>"), "<a>a</a>");
+        assert_eq!(
+            stripped(
+                "<img src=\"https://x.org/a.png\" /><img src=\"mxc://x/y\" />"
+            ),
+            "<img /><img src=\"mxc://x/y\" />"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Dom, DomNode, NodeId};
+use crate::mention::{MENTION_AVATAR_ATTR, MENTION_ID_ATTR, MENTION_KIND_ATTR};
+use crate::{CodeUnit, Mention, MentionKind};
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * The start and end of each mention (see
+     * ComposerModel::replace_text_at_suggestion) in document order. A
+     * mention is edited as a single unit, like a pill, rather than as text.
+     */
+    pub fn mention_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
+        let mut pos = 0;
+        let mut pending: Vec<NodeId> = self
+            .children(self.document())
+            .iter()
+            .rev()
+            .cloned()
+            .collect();
+        while let Some(id) = pending.pop() {
+            match self.node(id) {
+                DomNode::Text(text) => pos += text.data().len(),
+                DomNode::Container(c)
+                    if c.name() == "a" && c.attr(MENTION_ID_ATTR).is_some() =>
+                {
+                    let len = self.node_len(id);
+                    ranges.push((pos, pos + len));
+                    pos += len;
+                }
+                DomNode::Container(c) if c.is_void() => pos += 1,
+                DomNode::Container(_) => {
+                    pending.extend(self.children(id).iter().rev())
+                }
+            }
+        }
+        ranges
+    }
+
+    /**
+     * Widen start..end so that neither edge is inside a mention, and so
+     * any mention it overlaps is covered whole. An empty range (a cursor)
+     * is left as it is.
+     */
+    pub fn expand_to_mentions(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> (usize, usize) {
+        if start >= end {
+            return (start, end);
+        }
+        let (mut s, mut e) = (start, end);
+        for (mention_start, mention_end) in self.mention_ranges() {
+            if mention_start < s && s < mention_end {
+                s = mention_start;
+            }
+            if mention_start < e && e < mention_end {
+                e = mention_end;
+            }
+        }
+        (s, e)
+    }
+
+    /**
+     * The parts of start..end that are not covered by mentions, e.g. to
+     * format a selection without formatting the mentions in it.
+     */
+    pub fn ranges_outside_mentions(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
+        let mut pos = start;
+        for (mention_start, mention_end) in self.mention_ranges() {
+            if mention_end <= pos || end <= mention_start {
+                continue;
+            }
+            if pos < mention_start {
+                ranges.push((pos, mention_start));
+            }
+            pos = mention_end;
+        }
+        if pos < end {
+            ranges.push((pos, end));
+        }
+        ranges
+    }
+
+    /**
+     * The kind of the mention id, or None if it is not a mention. Mentions
+     * made before their kind was recorded are told apart by their IDs.
+     */
+    pub fn mention_kind(&self, id: NodeId) -> Option<MentionKind> {
+        match self.node(id) {
+            DomNode::Container(c) if c.name == "a" => {
+                let kind = c.attr(MENTION_KIND_ATTR).map(C::decode);
+                match kind.as_deref().and_then(MentionKind::from_name) {
+                    Some(kind) => Some(kind),
+                    None => {
+                        MentionKind::of(&C::decode(c.attr(MENTION_ID_ATTR)?))
+                    }
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /**
+     * Show the display name and avatar of each of mentions in every
+     * mention of its ID, e.g. after a user changes their name. Returns
+     * whether anything changed.
+     */
+    pub(crate) fn update_mentions(&mut self, mentions: &[Mention<C>]) -> bool {
+        let mut changed = false;
+        for id in self.descendants(self.document()) {
+            let mention = match self.get(id) {
+                Some(DomNode::Container(c)) if c.name == "a" => c
+                    .attr(MENTION_ID_ATTR)
+                    .and_then(|m| mentions.iter().find(|x| x.id == m)),
+                _ => None,
+            };
+            let mention = match mention {
+                Some(mention) => mention,
+                None => continue,
+            };
+            if !mention.display_name.is_empty()
+                && self.text_content(id) != mention.display_name
+            {
+                self.replace_content(id, &mention.display_name);
+                changed = true;
+            }
+            let attrs = &mut self.container_mut(id).attrs;
+            let avatar =
+                attrs.iter().position(|(n, _)| n == MENTION_AVATAR_ATTR);
+            match (avatar, &mention.avatar_url) {
+                (Some(i), Some(url)) if attrs[i].1 == *url => {}
+                (Some(i), None) => {
+                    attrs.remove(i);
+                    changed = true;
+                }
+                (_, Some(url)) => {
+                    self.set_attr(id, MENTION_AVATAR_ATTR, url.clone());
+                    changed = true;
+                }
+                (None, None) => {}
+            }
+        }
+        changed
+    }
+
+    /**
+     * Replace the text of each mention of a room or an event by what
+     * stands for it in a plain text body, where it cannot be a link: the
+     * room's alias or ID, or the event's permalink. Mentions of users keep
+     * their display names.
+     */
+    pub(crate) fn use_mention_fallbacks(&mut self) {
+        for id in self.descendants(self.document()) {
+            if self.get(id).is_none() {
+                continue;
+            }
+            let attr = match self.mention_kind(id) {
+                Some(MentionKind::Room) => MENTION_ID_ATTR,
+                Some(MentionKind::Event) => "href",
+                Some(MentionKind::User) | None => continue,
+            };
+            let fallback = match self.node(id) {
+                DomNode::Container(c) => c.attr(attr).map(<[C]>::to_vec),
+                DomNode::Text(_) => None,
+            };
+            if let Some(fallback) = fallback {
+                self.replace_content(id, &fallback);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, Dom};
+
+    fn dom(html: &str) -> Dom<u16> {
+        parse(&html.encode_utf16().collect::<Vec<_>>())
+    }
+
+    const HTML: &str = "ab <a href=\"https://matrix.to/#/@c:d\" \
+        data-mention-id=\"@c:d\">Cat</a> <em>e<a href=\"https://x.org\">f</a>\
+        <a href=\"https://matrix.to/#/@g:h\" data-mention-id=\"@g:h\">G</a></em>";
+
+    #[test]
+    fn mentions_are_found_but_not_other_links() {
+        assert_eq!(dom(HTML).mention_ranges(), vec![(3, 6), (9, 10)]);
+    }
+
+    #[test]
+    fn ranges_ending_inside_a_mention_cover_it() {
+        let dom = dom(HTML);
+        assert_eq!(dom.expand_to_mentions(1, 4), (1, 6));
+        assert_eq!(dom.expand_to_mentions(5, 8), (3, 8));
+        assert_eq!(dom.expand_to_mentions(4, 5), (3, 6));
+        assert_eq!(dom.expand_to_mentions(3, 6), (3, 6));
+        assert_eq!(dom.expand_to_mentions(4, 4), (4, 4));
+    }
+
+    #[test]
+    fn ranges_outside_mentions_skip_them() {
+        let dom = dom(HTML);
+        assert_eq!(dom.ranges_outside_mentions(0, 10), vec![(0, 3), (6, 9)]);
+        assert_eq!(dom.ranges_outside_mentions(3, 6), vec![]);
+        assert_eq!(dom.ranges_outside_mentions(1, 2), vec![(1, 2)]);
+    }
+}
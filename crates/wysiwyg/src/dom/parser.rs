@@ -0,0 +1,740 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::code_unit::replace_invalid;
+use crate::dom::serializer::PLACEHOLDER;
+use crate::dom::{element_spec, Atom, Dom, DomNode, NodeId};
+use crate::{CodeUnit, ParseWarning};
+
+/**
+ * How deeply elements may be nested by default before the parser stops
+ * opening new ones (see parse_to_depth).
+ */
+pub const DEFAULT_MAX_DEPTH: usize = 100;
+
+/**
+ * Parse an HTML fragment into a Dom.
+ *
+ * Parsing is lenient, like a browser's: unknown markup is kept, unclosed
+ * elements are closed at the end, stray end tags are ignored and
+ * comments/doctypes are dropped. Whitespace that only lays out the source,
+ * such as the indentation between blocks of pretty-printed HTML, is
+ * dropped too (see strip_layout_whitespace), as are the placeholders the
+ * Editor dialect writes into empty elements (see strip_placeholders).
+ */
+pub fn parse<C>(html: &[C]) -> Dom<C>
+where
+    C: CodeUnit,
+{
+    parse_with_warnings(html).dom
+}
+
+/**
+ * The Dom parsed from some HTML, and what was wrong with the HTML.
+ */
+#[derive(Clone, Debug)]
+pub struct ParseResult<C> {
+    pub dom: Dom<C>,
+    /**
+     * What the parser had to recover from, in the order it was found.
+     */
+    pub warnings: Vec<ParseWarning>,
+}
+
+/**
+ * Parse an HTML fragment into a Dom, as parse does, and also report what
+ * was not well formed about it.
+ */
+pub fn parse_with_warnings<C>(html: &[C]) -> ParseResult<C>
+where
+    C: CodeUnit,
+{
+    parse_to_depth(html, DEFAULT_MAX_DEPTH)
+}
+
+/**
+ * Parse an HTML fragment into a Dom, as parse_with_warnings does, with at
+ * most max_depth elements nested inside each other. Elements opened any
+ * deeper are left out, with their contents kept in the innermost element
+ * allowed, so that no walk of the Dom has to go deeper.
+ */
+pub fn parse_to_depth<C>(html: &[C], max_depth: usize) -> ParseResult<C>
+where
+    C: CodeUnit,
+{
+    let html = replace_invalid(html);
+    let dom = Dom::new();
+    let mut parser = Parser {
+        html: &html,
+        pos: 0,
+        stack: vec![dom.document()],
+        dom,
+        text: Vec::new(),
+        warnings: Vec::new(),
+        max_depth,
+        skipped: Vec::new(),
+    };
+    parser.run();
+    let mut dom = parser.dom;
+    strip_layout_whitespace(&mut dom);
+    dom.normalize(dom.document());
+    strip_placeholders(&mut dom);
+    ParseResult {
+        dom,
+        warnings: parser.warnings,
+    }
+}
+
+/**
+ * Remove the text nodes that are only whitespace and sit between blocks,
+ * or between a block and the start or end of its parent, as a browser
+ * would not show them. Without this, indenting the source would add
+ * locations to the content. Text inside pre and code is left alone.
+ */
+fn strip_layout_whitespace<C>(dom: &mut Dom<C>)
+where
+    C: CodeUnit,
+{
+    let mut pending = vec![dom.document()];
+    let mut layout = Vec::new();
+    while let Some(container) = pending.pop() {
+        let children = dom.children(container);
+        let is_block = |id: Option<&NodeId>| match id {
+            Some(&id) => {
+                matches!(dom.node(id), DomNode::Container(c) if c.is_block())
+            }
+            None => false,
+        };
+        for (i, &child) in children.iter().enumerate() {
+            match dom.node(child) {
+                DomNode::Text(text) => {
+                    let blank = text.data().iter().all(|&c| {
+                        c.to_ascii().map_or(false, |c| c.is_ascii_whitespace())
+                    });
+                    let before = i.checked_sub(1).and_then(|i| children.get(i));
+                    let after = children.get(i + 1);
+                    let by_block = (before.is_none() || is_block(before))
+                        && (after.is_none() || is_block(after))
+                        && (is_block(before) || is_block(after));
+                    if blank && by_block {
+                        layout.push(child);
+                    }
+                }
+                DomNode::Container(c) => {
+                    let preserves_whitespace = c
+                        .name
+                        .element()
+                        .map_or(false, |spec| spec.preserves_whitespace);
+                    if !preserves_whitespace {
+                        pending.push(child);
+                    }
+                }
+            }
+        }
+    }
+    for id in layout {
+        dom.remove(id);
+    }
+}
+
+/**
+ * Empty the inline elements whose only content is the placeholder that
+ * the Editor dialect writes into empty inline elements, so that parsing
+ * Editor HTML gives back the Dom it was written from rather than one
+ * with the placeholders as text.
+ */
+fn strip_placeholders<C>(dom: &mut Dom<C>)
+where
+    C: CodeUnit,
+{
+    let placeholder = C::encode(PLACEHOLDER);
+    let placeholders: Vec<NodeId> = dom
+        .descendants(dom.document())
+        .into_iter()
+        .filter_map(|id| match dom.node(id) {
+            DomNode::Container(c) if !c.is_block() => match c.children() {
+                [only] => Some(*only),
+                _ => None,
+            },
+            _ => None,
+        })
+        .filter(|&id| {
+            matches!(dom.node(id), DomNode::Text(t) if t.data() == placeholder)
+        })
+        .collect();
+    for id in placeholders {
+        dom.remove(id);
+    }
+}
+
+struct Parser<'a, C> {
+    html: &'a [C],
+    pos: usize,
+    dom: Dom<C>,
+    /**
+     * The open elements, outermost (the document) first.
+     */
+    stack: Vec<NodeId>,
+    /**
+     * Text seen since the last tag.
+     */
+    text: Vec<C>,
+    warnings: Vec<ParseWarning>,
+    max_depth: usize,
+    /**
+     * The names of the elements left out for being too deep that are still
+     * open, outermost first.
+     */
+    skipped: Vec<String>,
+}
+
+impl<'a, C> Parser<'a, C>
+where
+    C: CodeUnit,
+{
+    fn run(&mut self) {
+        while self.pos < self.html.len() {
+            match self.ascii_at(self.pos) {
+                Some(b'<') => self.markup(),
+                Some(b'&') => {
+                    let decoded = decode_entity(self.html, self.pos);
+                    if let Some((chars, end)) = decoded {
+                        self.text.extend(chars);
+                        self.pos = end;
+                    } else {
+                        self.text.push(self.html[self.pos]);
+                        self.pos += 1;
+                    }
+                }
+                _ => {
+                    self.text.push(self.html[self.pos]);
+                    self.pos += 1;
+                }
+            }
+        }
+        self.flush_text();
+        self.close_above(1);
+    }
+
+    /**
+     * Handle what follows a '<' at the current position.
+     */
+    fn markup(&mut self) {
+        let next = self.ascii_at(self.pos + 1);
+        if self.starts_with(self.pos, "<!--") {
+            self.flush_text();
+            self.pos = match self.find(self.pos + 4, "-->") {
+                Some(end) => end + 3,
+                None => {
+                    self.warnings.push(ParseWarning::UnterminatedComment);
+                    self.html.len()
+                }
+            };
+        } else if next == Some(b'!') || next == Some(b'?') {
+            self.flush_text();
+            self.skip_past_gt();
+        } else if next == Some(b'/')
+            && self
+                .ascii_at(self.pos + 2)
+                .map_or(false, |c| c.is_ascii_alphabetic())
+        {
+            self.flush_text();
+            self.pos += 2;
+            let name = canonical_name(&self.read_name());
+            self.skip_past_gt();
+            self.end_tag(&name);
+        } else if next.map_or(false, |c| c.is_ascii_alphabetic()) {
+            self.flush_text();
+            self.pos += 1;
+            self.start_tag();
+        } else {
+            self.text.push(self.html[self.pos]);
+            self.pos += 1;
+        }
+    }
+
+    fn start_tag(&mut self) {
+        let name = canonical_name(&self.read_name());
+        let mut attrs = Vec::new();
+        let mut self_closing = false;
+        loop {
+            self.skip_whitespace();
+            match self.ascii_at(self.pos) {
+                None => break,
+                Some(b'>') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'/') => {
+                    self.pos += 1;
+                    if self.ascii_at(self.pos) == Some(b'>') {
+                        self.pos += 1;
+                        self_closing = true;
+                        break;
+                    }
+                }
+                _ => {
+                    let attr_name = self.read_name().to_lowercase();
+                    if attr_name.is_empty() {
+                        // Not a valid attribute: skip this code unit
+                        self.pos += 1;
+                        continue;
+                    }
+                    self.skip_whitespace();
+                    let value = if self.ascii_at(self.pos) == Some(b'=') {
+                        self.pos += 1;
+                        self.skip_whitespace();
+                        self.read_attr_value()
+                    } else {
+                        Vec::new()
+                    };
+                    attrs.push((Atom::from(attr_name), value));
+                }
+            }
+        }
+
+        let is_void =
+            self_closing || element_spec(&name).map_or(false, |spec| spec.void);
+        if !is_void && self.stack.len() > self.max_depth {
+            if self.skipped.is_empty() {
+                self.warnings.push(ParseWarning::TooDeep {
+                    max_depth: self.max_depth,
+                });
+            }
+            self.skipped.push(name);
+            return;
+        }
+        let node = self.dom.create_container(&name, attrs, Vec::new());
+        self.dom.append_child(self.top(), node);
+        if !self_closing && !self.dom.container(node).is_void() {
+            self.stack.push(node);
+        }
+    }
+
+    fn end_tag(&mut self, name: &str) {
+        if let Some(depth) = self.skipped.iter().rposition(|n| n == name) {
+            self.skipped.truncate(depth);
+            return;
+        }
+        self.skipped.clear();
+        let open = |&id: &NodeId| self.dom.container(id).name == name;
+        match self.stack.iter().rposition(open) {
+            Some(depth) if depth > 0 => {
+                self.close_above(depth + 1);
+                self.stack.truncate(depth);
+            }
+            _ => self.warnings.push(ParseWarning::StrayEndTag {
+                name: String::from(name),
+            }),
+        }
+    }
+
+    /**
+     * Close the open elements from depth in the stack upwards, which are
+     * missing their end tags.
+     */
+    fn close_above(&mut self, depth: usize) {
+        while self.stack.len() > depth {
+            let id = self.stack.pop().expect("stack is longer than depth");
+            self.warnings.push(ParseWarning::UnclosedElement {
+                name: self.dom.container(id).name.to_string(),
+            });
+        }
+    }
+
+    fn flush_text(&mut self) {
+        if !self.text.is_empty() {
+            let data = std::mem::take(&mut self.text);
+            let text = self.dom.create_text(data);
+            self.dom.append_child(self.top(), text);
+        }
+    }
+
+    fn top(&self) -> NodeId {
+        *self.stack.last().expect("Parser lost the document")
+    }
+
+    fn read_name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.ascii_at(self.pos) {
+            if c.is_ascii_whitespace() || matches!(c, b'/' | b'>' | b'=') {
+                break;
+            }
+            name.push(char::from(c));
+            self.pos += 1;
+        }
+        name
+    }
+
+    fn read_attr_value(&mut self) -> Vec<C> {
+        let quote = match self.ascii_at(self.pos) {
+            Some(q @ (b'"' | b'\'')) => {
+                self.pos += 1;
+                Some(q)
+            }
+            _ => None,
+        };
+        let mut value = Vec::new();
+        while self.pos < self.html.len() {
+            let c = self.ascii_at(self.pos);
+            match (quote, c) {
+                (Some(q), Some(c)) if c == q => {
+                    self.pos += 1;
+                    break;
+                }
+                (None, Some(c)) if c.is_ascii_whitespace() || c == b'>' => {
+                    break;
+                }
+                (_, Some(b'&')) => {
+                    if let Some((chars, end)) =
+                        decode_entity(self.html, self.pos)
+                    {
+                        value.extend(chars);
+                        self.pos = end;
+                        continue;
+                    }
+                    value.push(self.html[self.pos]);
+                    self.pos += 1;
+                }
+                _ => {
+                    value.push(self.html[self.pos]);
+                    self.pos += 1;
+                }
+            }
+        }
+        value
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .ascii_at(self.pos)
+            .map_or(false, |c| c.is_ascii_whitespace())
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_past_gt(&mut self) {
+        self.pos = self
+            .find(self.pos, ">")
+            .map_or(self.html.len(), |end| end + 1);
+    }
+
+    fn ascii_at(&self, pos: usize) -> Option<u8> {
+        self.html.get(pos).and_then(|c| c.to_ascii())
+    }
+
+    fn starts_with(&self, pos: usize, needle: &str) -> bool {
+        needle
+            .bytes()
+            .enumerate()
+            .all(|(i, b)| self.ascii_at(pos + i) == Some(b))
+    }
+
+    fn find(&self, from: usize, needle: &str) -> Option<usize> {
+        (from..self.html.len()).find(|&pos| self.starts_with(pos, needle))
+    }
+}
+
+/**
+ * The name we store for an element, so that synonyms like <b> and <strong>
+ * are treated identically.
+ */
+fn canonical_name(name: &str) -> String {
+    let name = name.to_lowercase();
+    match element_spec(&name).and_then(|spec| spec.parse_as) {
+        Some(canonical) => String::from(canonical),
+        None => name,
+    }
+}
+
+/**
+ * If a character reference (e.g. "&amp;" or "&#x1F4A9;") starts at pos,
+ * return the code units it represents and the position after it.
+ */
+fn decode_entity<C>(html: &[C], pos: usize) -> Option<(Vec<C>, usize)>
+where
+    C: CodeUnit,
+{
+    const MAX_ENTITY_LEN: usize = 10;
+    let mut reference = String::new();
+    let mut end = pos + 1;
+    loop {
+        let c = html.get(end)?.to_ascii()?;
+        if c == b';' {
+            break;
+        }
+        if !c.is_ascii_alphanumeric() && c != b'#'
+            || reference.len() >= MAX_ENTITY_LEN
+        {
+            return None;
+        }
+        reference.push(char::from(c));
+        end += 1;
+    }
+
+    let ch = match reference.as_str() {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{A0}',
+        _ => {
+            let number = reference.strip_prefix('#')?;
+            let code = if let Some(hex) = number
+                .strip_prefix('x')
+                .or_else(|| number.strip_prefix('X'))
+            {
+                u32::from_str_radix(hex, 16).ok()?
+            } else {
+                number.parse().ok()?
+            };
+            char::from_u32(code)?
+        }
+    };
+    Some((C::encode(ch.encode_utf8(&mut [0; 4])), end + 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::DEFAULT_MAX_DEPTH;
+    use crate::dom::{
+        parse, parse_to_depth, parse_with_warnings, DomNode, TextNode,
+    };
+    use crate::{HtmlDialect, ParseWarning};
+
+    fn roundtrip(html: &str) -> String {
+        let dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    #[test]
+    fn indentation_between_blocks_is_dropped() {
+        let html =
+            "\n<ul>\n  <li>a</li>\n  <li>\n    <p>b</p>\n  </li>\n</ul>\n\
+            <blockquote>\n  <p>c <em>d</em></p>\n</blockquote>\n";
+        assert_eq!(
+            roundtrip(html),
+            "<ul><li>a</li><li><p>b</p></li></ul>\
+            <blockquote><p>c <em>d</em></p></blockquote>"
+        );
+    }
+
+    #[test]
+    fn whitespace_between_inline_elements_is_kept() {
+        assert_eq!(
+            roundtrip("<p><em>a</em> <strong>b</strong></p> "),
+            "<p><em>a</em> <strong>b</strong></p>"
+        );
+        assert_eq!(roundtrip("<p> </p>"), "<p> </p>");
+    }
+
+    #[test]
+    fn whitespace_in_preformatted_text_is_kept() {
+        let html = "<pre>\n<code>a\n  b\n</code>\n</pre>";
+        assert_eq!(roundtrip(html), html);
+        assert_eq!(
+            roundtrip("<p>a</p>\n<pre>  </pre>"),
+            "<p>a</p><pre>  </pre>"
+        );
+    }
+
+    #[test]
+    fn parsing_plain_text_makes_a_text_node() {
+        let dom = parse(&"abc".encode_utf16().collect::<Vec<_>>());
+        let children = dom.children(dom.document());
+        assert_eq!(children.len(), 1);
+        assert_eq!(
+            dom.node(children[0]),
+            &DomNode::Text(TextNode {
+                data: "abc".encode_utf16().collect()
+            })
+        );
+    }
+
+    #[test]
+    fn lone_surrogates_are_replaced() {
+        let mut html: Vec<u16> = "<a title=\"".encode_utf16().collect();
+        html.push(0xDC00);
+        html.extend("\">b".encode_utf16());
+        html.push(0xD800);
+        let dom = parse(&html);
+        assert_eq!(
+            String::from_utf16(&dom.to_html(HtmlDialect::Editor)).unwrap(),
+            "<a title=\"\u{FFFD}\">b\u{FFFD}</a>"
+        );
+    }
+
+    #[test]
+    fn text_separated_only_by_a_comment_is_one_node() {
+        let dom = parse(&"a<!-- x -->b".encode_utf16().collect::<Vec<_>>());
+        assert_eq!(dom.children(dom.document()).len(), 1);
+    }
+
+    #[test]
+    fn simple_html_roundtrips() {
+        assert_eq!(roundtrip("a<strong>b</strong>c"), "a<strong>b</strong>c");
+        assert_eq!(
+            roundtrip("<a href=\"https://matrix.org\">x</a>"),
+            "<a href=\"https://matrix.org\">x</a>"
+        );
+        assert_eq!(
+            roundtrip("<ul><li>a</li><li>b</li></ul>"),
+            "<ul><li>a</li><li>b</li></ul>"
+        );
+    }
+
+    #[test]
+    fn editor_placeholders_are_dropped() {
+        let html = "a<strong>\u{200B}</strong><em>\u{200B}b</em>";
+        assert_eq!(roundtrip(html), "a<strong></strong><em>\u{200B}b</em>");
+        let dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        assert_eq!(
+            dom.to_html(HtmlDialect::Editor),
+            html.encode_utf16().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn void_elements_have_no_children() {
+        assert_eq!(roundtrip("a<br>b"), "a<br />b");
+        assert_eq!(roundtrip("a<br/>b"), "a<br />b");
+        assert_eq!(roundtrip("<img src=\"x\">b"), "<img src=\"x\" />b");
+    }
+
+    #[test]
+    fn entities_are_decoded_and_reescaped() {
+        assert_eq!(
+            roundtrip("&lt;b&gt; &amp; &#33;&#x21;"),
+            "&lt;b&gt; &amp; !!"
+        );
+        assert_eq!(roundtrip("a&nbsp;b"), "a\u{A0}b");
+        assert_eq!(
+            roundtrip("AT&T &unknown; &"),
+            "AT&amp;T &amp;unknown; &amp;"
+        );
+    }
+
+    #[test]
+    fn attribute_values_may_use_any_quoting() {
+        assert_eq!(
+            roundtrip("<a href='x\"y'>a</a><a href=z>b</a>"),
+            "<a href=\"x&quot;y\">a</a><a href=\"z\">b</a>"
+        );
+    }
+
+    #[test]
+    fn tag_names_are_normalised() {
+        assert_eq!(
+            roundtrip("<B>a</B><i>b</i>"),
+            "<strong>a</strong><em>b</em>"
+        );
+    }
+
+    #[test]
+    fn unclosed_and_stray_tags_are_repaired() {
+        assert_eq!(roundtrip("<em>a<strong>b"), "<em>a<strong>b</strong></em>");
+        assert_eq!(roundtrip("a</strong>b"), "ab");
+        assert_eq!(
+            roundtrip("<em>a<strong>b</em>c"),
+            "<em>a<strong>b</strong></em>c"
+        );
+    }
+
+    #[test]
+    fn repairs_are_reported_as_warnings() {
+        let result = parse_with_warnings(
+            &"<p>a<em>b</p></div><strong>c<!-- d"
+                .encode_utf16()
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(
+            result.warnings,
+            vec![
+                ParseWarning::UnclosedElement {
+                    name: String::from("em")
+                },
+                ParseWarning::StrayEndTag {
+                    name: String::from("div")
+                },
+                ParseWarning::UnterminatedComment,
+                ParseWarning::UnclosedElement {
+                    name: String::from("strong")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn well_formed_html_has_no_warnings() {
+        let result = parse_with_warnings(
+            &"<p>a<br />b</p><!-- c -->"
+                .encode_utf16()
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(result.warnings, vec![]);
+    }
+
+    #[test]
+    fn comments_and_doctypes_are_dropped() {
+        assert_eq!(roundtrip("<!DOCTYPE html>a<!-- x -->b"), "ab");
+    }
+
+    #[test]
+    fn lone_angle_brackets_are_text() {
+        assert_eq!(roundtrip("a < b > c"), "a &lt; b &gt; c");
+    }
+
+    #[test]
+    fn elements_nested_too_deeply_are_left_out() {
+        let result = parse_to_depth(
+            &"<em><strong><u>a<br />b</u>c</strong>d</em>e"
+                .encode_utf16()
+                .collect::<Vec<_>>(),
+            2,
+        );
+        assert_eq!(
+            String::from_utf16(&result.dom.to_html(HtmlDialect::MatrixMessage))
+                .unwrap(),
+            "<em><strong>a<br />bc</strong>d</em>e"
+        );
+        assert_eq!(
+            result.warnings,
+            vec![ParseWarning::TooDeep { max_depth: 2 }]
+        );
+    }
+
+    #[test]
+    fn thousands_of_nested_elements_can_be_parsed() {
+        let html = "<em>".repeat(100_000) + "a";
+        let result =
+            parse_with_warnings(&html.encode_utf16().collect::<Vec<_>>());
+        assert!(result.warnings.contains(&ParseWarning::TooDeep {
+            max_depth: DEFAULT_MAX_DEPTH
+        }));
+        assert_eq!(result.dom.text_len(), 1);
+    }
+
+    #[test]
+    fn thousands_of_nested_elements_can_be_kept() {
+        let html = "<em>".repeat(50_000) + "a<br />" + &"</em>".repeat(50_000);
+        let result = parse_to_depth(
+            &html.encode_utf16().collect::<Vec<_>>(),
+            usize::MAX,
+        );
+        assert!(result.warnings.is_empty());
+        assert_eq!(result.dom.text_len(), 2);
+    }
+}
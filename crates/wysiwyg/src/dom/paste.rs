@@ -0,0 +1,296 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Dom, DomNode, NodeId};
+use crate::{CodeUnit, SanitizeReport};
+
+/**
+ * The applications whose clipboard HTML needs its own cleanup, because
+ * they express formatting with inline styles rather than elements.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasteSource {
+    /**
+     * Recognised by the "docs-internal-guid" id on the element wrapping
+     * the whole fragment.
+     */
+    GoogleDocs,
+    /**
+     * Recognised by its Mso classes, mso- styles and Office namespaced
+     * elements such as o:p.
+     */
+    Word,
+    Other,
+}
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Which application this Dom appears to have been copied from.
+     */
+    pub fn paste_source(&self) -> PasteSource {
+        let mut source = PasteSource::Other;
+        for id in self.descendants(self.document()) {
+            if let DomNode::Container(c) = self.node(id) {
+                let id = c.attr("id").map(C::decode).unwrap_or_default();
+                let class = c.attr("class").map(C::decode).unwrap_or_default();
+                let style = c.attr("style").map(C::decode).unwrap_or_default();
+                if id.starts_with("docs-internal-guid") {
+                    return PasteSource::GoogleDocs;
+                }
+                if class.contains("Mso")
+                    || style.contains("mso-")
+                    || c.name.contains(':')
+                {
+                    source = PasteSource::Word;
+                }
+            }
+        }
+        source
+    }
+
+    /**
+     * Clean up HTML from the clipboard: map the inline styles of the
+     * application it came from onto formatting elements, drop its
+     * bookkeeping markup, and then remove everything Matrix does not
+     * allow, with links allowed only if their scheme is one of
+     * link_schemes, reporting what that removed (see Dom::sanitize).
+     */
+    pub fn clean_pasted<S>(&mut self, link_schemes: &[S]) -> SanitizeReport
+    where
+        S: AsRef<str>,
+    {
+        let source = self.paste_source();
+        if source != PasteSource::Other {
+            for id in self.containers_innermost_first(self.document()) {
+                self.clean_in(id, source);
+            }
+        }
+        self.sanitize(link_schemes)
+    }
+
+    /**
+     * Clean up the children of container, whose own children have already
+     * been cleaned.
+     */
+    fn clean_in(&mut self, container: NodeId, source: PasteSource) {
+        let mut new_children = Vec::new();
+        let mut removed = Vec::new();
+        for child in self.children(container).to_vec() {
+            let (name, style) = match self.node(child) {
+                DomNode::Container(c) => (
+                    c.name.clone(),
+                    c.attr("style").map(C::decode).unwrap_or_default(),
+                ),
+                DomNode::Text(_) => {
+                    new_children.push(child);
+                    continue;
+                }
+            };
+            let style = parse_style(&style);
+            if is_bookkeeping(&name, &style, source) {
+                removed.push(child);
+                continue;
+            }
+            if name == "span"
+                || (name == "font" && source == PasteSource::Word)
+                || is_not_really(&name, &style)
+            {
+                let content = self.children(child).to_vec();
+                match self.wrap_in_formats(content.clone(), &style) {
+                    Some(wrapper) => new_children.push(wrapper),
+                    None => new_children.extend(content),
+                }
+                removed.push(child);
+            } else {
+                new_children.push(child);
+            }
+        }
+        self.set_children(container, new_children);
+        for id in removed {
+            self.remove(id);
+        }
+    }
+
+    /**
+     * Wrap content in the formatting elements that style asks for,
+     * returning the outermost, or None if it asks for none.
+     */
+    fn wrap_in_formats(
+        &mut self,
+        content: Vec<NodeId>,
+        style: &[(String, String)],
+    ) -> Option<NodeId> {
+        let mut children = content;
+        let mut outermost = None;
+        for name in formats(style).into_iter().rev() {
+            let id = self.create_container(name, Vec::new(), children);
+            children = vec![id];
+            outermost = Some(id);
+        }
+        outermost
+    }
+}
+
+/**
+ * The declarations in a style attribute, as lowercase (property, value)
+ * pairs.
+ */
+fn parse_style(style: &str) -> Vec<(String, String)> {
+    style
+        .split(';')
+        .filter_map(|declaration| declaration.split_once(':'))
+        .map(|(property, value)| {
+            (property.trim().to_lowercase(), value.trim().to_lowercase())
+        })
+        .collect()
+}
+
+fn style_value<'a>(style: &'a [(String, String)], property: &str) -> &'a str {
+    style
+        .iter()
+        .rev()
+        .find(|(p, _)| p == property)
+        .map_or("", |(_, v)| v.as_str())
+}
+
+/**
+ * The formatting elements that style expresses, outermost first.
+ */
+fn formats(style: &[(String, String)]) -> Vec<&'static str> {
+    let mut formats = Vec::new();
+    let weight = style_value(style, "font-weight");
+    if weight == "bold"
+        || weight == "bolder"
+        || weight.parse::<u32>().map_or(false, |w| w >= 600)
+    {
+        formats.push("strong");
+    }
+    if matches!(style_value(style, "font-style"), "italic" | "oblique") {
+        formats.push("em");
+    }
+    let decoration = format!(
+        "{} {}",
+        style_value(style, "text-decoration"),
+        style_value(style, "text-decoration-line")
+    );
+    if decoration.contains("underline") {
+        formats.push("u");
+    }
+    if decoration.contains("line-through") {
+        formats.push("del");
+    }
+    match style_value(style, "vertical-align") {
+        "super" => formats.push("sup"),
+        "sub" => formats.push("sub"),
+        _ => {}
+    }
+    formats
+}
+
+/**
+ * Whether an element is markup that only matters to the application it
+ * came from, and is removed along with its content: Office namespaced
+ * elements such as o:p, and the bullets and numbers Word writes out for
+ * list items.
+ */
+fn is_bookkeeping(
+    name: &str,
+    style: &[(String, String)],
+    source: PasteSource,
+) -> bool {
+    source == PasteSource::Word
+        && (name.contains(':') || style_value(style, "mso-list") == "ignore")
+}
+
+/**
+ * Whether a formatting element has a style that undoes it, like the
+ * <b style="font-weight:normal"> Google Docs wraps around everything.
+ */
+fn is_not_really(name: &str, style: &[(String, String)]) -> bool {
+    match name {
+        "strong" => {
+            matches!(style_value(style, "font-weight"), "normal" | "400")
+        }
+        "em" => style_value(style, "font-style") == "normal",
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, PasteSource, MATRIX_LINK_SCHEMES};
+    use crate::HtmlDialect;
+
+    fn cleaned(html: &str) -> String {
+        let mut dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        dom.clean_pasted(MATRIX_LINK_SCHEMES);
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    fn source(html: &str) -> PasteSource {
+        parse(&html.encode_utf16().collect::<Vec<_>>()).paste_source()
+    }
+
+    #[test]
+    fn the_source_is_detected_from_its_markup() {
+        assert_eq!(
+            source("<b id=\"docs-internal-guid-1\"><p>a</p></b>"),
+            PasteSource::GoogleDocs
+        );
+        assert_eq!(
+            source("<p class=\"MsoNormal\">a<o:p></o:p></p>"),
+            PasteSource::Word
+        );
+        assert_eq!(source("<p>a</p>"), PasteSource::Other);
+    }
+
+    #[test]
+    fn google_docs_styles_become_formatting() {
+        assert_eq!(
+            cleaned(
+                "<meta charset=\"utf-8\"><b style=\"font-weight:normal;\" \
+                id=\"docs-internal-guid-1\"><p dir=\"ltr\"><span \
+                style=\"font-weight:700;font-style:italic\">a</span><span \
+                style=\"font-size:11pt\">b</span><span \
+                style=\"text-decoration:line-through\">c</span></p></b>"
+            ),
+            "<p><strong><em>a</em></strong>b<del>c</del></p>"
+        );
+    }
+
+    #[test]
+    fn word_markup_is_dropped_and_styles_become_formatting() {
+        assert_eq!(
+            cleaned(
+                "<p class=\"MsoListParagraph\"><span \
+                style=\"mso-list:Ignore\">\u{b7} </span><font face=\"Calibri\">\
+                <font size=\"2\">a<span style=\"mso-bidi-font-weight:bold; \
+                text-decoration:underline\">b</span></font></font>\
+                <o:p>&nbsp;</o:p></p>"
+            ),
+            "<p>a<u>b</u></p>"
+        );
+    }
+
+    #[test]
+    fn other_html_is_only_sanitized() {
+        assert_eq!(
+            cleaned("<span style=\"font-weight:bold\">a</span><b>b</b>"),
+            "<span>a</span><strong>b</strong>"
+        );
+    }
+}
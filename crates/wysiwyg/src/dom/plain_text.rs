@@ -0,0 +1,515 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Dom, DomNode, NodeId};
+use crate::CodeUnit;
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Serialize this Dom as plain text, with lines ended by line breaks and
+     * block boundaries.
+     *
+     * If fallback_formatting is true, formatting is reproduced using the
+     * conventions Matrix clients use in the plain text body of formatted
+     * messages: quoted lines are prefixed with "> ", list items with "- "
+     * or their number, and bold and italic text is surrounded by "**" and
+     * "*". Ordered lists are numbered 1, 2, 3 and so on whatever their
+     * start and type attributes say, and nested lists are indented to line
+     * up with the text of the item they are in. Otherwise, formatting
+     * (including quotes and list markers) is dropped.
+     */
+    pub fn to_plain_text(&self, fallback_formatting: bool) -> Vec<C> {
+        let mut writer = PlainTextWriter {
+            fallback_formatting,
+            lines: Vec::new(),
+            current: Some((0, Vec::new())),
+            lists: Vec::new(),
+            indent: 0,
+        };
+        self.write_plain_text(self.document(), 0, &mut writer);
+        if let Some(line) = writer.current.take() {
+            writer.lines.push(line);
+        }
+
+        let mut out = Vec::new();
+        for (i, (quote_depth, text)) in writer.lines.into_iter().enumerate() {
+            if i > 0 {
+                out.extend(C::encode("\n"));
+            }
+            if fallback_formatting {
+                for _ in 0..quote_depth {
+                    out.extend(C::encode("> "));
+                }
+            }
+            out.extend(text);
+        }
+        out
+    }
+
+    /**
+     * The text between locations start and end, with line breaks and the
+     * boundaries between blocks as "\n", like to_plain_text without
+     * fallback formatting. Other void elements, such as images, have no
+     * text.
+     */
+    pub fn text_in_range(&self, start: usize, end: usize) -> Vec<C> {
+        let mut out = LocatedText::default();
+        let mut between_blocks = false;
+        self.write_text_in_range(
+            self.document(),
+            0,
+            (start, end),
+            &mut out,
+            &mut between_blocks,
+        );
+        out.text
+    }
+
+    /**
+     * The whole text, as text_in_range gives it, along with the location
+     * of each code unit of it, and then the length of the document, so
+     * that an offset into the text can be turned into a location. A
+     * newline between blocks has the location of the start of the block
+     * after it.
+     */
+    pub fn text_with_locations(&self) -> (Vec<C>, Vec<usize>) {
+        let mut out = LocatedText::default();
+        let mut between_blocks = false;
+        let len = self.write_text_in_range(
+            self.document(),
+            0,
+            (0, usize::MAX),
+            &mut out,
+            &mut between_blocks,
+        );
+        out.locations.push(len);
+        (out.text, out.locations)
+    }
+
+    /**
+     * Append the text of the part of container (which starts at offset)
+     * inside range to out, and return the offset of the end of container.
+     * between_blocks says whether a block boundary has been passed since
+     * the last text written.
+     */
+    fn write_text_in_range(
+        &self,
+        container: NodeId,
+        mut offset: usize,
+        range: (usize, usize),
+        out: &mut LocatedText<C>,
+        between_blocks: &mut bool,
+    ) -> usize {
+        let (start, end) = range;
+        // The nodes still to visit, with None marking where a block ends
+        let mut pending: Vec<Option<NodeId>> = self
+            .children(container)
+            .iter()
+            .rev()
+            .map(|&c| Some(c))
+            .collect();
+        while let Some(next) = pending.pop() {
+            if offset > end {
+                break;
+            }
+            let child = match next {
+                Some(child) => child,
+                None => {
+                    *between_blocks = true;
+                    continue;
+                }
+            };
+            let mut write = |text: &[C], location, out: &mut LocatedText<C>| {
+                if *between_blocks && !out.text.is_empty() {
+                    out.text.extend(C::encode("\n"));
+                    out.locations.push(location);
+                }
+                *between_blocks = false;
+                out.text.extend_from_slice(text);
+                out.locations.extend(location..location + text.len());
+            };
+            match self.node(child) {
+                DomNode::Text(text) => {
+                    let len = text.data.len();
+                    let s = start.clamp(offset, offset + len) - offset;
+                    let e = end.clamp(offset, offset + len) - offset;
+                    if s < e {
+                        write(&text.data[s..e], offset + s, out);
+                    }
+                    offset += len;
+                }
+                DomNode::Container(c) if c.is_void() => {
+                    if c.name == "br" && start <= offset && offset < end {
+                        write(&C::encode("\n"), offset, out);
+                    }
+                    offset += 1;
+                }
+                DomNode::Container(c) => {
+                    if c.is_block() {
+                        *between_blocks = true;
+                        pending.push(None);
+                    }
+                    pending.extend(
+                        self.children(child).iter().rev().map(|&c| Some(c)),
+                    );
+                }
+            }
+        }
+        offset
+    }
+
+    fn write_plain_text(
+        &self,
+        container: NodeId,
+        quote_depth: usize,
+        writer: &mut PlainTextWriter<C>,
+    ) {
+        let mut pending: Vec<PlainTextStep> = self
+            .children(container)
+            .iter()
+            .rev()
+            .map(|&c| PlainTextStep::Node(c, quote_depth))
+            .collect();
+        while let Some(step) = pending.pop() {
+            let (child, quote_depth) = match step {
+                PlainTextStep::Node(child, quote_depth) => (child, quote_depth),
+                PlainTextStep::EndBlock { is_list, indent } => {
+                    writer.end_line();
+                    if is_list {
+                        writer.lists.pop();
+                    }
+                    writer.indent = indent;
+                    continue;
+                }
+                PlainTextStep::Marker(marker, quote_depth) => {
+                    writer.line(quote_depth).extend(C::encode(marker));
+                    continue;
+                }
+            };
+            match self.node(child) {
+                DomNode::Text(text) => {
+                    writer.line(quote_depth).extend_from_slice(&text.data)
+                }
+                DomNode::Container(c) if c.name == "br" => {
+                    writer.line(quote_depth);
+                    writer.end_line();
+                    writer.current = Some((quote_depth, writer.indentation()));
+                }
+                DomNode::Container(c)
+                    if c.attr("data-mx-emoticon").is_some() =>
+                {
+                    // Custom emotes stand for their shortcode
+                    if let Some(alt) = c.attr("alt") {
+                        writer.line(quote_depth).extend_from_slice(alt);
+                    }
+                }
+                DomNode::Container(c) if c.is_void() => {}
+                DomNode::Container(c) if c.is_block() => {
+                    let depth = if c.name == "blockquote" {
+                        quote_depth + 1
+                    } else {
+                        quote_depth
+                    };
+                    if writer
+                        .current
+                        .as_ref()
+                        .map_or(false, |(_, t)| !t.is_empty())
+                    {
+                        writer.end_line();
+                    }
+                    writer.current = Some((depth, Vec::new()));
+                    let indent = writer.indent;
+                    match c.name.as_str() {
+                        "ol" | "ul" => writer.lists.push(ListState {
+                            next_number: c.name.eq("ol").then(|| 1),
+                            indent,
+                        }),
+                        "li" => writer.start_item(),
+                        _ => {}
+                    }
+                    pending.push(PlainTextStep::EndBlock {
+                        is_list: matches!(c.name.as_str(), "ol" | "ul"),
+                        indent,
+                    });
+                    pending.extend(
+                        self.children(child)
+                            .iter()
+                            .rev()
+                            .map(|&c| PlainTextStep::Node(c, depth)),
+                    );
+                }
+                DomNode::Container(c) => {
+                    let marker = match c.name.as_str() {
+                        "strong" if writer.fallback_formatting => "**",
+                        "em" if writer.fallback_formatting => "*",
+                        _ => "",
+                    };
+                    if !marker.is_empty() {
+                        writer.line(quote_depth).extend(C::encode(marker));
+                        pending
+                            .push(PlainTextStep::Marker(marker, quote_depth));
+                    }
+                    pending.extend(
+                        self.children(child)
+                            .iter()
+                            .rev()
+                            .map(|&c| PlainTextStep::Node(c, quote_depth)),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Text, and the location each of its code units came from.
+ */
+struct LocatedText<C> {
+    text: Vec<C>,
+    locations: Vec<usize>,
+}
+
+impl<C> Default for LocatedText<C> {
+    fn default() -> Self {
+        Self {
+            text: Vec::new(),
+            locations: Vec::new(),
+        }
+    }
+}
+
+/**
+ * What to_plain_text does next: write a node inside the given number of
+ * quotes, finish a block, or close formatting with a marker.
+ */
+enum PlainTextStep {
+    Node(NodeId, usize),
+    EndBlock { is_list: bool, indent: usize },
+    Marker(&'static str, usize),
+}
+
+struct PlainTextWriter<C> {
+    fallback_formatting: bool,
+    /**
+     * The finished lines, each with the number of quotes it is inside.
+     */
+    lines: Vec<(usize, Vec<C>)>,
+    /**
+     * The line being written, or None if we are between blocks.
+     */
+    current: Option<(usize, Vec<C>)>,
+    /**
+     * The lists we are inside, innermost last.
+     */
+    lists: Vec<ListState>,
+    /**
+     * How many spaces the text of the current list item is indented by.
+     */
+    indent: usize,
+}
+
+struct ListState {
+    /**
+     * The number of the next item, or None for an unordered list.
+     */
+    next_number: Option<usize>,
+    /**
+     * How many spaces the markers of this list's items are indented by.
+     */
+    indent: usize,
+}
+
+impl<C> PlainTextWriter<C>
+where
+    C: CodeUnit,
+{
+    fn line(&mut self, quote_depth: usize) -> &mut Vec<C> {
+        &mut self.current.get_or_insert((quote_depth, Vec::new())).1
+    }
+
+    fn end_line(&mut self) {
+        if let Some(line) = self.current.take() {
+            self.lines.push(line);
+        }
+    }
+
+    /**
+     * Spaces to start a line continuing the current list item with, if
+     * list markers are being written.
+     */
+    fn indentation(&self) -> Vec<C> {
+        if self.fallback_formatting {
+            C::encode(&" ".repeat(self.indent))
+        } else {
+            Vec::new()
+        }
+    }
+
+    /**
+     * Write the marker of a new item of the innermost list, and indent
+     * what follows to line up with the item's text.
+     */
+    fn start_item(&mut self) {
+        let list = match self.lists.last_mut() {
+            Some(list) if self.fallback_formatting => list,
+            _ => return,
+        };
+        let marker = match &mut list.next_number {
+            Some(number) => {
+                *number += 1;
+                format!("{}. ", *number - 1)
+            }
+            None => String::from("- "),
+        };
+        let prefix = format!("{}{}", " ".repeat(list.indent), marker);
+        self.indent = prefix.len();
+        if let Some((_, line)) = &mut self.current {
+            line.extend(C::encode(&prefix));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::parse;
+
+    fn plain(html: &str, fallback_formatting: bool) -> String {
+        let dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        String::from_utf16(&dom.to_plain_text(fallback_formatting)).unwrap()
+    }
+
+    #[test]
+    fn tags_are_dropped_and_entities_decoded() {
+        assert_eq!(
+            plain("a<strong>b</strong> &amp; <em>c</em>", false),
+            "ab & c"
+        );
+    }
+
+    #[test]
+    fn line_breaks_and_blocks_become_newlines() {
+        assert_eq!(plain("a<br />b", false), "a\nb");
+        assert_eq!(plain("<p>a</p><p>b</p>", false), "a\nb");
+        assert_eq!(
+            plain("a<ul><li>b</li><li>c</li></ul>d", false),
+            "a\nb\nc\nd"
+        );
+    }
+
+    #[test]
+    fn quotes_are_stripped_without_fallback_formatting() {
+        assert_eq!(plain("<blockquote>a</blockquote>b", false), "a\nb");
+    }
+
+    #[test]
+    fn quoted_lines_are_prefixed_with_fallback_formatting() {
+        assert_eq!(
+            plain("<blockquote><p>a</p><p>b<br />c</p></blockquote>d", true),
+            "> a\n> b\n> c\nd"
+        );
+        assert_eq!(
+            plain("<blockquote>a<blockquote>b</blockquote></blockquote>", true),
+            "> a\n> > b"
+        );
+    }
+
+    #[test]
+    fn emphasis_is_marked_with_fallback_formatting() {
+        assert_eq!(plain("a<strong>b</strong><em>c</em>", true), "a**b***c*");
+        assert_eq!(plain("<b>x</b>", false), "x");
+    }
+
+    #[test]
+    fn list_items_are_marked_with_fallback_formatting() {
+        assert_eq!(
+            plain("a<ul><li>b</li><li>c</li></ul><ol><li>d</li></ol>", true),
+            "a\n- b\n- c\n1. d"
+        );
+        assert_eq!(plain("<ol><li>a</li></ol>", false), "a");
+    }
+
+    #[test]
+    fn ordered_lists_are_renumbered_from_one() {
+        assert_eq!(
+            plain(
+                "<ol start=\"7\" type=\"a\"><li>a</li><li>b</li></ol>\
+                <ol start=\"3\"><li>c</li></ol>",
+                true
+            ),
+            "1. a\n2. b\n1. c"
+        );
+    }
+
+    #[test]
+    fn nested_lists_are_indented_and_numbered_separately() {
+        assert_eq!(
+            plain(
+                "<ol><li>a<ol start=\"5\"><li>b</li><li>c<ul><li>d</li>\
+                </ul></li></ol></li><li>e<br />f</li></ol>",
+                true
+            ),
+            "1. a\n   1. b\n   2. c\n      - d\n2. e\n   f"
+        );
+    }
+
+    #[test]
+    fn custom_emotes_become_their_shortcode() {
+        let html = "a<img data-mx-emoticon src=\"mxc://a/b\" alt=\":cat:\" />\
+            <img src=\"mxc://a/c\" alt=\"photo\" />";
+        assert_eq!(plain(html, false), "a:cat:");
+        assert_eq!(plain(html, true), "a:cat:");
+    }
+
+    fn text_in_range(html: &str, start: usize, end: usize) -> String {
+        let dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        String::from_utf16(&dom.text_in_range(start, end)).unwrap()
+    }
+
+    #[test]
+    fn text_in_a_range_ignores_formatting() {
+        assert_eq!(text_in_range("ab<strong>cd</strong>ef", 1, 5), "bcde");
+        assert_eq!(text_in_range("ab<strong>cd</strong>ef", 2, 2), "");
+        assert_eq!(text_in_range("ab", 1, 10), "b");
+    }
+
+    #[test]
+    fn text_in_a_range_has_newlines_for_breaks_and_blocks() {
+        assert_eq!(text_in_range("a<br />b<br />", 0, 3), "a\nb");
+        assert_eq!(
+            text_in_range("<p>ab</p><ul><li>c</li></ul>d", 1, 4),
+            "b\nc\nd"
+        );
+        assert_eq!(text_in_range("<p>ab</p><p>cd</p>", 2, 4), "cd");
+    }
+
+    #[test]
+    fn images_have_no_text_in_a_range() {
+        assert_eq!(text_in_range("a<img src=\"mxc://a/b\" />b", 0, 3), "ab");
+    }
+
+    #[test]
+    fn each_unit_of_the_text_has_its_location() {
+        let dom = parse(
+            &"<p>ab</p><p>c<br />d</p><img src=\"mxc://a/b\" />"
+                .encode_utf16()
+                .collect::<Vec<_>>(),
+        );
+        let (text, locations) = dom.text_with_locations();
+        assert_eq!(String::from_utf16(&text).unwrap(), "ab\nc\nd");
+        assert_eq!(locations, vec![0, 1, 2, 2, 3, 4, 6]);
+    }
+}
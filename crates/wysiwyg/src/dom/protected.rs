@@ -0,0 +1,155 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Dom, DomNode, NodeId};
+use crate::{CodeUnit, HtmlDialect};
+
+/**
+ * The attribute that marks an element, and everything in it, as protected:
+ * shown in the composer but not editable, e.g. the header of a forwarded
+ * message.
+ */
+pub const PROTECTED_ATTR: &str = "data-protected";
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * The protected elements (see PROTECTED_ATTR) in document order. An
+     * element inside another protected element is not listed, as it is
+     * protected with it.
+     */
+    pub fn protected_nodes(&self) -> Vec<NodeId> {
+        let mut nodes = Vec::new();
+        let mut pending = vec![self.document()];
+        while let Some(id) = pending.pop() {
+            if let DomNode::Container(c) = self.node(id) {
+                if id != self.document() && c.attr(PROTECTED_ATTR).is_some() {
+                    nodes.push(id);
+                } else {
+                    pending.extend(c.children().iter().rev());
+                }
+            }
+        }
+        nodes
+    }
+
+    /**
+     * The start and end of each protected element in document order.
+     */
+    pub fn protected_ranges(&self) -> Vec<(usize, usize)> {
+        self.protected_nodes()
+            .into_iter()
+            .map(|id| {
+                let start = self.start_of(id);
+                (start, start + self.node_len(id))
+            })
+            .collect()
+    }
+
+    /**
+     * Move start..end so that neither edge is inside a protected element: a
+     * cursor inside one moves to whichever of its edges is nearer (its end
+     * if they are equally near), and a range that ends inside one is
+     * widened to cover it whole.
+     */
+    pub fn snap_out_of_protected(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> (usize, usize) {
+        let (mut s, mut e) = (start, end);
+        for (protected_start, protected_end) in self.protected_ranges() {
+            if s == e {
+                if protected_start < s && s < protected_end {
+                    let nearer = if s - protected_start < protected_end - s {
+                        protected_start
+                    } else {
+                        protected_end
+                    };
+                    return (nearer, nearer);
+                }
+                continue;
+            }
+            if protected_start < s && s < protected_end {
+                s = protected_start;
+            }
+            if protected_start < e && e < protected_end {
+                e = protected_end;
+            }
+        }
+        (s, e)
+    }
+
+    /**
+     * The HTML of each protected element, or None if there are none, so
+     * that protected_unchanged can tell after an edit whether any of them
+     * was changed or removed.
+     */
+    pub(crate) fn protected_contents(&self) -> Option<Vec<(NodeId, Vec<C>)>> {
+        let nodes = self.protected_nodes();
+        if nodes.is_empty() {
+            return None;
+        }
+        Some(
+            nodes
+                .into_iter()
+                .map(|id| (id, self.node_to_html(id, HtmlDialect::Editor)))
+                .collect(),
+        )
+    }
+
+    /**
+     * Whether each of the protected elements that protected_contents
+     * recorded is still in the document, still protected and unchanged.
+     */
+    pub(crate) fn protected_unchanged(
+        &self,
+        contents: &[(NodeId, Vec<C>)],
+    ) -> bool {
+        let now = self.protected_nodes();
+        contents.iter().all(|(id, html)| {
+            now.contains(id)
+                && self.node_to_html(*id, HtmlDialect::Editor) == *html
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, Dom};
+
+    fn dom(html: &str) -> Dom<u16> {
+        parse(&html.encode_utf16().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn protected_ranges_skip_elements_nested_in_protected_ones() {
+        let dom = dom("a<blockquote data-protected=\"\">b\
+            <span data-protected=\"\">c</span></blockquote>\
+            d<span data-protected=\"\">ef</span>");
+        assert_eq!(dom.protected_ranges(), vec![(1, 3), (4, 6)]);
+    }
+
+    #[test]
+    fn a_cursor_inside_a_protected_element_snaps_to_its_nearer_edge() {
+        let dom = dom("ab<span data-protected=\"\">cdef</span>g");
+        assert_eq!(dom.snap_out_of_protected(3, 3), (2, 2));
+        assert_eq!(dom.snap_out_of_protected(4, 4), (6, 6));
+        assert_eq!(dom.snap_out_of_protected(2, 2), (2, 2));
+        assert_eq!(dom.snap_out_of_protected(1, 3), (1, 6));
+        assert_eq!(dom.snap_out_of_protected(5, 7), (2, 7));
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{Atom, Dom, DomNode, NodeId};
+use crate::mention::{MATRIX_TO_PREFIX, MENTION_ID_ATTR, MENTION_KIND_ATTR};
+use crate::{CodeUnit, MentionKind, RestoreChange, RestoreOptions};
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Make the HTML of a sent message editable in the composer, as
+     * described by options, and return what was changed. Links are kept
+     * only if their scheme is one of link_schemes.
+     */
+    pub(crate) fn restore_message<S>(
+        &mut self,
+        options: &RestoreOptions,
+        link_schemes: &[S],
+    ) -> Vec<RestoreChange>
+    where
+        S: AsRef<str>,
+    {
+        let mut changes = Vec::new();
+        if options.strip_reply && self.remove_elements("mx-reply") {
+            changes.push(RestoreChange::ReplyRemoved);
+        }
+        if options.downgrade_unsupported {
+            self.downgrade_unsupported(&mut changes);
+        }
+        let violations = self.matrix_violations(link_schemes);
+        if !violations.is_empty() {
+            self.strip_matrix_violations(link_schemes);
+            changes.extend(violations.into_iter().map(RestoreChange::Stripped));
+        }
+        if options.resolve_mentions {
+            self.resolve_mentions(&mut changes);
+        }
+        self.normalize(self.document());
+        changes
+    }
+
+    /**
+     * Remove every element called name, with its content. Returns whether
+     * there were any.
+     */
+    fn remove_elements(&mut self, name: &str) -> bool {
+        let found: Vec<NodeId> = self
+            .descendants(self.document())
+            .into_iter()
+            .filter(|&id| {
+                matches!(self.node(id), DomNode::Container(c) if c.name == name)
+            })
+            .collect();
+        for &id in &found {
+            // An element inside one already removed is gone
+            if self.get(id).is_some() {
+                self.remove(id);
+            }
+        }
+        !found.is_empty()
+    }
+
+    fn downgrade_unsupported(&mut self, changes: &mut Vec<RestoreChange>) {
+        for id in self.descendants(self.document()) {
+            let name = match self.node(id) {
+                DomNode::Container(c) => c.name.clone(),
+                DomNode::Text(_) => continue,
+            };
+            let spec = match name.element() {
+                Some(spec) => spec,
+                None => continue,
+            };
+            if let Some(to) = spec.restore_as {
+                self.touch(id.index);
+                self.container_mut(id).name = Atom::from(to);
+                changes.push(RestoreChange::Downgraded {
+                    element: name.to_string(),
+                    replacement: Some(String::from(to)),
+                });
+            } else if spec.restore_unwrapped {
+                self.unwrap(id);
+                changes.push(RestoreChange::Downgraded {
+                    element: name.to_string(),
+                    replacement: None,
+                });
+            }
+        }
+    }
+
+    /**
+     * Replace id by its children.
+     */
+    pub(crate) fn unwrap(&mut self, id: NodeId) {
+        let (parent, index) = self.index_in_parent(id);
+        let mut children = self.children(parent).to_vec();
+        let content = self.children(id).to_vec();
+        children.splice(index..=index, content);
+        self.set_children(parent, children);
+        self.remove(id);
+    }
+
+    /**
+     * Mark links to users and rooms on matrix.to (i.e. pills) as mentions.
+     */
+    fn resolve_mentions(&mut self, changes: &mut Vec<RestoreChange>) {
+        for id in self.descendants(self.document()) {
+            let mentioned = match self.node(id) {
+                DomNode::Container(c)
+                    if c.name == "a" && c.attr(MENTION_ID_ATTR).is_none() =>
+                {
+                    c.attr("href")
+                        .and_then(|href| mentioned_id(&C::decode(href)))
+                }
+                _ => None,
+            };
+            if let Some(mentioned) = mentioned {
+                self.set_attr(id, MENTION_ID_ATTR, C::encode(&mentioned));
+                if let Some(kind) = MentionKind::of(&mentioned) {
+                    self.set_attr(
+                        id,
+                        MENTION_KIND_ATTR,
+                        C::encode(kind.name()),
+                    );
+                }
+                changes.push(RestoreChange::MentionResolved { id: mentioned });
+            }
+        }
+    }
+}
+
+/**
+ * The user or room ID that href, a matrix.to link, points to, if it points
+ * to one. Any query (e.g. ?via=) is ignored, and escapes are decoded.
+ */
+fn mentioned_id(href: &str) -> Option<String> {
+    let path = href.strip_prefix(MATRIX_TO_PREFIX)?;
+    let path = path.split('?').next().unwrap_or_default();
+    let id = percent_decode(path)?;
+    let is_mention = (id.starts_with('@') || id.starts_with('#'))
+        && id.contains(':')
+        && !id.contains('/');
+    is_mention.then(|| id)
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, MATRIX_LINK_SCHEMES};
+    use crate::{HtmlDialect, HtmlViolation, RestoreChange, RestoreOptions};
+
+    fn restored(html: &str) -> (String, Vec<RestoreChange>) {
+        let mut dom = parse(&html.encode_utf16().collect::<Vec<u16>>());
+        let changes = dom
+            .restore_message(&RestoreOptions::default(), MATRIX_LINK_SCHEMES);
+        let html = String::from_utf16(&dom.to_html(HtmlDialect::Editor));
+        (html.unwrap(), changes)
+    }
+
+    #[test]
+    fn replies_are_removed_and_reported_once() {
+        let (html, changes) = restored(
+            "<mx-reply><blockquote>a</blockquote></mx-reply>\
+            <mx-reply>b</mx-reply>c",
+        );
+        assert_eq!(html, "c");
+        assert_eq!(changes, vec![RestoreChange::ReplyRemoved]);
+    }
+
+    #[test]
+    fn unsupported_elements_are_downgraded() {
+        let (html, changes) =
+            restored("<h1>a</h1><h6>b</h6><font color=\"red\">c</font>");
+        assert_eq!(html, "<p>a</p><p>b</p>c");
+        assert_eq!(
+            changes,
+            vec![
+                RestoreChange::Downgraded {
+                    element: String::from("h1"),
+                    replacement: Some(String::from("p")),
+                },
+                RestoreChange::Downgraded {
+                    element: String::from("h6"),
+                    replacement: Some(String::from("p")),
+                },
+                RestoreChange::Downgraded {
+                    element: String::from("font"),
+                    replacement: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn disallowed_markup_is_stripped_and_reported() {
+        let (html, changes) = restored("<em onclick=\"x\">a</em>");
+        assert_eq!(html, "<em>a</em>");
+        assert_eq!(
+            changes,
+            vec![RestoreChange::Stripped(HtmlViolation::Attribute {
+                element: String::from("em"),
+                attribute: String::from("onclick"),
+            })]
+        );
+    }
+
+    #[test]
+    fn pills_become_mentions() {
+        let (html, changes) = restored(
+            "<a href=\"https://matrix.to/#/%40alice:matrix.org?via=x\">\
+            Alice</a> <a href=\"https://matrix.to/#/!room:x/$event\">e</a>",
+        );
+        assert_eq!(
+            html,
+            "<a href=\"https://matrix.to/#/%40alice:matrix.org?via=x\" \
+            data-mention-id=\"@alice:matrix.org\" data-mention-type=\"user\">\
+            Alice</a> \
+            <a href=\"https://matrix.to/#/!room:x/$event\">e</a>"
+        );
+        assert_eq!(
+            changes,
+            vec![RestoreChange::MentionResolved {
+                id: String::from("@alice:matrix.org")
+            }]
+        );
+    }
+
+    #[test]
+    fn nothing_is_done_that_the_options_leave_out() {
+        let html = "<mx-reply>a</mx-reply><h1>b</h1>\
+            <a href=\"https://matrix.to/#/@c:d\">c</a>";
+        let mut dom = parse(&html.encode_utf16().collect::<Vec<u16>>());
+        let options = RestoreOptions {
+            strip_reply: false,
+            resolve_mentions: false,
+            downgrade_unsupported: false,
+        };
+        let changes = dom.restore_message(&options, MATRIX_LINK_SCHEMES);
+        assert_eq!(
+            changes,
+            vec![RestoreChange::Stripped(HtmlViolation::Element {
+                name: String::from("mx-reply")
+            })]
+        );
+        assert_eq!(
+            String::from_utf16(&dom.to_html(HtmlDialect::Editor)).unwrap(),
+            "a<h1>b</h1><a href=\"https://matrix.to/#/@c:d\">c</a>"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{ContainerNode, Dom, DomNode, NodeId};
+use crate::{CodeUnit, HtmlDialect};
+
+/**
+ * What the Editor dialect writes inside empty inline elements, so that
+ * the editing surface can place a cursor in them.
+ */
+pub(crate) const PLACEHOLDER: &str = "\u{200B}";
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Serialize this Dom as HTML in the supplied dialect.
+     */
+    pub fn to_html(&self, dialect: HtmlDialect) -> Vec<C> {
+        let mut out = Vec::new();
+        self.write_html(dialect, &mut out);
+        out
+    }
+
+    /**
+     * Serialize this Dom as HTML in the supplied dialect onto the end of
+     * out, so that a caller serializing repeatedly can reuse one buffer
+     * rather than allocating a new one each time.
+     */
+    pub fn write_html(&self, dialect: HtmlDialect, out: &mut Vec<C>) {
+        self.write_nodes(self.children(self.document), dialect, out);
+    }
+
+    /**
+     * Serialize the node id, with its descendants, as HTML in the supplied
+     * dialect.
+     */
+    pub fn node_to_html(&self, id: NodeId, dialect: HtmlDialect) -> Vec<C> {
+        let mut out = Vec::new();
+        self.write_nodes(&[id], dialect, &mut out);
+        out
+    }
+
+    fn write_nodes(
+        &self,
+        ids: &[NodeId],
+        dialect: HtmlDialect,
+        out: &mut Vec<C>,
+    ) {
+        // Keep our own stack of what is left to write rather than recursing,
+        // so that content nested however deeply cannot overflow the stack
+        let mut pending: Vec<_> =
+            ids.iter().rev().map(|&id| Pending::Node(id)).collect();
+        while let Some(next) = pending.pop() {
+            match next {
+                Pending::Node(id) => match self.node(id) {
+                    DomNode::Text(text) => {
+                        write_escaped(&text.data, false, out)
+                    }
+                    DomNode::Container(container) => {
+                        self.write_start_tag(container, dialect, out);
+                        if !container.is_void() {
+                            pending.push(Pending::EndTag(
+                                dialect.tag_name(&container.name),
+                            ));
+                            if container.is_block()
+                                && dialect.emits_placeholders()
+                                && self.ends_with_break(id)
+                            {
+                                // Without another break after it, the
+                                // last line would not be shown
+                                pending.push(Pending::Markup("<br />"));
+                            }
+                            pending.extend(
+                                container
+                                    .children
+                                    .iter()
+                                    .rev()
+                                    .map(|&child| Pending::Node(child)),
+                            );
+                        }
+                    }
+                },
+                Pending::EndTag(name) => {
+                    C::encode_into("</", out);
+                    C::encode_into(name, out);
+                    C::encode_into(">", out);
+                }
+                Pending::Markup(markup) => C::encode_into(markup, out),
+            }
+        }
+    }
+
+    fn write_start_tag(
+        &self,
+        container: &ContainerNode<C>,
+        dialect: HtmlDialect,
+        out: &mut Vec<C>,
+    ) {
+        let name = dialect.tag_name(&container.name);
+        C::encode_into("<", out);
+        C::encode_into(name, out);
+        for (attr_name, value) in &container.attrs {
+            C::encode_into(" ", out);
+            C::encode_into(attr_name, out);
+            C::encode_into("=\"", out);
+            write_escaped(value, true, out);
+            C::encode_into("\"", out);
+        }
+        if container.is_void() {
+            C::encode_into(" />", out);
+            return;
+        }
+        C::encode_into(">", out);
+        if container.children.is_empty()
+            && !container.is_block()
+            && dialect.emits_placeholders()
+        {
+            C::encode_into(PLACEHOLDER, out);
+        }
+    }
+}
+
+/**
+ * Something write_node has yet to write.
+ */
+enum Pending<'a> {
+    Node(NodeId),
+    EndTag(&'a str),
+    Markup(&'static str),
+}
+
+/**
+ * Append text to out, escaping characters that have a meaning in HTML.
+ */
+fn write_escaped<C>(text: &[C], in_attribute: bool, out: &mut Vec<C>)
+where
+    C: CodeUnit,
+{
+    for &c in text {
+        let escaped = match c.to_ascii() {
+            Some(b'&') => "&amp;",
+            Some(b'<') => "&lt;",
+            Some(b'>') => "&gt;",
+            Some(b'"') if in_attribute => "&quot;",
+            _ => {
+                out.push(c);
+                continue;
+            }
+        };
+        C::encode_into(escaped, out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::parse;
+    use crate::HtmlDialect;
+
+    fn to_html(html: &str, dialect: HtmlDialect) -> String {
+        let dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        String::from_utf16(&dom.to_html(dialect)).unwrap()
+    }
+
+    #[test]
+    fn matrix_message_dialect_uses_semantic_tags() {
+        assert_eq!(
+            to_html("<b>a</b><i>b</i>", HtmlDialect::MatrixMessage),
+            "<strong>a</strong><em>b</em>"
+        );
+    }
+
+    #[test]
+    fn preview_dialect_uses_presentational_tags() {
+        assert_eq!(
+            to_html("<strong>a</strong><em>b</em>", HtmlDialect::Preview),
+            "<b>a</b><i>b</i>"
+        );
+    }
+
+    #[test]
+    fn only_the_editor_dialect_emits_placeholders() {
+        let html = "a<strong></strong><p></p>";
+        assert_eq!(
+            to_html(html, HtmlDialect::Editor),
+            "a<strong>\u{200B}</strong><p></p>"
+        );
+        assert_eq!(
+            to_html(html, HtmlDialect::MatrixMessage),
+            "a<strong></strong><p></p>"
+        );
+        assert_eq!(to_html(html, HtmlDialect::Preview), "a<b></b><p></p>");
+    }
+
+    #[test]
+    fn text_and_attributes_are_escaped() {
+        assert_eq!(
+            to_html(
+                "<a href=\"&quot;&amp;\">&lt;&quot;</a>",
+                HtmlDialect::MatrixMessage
+            ),
+            "<a href=\"&quot;&amp;\">&lt;\"</a>"
+        );
+    }
+}
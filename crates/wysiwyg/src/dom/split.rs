@@ -0,0 +1,209 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use crate::dom::{Dom, DomNode, NodeId};
+use crate::CodeUnit;
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Split container in two at location, which must be inside it. The
+     * content after location moves into a new element with the same name
+     * and attributes, inserted just after container, which is returned.
+     * Text nodes and elements inside container that straddle location are
+     * split too, so the content that moves keeps its formatting.
+     */
+    pub(crate) fn split_at(
+        &mut self,
+        container: NodeId,
+        location: usize,
+    ) -> NodeId {
+        let spans: HashMap<NodeId, (usize, usize)> = self
+            .spans(container, self.start_of(container))
+            .into_iter()
+            .map(|span| (span.id, (span.start, span.end)))
+            .collect();
+        // Each element to split, from container inwards, with the children
+        // that move out of it after the second part of the next one
+        let mut levels: Vec<(NodeId, Vec<NodeId>)> = Vec::new();
+        let mut current = Some(container);
+        while let Some(element) = current.take() {
+            let mut moved = Vec::new();
+            for child in self.children(element).to_vec() {
+                let (start, end) = spans[&child];
+                if start >= location {
+                    moved.push(child);
+                } else if end > location {
+                    match self.node(child) {
+                        DomNode::Text(text) => {
+                            let rest = text.data[location - start..].to_vec();
+                            self.text_mut(child)
+                                .data
+                                .truncate(location - start);
+                            moved.push(self.create_text(rest));
+                        }
+                        DomNode::Container(_) => current = Some(child),
+                    }
+                }
+            }
+            levels.push((element, moved));
+        }
+        let mut second = None;
+        for (element, mut moved) in levels.into_iter().rev() {
+            if let Some(inner) = second {
+                moved.insert(0, inner);
+            }
+            let (name, attrs) = match self.node(element) {
+                DomNode::Container(c) => (c.name.clone(), c.attrs.clone()),
+                DomNode::Text(_) => panic!("Only containers can be split"),
+            };
+            second = Some(self.create_container(&name, attrs, moved));
+        }
+        let copy = second.expect("container was split");
+        let (parent, index) = self.index_in_parent(container);
+        self.insert_child(parent, index + 1, copy);
+        copy
+    }
+
+    /**
+     * Split each element called name that location is strictly inside, so
+     * that location falls on a boundary between two such elements.
+     */
+    pub(crate) fn split_elements_at(&mut self, location: usize, name: &str) {
+        while let Some(id) = self.element_at(location, name) {
+            let start = self.start_of(id);
+            if location <= start || start + self.node_len(id) <= location {
+                break;
+            }
+            self.split_at(id, location);
+        }
+    }
+
+    /**
+     * Insert new_text at the start of container, inside whatever
+     * formatting its content starts with. Unlike insert_text at the same
+     * location, this never adds to the content before container.
+     */
+    pub(crate) fn insert_text_at_start(
+        &mut self,
+        container: NodeId,
+        new_text: &[C],
+    ) {
+        if new_text.is_empty() {
+            return;
+        }
+        let mut parent = container;
+        while let Some(&first) = self.children(parent).first() {
+            match self.node(first) {
+                DomNode::Text(_) => {
+                    let data = &mut self.text_mut(first).data;
+                    data.splice(0..0, new_text.iter().copied());
+                    return;
+                }
+                DomNode::Container(c) if !c.is_void() => parent = first,
+                DomNode::Container(_) => break,
+            }
+        }
+        let text = self.create_text(new_text.to_vec());
+        self.insert_child(parent, 0, text);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::{parse, Dom};
+    use crate::HtmlDialect;
+
+    fn dom(html: &str) -> Dom<u16> {
+        parse(&utf16(html))
+    }
+
+    fn html(dom: &Dom<u16>) -> String {
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    fn utf16(text: &str) -> Vec<u16> {
+        text.encode_utf16().collect()
+    }
+
+    fn first_child(dom: &Dom<u16>) -> crate::dom::NodeId {
+        dom.children(dom.document())[0]
+    }
+
+    #[test]
+    fn splitting_moves_the_content_after_the_location() {
+        let mut d = dom("<p>abcd</p>");
+        let p = first_child(&d);
+        d.split_at(p, 2);
+        assert_eq!(html(&d), "<p>ab</p><p>cd</p>");
+    }
+
+    #[test]
+    fn splitting_carries_formatting_across() {
+        let mut d = dom("<ul><li>a<strong>b<em>cd</em></strong>e</li></ul>");
+        let li = d.children(first_child(&d))[0];
+        d.split_at(li, 3);
+        assert_eq!(
+            html(&d),
+            "<ul><li>a<strong>b<em>c</em></strong></li>\
+            <li><strong><em>d</em></strong>e</li></ul>"
+        );
+    }
+
+    #[test]
+    fn splitting_at_the_edges_leaves_an_empty_half() {
+        let mut d = dom("<p>ab</p>");
+        let p = first_child(&d);
+        d.split_at(p, 2);
+        assert_eq!(html(&d), "<p>ab</p><p></p>");
+
+        let mut d = dom("<p>ab</p>");
+        let p = first_child(&d);
+        d.split_at(p, 0);
+        assert_eq!(html(&d), "<p></p><p>ab</p>");
+    }
+
+    #[test]
+    fn splitting_keeps_attributes() {
+        let mut d = dom("<a href=\"x\">ab</a>");
+        let a = first_child(&d);
+        d.split_at(a, 1);
+        assert_eq!(html(&d), "<a href=\"x\">a</a><a href=\"x\">b</a>");
+    }
+
+    #[test]
+    fn splitting_elements_only_splits_those_around_the_location() {
+        let mut d = dom("<a href=\"x\">ab</a><em>cd</em>");
+        d.split_elements_at(1, "a");
+        d.split_elements_at(3, "a");
+        d.split_elements_at(2, "a");
+        assert_eq!(
+            html(&d),
+            "<a href=\"x\">a</a><a href=\"x\">b</a><em>cd</em>"
+        );
+    }
+
+    #[test]
+    fn text_inserted_at_the_start_goes_inside_the_first_formatting() {
+        let mut d = dom("<p>a</p><p><strong>b</strong></p><p></p>");
+        let blocks = d.children(d.document()).to_vec();
+        d.insert_text_at_start(blocks[1], &utf16("x"));
+        d.insert_text_at_start(blocks[2], &utf16("y"));
+        assert_eq!(html(&d), "<p>a</p><p><strong>xb</strong></p><p>y</p>");
+    }
+}
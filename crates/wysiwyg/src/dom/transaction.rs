@@ -0,0 +1,259 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+use std::mem::size_of;
+
+use crate::dom::{next_revision, Atom, Dom, DomNode, NodeId, Slot};
+
+/**
+ * The changes made to a Dom by some operations, recorded as the previous
+ * contents of only the slots that changed, so that they can be undone
+ * precisely without keeping a copy of the whole Dom.
+ */
+#[derive(Clone, Debug)]
+pub struct Transaction<C> {
+    /**
+     * The previous contents of each slot touched, or None if the slot did
+     * not exist.
+     */
+    slots: Vec<(u32, Option<Slot<C>>)>,
+    slots_len: usize,
+    free: Vec<u32>,
+}
+
+/**
+ * A Transaction being recorded.
+ */
+#[derive(Clone, Debug)]
+pub(super) struct Journal<C> {
+    transaction: Transaction<C>,
+    touched: HashSet<u32>,
+}
+
+impl<C> Transaction<C> {
+    /**
+     * Combine this with later, the transaction recorded straight after it,
+     * into one that reverts both.
+     */
+    pub fn followed_by(mut self, later: Transaction<C>) -> Self {
+        let touched: HashSet<u32> =
+            self.slots.iter().map(|(index, _)| *index).collect();
+        self.slots.extend(
+            later
+                .slots
+                .into_iter()
+                .filter(|(index, _)| !touched.contains(index)),
+        );
+        self
+    }
+
+    /**
+     * The number of nodes whose previous state this records.
+     */
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /**
+     * Roughly how much memory this takes up, in bytes, counting the
+     * recorded nodes and their text, attributes and children.
+     */
+    pub fn size_in_bytes(&self) -> usize {
+        let slot_size = size_of::<(u32, Option<Slot<C>>)>();
+        let nodes: usize = self
+            .slots
+            .iter()
+            .filter_map(|(_, slot)| slot.as_ref()?.entry.as_ref())
+            .map(|entry| match &entry.node {
+                DomNode::Container(container) => {
+                    let attrs: usize = container
+                        .attrs
+                        .iter()
+                        .map(|(name, value)| {
+                            size_of::<(Atom, Vec<C>)>()
+                                + name.len()
+                                + value.len() * size_of::<C>()
+                        })
+                        .sum();
+                    attrs + container.children.len() * size_of::<NodeId>()
+                }
+                DomNode::Text(text) => text.data.len() * size_of::<C>(),
+            })
+            .sum();
+        size_of::<Self>()
+            + self.slots.len() * slot_size
+            + nodes
+            + self.free.len() * size_of::<u32>()
+    }
+}
+
+impl<C> Dom<C>
+where
+    C: Clone,
+{
+    /**
+     * Start recording all changes to this Dom, until end_transaction is
+     * called.
+     */
+    pub(crate) fn start_transaction(&mut self) {
+        self.journal = Some(Journal {
+            transaction: Transaction {
+                slots: Vec::new(),
+                slots_len: self.slots.len(),
+                free: self.free.clone(),
+            },
+            touched: HashSet::new(),
+        });
+    }
+
+    /**
+     * Stop recording changes, and return them unless there were none.
+     */
+    pub(crate) fn end_transaction(&mut self) -> Option<Transaction<C>> {
+        self.journal
+            .take()
+            .map(|journal| journal.transaction)
+            .filter(|transaction| !transaction.is_empty())
+    }
+
+    /**
+     * Undo the changes recorded in transaction, which must be the most
+     * recent changes not yet reverted. Returns a transaction that reverts
+     * this revert, i.e. redoes the changes.
+     */
+    pub(crate) fn revert(
+        &mut self,
+        transaction: Transaction<C>,
+    ) -> Transaction<C> {
+        self.revision = next_revision();
+        let mut inverse = Transaction {
+            slots: Vec::with_capacity(transaction.slots.len()),
+            slots_len: self.slots.len(),
+            free: std::mem::replace(&mut self.free, transaction.free),
+        };
+        for (index, previous) in transaction.slots {
+            let i = index as usize;
+            if i >= self.slots.len() {
+                self.slots.resize_with(i + 1, || Slot {
+                    generation: 0,
+                    entry: None,
+                });
+                inverse.slots.push((index, None));
+            } else {
+                inverse.slots.push((index, Some(self.slots[i].clone())));
+            }
+            if let Some(previous) = previous {
+                self.slots[i] = previous;
+            }
+        }
+        self.slots.truncate(transaction.slots_len);
+        inverse
+    }
+
+    /**
+     * Note that slot index is about to change: take a new revision, and
+     * record the current contents of the slot if a transaction is being
+     * recorded and it has not already recorded this slot.
+     */
+    pub(super) fn touch(&mut self, index: u32) {
+        self.revision = next_revision();
+        if let Some(journal) = &mut self.journal {
+            if journal.touched.insert(index) {
+                let previous = self.slots.get(index as usize).cloned();
+                journal.transaction.slots.push((index, previous));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::parse;
+    use crate::HtmlDialect;
+
+    fn html(dom: &crate::dom::Dom<u16>) -> String {
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    #[test]
+    fn reverting_a_transaction_restores_the_content() {
+        let mut dom =
+            parse(&"a<em>bc</em>d".encode_utf16().collect::<Vec<_>>());
+        dom.start_transaction();
+        dom.wrap_inline_range(2, 4, "strong");
+        dom.delete_range(0, 1);
+        let transaction = dom.end_transaction().unwrap();
+        assert_eq!(
+            html(&dom),
+            "<em>b<strong>c</strong></em><strong>d</strong>"
+        );
+
+        let redo = dom.revert(transaction);
+        assert_eq!(html(&dom), "a<em>bc</em>d");
+        dom.revert(redo);
+        assert_eq!(
+            html(&dom),
+            "<em>b<strong>c</strong></em><strong>d</strong>"
+        );
+    }
+
+    #[test]
+    fn transactions_only_record_the_nodes_that_changed() {
+        let html = "<p>a</p>".repeat(100) + "<p>b</p>";
+        let mut dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        dom.start_transaction();
+        dom.wrap_inline_range(100, 101, "strong");
+        let transaction = dom.end_transaction().unwrap();
+        assert!(transaction.len() < 5);
+    }
+
+    #[test]
+    fn a_transaction_without_changes_is_not_returned() {
+        let mut dom = parse(&"a".encode_utf16().collect::<Vec<_>>());
+        dom.start_transaction();
+        assert!(dom.end_transaction().is_none());
+    }
+
+    #[test]
+    fn the_revision_changes_with_the_content() {
+        let mut dom = parse(&"a".encode_utf16().collect::<Vec<_>>());
+        let unchanged = dom.revision();
+        html(&dom);
+        assert_eq!(dom.revision(), unchanged);
+        assert_eq!(dom.clone().revision(), unchanged);
+
+        dom.start_transaction();
+        dom.insert_text(1, &"b".encode_utf16().collect::<Vec<_>>());
+        let transaction = dom.end_transaction().unwrap();
+        let edited = dom.revision();
+        assert_ne!(edited, unchanged);
+
+        dom.revert(transaction);
+        assert_ne!(dom.revision(), edited);
+        assert_ne!(dom.revision(), unchanged);
+    }
+
+    #[test]
+    fn separate_doms_have_separate_revisions() {
+        let a = parse(&"a".encode_utf16().collect::<Vec<_>>());
+        let b = parse(&"a".encode_utf16().collect::<Vec<_>>());
+        assert_ne!(a.revision(), b.revision());
+    }
+}
@@ -0,0 +1,452 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use crate::dom::{Dom, DomNode, NodeId};
+use crate::CodeUnit;
+
+#[derive(Clone, Copy)]
+enum Edge {
+    Start,
+    End,
+}
+
+impl<C> Dom<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Remove whitespace, line breaks and empty elements from the start and
+     * end of the content, so that nothing invisible is left around it.
+     */
+    pub(crate) fn trim(&mut self) {
+        for edge in [Edge::Start, Edge::End] {
+            while let Some(leaf) = self.edge_leaf(self.document(), edge) {
+                if !self.trim_leaf(leaf, edge) {
+                    break;
+                }
+            }
+        }
+    }
+
+    /**
+     * Unwrap formatting elements at the start or end of the content that
+     * cover nothing but whitespace and line breaks, and remove empty items
+     * from the end of every list, so that formats toggled or pasted at the
+     * edges of a message do not leave invisible markup in it. Unlike trim,
+     * the whitespace itself is kept.
+     */
+    pub(crate) fn drop_edge_formatting(&mut self) {
+        // Unwrapping and removing blank nodes leaves every other node as
+        // blank as it was
+        let blank = self.blank_nodes();
+        for edge in [Edge::Start, Edge::End] {
+            self.unwrap_blank_formatting(self.document(), edge, &blank);
+        }
+        self.remove_blank_trailing_items(self.document(), &blank);
+    }
+
+    /**
+     * Remove inline elements (formatting and links) that contain no text
+     * or void elements, such as those left behind when all of their text
+     * is deleted. Empty blocks, e.g. list items, are kept, since the
+     * cursor can still be in them.
+     */
+    pub(crate) fn remove_empty_formatting(&mut self) {
+        self.remove_empty_inline(self.document());
+    }
+
+    pub(super) fn remove_empty_inline(&mut self, container: NodeId) {
+        // Each element after those inside it, which may leave it empty
+        for id in self.descendants(container).into_iter().rev() {
+            let is_inline = match self.node(id) {
+                DomNode::Container(c) if !c.is_void() => !c.is_block(),
+                _ => continue,
+            };
+            let empty = self.children(id).iter().all(|&child| {
+                matches!(
+                    self.node(child),
+                    DomNode::Text(text) if text.data().is_empty()
+                )
+            });
+            if is_inline && empty {
+                self.remove(id);
+            }
+        }
+    }
+
+    fn unwrap_blank_formatting(
+        &mut self,
+        container: NodeId,
+        edge: Edge,
+        blank: &HashMap<NodeId, bool>,
+    ) {
+        let mut container = container;
+        loop {
+            let mut children = self.children(container).to_vec();
+            if let Edge::End = edge {
+                children.reverse();
+            }
+            let child = children.into_iter().find(|&child| {
+                !matches!(self.node(child), DomNode::Text(_)) || !blank[&child]
+            });
+            let child = match child {
+                Some(child) => child,
+                None => return,
+            };
+            match self.node(child) {
+                DomNode::Container(c)
+                    if c.name.element().map_or(false, |spec| spec.format)
+                        && blank[&child] =>
+                {
+                    let mut new_children = self.children(container).to_vec();
+                    let i =
+                        new_children.iter().position(|&c| c == child).unwrap();
+                    let content = self.children(child).to_vec();
+                    new_children.splice(i..=i, content);
+                    self.set_children(container, new_children);
+                    self.remove(child);
+                }
+                DomNode::Container(c) if !c.is_void() => container = child,
+                _ => return,
+            }
+        }
+    }
+
+    fn remove_blank_trailing_items(
+        &mut self,
+        container: NodeId,
+        blank: &HashMap<NodeId, bool>,
+    ) {
+        // Each list after those inside it, whose removal may leave it blank
+        for id in self.descendants(container).into_iter().rev() {
+            let is_list = matches!(
+                self.node(id),
+                DomNode::Container(c) if matches!(c.name(), "ol" | "ul")
+            );
+            if is_list {
+                while let Some(&last) = self.children(id).last() {
+                    if !blank[&last] {
+                        break;
+                    }
+                    self.remove(last);
+                }
+                if self.children(id).is_empty() {
+                    self.remove(id);
+                }
+            }
+        }
+    }
+
+    /**
+     * Whether the content shows nothing, e.g. so that a send button can be
+     * disabled. Zero-width characters (such as the placeholders in the
+     * editor dialect), empty paragraphs and a line break that is alone in
+     * its block never count as content. Other whitespace and line breaks
+     * count unless ignore_whitespace is true.
+     */
+    pub fn is_empty(&self, ignore_whitespace: bool) -> bool {
+        let document = self.document();
+        // Whether each node shows nothing, found for each element after
+        // the nodes inside it
+        let mut nothing: HashMap<NodeId, bool> = HashMap::new();
+        for id in self.descendants(document).into_iter().rev() {
+            nothing.insert(
+                id,
+                self.shows_nothing(id, ignore_whitespace, &nothing),
+            );
+        }
+        self.shows_nothing(document, ignore_whitespace, &nothing)
+    }
+
+    /**
+     * Whether id shows nothing, given whether each of its children does.
+     */
+    fn shows_nothing(
+        &self,
+        id: NodeId,
+        ignore_whitespace: bool,
+        nothing: &HashMap<NodeId, bool>,
+    ) -> bool {
+        match self.node(id) {
+            DomNode::Text(text) => C::decode(text.data()).chars().all(|c| {
+                is_zero_width(c) || (ignore_whitespace && c.is_whitespace())
+            }),
+            DomNode::Container(c) if c.name == "br" => ignore_whitespace,
+            DomNode::Container(c) if c.is_void() => false,
+            DomNode::Container(c) => {
+                let shown: Vec<NodeId> = c
+                    .children
+                    .iter()
+                    .copied()
+                    .filter(|child| !nothing[child])
+                    .collect();
+                match shown[..] {
+                    [] => true,
+                    // A line break alone in its block only gives it height
+                    [only] => {
+                        (id == self.document() || c.is_block())
+                            && matches!(
+                                self.node(only),
+                                DomNode::Container(c) if c.name == "br"
+                            )
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    /**
+     * Whether each node inside the document contains nothing visible: only
+     * whitespace, line breaks and empty elements.
+     */
+    fn blank_nodes(&self) -> HashMap<NodeId, bool> {
+        let mut blank: HashMap<NodeId, bool> = HashMap::new();
+        // Each element after the nodes inside it
+        for id in self.descendants(self.document()).into_iter().rev() {
+            let is_blank = match self.node(id) {
+                DomNode::Text(text) => {
+                    text.data().iter().all(|&c| is_whitespace(c))
+                }
+                DomNode::Container(c) if c.is_void() => c.name == "br",
+                DomNode::Container(c) => {
+                    c.children.iter().all(|child| blank[child])
+                }
+            };
+            blank.insert(id, is_blank);
+        }
+        blank
+    }
+
+    /**
+     * The first or last text node or childless element inside container.
+     */
+    fn edge_leaf(&self, container: NodeId, edge: Edge) -> Option<NodeId> {
+        let mut container = container;
+        loop {
+            let children = self.children(container);
+            let child = *match edge {
+                Edge::Start => children.first(),
+                Edge::End => children.last(),
+            }?;
+            match self.node(child) {
+                DomNode::Container(c) if !c.children.is_empty() => {
+                    container = child
+                }
+                _ => return Some(child),
+            }
+        }
+    }
+
+    /**
+     * Trim leaf from the edge of the content, returning true if it was
+     * removed entirely, so that trimming should continue with the next one.
+     */
+    fn trim_leaf(&mut self, leaf: NodeId, edge: Edge) -> bool {
+        match self.node(leaf) {
+            DomNode::Text(text) => {
+                let data = text.data();
+                let kept = match edge {
+                    Edge::Start => data
+                        .iter()
+                        .position(|&c| !is_whitespace(c))
+                        .map(|i| data[i..].to_vec()),
+                    Edge::End => data
+                        .iter()
+                        .rposition(|&c| !is_whitespace(c))
+                        .map(|i| data[..=i].to_vec()),
+                };
+                match kept {
+                    Some(kept) => {
+                        if kept.len() != data.len() {
+                            self.text_mut(leaf).data = kept;
+                        }
+                        false
+                    }
+                    None => {
+                        self.remove_with_empty_ancestors(leaf);
+                        true
+                    }
+                }
+            }
+            DomNode::Container(c) if c.is_void() && c.name != "br" => false,
+            DomNode::Container(_) => {
+                self.remove_with_empty_ancestors(leaf);
+                true
+            }
+        }
+    }
+
+    fn remove_with_empty_ancestors(&mut self, id: NodeId) {
+        let mut id = id;
+        while let Some(parent) = self.parent(id) {
+            if parent == self.document() || self.children(parent).len() > 1 {
+                break;
+            }
+            id = parent;
+        }
+        self.remove(id);
+    }
+}
+
+fn is_whitespace<C>(c: C) -> bool
+where
+    C: CodeUnit,
+{
+    c.to_ascii()
+        .map_or(false, |ascii| ascii.is_ascii_whitespace())
+}
+
+fn is_zero_width(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}'
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::parse;
+    use crate::HtmlDialect;
+
+    fn trimmed(html: &str) -> String {
+        let mut dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        dom.trim();
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    fn without_edge_formatting(html: &str) -> String {
+        let mut dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        dom.drop_edge_formatting();
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap()
+    }
+
+    fn is_empty(html: &str, ignore_whitespace: bool) -> bool {
+        parse(&html.encode_utf16().collect::<Vec<_>>())
+            .is_empty(ignore_whitespace)
+    }
+
+    #[test]
+    fn placeholders_and_empty_paragraphs_are_empty() {
+        for html in ["", "<br />", "<p></p><p><br /></p>", "<em>\u{200B}</em>"]
+        {
+            assert!(is_empty(html, false), "{}", html);
+        }
+    }
+
+    #[test]
+    fn whitespace_is_empty_only_if_ignored() {
+        for html in [" ", "\u{a0}", "<br /><br />", "<p> </p>"] {
+            assert!(!is_empty(html, false), "{}", html);
+            assert!(is_empty(html, true), "{}", html);
+        }
+    }
+
+    #[test]
+    fn text_and_void_elements_are_content() {
+        for html in ["a", "<p><br /></p><p>a</p>", "<img src=\"x\" />"] {
+            assert!(!is_empty(html, true), "{}", html);
+        }
+    }
+
+    #[test]
+    fn whitespace_around_text_is_removed() {
+        assert_eq!(trimmed("  a b \n"), "a b");
+    }
+
+    #[test]
+    fn trailing_line_breaks_are_removed() {
+        assert_eq!(trimmed("a<br />b<br /><br />"), "a<br />b");
+    }
+
+    #[test]
+    fn empty_paragraphs_at_either_end_are_removed() {
+        assert_eq!(
+            trimmed(
+                "<p></p><p> <br /></p><p>a</p><p>b</p><p>\u{a0}</p><p></p>"
+            ),
+            "<p>a</p><p>b</p><p>\u{a0}</p>"
+        );
+    }
+
+    #[test]
+    fn whitespace_inside_formatting_is_removed() {
+        assert_eq!(
+            trimmed("<em> </em><strong> a</strong> "),
+            "<strong>a</strong>"
+        );
+    }
+
+    #[test]
+    fn images_are_kept() {
+        assert_eq!(
+            trimmed(" <img src=\"mxc://a/b\" /> "),
+            "<img src=\"mxc://a/b\" />"
+        );
+    }
+
+    #[test]
+    fn whitespace_only_content_becomes_empty() {
+        assert_eq!(trimmed(" <br /> <p> </p>"), "");
+    }
+
+    #[test]
+    fn formatting_around_whitespace_at_the_edges_is_unwrapped() {
+        assert_eq!(
+            without_edge_formatting(
+                "<em> </em><strong> <br /></strong>a <u>b</u><del> </del>"
+            ),
+            "  <br />a <u>b</u> "
+        );
+    }
+
+    #[test]
+    fn formatting_inside_blocks_at_the_edges_is_unwrapped() {
+        assert_eq!(
+            without_edge_formatting(
+                "<p><strong> </strong>a</p><p><em> </em>b<em> </em></p>"
+            ),
+            "<p> a</p><p><em> </em>b </p>"
+        );
+    }
+
+    #[test]
+    fn empty_inline_elements_are_removed_but_empty_blocks_kept() {
+        let mut dom = parse(
+            &"<del></del><em><strong></strong></em>a<a href=\"x\"></a>\
+            <ul><li><em></em></li></ul><em> </em><strong><br /></strong>"
+                .encode_utf16()
+                .collect::<Vec<_>>(),
+        );
+        dom.remove_empty_formatting();
+        assert_eq!(
+            String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage))
+                .unwrap(),
+            "a<ul><li></li></ul><em> </em><strong><br /></strong>"
+        );
+    }
+
+    #[test]
+    fn empty_items_at_the_end_of_lists_are_removed() {
+        assert_eq!(
+            without_edge_formatting(
+                "<ul><li>a</li><li></li><li> <br /></li></ul>\
+                <ol><li></li></ol>b"
+            ),
+            "<ul><li>a</li></ul>b"
+        );
+    }
+}
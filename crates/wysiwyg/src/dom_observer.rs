@@ -0,0 +1,45 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::DomHandle;
+
+/**
+ * A change to the content, described in terms of a platform DOM built from
+ * the Editor HTML, so that it can be applied to that DOM directly instead
+ * of replacing it. See ComposerModel::subscribe.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DomEvent<C> {
+    /**
+     * A node was inserted at handle. html is the node, with its
+     * descendants, as Editor HTML.
+     */
+    NodeInserted { handle: DomHandle, html: Vec<C> },
+    /**
+     * The node at handle was removed, with its descendants.
+     */
+    NodeRemoved { handle: DomHandle },
+    /**
+     * The text of the text node at handle changed to new_text.
+     */
+    TextChanged { handle: DomHandle, new_text: Vec<C> },
+}
+
+/**
+ * Receives the DomEvents describing each change to the content of a
+ * ComposerModel it is subscribed to.
+ */
+pub trait DomObserver<C>: Send {
+    fn on_event(&mut self, event: &DomEvent<C>);
+}
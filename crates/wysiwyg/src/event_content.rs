@@ -0,0 +1,177 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/*!
+ * The content of the composer as the content of an m.room.message event,
+ * so that clients need not assemble body, formatted_body and m.mentions
+ * themselves, and an event's content back into the composer to edit it.
+ */
+
+use serde_json::{json, Map, Value};
+
+use crate::dom::{plain_text_to_html, DomNode};
+use crate::{CodeUnit, ComposerModel, RestoreOptions, RestoredMessage};
+
+/**
+ * The value of format for a formatted_body in HTML.
+ */
+const HTML_FORMAT: &str = "org.matrix.custom.html";
+
+impl<C> ComposerModel<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * The content as the JSON content of an m.room.message event: a body
+     * with the plain text fallback formatting, a formatted_body if the
+     * content has any formatting, and the users mentioned in m.mentions.
+     */
+    pub fn to_event_content(&self) -> String {
+        let dom = self.message_dom();
+        let body = self.to_message_dom(self.plain_text_dom(true));
+        let mut content = Map::new();
+        content.insert(String::from("msgtype"), json!("m.text"));
+        content.insert(
+            String::from("body"),
+            json!(C::decode(&body.to_plain_text(true))),
+        );
+        let formatted = dom
+            .descendants(dom.document())
+            .into_iter()
+            .any(|id| matches!(dom.node(id), DomNode::Container(_)));
+        if formatted {
+            content.insert(String::from("format"), json!(HTML_FORMAT));
+            content.insert(
+                String::from("formatted_body"),
+                json!(C::decode(&self.get_content_as_message_html())),
+            );
+        }
+        let user_ids: Vec<String> = self
+            .mentioned_user_ids()
+            .iter()
+            .map(|id| C::decode(id))
+            .collect();
+        let mentions = if user_ids.is_empty() {
+            json!({})
+        } else {
+            json!({ "user_ids": user_ids })
+        };
+        content.insert(String::from("m.mentions"), mentions);
+        Value::Object(content).to_string()
+    }
+
+    /**
+     * Replace all the content with that of an m.room.message event given
+     * as JSON, e.g. to edit it, as set_content_from_message_html does. The
+     * JSON can be the event or just its content; for an edit, its
+     * m.new_content is used. Without an HTML formatted_body, the body is
+     * taken as plain text. An error, changing nothing, if the JSON is not
+     * an object with a body.
+     */
+    pub fn from_event_content(
+        &mut self,
+        json: &str,
+        options: &RestoreOptions,
+    ) -> Result<RestoredMessage<C>, String> {
+        let event: Value =
+            serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let mut content = event.get("content").unwrap_or(&event);
+        if let Some(new_content) = content.get("m.new_content") {
+            content = new_content;
+        }
+        let body = content
+            .get("body")
+            .and_then(Value::as_str)
+            .ok_or_else(|| String::from("The content has no body"))?;
+        let format = content.get("format").and_then(Value::as_str);
+        let formatted_body =
+            content.get("formatted_body").and_then(Value::as_str);
+        let html = match (format, formatted_body) {
+            (Some(HTML_FORMAT), Some(html)) => String::from(html),
+            _ => plain_text_to_html(body),
+        };
+        Ok(self.set_content_from_message_html(&C::encode(&html), options))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::{json, Value};
+
+    use crate::{ComposerModel, RestoreOptions};
+
+    fn model(html: &str) -> ComposerModel<u16> {
+        let mut model = ComposerModel::new();
+        model.set_content_from_html(&html.encode_utf16().collect::<Vec<_>>());
+        model
+    }
+
+    fn content(model: &ComposerModel<u16>) -> Value {
+        serde_json::from_str(&model.to_event_content()).unwrap()
+    }
+
+    fn html(model: &ComposerModel<u16>) -> String {
+        String::from_utf16(&model.get_content_as_message_html()).unwrap()
+    }
+
+    #[test]
+    fn plain_content_has_only_a_body() {
+        assert_eq!(
+            content(&model("a &amp; b")),
+            json!({ "msgtype": "m.text", "body": "a & b", "m.mentions": {} })
+        );
+    }
+
+    #[test]
+    fn formatted_content_has_a_formatted_body_and_mentions() {
+        let model = model(
+            "<strong>hi</strong> <a href=\"https://matrix.to/#/@c:d\" \
+            data-mention-id=\"@c:d\">Cat</a>",
+        );
+        let content = content(&model);
+        assert_eq!(content["body"], json!("**hi** Cat"));
+        assert_eq!(content["format"], json!("org.matrix.custom.html"));
+        assert_eq!(content["formatted_body"], json!(html(&model)));
+        assert_eq!(content["m.mentions"], json!({ "user_ids": ["@c:d"] }));
+    }
+
+    #[test]
+    fn event_content_can_be_loaded_for_editing() {
+        let mut model = model("");
+        let json = json!({
+            "type": "m.room.message",
+            "content": {
+                "body": "* new",
+                "m.new_content": {
+                    "body": "new",
+                    "format": "org.matrix.custom.html",
+                    "formatted_body": "<em>new</em>",
+                },
+            },
+        });
+        model
+            .from_event_content(&json.to_string(), &RestoreOptions::default())
+            .unwrap();
+        assert_eq!(html(&model), "<em>new</em>");
+        let json = json!({ "body": "a\nb <c>" });
+        model
+            .from_event_content(&json.to_string(), &RestoreOptions::default())
+            .unwrap();
+        assert_eq!(html(&model), "a<br />b &lt;c&gt;");
+        assert!(model
+            .from_event_content("[]", &RestoreOptions::default())
+            .is_err());
+        assert_eq!(html(&model), "a<br />b &lt;c&gt;");
+    }
+}
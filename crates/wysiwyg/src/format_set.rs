@@ -0,0 +1,204 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::{element_spec, Dom};
+use crate::{CodeUnit, Feature};
+
+/**
+ * An inline format that can be applied to text. The order of the variants
+ * is the order formats are nested in when several are applied at once,
+ * outermost first, which is also the order pasted styles become formats.
+ */
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Format {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Superscript,
+    Subscript,
+    InlineCode,
+}
+
+impl Format {
+    const ALL: [Format; 7] = [
+        Self::Bold,
+        Self::Italic,
+        Self::Underline,
+        Self::Strikethrough,
+        Self::Superscript,
+        Self::Subscript,
+        Self::InlineCode,
+    ];
+
+    /**
+     * A name for the format, e.g. "inline-code", for platforms that pass
+     * formats by name.
+     */
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Bold => "bold",
+            Self::Italic => "italic",
+            Self::Underline => "underline",
+            Self::Strikethrough => "strikethrough",
+            Self::Superscript => "superscript",
+            Self::Subscript => "subscript",
+            Self::InlineCode => "inline-code",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.name() == name)
+    }
+
+    pub fn element_name(&self) -> &'static str {
+        match self {
+            Self::Bold => "strong",
+            Self::Italic => "em",
+            Self::Underline => "u",
+            Self::Strikethrough => "del",
+            Self::Superscript => "sup",
+            Self::Subscript => "sub",
+            Self::InlineCode => "code",
+        }
+    }
+
+    /**
+     * The feature that must be enabled for this format to be applied, if
+     * there is one.
+     */
+    pub(crate) fn feature(&self) -> Option<Feature> {
+        element_spec(self.element_name()).and_then(|spec| spec.feature)
+    }
+}
+
+/**
+ * The elements that a location or range is inside, outermost first and
+ * each only once.
+ */
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FormatSet {
+    names: Vec<String>,
+}
+
+impl FormatSet {
+    /**
+     * The elements that all the text in start..end is inside, or that text
+     * typed at start would be inside if the range is empty.
+     */
+    pub fn around<C>(dom: &Dom<C>, start: usize, end: usize) -> Self
+    where
+        C: CodeUnit,
+    {
+        let mut names: Vec<String> = Vec::new();
+        for name in dom.elements_around(start, end).into_iter().rev() {
+            if !names.iter().any(|n| n == name) {
+                names.push(String::from(name));
+            }
+        }
+        Self { names }
+    }
+
+    /**
+     * The elements that any of the text in start..end is inside, so that
+     * those not in FormatSet::around only cover part of the range.
+     */
+    pub fn within<C>(dom: &Dom<C>, start: usize, end: usize) -> Self
+    where
+        C: CodeUnit,
+    {
+        let mut names: Vec<String> = Vec::new();
+        for name in dom.elements_within(start, end) {
+            if !names.iter().any(|n| n == name) {
+                names.push(String::from(name));
+            }
+        }
+        Self { names }
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /**
+     * Only the elements in this set that are inline formatting, such as
+     * strong and em, rather than links, lists and blocks.
+     */
+    pub fn inline_formats(&self) -> Self {
+        Self {
+            names: self
+                .names
+                .iter()
+                .filter(|name| {
+                    element_spec(name).map_or(false, |spec| spec.format)
+                })
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dom::parse;
+
+    use super::{Format, FormatSet};
+
+    fn formats(html: &str, start: usize, end: usize) -> Vec<String> {
+        let dom = parse(&html.encode_utf16().collect::<Vec<_>>());
+        FormatSet::around(&dom, start, end).names().to_vec()
+    }
+
+    #[test]
+    fn formats_are_listed_outermost_first_without_duplicates() {
+        assert_eq!(
+            formats("<em><strong>a<em>b</em></strong></em>", 1, 2),
+            vec!["em", "strong"]
+        );
+    }
+
+    #[test]
+    fn formats_are_found_by_name_and_are_inline_formats() {
+        for format in Format::ALL {
+            assert_eq!(Format::from_name(format.name()), Some(format));
+            let dom = parse(
+                &format!("<{0}>a</{0}>", format.element_name())
+                    .encode_utf16()
+                    .collect::<Vec<_>>(),
+            );
+            assert_eq!(
+                FormatSet::around(&dom, 0, 1).inline_formats().names(),
+                [format.element_name()]
+            );
+        }
+        assert_eq!(Format::from_name("strong"), None);
+    }
+
+    #[test]
+    fn inline_formats_leave_out_links_and_blocks() {
+        let dom = parse(
+            &"<ul><li><a href=\"x\"><u>a</u></a></li></ul>"
+                .encode_utf16()
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(
+            FormatSet::around(&dom, 0, 1).inline_formats().names(),
+            ["u"]
+        );
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/*!
+ * An approximation of Unicode extended grapheme clusters (what a user
+ * thinks of as one character), good enough for counting characters the way
+ * a user would. It keeps together: combining marks and variation selectors
+ * with the character before them, emoji joined by zero-width joiners, emoji
+ * with their skin tone modifiers and tags, pairs of regional indicators
+ * (flags), Hangul jamo, and "\r\n".
+ */
+
+/**
+ * The number of grapheme clusters in text.
+ */
+pub(crate) fn grapheme_count(text: &str) -> usize {
+    let mut count = 0;
+    let mut previous: Option<char> = None;
+    let mut regional_indicators = 0;
+    for ch in text.chars() {
+        let continues = match previous {
+            Some(prev) => continues_cluster(prev, ch, regional_indicators),
+            None => false,
+        };
+        if !continues {
+            count += 1;
+        }
+        regional_indicators = if is_regional_indicator(ch) {
+            if continues {
+                0
+            } else {
+                1
+            }
+        } else {
+            0
+        };
+        previous = Some(ch);
+    }
+    count
+}
+
+/**
+ * Whether ch belongs to the same cluster as prev, which comes just before
+ * it. regional_indicators is 1 if prev is a regional indicator that starts
+ * a new flag.
+ */
+fn continues_cluster(prev: char, ch: char, regional_indicators: usize) -> bool {
+    (prev == '\r' && ch == '\n')
+        || is_extend(ch)
+        || ch == ZWJ
+        || (prev == ZWJ && is_pictographic(ch))
+        || (regional_indicators == 1 && is_regional_indicator(ch))
+        || continues_hangul(prev, ch)
+}
+
+const ZWJ: char = '\u{200D}';
+
+pub(crate) fn is_extend(ch: char) -> bool {
+    matches!(ch,
+        '\u{0300}'..='\u{036F}'
+        | '\u{0483}'..='\u{0489}'
+        | '\u{0591}'..='\u{05BD}'
+        | '\u{0610}'..='\u{061A}'
+        | '\u{064B}'..='\u{065F}'
+        | '\u{0900}'..='\u{0903}'
+        | '\u{093A}'..='\u{094F}'
+        | '\u{0E31}'
+        | '\u{0E34}'..='\u{0E3A}'
+        | '\u{0E47}'..='\u{0E4E}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}'
+        | '\u{1F3FB}'..='\u{1F3FF}'
+        | '\u{E0020}'..='\u{E007F}'
+        | '\u{E0100}'..='\u{E01EF}'
+    )
+}
+
+fn is_pictographic(ch: char) -> bool {
+    matches!(ch,
+        '\u{2600}'..='\u{27BF}'
+        | '\u{1F000}'..='\u{1FAFF}'
+    )
+}
+
+fn is_regional_indicator(ch: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&ch)
+}
+
+/**
+ * Whether the Hangul jamo ch continues a syllable ending with prev.
+ */
+fn continues_hangul(prev: char, ch: char) -> bool {
+    let leading = '\u{1100}'..='\u{115F}';
+    let vowel = '\u{1160}'..='\u{11A7}';
+    let trailing = '\u{11A8}'..='\u{11FF}';
+    let syllable = '\u{AC00}'..='\u{D7A3}';
+    let syllable_has_trailing = |c: char| (u32::from(c) - 0xAC00) % 28 != 0;
+    (leading.contains(&prev) && (leading.contains(&ch) || vowel.contains(&ch)))
+        || (vowel.contains(&prev)
+            && (vowel.contains(&ch) || trailing.contains(&ch)))
+        || (trailing.contains(&prev) && trailing.contains(&ch))
+        || (syllable.contains(&prev)
+            && (trailing.contains(&ch)
+                || (vowel.contains(&ch) && !syllable_has_trailing(prev))))
+}
+
+#[cfg(test)]
+mod test {
+    use super::grapheme_count;
+
+    #[test]
+    fn plain_characters_count_once_each() {
+        assert_eq!(grapheme_count(""), 0);
+        assert_eq!(grapheme_count("abc d"), 5);
+        assert_eq!(grapheme_count("a\r\nb\n"), 4);
+    }
+
+    #[test]
+    fn combining_marks_join_the_preceding_character() {
+        assert_eq!(grapheme_count("cafe\u{301}"), 4);
+        assert_eq!(grapheme_count("e\u{323}\u{302}"), 1);
+    }
+
+    #[test]
+    fn emoji_sequences_count_once() {
+        // Woman with dark skin tone
+        assert_eq!(grapheme_count("\u{1F469}\u{1F3FF}"), 1);
+        // Family: man, woman, girl
+        assert_eq!(
+            grapheme_count("\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}"),
+            1
+        );
+        // Red heart with variation selector
+        assert_eq!(grapheme_count("\u{2764}\u{FE0F}"), 1);
+    }
+
+    #[test]
+    fn regional_indicators_pair_up_into_flags() {
+        // GB, FR, then a lone G
+        assert_eq!(
+            grapheme_count("\u{1F1EC}\u{1F1E7}\u{1F1EB}\u{1F1F7}\u{1F1EC}"),
+            3
+        );
+    }
+
+    #[test]
+    fn hangul_jamo_form_syllables() {
+        assert_eq!(grapheme_count("\u{1112}\u{1161}\u{11AB}\u{AE00}"), 2);
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::Transaction;
+use crate::{CodeUnit, Location, UndoCoalescing};
+
+/**
+ * The edits that can be undone and redone. Once the edits that can be
+ * undone exceed max_entries, or take up more than max_bytes, the oldest are
+ * forgotten.
+ */
+#[derive(Clone, Debug)]
+pub(crate) struct History<C> {
+    undo: Vec<HistoryEntry<C>>,
+    redo: Vec<HistoryEntry<C>>,
+    undo_bytes: usize,
+    max_entries: Option<usize>,
+    max_bytes: Option<usize>,
+    coalescing: UndoCoalescing,
+    /**
+     * Whether the most recent edit that can be undone was typing that
+     * further typing may still be coalesced with.
+     */
+    typing_open: bool,
+}
+
+/**
+ * One edit, as the changes needed to reverse it, along with the selection
+ * to restore when it is reversed (the selection before the edit), and the
+ * selection to restore when it is made again (the selection after it).
+ */
+#[derive(Clone, Debug)]
+pub(crate) struct HistoryEntry<C> {
+    pub transaction: Transaction<C>,
+    pub start: Location,
+    pub end: Location,
+    pub after: (Location, Location),
+    pub item: HistoryItem,
+    /**
+     * What the edit typed, if all it did was type text.
+     */
+    pub typing: Option<Typing>,
+}
+
+/**
+ * Enough about some typed text to decide whether more typing should be
+ * undone along with it.
+ */
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Typing {
+    chars: usize,
+    starts_with_space: bool,
+    ends_with_space: bool,
+}
+
+impl<C> HistoryEntry<C> {
+    /**
+     * Combine this with later, the edit made straight after it, into one
+     * edit described by later's item.
+     */
+    fn followed_by(self, later: HistoryEntry<C>) -> Self {
+        Self {
+            transaction: self.transaction.followed_by(later.transaction),
+            start: self.start,
+            end: self.end,
+            after: later.after,
+            item: later.item,
+            typing: self.typing.zip(later.typing).map(|(before, typed)| {
+                Typing {
+                    chars: before.chars + typed.chars,
+                    starts_with_space: before.starts_with_space,
+                    ends_with_space: typed.ends_with_space,
+                }
+            }),
+        }
+    }
+}
+
+impl Typing {
+    pub fn of<C: CodeUnit>(text: &[C]) -> Option<Self> {
+        let text = C::decode(text);
+        if text.contains(['\n', '\r']) {
+            return None;
+        }
+        let first = text.chars().next()?;
+        let last = text.chars().last()?;
+        Some(Self {
+            chars: text.chars().count(),
+            starts_with_space: first.is_whitespace(),
+            ends_with_space: last.is_whitespace(),
+        })
+    }
+}
+
+/**
+ * What a client can see of an edit in the history: which operation made
+ * it, when, and how long the content was once it was made. Reverting an
+ * edit keeps its item, so an edit looks the same in the undo and redo
+ * history.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistoryItem {
+    /**
+     * The name of the ComposerModel method that made the edit, e.g.
+     * "replace_text" or "format".
+     */
+    pub label: &'static str,
+    /**
+     * When the edit was made, in milliseconds since the Unix epoch, or None
+     * where the time is not available (wasm).
+     */
+    pub timestamp_ms: Option<u64>,
+    /**
+     * The length of the content, in code units, after the edit.
+     */
+    pub content_len: usize,
+}
+
+impl<C> History<C> {
+    pub fn new(
+        max_entries: Option<usize>,
+        max_bytes: Option<usize>,
+        coalescing: UndoCoalescing,
+    ) -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            undo_bytes: 0,
+            max_entries,
+            max_bytes,
+            coalescing,
+            typing_open: false,
+        }
+    }
+
+    /**
+     * Change the limits, forgetting the oldest edits if there are now too
+     * many.
+     */
+    pub fn set_limits(
+        &mut self,
+        max_entries: Option<usize>,
+        max_bytes: Option<usize>,
+    ) {
+        self.max_entries = max_entries;
+        self.max_bytes = max_bytes;
+        self.evict();
+    }
+
+    /**
+     * Record a new edit, combining it with the previous one if both only
+     * typed text and the coalescing setting groups them. Edits that were
+     * undone can no longer be redone.
+     */
+    pub fn record(&mut self, entry: HistoryEntry<C>) {
+        self.redo.clear();
+        let coalesce = self.typing_open
+            && self
+                .undo
+                .last()
+                .map_or(false, |last| self.coalesces(last, &entry));
+        let typing = entry.typing.is_some();
+        let entry = if coalesce {
+            self.take_undo().unwrap().followed_by(entry)
+        } else {
+            entry
+        };
+        self.push_undo(entry);
+        self.typing_open = typing;
+    }
+
+    pub fn take_undo(&mut self) -> Option<HistoryEntry<C>> {
+        self.typing_open = false;
+        let entry = self.undo.pop()?;
+        self.undo_bytes -= entry.transaction.size_in_bytes();
+        Some(entry)
+    }
+
+    pub fn take_redo(&mut self) -> Option<HistoryEntry<C>> {
+        self.redo.pop()
+    }
+
+    pub fn push_undo(&mut self, entry: HistoryEntry<C>) {
+        self.undo_bytes += entry.transaction.size_in_bytes();
+        self.undo.push(entry);
+        self.evict();
+    }
+
+    pub fn push_redo(&mut self, entry: HistoryEntry<C>) {
+        self.redo.push(entry);
+    }
+
+    /**
+     * The edits that can be undone, most recent last.
+     */
+    pub fn undo_entries(&self) -> &[HistoryEntry<C>] {
+        &self.undo
+    }
+
+    /**
+     * The edits that can be redone, next to be redone last.
+     */
+    pub fn redo_entries(&self) -> &[HistoryEntry<C>] {
+        &self.redo
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.undo_bytes = 0;
+        self.typing_open = false;
+    }
+
+    /**
+     * Whether entry, just made, should be undone along with last, the
+     * previous edit: both only typed text, entry typed it where last left
+     * the cursor, and the coalescing setting groups them.
+     */
+    fn coalesces(
+        &self,
+        last: &HistoryEntry<C>,
+        entry: &HistoryEntry<C>,
+    ) -> bool {
+        let (before, typed) = match (last.typing, entry.typing) {
+            (Some(before), Some(typed)) => (before, typed),
+            _ => return false,
+        };
+        let at_cursor =
+            entry.start == entry.end && last.after == (entry.start, entry.end);
+        at_cursor
+            && match self.coalescing {
+                UndoCoalescing::EachEdit => false,
+                UndoCoalescing::Words => {
+                    !before.ends_with_space || typed.starts_with_space
+                }
+                UndoCoalescing::Pauses { max_pause_ms } => {
+                    match (last.item.timestamp_ms, entry.item.timestamp_ms) {
+                        (Some(then), Some(now)) => {
+                            now.saturating_sub(then) <= max_pause_ms
+                        }
+                        _ => false,
+                    }
+                }
+                UndoCoalescing::Characters { max } => {
+                    before.chars + typed.chars <= max
+                }
+            }
+    }
+
+    /**
+     * Forget the oldest edits that can be undone until they are within
+     * the limits.
+     */
+    fn evict(&mut self) {
+        let mut count = 0;
+        while count < self.undo.len() {
+            let too_many = self
+                .max_entries
+                .map_or(false, |max| self.undo.len() - count > max);
+            let too_big =
+                self.max_bytes.map_or(false, |max| self.undo_bytes > max);
+            if !too_many && !too_big {
+                break;
+            }
+            self.undo_bytes -= self.undo[count].transaction.size_in_bytes();
+            count += 1;
+        }
+        self.undo.drain(..count);
+    }
+}
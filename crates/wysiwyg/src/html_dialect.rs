@@ -0,0 +1,58 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/**
+ * The flavour of HTML to produce when serializing the composer's content.
+ * The same content is shown differently on the editing surface than it is
+ * sent in a Matrix event.
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HtmlDialect {
+    /**
+     * The formatted_body of an outgoing Matrix message: semantic tags only.
+     */
+    MatrixMessage,
+    /**
+     * HTML for the editing surface (e.g. a contenteditable). Empty
+     * formatting elements contain a zero-width space so that the caret can
+     * be placed inside them.
+     */
+    Editor,
+    /**
+     * HTML for simple read-only renderers such as notification previews,
+     * using presentational tags.
+     */
+    Preview,
+}
+
+impl HtmlDialect {
+    /**
+     * The tag name this dialect uses for an element whose canonical name is
+     * supplied.
+     */
+    pub(crate) fn tag_name<'a>(&self, canonical_name: &'a str) -> &'a str {
+        match (self, canonical_name) {
+            (Self::Preview, "strong") => "b",
+            (Self::Preview, "em") => "i",
+            _ => canonical_name,
+        }
+    }
+
+    /**
+     * True if empty elements should be given placeholder content.
+     */
+    pub(crate) fn emits_placeholders(&self) -> bool {
+        matches!(self, Self::Editor)
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::dom::element_spec;
+
+/**
+ * A piece of the composer's content that the Matrix specification does not
+ * allow in the formatted_body of a message, and that receiving clients may
+ * therefore remove or mangle.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HtmlViolation {
+    /**
+     * An element whose tag is not allowed.
+     */
+    Element { name: String },
+    /**
+     * An attribute that is not allowed on the element carrying it.
+     */
+    Attribute { element: String, attribute: String },
+    /**
+     * An allowed attribute with a value that is not, e.g. a link whose
+     * scheme is not permitted.
+     */
+    AttributeValue {
+        element: String,
+        attribute: String,
+        value: String,
+    },
+}
+
+/**
+ * How much removing an HtmlViolation changes the message, so that clients
+ * can warn that formatting was removed only when it matters. Ordered from
+ * least to most serious.
+ */
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum RemovalSeverity {
+    /**
+     * Only presentation was lost, e.g. a class or style attribute, or a
+     * wrapper element such as <section> whose content is kept.
+     */
+    Cosmetic,
+    /**
+     * Content or structure the message cannot carry was lost, e.g. an
+     * unsupported element or a link with a disallowed scheme.
+     */
+    Unsupported,
+    /**
+     * Something that could run script or load content when shown was
+     * removed, e.g. a <script> element, an onclick attribute or a
+     * javascript: link.
+     */
+    Dangerous,
+}
+
+/**
+ * Attributes whose value is content rather than presentation.
+ */
+const CONTENT_ATTRIBUTES: &[&str] = &["alt", "href", "src"];
+
+const DANGEROUS_SCHEMES: &[&str] = &["data", "javascript", "vbscript"];
+
+impl HtmlViolation {
+    pub fn severity(&self) -> RemovalSeverity {
+        match self {
+            Self::Element { name } => element_spec(name)
+                .map_or(RemovalSeverity::Unsupported, |spec| spec.severity),
+            Self::Attribute { attribute, .. } => {
+                if attribute.starts_with("on") {
+                    RemovalSeverity::Dangerous
+                } else if CONTENT_ATTRIBUTES.contains(&attribute.as_str()) {
+                    RemovalSeverity::Unsupported
+                } else {
+                    RemovalSeverity::Cosmetic
+                }
+            }
+            Self::AttributeValue {
+                attribute, value, ..
+            } => {
+                let scheme = value
+                    .split_once(':')
+                    .map_or("", |(scheme, _)| scheme.trim());
+                if DANGEROUS_SCHEMES
+                    .iter()
+                    .any(|s| s.eq_ignore_ascii_case(scheme))
+                {
+                    RemovalSeverity::Dangerous
+                } else if CONTENT_ATTRIBUTES.contains(&attribute.as_str()) {
+                    RemovalSeverity::Unsupported
+                } else {
+                    RemovalSeverity::Cosmetic
+                }
+            }
+        }
+    }
+}
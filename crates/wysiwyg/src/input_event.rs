@@ -0,0 +1,42 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Location;
+
+/**
+ * A single edit reported by the platform. A batch of these can be applied
+ * together using ComposerModel::apply_events.
+ */
+#[derive(Debug, Clone)]
+pub enum InputEvent<C> {
+    Select {
+        start: Location,
+        end: Location,
+    },
+    ReplaceText {
+        new_text: Vec<C>,
+    },
+    ReplaceTextIn {
+        new_text: Vec<C>,
+        start: usize,
+        end: usize,
+    },
+    Backspace,
+    Delete,
+    DeleteIn {
+        start: usize,
+        end: usize,
+    },
+    Enter,
+}
@@ -0,0 +1,165 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/*!
+ * Drafts saved by the composers that Element used before this one, so
+ * that clients switching to it can load them rather than discarding them.
+ */
+
+use serde_json::Value;
+
+use crate::dom::{escape_text, escape_url, markdown_to_html};
+use crate::mention::MATRIX_TO_PREFIX;
+use crate::{CodeUnit, ComposerModel, RestoreOptions, RestoredMessage};
+
+impl<C> ComposerModel<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Replace all the content with a draft saved by one of Element's
+     * earlier composers, as set_content_from_message_html does. The draft
+     * can be the "parts" of element-web's rich text editor, as JSON (either
+     * the saved object, with its other fields ignored, or just the array),
+     * or the markdown of the older markdown composer, either as it is or as
+     * a JSON string. Pills become mentions if options resolve them. An
+     * error, changing nothing, if the draft is JSON that is not one of
+     * these.
+     */
+    pub fn from_legacy_draft(
+        &mut self,
+        draft: &str,
+        options: &RestoreOptions,
+    ) -> Result<RestoredMessage<C>, String> {
+        let markdown = match serde_json::from_str::<Value>(draft) {
+            Ok(Value::String(markdown)) => markdown,
+            Ok(Value::Array(parts)) => parts_to_markdown(&parts)?,
+            Ok(Value::Object(saved)) => match saved.get("parts") {
+                Some(Value::Array(parts)) => parts_to_markdown(parts)?,
+                _ => return Err(String::from("The draft has no parts")),
+            },
+            Ok(_) | Err(_) => String::from(draft),
+        };
+        let html = markdown_to_html(&markdown, self.config().max_depth);
+        Ok(self.set_content_from_message_html(&C::encode(&html), options))
+    }
+}
+
+/**
+ * The markdown that the parts of a draft stand for. The text of plain
+ * parts is already markdown; pills become links to matrix.to.
+ */
+fn parts_to_markdown(parts: &[Value]) -> Result<String, String> {
+    let mut markdown = String::new();
+    for part in parts {
+        let text = part
+            .get("text")
+            .and_then(Value::as_str)
+            .ok_or_else(|| String::from("A part of the draft has no text"))?;
+        let kind = part.get("type").and_then(Value::as_str);
+        let resource = part.get("resourceId").and_then(Value::as_str);
+        match (kind, resource) {
+            (Some("user-pill" | "room-pill"), Some(id)) => {
+                markdown.push_str(&format!(
+                    "[{}]({}{})",
+                    escape_text(text),
+                    MATRIX_TO_PREFIX,
+                    escape_url(id)
+                ));
+            }
+            _ => markdown.push_str(text),
+        }
+    }
+    Ok(markdown)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::{ComposerModel, RestoreChange, RestoreOptions};
+
+    fn utf16(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    fn html(model: &ComposerModel<u16>) -> String {
+        String::from_utf16(&model.get_content_as_message_html()).unwrap()
+    }
+
+    #[test]
+    fn parts_are_loaded_with_pills_as_mentions() {
+        let mut model = ComposerModel::new();
+        let draft = json!({
+            "parts": [
+                { "type": "plain", "text": "**hi** " },
+                {
+                    "type": "user-pill",
+                    "text": "Al [ice]",
+                    "resourceId": "@alice:x.org",
+                },
+                { "type": "newline", "text": "\n" },
+                { "type": "at-room-pill", "text": "@room" },
+            ],
+            "replyEventId": "$e",
+        });
+        let restored = model
+            .from_legacy_draft(&draft.to_string(), &RestoreOptions::default())
+            .unwrap();
+        assert_eq!(
+            html(&model),
+            "<strong>hi</strong> <a href=\"https://matrix.to/#/@alice:x.org\">\
+            Al [ice]</a><br />@room"
+        );
+        assert_eq!(model.mentioned_user_ids(), vec![utf16("@alice:x.org")]);
+        assert_eq!(
+            restored.changes,
+            vec![RestoreChange::MentionResolved {
+                id: String::from("@alice:x.org")
+            }]
+        );
+    }
+
+    #[test]
+    fn markdown_drafts_are_loaded_as_they_are_or_as_json() {
+        let mut model = ComposerModel::new();
+        let options = RestoreOptions::default();
+        model.from_legacy_draft("- *a*", &options).unwrap();
+        assert_eq!(html(&model), "<ul><li><em>a</em></li></ul>");
+        model.from_legacy_draft("\"b\\n~~c~~\"", &options).unwrap();
+        assert_eq!(html(&model), "b<br /><del>c</del>");
+    }
+
+    #[test]
+    fn deeply_quoted_drafts_are_loaded() {
+        let mut model = ComposerModel::new();
+        let draft = format!("{}a", ">".repeat(100_000));
+        model
+            .from_legacy_draft(&draft, &RestoreOptions::default())
+            .unwrap();
+        assert!(html(&model).contains("&gt;a</blockquote>"));
+    }
+
+    #[test]
+    fn json_that_is_not_a_draft_changes_nothing() {
+        let mut model = ComposerModel::new();
+        let options = RestoreOptions::default();
+        model.from_legacy_draft("a", &options).unwrap();
+        assert!(model.from_legacy_draft("{\"x\": 1}", &options).is_err());
+        assert!(model
+            .from_legacy_draft("[{\"type\": \"plain\"}]", &options)
+            .is_err());
+        assert_eq!(html(&model), "a");
+    }
+}
@@ -12,19 +12,104 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+// The Dom is an arena of nodes addressed by NodeId, so needs no unsafe code
+#![forbid(unsafe_code)]
+
+mod anchoring;
+mod clock;
+mod code_lines;
+mod code_unit;
 mod composer_action;
+mod composer_command;
+mod composer_config;
+mod composer_effect;
+mod composer_error;
 mod composer_model;
+mod composer_model_builder;
+mod composer_snapshot;
 mod composer_update;
+mod coverage;
+mod dom;
+mod dom_observer;
+#[cfg(feature = "event-content")]
+mod event_content;
+mod format_set;
+mod graphemes;
+mod history;
+mod html_dialect;
+mod html_violation;
+mod input_event;
+#[cfg(feature = "legacy-drafts")]
+mod legacy_drafts;
+mod link_info;
 mod location;
+#[cfg(feature = "long-form")]
+pub mod long_form;
+mod markdown_style;
+mod mention;
+mod menu_action;
 mod menu_state;
+mod normalization;
+mod parse_warning;
+mod restore_options;
+mod sanitize_report;
+#[cfg(feature = "test-vectors")]
+pub mod test_vectors;
 mod text_update;
+mod words;
 
+pub use crate::clock::Clock;
+pub use crate::code_unit::CodeUnit;
 pub use crate::composer_action::ActionRequest;
 pub use crate::composer_action::ActionResponse;
 pub use crate::composer_action::ComposerAction;
+pub use crate::composer_command::ComposerCommand;
+pub use crate::composer_config::ComposerConfig;
+pub use crate::composer_config::EnterBehaviour;
+pub use crate::composer_config::Feature;
+pub use crate::composer_config::LinkFormatting;
+pub use crate::composer_config::MentionFormatting;
+pub use crate::composer_config::UndoCoalescing;
+pub use crate::composer_effect::ComposerEffect;
+pub use crate::composer_error::ComposerError;
 pub use crate::composer_model::ComposerModel;
+pub use crate::composer_model_builder::ComposerModelBuilder;
+pub use crate::composer_snapshot::ComposerSnapshot;
+pub use crate::composer_snapshot::SnapshotState;
 pub use crate::composer_update::ComposerUpdate;
+pub use crate::coverage::Coverage;
+// The document model, for use without a ComposerModel: its nodes,
+// parsing, serialization and queries, but not the edits the composer
+// makes through it
+pub use crate::dom::{
+    diff_html, parse, parse_markdown, parse_message_html, parse_to_depth,
+    parse_with_warnings, AnchoredLocation, Atom, ContainerNode, DiffHtml, Dom,
+    DomBuilder, DomFragment, DomHandle, DomNode, NodeId, ParseResult,
+    PasteSource, PinnedNode, TextNode, DEFAULT_MAX_DEPTH, MATRIX_LINK_SCHEMES,
+    PROTECTED_ATTR,
+};
+pub use crate::dom_observer::DomEvent;
+pub use crate::dom_observer::DomObserver;
+pub use crate::format_set::{Format, FormatSet};
+pub use crate::history::HistoryItem;
+pub use crate::html_dialect::HtmlDialect;
+pub use crate::html_violation::HtmlViolation;
+pub use crate::html_violation::RemovalSeverity;
+pub use crate::input_event::InputEvent;
+pub use crate::link_info::LinkInfo;
 pub use crate::location::Location;
+pub use crate::markdown_style::{MarkdownStyle, MarkdownStyleKind};
+pub use crate::mention::{Mention, MentionKind};
+pub use crate::menu_action::MenuAction;
+pub use crate::menu_action::PatternKey;
+pub use crate::menu_action::SuggestionPattern;
 pub use crate::menu_state::MenuState;
+pub use crate::parse_warning::ParseWarning;
+pub use crate::restore_options::RestoreChange;
+pub use crate::restore_options::RestoreOptions;
+pub use crate::restore_options::RestoredMessage;
+pub use crate::sanitize_report::SanitizeReport;
 pub use crate::text_update::ReplaceAll;
 pub use crate::text_update::TextUpdate;
+pub use crate::text_update::UpdateTextNode;
+pub use crate::words::WordDictionary;
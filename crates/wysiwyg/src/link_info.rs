@@ -0,0 +1,32 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Location;
+
+/**
+ * A link in the content, as an "Edit link" dialog needs it.
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LinkInfo<C> {
+    pub url: Vec<C>,
+    /**
+     * The visible text of the link, without formatting.
+     */
+    pub text: Vec<C>,
+    /**
+     * The range covered by the link.
+     */
+    pub start: Location,
+    pub end: Location,
+}
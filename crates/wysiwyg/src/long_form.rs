@@ -0,0 +1,177 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/*!
+ * Statistics and structure for long pieces of writing, such as blog posts
+ * composed on top of this crate, rather than chat messages.
+ */
+
+use crate::dom::{DomNode, NodeId};
+use crate::{CodeUnit, ComposerModel, Location};
+
+/**
+ * The reading speed assumed by TextStatistics::reading_time_seconds.
+ */
+const WORDS_PER_MINUTE: usize = 200;
+
+/**
+ * Counts of the words and sentences in some text, and how long it takes
+ * to read.
+ */
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TextStatistics {
+    /**
+     * Runs of characters between whitespace that contain at least one
+     * letter or digit.
+     */
+    pub words: usize,
+    /**
+     * Runs of text ended by ".", "!", "?", "…" or the end of a line or
+     * block, that contain at least one letter or digit.
+     */
+    pub sentences: usize,
+    /**
+     * The time needed to read the words, rounded up to a whole second.
+     */
+    pub reading_time_seconds: u64,
+}
+
+/**
+ * A heading, as an entry in the outline of a document.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OutlineEntry<C> {
+    /**
+     * 1 for h1, through 6 for h6.
+     */
+    pub level: u8,
+    pub text: Vec<C>,
+    pub start: Location,
+}
+
+impl TextStatistics {
+    pub fn of(text: &str) -> Self {
+        let words = text.split_whitespace().filter(|w| has_content(w)).count();
+        let sentences = text
+            .split(['.', '!', '?', '…', '\n'])
+            .filter(|s| has_content(s))
+            .count();
+        let reading_time_seconds =
+            ((words * 60 + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE) as u64;
+        Self {
+            words,
+            sentences,
+            reading_time_seconds,
+        }
+    }
+}
+
+impl<C> ComposerModel<C>
+where
+    C: CodeUnit,
+{
+    /**
+     * Word and sentence counts and the reading time of the content.
+     */
+    pub fn text_statistics(&self) -> TextStatistics {
+        TextStatistics::of(&C::decode(&self.get_content_as_plain_text(false)))
+    }
+
+    /**
+     * The headings in the content, in document order.
+     */
+    pub fn outline(&self) -> Vec<OutlineEntry<C>> {
+        let dom = self.dom();
+        let mut entries = Vec::new();
+        let mut pending: Vec<NodeId> =
+            dom.children(dom.document()).iter().rev().cloned().collect();
+        while let Some(child) = pending.pop() {
+            if let DomNode::Container(c) = dom.node(child) {
+                match heading_level(c.name()) {
+                    Some(level) => entries.push(OutlineEntry {
+                        level,
+                        text: dom.text_content(child),
+                        start: Location::from(dom.start_of(child)),
+                    }),
+                    None => pending.extend(dom.children(child).iter().rev()),
+                }
+            }
+        }
+        entries
+    }
+}
+
+fn heading_level(name: &str) -> Option<u8> {
+    match name.as_bytes() {
+        [b'h', level @ b'1'..=b'6'] => Some(level - b'0'),
+        _ => None,
+    }
+}
+
+fn has_content(text: &str) -> bool {
+    text.chars().any(char::is_alphanumeric)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ComposerModel, Location};
+
+    use super::{OutlineEntry, TextStatistics};
+
+    fn model(html: &str) -> ComposerModel<u16> {
+        let mut model = ComposerModel::new();
+        model.set_content_from_html(&html.encode_utf16().collect::<Vec<_>>());
+        model
+    }
+
+    #[test]
+    fn words_and_sentences_are_counted_across_blocks() {
+        let stats =
+            model("<h1>Title</h1><p>One two. Three - four!</p><p>Five</p>")
+                .text_statistics();
+        assert_eq!(stats.words, 6);
+        assert_eq!(stats.sentences, 4);
+    }
+
+    #[test]
+    fn reading_time_rounds_up_to_a_second() {
+        assert_eq!(TextStatistics::of("").reading_time_seconds, 0);
+        assert_eq!(TextStatistics::of("word").reading_time_seconds, 1);
+        let text = "word ".repeat(400);
+        assert_eq!(TextStatistics::of(&text).reading_time_seconds, 120);
+    }
+
+    #[test]
+    fn the_outline_lists_headings_in_order() {
+        let outline = model(
+            "<h1>A</h1><p>b</p><blockquote><h3>C<em>d</em></h3></blockquote>",
+        )
+        .outline();
+        assert_eq!(
+            outline,
+            vec![
+                OutlineEntry {
+                    level: 1,
+                    text: "A".encode_utf16().collect(),
+                    start: Location::from(0),
+                },
+                OutlineEntry {
+                    level: 3,
+                    text: "Cd".encode_utf16().collect(),
+                    start: Location::from(2),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Location;
+
+/**
+ * A part of the markdown shown in plain text mode that the platform may
+ * want to style, e.g. to dim the markers around bold text.
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MarkdownStyle {
+    pub kind: MarkdownStyleKind,
+    pub start: Location,
+    pub end: Location,
+}
+
+/**
+ * What a MarkdownStyle covers. Markers are the syntax characters, and the
+ * kinds without Marker in their names are the content between them, which
+ * contains the ranges of anything inside it.
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MarkdownStyleKind {
+    BoldMarker,
+    Bold,
+    ItalicMarker,
+    Italic,
+    StrikethroughMarker,
+    Strikethrough,
+    CodeMarker,
+    Code,
+    /**
+     * The brackets and parentheses of a link.
+     */
+    LinkMarker,
+    LinkText,
+    LinkUrl,
+    QuoteMarker,
+    ListMarker,
+    /**
+     * A line of backticks opening or closing a code block.
+     */
+    CodeBlockFence,
+    CodeBlock,
+    /**
+     * The backslash before an escaped character.
+     */
+    Escape,
+    /**
+     * A tag of HTML written in the markdown.
+     */
+    Html,
+}
+
+impl MarkdownStyleKind {
+    /**
+     * A name for the kind, e.g. "bold-marker", for platforms that style by
+     * name (such as with CSS classes).
+     */
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::BoldMarker => "bold-marker",
+            Self::Bold => "bold",
+            Self::ItalicMarker => "italic-marker",
+            Self::Italic => "italic",
+            Self::StrikethroughMarker => "strikethrough-marker",
+            Self::Strikethrough => "strikethrough",
+            Self::CodeMarker => "code-marker",
+            Self::Code => "code",
+            Self::LinkMarker => "link-marker",
+            Self::LinkText => "link-text",
+            Self::LinkUrl => "link-url",
+            Self::QuoteMarker => "quote-marker",
+            Self::ListMarker => "list-marker",
+            Self::CodeBlockFence => "code-block-fence",
+            Self::CodeBlock => "code-block",
+            Self::Escape => "escape",
+            Self::Html => "html",
+        }
+    }
+}
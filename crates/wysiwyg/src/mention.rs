@@ -0,0 +1,137 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::CodeUnit;
+
+/**
+ * What mention links point to, followed by the mentioned ID.
+ */
+pub(crate) const MATRIX_TO_PREFIX: &str = "https://matrix.to/#/";
+
+/**
+ * The attributes of a mention link holding the mention's ID and avatar.
+ * They are not allowed in Matrix messages, so are removed when sending.
+ */
+pub(crate) const MENTION_ID_ATTR: &str = "data-mention-id";
+pub(crate) const MENTION_AVATAR_ATTR: &str = "data-mention-avatar";
+/**
+ * The attribute of a mention link holding its kind (see MentionKind::name).
+ */
+pub(crate) const MENTION_KIND_ATTR: &str = "data-mention-type";
+
+/**
+ * What a mention refers to, which can be told from its ID.
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MentionKind {
+    /**
+     * A user, e.g. "@alice:matrix.org".
+     */
+    User,
+    /**
+     * A room, by its alias (e.g. "#room:matrix.org") or its ID.
+     */
+    Room,
+    /**
+     * An event, e.g. the root of a thread: the room's alias or ID, then
+     * "/" and the event's ID, as in a permalink ("!abc:matrix.org/$def").
+     */
+    Event,
+}
+
+impl MentionKind {
+    /**
+     * The kind of thing id refers to, or None if it is not a Matrix ID
+     * that can be mentioned.
+     */
+    pub fn of(id: &str) -> Option<Self> {
+        let (room, event) = match id.split_once('/') {
+            Some((room, event)) => (room, Some(event)),
+            None => (id, None),
+        };
+        match (room.chars().next()?, event) {
+            ('@', None) => Some(Self::User),
+            ('#' | '!', None) => Some(Self::Room),
+            ('#' | '!', Some(event)) if event.starts_with('$') => {
+                Some(Self::Event)
+            }
+            _ => None,
+        }
+    }
+
+    /**
+     * The value of a mention link's data-mention-type attribute.
+     */
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Room => "room",
+            Self::Event => "event",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        [Self::User, Self::Room, Self::Event]
+            .into_iter()
+            .find(|kind| kind.name() == name)
+    }
+}
+
+/**
+ * A user or room chosen to complete a suggestion (see
+ * ComposerModel::replace_text_at_suggestion).
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Mention<C> {
+    /**
+     * The Matrix ID being mentioned, e.g. "@alice:matrix.org" for a user
+     * or "#room:matrix.org" for a room.
+     */
+    pub id: Vec<C>,
+    /**
+     * The text shown for the mention, e.g. the user's display name.
+     */
+    pub display_name: Vec<C>,
+    /**
+     * The avatar to show for the mention, e.g. an mxc: URI, if the
+     * platform wants to render mentions as pills.
+     */
+    pub avatar_url: Option<Vec<C>>,
+}
+
+impl<C> Mention<C>
+where
+    C: CodeUnit,
+{
+    pub fn kind(&self) -> Option<MentionKind> {
+        MentionKind::of(&C::decode(&self.id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MentionKind;
+
+    #[test]
+    fn the_kind_of_a_mention_is_told_from_its_id() {
+        assert_eq!(MentionKind::of("@a:b"), Some(MentionKind::User));
+        assert_eq!(MentionKind::of("#r:b"), Some(MentionKind::Room));
+        assert_eq!(MentionKind::of("!r:b"), Some(MentionKind::Room));
+        assert_eq!(MentionKind::of("!r:b/$e"), Some(MentionKind::Event));
+        assert_eq!(MentionKind::of("#r:b/$e"), Some(MentionKind::Event));
+        assert_eq!(MentionKind::of("@a:b/$e"), None);
+        assert_eq!(MentionKind::of("!r:b/x"), None);
+        assert_eq!(MentionKind::of(""), None);
+    }
+}
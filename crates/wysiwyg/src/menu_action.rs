@@ -0,0 +1,80 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Location;
+
+/**
+ * Something the platform should offer the user because of what is around
+ * the cursor, separate from the toggles in MenuState.
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MenuAction<C> {
+    None,
+    /**
+     * The user is typing something that could be completed, e.g. "@al"
+     * could become a mention of @alice.
+     */
+    Suggestion(SuggestionPattern<C>),
+    /**
+     * The user pressed Up with nothing in the composer, so the platform
+     * should recall their previous message, e.g. to edit it, instead of
+     * moving the cursor.
+     */
+    NavigateHistory,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SuggestionPattern<C> {
+    pub key: PatternKey,
+    /**
+     * What has been typed after the key.
+     */
+    pub text: Vec<C>,
+    /**
+     * The range covered by the key and text, to be replaced by the chosen
+     * suggestion.
+     */
+    pub start: Location,
+    pub end: Location,
+}
+
+/**
+ * The character that starts a suggestion pattern.
+ */
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PatternKey {
+    /**
+     * '@', for mentioning a user.
+     */
+    At,
+    /**
+     * '#', for mentioning a room.
+     */
+    Hash,
+    /**
+     * '/', at the start of the content, for a command.
+     */
+    Slash,
+}
+
+impl PatternKey {
+    pub(crate) fn from_ascii(c: u8) -> Option<Self> {
+        match c {
+            b'@' => Some(Self::At),
+            b'#' => Some(Self::Hash),
+            b'/' => Some(Self::Slash),
+            _ => None,
+        }
+    }
+}
@@ -12,7 +12,35 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#[derive(Debug, Clone)]
+use crate::Feature;
+
+/**
+ * The state of the formatting menu: which of its toggles are on at the
+ * current selection, and whether undo and redo can be used.
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum MenuState {
+    /**
+     * The menu does not need to change.
+     */
     None,
+    Update {
+        /**
+         * The features that apply to all of the selection.
+         */
+        active_features: Vec<Feature>,
+        /**
+         * The features that apply to only part of the selection, which
+         * toolbars can show as indeterminate.
+         */
+        partially_active_features: Vec<Feature>,
+        /**
+         * Whether there is an edit to undo.
+         */
+        can_undo: bool,
+        /**
+         * Whether there is an undone edit to redo.
+         */
+        can_redo: bool,
+    },
 }
@@ -0,0 +1,41 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/**
+ * Something in HTML given to the composer that was not well formed. The
+ * parser recovers from all of these the way a browser would, so they are
+ * only worth reporting to whoever produced the HTML.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseWarning {
+    /**
+     * An element that was still open when its parent closed or the input
+     * ended, and so was closed there.
+     */
+    UnclosedElement { name: String },
+    /**
+     * An end tag with no matching open element, which was ignored.
+     */
+    StrayEndTag { name: String },
+    /**
+     * A comment with no "-->", which was taken to run to the end of the
+     * input.
+     */
+    UnterminatedComment,
+    /**
+     * Elements nested more than max_depth deep, which were left out while
+     * their contents were kept.
+     */
+    TooDeep { max_depth: usize },
+}
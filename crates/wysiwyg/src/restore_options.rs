@@ -0,0 +1,83 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{ComposerUpdate, HtmlViolation};
+
+/**
+ * What ComposerModel::set_content_from_message_html does to a sent
+ * message's HTML to make it editable. Disallowed markup is always removed.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RestoreOptions {
+    /**
+     * Whether to remove the reply fallback (<mx-reply>), which is sent
+     * again with the edit rather than edited.
+     */
+    pub strip_reply: bool,
+    /**
+     * Whether links to users and rooms on matrix.to become mentions again,
+     * as if inserted with ComposerModel::replace_text_at_suggestion.
+     */
+    pub resolve_mentions: bool,
+    /**
+     * Whether elements the composer cannot edit are replaced by ones it
+     * can (e.g. <b> by <strong>), or by their content.
+     */
+    pub downgrade_unsupported: bool,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        Self {
+            strip_reply: true,
+            resolve_mentions: true,
+            downgrade_unsupported: true,
+        }
+    }
+}
+
+/**
+ * One way in which set_content_from_message_html changed the message.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RestoreChange {
+    ReplyRemoved,
+    /**
+     * An element was replaced by the named element, or by its content if
+     * replacement is None.
+     */
+    Downgraded {
+        element: String,
+        replacement: Option<String>,
+    },
+    /**
+     * Markup the Matrix specification does not allow was removed.
+     */
+    Stripped(HtmlViolation),
+    MentionResolved {
+        id: String,
+    },
+}
+
+/**
+ * The result of ComposerModel::set_content_from_message_html.
+ */
+#[derive(Clone, Debug)]
+pub struct RestoredMessage<C> {
+    pub update: ComposerUpdate<C>,
+    /**
+     * What was changed, in the order it was done.
+     */
+    pub changes: Vec<RestoreChange>,
+}
@@ -0,0 +1,114 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{HtmlViolation, RemovalSeverity};
+
+/**
+ * What was removed when HTML given to the composer was sanitized to what
+ * the Matrix specification allows, e.g. when pasting.
+ */
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SanitizeReport {
+    /**
+     * Each thing removed, in document order.
+     */
+    pub removals: Vec<HtmlViolation>,
+}
+
+impl SanitizeReport {
+    /**
+     * The most serious of the removals, or None if nothing was removed.
+     */
+    pub fn severity(&self) -> Option<RemovalSeverity> {
+        self.removals.iter().map(HtmlViolation::severity).max()
+    }
+
+    /**
+     * Whether anything more than presentation was removed, so that the
+     * user should be told, e.g. "some formatting was removed".
+     */
+    pub fn is_meaningful(&self) -> bool {
+        self.severity() > Some(RemovalSeverity::Cosmetic)
+    }
+
+    /**
+     * The number of removals of the given severity.
+     */
+    pub fn count(&self, severity: RemovalSeverity) -> usize {
+        self.removals
+            .iter()
+            .filter(|removal| removal.severity() == severity)
+            .count()
+    }
+
+    pub(crate) fn extend(&mut self, other: SanitizeReport) {
+        self.removals.extend(other.removals);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{HtmlViolation, RemovalSeverity, SanitizeReport};
+
+    fn element(name: &str) -> HtmlViolation {
+        HtmlViolation::Element {
+            name: String::from(name),
+        }
+    }
+
+    fn attribute(attribute: &str) -> HtmlViolation {
+        HtmlViolation::Attribute {
+            element: String::from("p"),
+            attribute: String::from(attribute),
+        }
+    }
+
+    fn href(value: &str) -> HtmlViolation {
+        HtmlViolation::AttributeValue {
+            element: String::from("a"),
+            attribute: String::from("href"),
+            value: String::from(value),
+        }
+    }
+
+    #[test]
+    fn removals_are_classified() {
+        assert_eq!(element("script").severity(), RemovalSeverity::Dangerous);
+        assert_eq!(element("marquee").severity(), RemovalSeverity::Unsupported);
+        assert_eq!(element("section").severity(), RemovalSeverity::Cosmetic);
+        assert_eq!(attribute("onclick").severity(), RemovalSeverity::Dangerous);
+        assert_eq!(attribute("href").severity(), RemovalSeverity::Unsupported);
+        assert_eq!(attribute("style").severity(), RemovalSeverity::Cosmetic);
+        assert_eq!(
+            href("JavaScript:alert(1)").severity(),
+            RemovalSeverity::Dangerous
+        );
+        assert_eq!(href("gopher://x").severity(), RemovalSeverity::Unsupported);
+    }
+
+    #[test]
+    fn only_more_than_cosmetic_removals_are_meaningful() {
+        let mut report = SanitizeReport::default();
+        assert_eq!(report.severity(), None);
+        assert!(!report.is_meaningful());
+        report.removals.push(attribute("class"));
+        assert_eq!(report.severity(), Some(RemovalSeverity::Cosmetic));
+        assert!(!report.is_meaningful());
+        report.removals.push(element("marquee"));
+        report.removals.push(attribute("id"));
+        assert_eq!(report.severity(), Some(RemovalSeverity::Unsupported));
+        assert!(report.is_meaningful());
+        assert_eq!(report.count(RemovalSeverity::Cosmetic), 2);
+    }
+}
@@ -0,0 +1,499 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/*!
+ * Test vectors: sequences of operations on a ComposerModel with the content
+ * and selection they should produce. The same vectors, exported as JSON
+ * (see examples/export_test_vectors.rs), let each platform's bindings check
+ * that they drive the model exactly as the Rust tests do. assert_idempotent
+ * checks that the toggles among the operations undo themselves.
+ */
+
+use serde::{Deserialize, Serialize};
+
+use crate::dom::parse;
+use crate::{ComposerModel, FormatSet, Location};
+
+/**
+ * Starting from initial_html with initial_start..initial_end selected,
+ * performing operations must leave expected_html (in the Editor dialect)
+ * with expected_start..expected_end selected. Locations are in UTF-16 code
+ * units.
+ */
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TestVector {
+    pub name: String,
+    pub initial_html: String,
+    pub initial_start: usize,
+    pub initial_end: usize,
+    pub operations: Vec<Operation>,
+    pub expected_html: String,
+    pub expected_start: usize,
+    pub expected_end: usize,
+}
+
+/**
+ * A call on ComposerModel, named after the method it makes.
+ */
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    Select {
+        start: usize,
+        end: usize,
+    },
+    ReplaceText {
+        text: String,
+    },
+    ReplaceTextIn {
+        text: String,
+        start: usize,
+        end: usize,
+    },
+    Backspace,
+    Delete,
+    DeleteIn {
+        start: usize,
+        end: usize,
+    },
+    Enter,
+    Bold,
+    Italic,
+    ToggleListType,
+    SetLinkWithText {
+        link: String,
+        text: String,
+    },
+    Undo,
+    Redo,
+}
+
+impl TestVector {
+    /**
+     * Perform this vector's operations on a new ComposerModel with the
+     * default configuration, returning a description of the difference if
+     * the result is not as expected.
+     */
+    pub fn run(&self) -> Result<(), String> {
+        let mut model = ComposerModel::<u16>::new();
+        model.set_content_from_html(&utf16(&self.initial_html));
+        model.select(
+            Location::from(self.initial_start),
+            Location::from(self.initial_end),
+        );
+        for operation in &self.operations {
+            operation.apply(&mut model);
+        }
+        let html = String::from_utf16(&model.get_html())
+            .expect("Model returned invalid UTF-16");
+        let (start, end) = model.get_selection();
+        let actual = (html, usize::from(start), usize::from(end));
+        let expected = (
+            self.expected_html.clone(),
+            self.expected_start,
+            self.expected_end,
+        );
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(format!(
+                "{}: expected {:?} but got {:?}",
+                self.name, expected, actual
+            ))
+        }
+    }
+}
+
+impl Operation {
+    fn apply(&self, model: &mut ComposerModel<u16>) {
+        match self {
+            Self::Select { start, end } => {
+                model.select(Location::from(*start), Location::from(*end))
+            }
+            Self::ReplaceText { text } => {
+                model.replace_text(&utf16(text));
+            }
+            Self::ReplaceTextIn { text, start, end } => {
+                model.replace_text_in(&utf16(text), *start, *end);
+            }
+            Self::Backspace => {
+                model.backspace();
+            }
+            Self::Delete => {
+                model.delete();
+            }
+            Self::DeleteIn { start, end } => {
+                model.delete_in(*start, *end);
+            }
+            Self::Enter => {
+                model.enter();
+            }
+            Self::Bold => {
+                model.bold();
+            }
+            Self::Italic => {
+                model.italic();
+            }
+            Self::ToggleListType => {
+                model.toggle_list_type();
+            }
+            Self::SetLinkWithText { link, text } => {
+                model.set_link_with_text(&utf16(link), &utf16(text));
+            }
+            Self::Undo => {
+                model.undo();
+            }
+            Self::Redo => {
+                model.redo();
+            }
+        }
+    }
+}
+
+/**
+ * The operations that, performed twice, should leave the content and
+ * selection as they were: the toolbar's toggles.
+ */
+pub fn toggles() -> Vec<Operation> {
+    vec![
+        Operation::Bold,
+        Operation::Italic,
+        Operation::ToggleListType,
+    ]
+}
+
+/**
+ * Panic, describing the difference, unless performing operation twice on
+ * a copy of model leaves its content and selection as they were, as it
+ * should for each of toggles. Formats of the same text may end up nested
+ * in a different order, but every location must have the same formats.
+ */
+pub fn assert_idempotent(model: &ComposerModel<u16>, operation: &Operation) {
+    let mut toggled = model.clone();
+    operation.apply(&mut toggled);
+    operation.apply(&mut toggled);
+    let same = toggled.has_same_state(model)
+        || (toggled.get_selection() == model.get_selection()
+            && formats_by_location(&toggled) == formats_by_location(model));
+    if !same {
+        panic!(
+            "{:?} twice changed {:?} into {:?}",
+            operation,
+            describe(model),
+            describe(&toggled)
+        );
+    }
+}
+
+/**
+ * The text of model, and the elements each code unit of it is inside, in
+ * no particular order.
+ */
+fn formats_by_location(
+    model: &ComposerModel<u16>,
+) -> (Vec<u16>, Vec<Vec<String>>) {
+    let dom = parse(&model.get_html());
+    let formats = (0..dom.text_len())
+        .map(|location| {
+            let mut names = FormatSet::around(&dom, location, location + 1)
+                .names()
+                .to_vec();
+            names.sort();
+            names
+        })
+        .collect();
+    (dom.to_plain_text(false), formats)
+}
+
+/**
+ * The content of model in the Editor dialect, and its selection.
+ */
+fn describe(model: &ComposerModel<u16>) -> (String, usize, usize) {
+    let (start, end) = model.get_selection();
+    (
+        String::from_utf16_lossy(&model.get_html()),
+        usize::from(start),
+        usize::from(end),
+    )
+}
+
+/**
+ * Parse vectors from JSON as produced by to_json.
+ */
+pub fn from_json(json: &str) -> Result<Vec<TestVector>, String> {
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+pub fn to_json(vectors: &[TestVector]) -> String {
+    serde_json::to_string_pretty(vectors).expect("Vectors are serializable")
+}
+
+/**
+ * The vectors every platform should pass.
+ */
+pub fn corpus() -> Vec<TestVector> {
+    use Operation::*;
+    vec![
+        vector(
+            "typing_into_empty",
+            "",
+            0,
+            0,
+            vec![text("abc")],
+            "abc",
+            3,
+            3,
+        ),
+        vector(
+            "typing_replaces_the_selection",
+            "abc",
+            1,
+            2,
+            vec![text("XY")],
+            "aXYc",
+            3,
+            3,
+        ),
+        vector(
+            "typing_extends_preceding_formatting",
+            "a<strong>b</strong>",
+            2,
+            2,
+            vec![text("c")],
+            "a<strong>bc</strong>",
+            3,
+            3,
+        ),
+        vector(
+            "typing_after_a_link_does_not_extend_it",
+            "<a href=\"https://matrix.org\">a</a>",
+            1,
+            1,
+            vec![text("b")],
+            "<a href=\"https://matrix.org\">a</a>b",
+            2,
+            2,
+        ),
+        vector(
+            "typing_multibyte_characters",
+            "",
+            0,
+            0,
+            vec![text("\u{1F469}\u{1F3FF}")],
+            "\u{1F469}\u{1F3FF}",
+            4,
+            4,
+        ),
+        vector("backspace", "abc", 3, 3, vec![Backspace], "ab", 2, 2),
+        vector(
+            "backspace_at_the_start",
+            "abc",
+            0,
+            0,
+            vec![Backspace],
+            "abc",
+            0,
+            0,
+        ),
+        vector("delete", "abc", 1, 1, vec![Delete], "ac", 1, 1),
+        vector(
+            "delete_across_formatting",
+            "a<strong>bc</strong>d",
+            2,
+            4,
+            vec![Delete],
+            "a<strong>b</strong>",
+            2,
+            2,
+        ),
+        vector(
+            "delete_in",
+            "abcd",
+            4,
+            4,
+            vec![DeleteIn { start: 1, end: 3 }],
+            "ad",
+            1,
+            1,
+        ),
+        vector(
+            "replace_text_in",
+            "abcd",
+            4,
+            4,
+            vec![ReplaceTextIn {
+                text: String::from("X"),
+                start: 1,
+                end: 3,
+            }],
+            "aXd",
+            2,
+            2,
+        ),
+        vector(
+            "bold_a_selection",
+            "foo",
+            1,
+            2,
+            vec![Bold],
+            "f<strong>o</strong>o",
+            1,
+            2,
+        ),
+        vector(
+            "set_link_with_text",
+            "aabb",
+            2,
+            2,
+            vec![SetLinkWithText {
+                link: String::from("https://matrix.org"),
+                text: String::from("link"),
+            }],
+            "aa<a href=\"https://matrix.org\">link</a>bb",
+            6,
+            6,
+        ),
+        vector("enter_sends", "abc", 3, 3, vec![Enter], "abc", 3, 3),
+        vector(
+            "undo_and_redo",
+            "",
+            0,
+            0,
+            vec![text("a"), text("b"), Undo, Undo, Redo],
+            "a",
+            1,
+            1,
+        ),
+        vector(
+            "typing_after_undo_discards_redo",
+            "",
+            0,
+            0,
+            vec![text("a"), Undo, text("b"), Redo],
+            "b",
+            1,
+            1,
+        ),
+        vector(
+            "select_then_type",
+            "abc",
+            0,
+            0,
+            vec![Select { start: 1, end: 3 }, text("X")],
+            "aX",
+            2,
+            2,
+        ),
+    ]
+}
+
+#[allow(clippy::too_many_arguments)]
+fn vector(
+    name: &str,
+    initial_html: &str,
+    initial_start: usize,
+    initial_end: usize,
+    operations: Vec<Operation>,
+    expected_html: &str,
+    expected_start: usize,
+    expected_end: usize,
+) -> TestVector {
+    TestVector {
+        name: String::from(name),
+        initial_html: String::from(initial_html),
+        initial_start,
+        initial_end,
+        operations,
+        expected_html: String::from(expected_html),
+        expected_start,
+        expected_end,
+    }
+}
+
+fn text(text: &str) -> Operation {
+    Operation::ReplaceText {
+        text: String::from(text),
+    }
+}
+
+fn utf16(text: &str) -> Vec<u16> {
+    text.encode_utf16().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        assert_idempotent, corpus, from_json, to_json, toggles, utf16,
+        Operation,
+    };
+    use crate::dom::parse;
+    use crate::{ComposerModel, FormatSet, Location};
+
+    #[test]
+    fn the_model_passes_every_vector() {
+        let failures: Vec<String> =
+            corpus().iter().filter_map(|v| v.run().err()).collect();
+        assert_eq!(failures, Vec::<String>::new());
+    }
+
+    #[test]
+    fn vectors_survive_a_json_round_trip() {
+        let vectors = corpus();
+        assert_eq!(from_json(&to_json(&vectors)).unwrap(), vectors);
+    }
+
+    #[test]
+    fn toggling_twice_on_any_selection_changes_nothing() {
+        let contents = [
+            "abc",
+            "a<strong>bc</strong>d",
+            "<em>a</em>b<strong><em>c</em></strong>",
+            "a<a href=\"https://x.org\">bc</a>d",
+            "a<br />b",
+            "<p>ab</p><p><em>c</em></p>",
+            "<ol><li>a</li><li><strong>b</strong>c</li></ol>d",
+            "<ul><li>a<ul><li>b</li></ul></li></ul>",
+        ];
+        for html in contents {
+            let mut model = ComposerModel::<u16>::new();
+            model.set_content_from_html(&utf16(html));
+            let dom = parse(&utf16(html));
+            let len = dom.text_len();
+            for start in 0..=len {
+                for end in start..=len {
+                    model.select(Location::from(start), Location::from(end));
+                    for operation in &toggles() {
+                        // Toggling part-formatted text formats all of it
+                        let name = match operation {
+                            Operation::Bold => "strong",
+                            Operation::Italic => "em",
+                            _ => "",
+                        };
+                        let around = FormatSet::around(&dom, start, end);
+                        let within = FormatSet::within(&dom, start, end);
+                        if around.contains(name) || !within.contains(name) {
+                            assert_idempotent(&model, operation);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn operations_are_tagged_by_name() {
+        let json = to_json(&corpus()[..1]);
+        assert!(json.contains("\"op\": \"replace_text\""));
+    }
+}
@@ -12,12 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::Location;
+use crate::{DomHandle, Location};
 
 #[derive(Debug, Clone)]
 pub enum TextUpdate<C> {
     Keep,
     ReplaceAll(ReplaceAll<C>),
+    UpdateTextNode(UpdateTextNode<C>),
 }
 
 #[derive(Debug, Clone)]
@@ -26,3 +27,16 @@ pub struct ReplaceAll<C> {
     pub start: Location,
     pub end: Location,
 }
+
+/**
+ * Only the text of one text node changed: the platform may replace the
+ * text of the node identified by node_handle with new_text instead of
+ * replacing all the content.
+ */
+#[derive(Debug, Clone)]
+pub struct UpdateTextNode<C> {
+    pub node_handle: DomHandle,
+    pub new_text: Vec<C>,
+    pub start: Location,
+    pub end: Location,
+}
@@ -0,0 +1,386 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/*!
+ * An approximation of the Unicode word boundaries of UAX #29, good enough
+ * for selecting the word a user double-clicks. Letters, digits and
+ * connectors such as "_" make up words, as do apostrophes, full stops and
+ * the like between letters ("can't", "e.g") or digits ("3.14", "1,000").
+ * Runs of spaces stay together, and everything else is a segment of its
+ * own.
+ *
+ * Scripts written without spaces between words (Chinese, Japanese, Thai,
+ * Lao, Khmer and Myanmar) cannot be divided into words by rules like
+ * these. Runs of their text are passed to a WordDictionary if there is
+ * one; otherwise each character is a word, except that katakana is kept
+ * together.
+ */
+
+use crate::graphemes::is_extend;
+
+/**
+ * Divides runs of text from scripts written without spaces into words,
+ * e.g. using a platform's dictionary-based word segmentation.
+ */
+pub trait WordDictionary: Send + Sync {
+    /**
+     * The lengths, in characters (Unicode scalar values), of the words that
+     * make up run, in order. locale is the ComposerConfig's word_locale, a
+     * hint about the language of the text. Lengths that do not add up to
+     * the length of run are ignored.
+     */
+    fn words(&self, run: &str, locale: Option<&str>) -> Vec<usize>;
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Class {
+    Letter,
+    Numeric,
+    Katakana,
+    /**
+     * A character from a script written without spaces, other than
+     * katakana.
+     */
+    Spaceless,
+    ExtendNumLet,
+    MidLetter,
+    MidNum,
+    MidNumLet,
+    Space,
+    Other,
+}
+
+impl Class {
+    fn of(ch: char) -> Self {
+        match ch {
+            '\u{30A0}'..='\u{30FF}'
+            | '\u{31F0}'..='\u{31FF}'
+            | '\u{FF66}'..='\u{FF9F}' => Self::Katakana,
+            '\u{0E00}'..='\u{0EFF}'
+            | '\u{1000}'..='\u{109F}'
+            | '\u{1780}'..='\u{17FF}'
+            | '\u{3040}'..='\u{309F}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{3134F}' => Self::Spaceless,
+            '_'
+            | '\u{203F}'
+            | '\u{2040}'
+            | '\u{2054}'
+            | '\u{FE33}'
+            | '\u{FE34}'
+            | '\u{FE4D}'..='\u{FE4F}'
+            | '\u{FF3F}' => Self::ExtendNumLet,
+            ':' | '\u{00B7}' | '\u{0387}' | '\u{05F4}' | '\u{2027}'
+            | '\u{FE13}' | '\u{FE55}' | '\u{FF1A}' => Self::MidLetter,
+            ',' | ';' | '\u{037E}' | '\u{0589}' | '\u{060C}' | '\u{060D}'
+            | '\u{066C}' | '\u{07F8}' | '\u{2044}' | '\u{FE10}'
+            | '\u{FE14}' | '\u{FE50}' | '\u{FE54}' | '\u{FF0C}'
+            | '\u{FF1B}' => Self::MidNum,
+            '.' | '\'' | '\u{2018}' | '\u{2019}' | '\u{2024}' | '\u{FE52}'
+            | '\u{FF07}' | '\u{FF0E}' => Self::MidNumLet,
+            '\n' | '\r' => Self::Other,
+            c if c.is_whitespace() => Self::Space,
+            c if c.is_numeric() => Self::Numeric,
+            c if c.is_alphabetic() => Self::Letter,
+            _ => Self::Other,
+        }
+    }
+
+    fn is_word(self) -> bool {
+        matches!(
+            self,
+            Self::Letter
+                | Self::Numeric
+                | Self::Katakana
+                | Self::Spaceless
+                | Self::ExtendNumLet
+        )
+    }
+
+    fn is_spaceless(self) -> bool {
+        matches!(self, Self::Katakana | Self::Spaceless)
+    }
+
+    fn is_mid_letter(self) -> bool {
+        matches!(self, Self::MidLetter | Self::MidNumLet)
+    }
+
+    fn is_mid_num(self) -> bool {
+        matches!(self, Self::MidNum | Self::MidNumLet)
+    }
+}
+
+/**
+ * The range of characters (as byte offsets into text) of the word around
+ * offset, which must be on a character boundary. If offset is between two
+ * segments, the one after it is preferred unless only the one before it is
+ * a word. None if text is empty.
+ */
+pub(crate) fn word_at(
+    text: &str,
+    offset: usize,
+    dictionary: Option<&dyn WordDictionary>,
+    locale: Option<&str>,
+) -> Option<(usize, usize)> {
+    let segments = segments(text, dictionary, locale);
+    let containing = segments.iter().position(|&(_, e)| offset < e);
+    let before = segments.iter().position(|&(_, e)| e == offset);
+    let is_word = |i: usize| is_word(&text[segments[i].0..segments[i].1]);
+    let chosen = match (before, containing) {
+        (_, Some(i)) if segments[i].0 < offset => Some(i),
+        (Some(b), Some(a)) if !is_word(a) && is_word(b) => Some(b),
+        (before, containing) => containing.or(before),
+    };
+    chosen.map(|i| segments[i])
+}
+
+pub(crate) fn is_word(segment: &str) -> bool {
+    segment
+        .chars()
+        .next()
+        .map_or(false, |c| Class::of(c).is_word())
+}
+
+/**
+ * Divide text into words and the segments between them, as ranges of byte
+ * offsets.
+ */
+fn segments(
+    text: &str,
+    dictionary: Option<&dyn WordDictionary>,
+    locale: Option<&str>,
+) -> Vec<(usize, usize)> {
+    // Each character with the combining marks after it (WB4)
+    let mut units: Vec<(usize, Class)> = Vec::new();
+    for (i, ch) in text.char_indices() {
+        if units.is_empty() || !(is_extend(ch) || ch == '\u{200D}') {
+            units.push((i, Class::of(ch)));
+        }
+    }
+    let class = |i: usize| units.get(i).map(|&(_, class)| class);
+
+    let mut starts = vec![0];
+    let mut i = 1;
+    while i < units.len() {
+        let (prev, cur) = (units[i - 1].1, units[i].1);
+        if prev.is_spaceless() && cur.is_spaceless() {
+            let run_start = i - 1;
+            let mut run_end = i;
+            while class(run_end).map_or(false, Class::is_spaceless) {
+                run_end += 1;
+            }
+            let run = &units[run_start..run_end];
+            let end = units.get(run_end).map_or(text.len(), |&(b, _)| b);
+            starts.extend(spaceless_words(
+                &text[run[0].0..end],
+                run,
+                dictionary,
+                locale,
+            ));
+            starts.push(end);
+            i = run_end + 1;
+            continue;
+        }
+        let next = class(i + 1);
+        let prev2 = i.checked_sub(2).and_then(class);
+        let joined = match (prev, cur) {
+            (Class::Space, Class::Space) => true,
+            (
+                Class::Letter | Class::Numeric,
+                Class::Letter | Class::Numeric,
+            ) => true,
+            (Class::Katakana, Class::Katakana) => true,
+            (
+                Class::Letter
+                | Class::Numeric
+                | Class::Katakana
+                | Class::ExtendNumLet,
+                Class::ExtendNumLet,
+            ) => true,
+            (
+                Class::ExtendNumLet,
+                Class::Letter | Class::Numeric | Class::Katakana,
+            ) => true,
+            (Class::Letter, mid) if mid.is_mid_letter() => {
+                next == Some(Class::Letter)
+            }
+            (mid, Class::Letter) if mid.is_mid_letter() => {
+                prev2 == Some(Class::Letter)
+            }
+            (Class::Numeric, mid) if mid.is_mid_num() => {
+                next == Some(Class::Numeric)
+            }
+            (mid, Class::Numeric) if mid.is_mid_num() => {
+                prev2 == Some(Class::Numeric)
+            }
+            _ => false,
+        };
+        if !joined {
+            starts.push(units[i].0);
+        }
+        i += 1;
+    }
+    starts.push(text.len());
+    starts.dedup();
+    starts.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+/**
+ * The byte offsets, after the start of run (a run of text from scripts
+ * written without spaces made of units), of the starts of the words in it
+ * other than the first. Uses dictionary if its answer fits run.
+ */
+fn spaceless_words(
+    run: &str,
+    units: &[(usize, Class)],
+    dictionary: Option<&dyn WordDictionary>,
+    locale: Option<&str>,
+) -> Vec<usize> {
+    let base = units[0].0;
+    if let Some(dictionary) = dictionary {
+        let lengths = dictionary.words(run, locale);
+        let char_starts: Vec<usize> =
+            run.char_indices().map(|(i, _)| base + i).collect();
+        if lengths.iter().sum::<usize>() == char_starts.len()
+            && lengths.iter().all(|&length| length > 0)
+        {
+            return lengths
+                .iter()
+                .scan(0, |chars, length| {
+                    *chars += length;
+                    Some(*chars)
+                })
+                .filter_map(|chars| char_starts.get(chars).copied())
+                .collect();
+        }
+    }
+    let mut starts = Vec::new();
+    for pair in units.windows(2) {
+        if !(pair[0].1 == Class::Katakana && pair[1].1 == Class::Katakana) {
+            starts.push(pair[1].0);
+        }
+    }
+    starts
+}
+
+#[cfg(test)]
+mod test {
+    use super::{segments, word_at, WordDictionary};
+
+    fn words(text: &str) -> Vec<&str> {
+        segments(text, None, None)
+            .into_iter()
+            .map(|(s, e)| &text[s..e])
+            .collect()
+    }
+
+    fn word(text: &str, offset: usize) -> Option<&str> {
+        word_at(text, offset, None, None).map(|(s, e)| &text[s..e])
+    }
+
+    #[test]
+    fn words_are_split_at_spaces_and_punctuation() {
+        assert_eq!(
+            words("Hello,  wörld!"),
+            vec!["Hello", ",", "  ", "wörld", "!"]
+        );
+    }
+
+    #[test]
+    fn punctuation_inside_words_and_numbers_is_kept() {
+        assert_eq!(
+            words("can't e.g. 3.14 1,000 snake_case"),
+            vec![
+                "can't",
+                " ",
+                "e.g",
+                ".",
+                " ",
+                "3.14",
+                " ",
+                "1,000",
+                " ",
+                "snake_case"
+            ]
+        );
+        assert_eq!(
+            words("end. 'quoted'"),
+            vec!["end", ".", " ", "'", "quoted", "'"]
+        );
+    }
+
+    #[test]
+    fn combining_marks_stay_with_their_character() {
+        assert_eq!(words("cafe\u{301} x"), vec!["cafe\u{301}", " ", "x"]);
+    }
+
+    #[test]
+    fn spaceless_scripts_are_split_per_character_without_a_dictionary() {
+        assert_eq!(words("日本語テキスト"), vec!["日", "本", "語", "テキスト"]);
+    }
+
+    struct Pairs;
+
+    impl WordDictionary for Pairs {
+        fn words(&self, run: &str, locale: Option<&str>) -> Vec<usize> {
+            assert_eq!(locale, Some("ja"));
+            let n = run.chars().count();
+            let mut lengths = vec![2; n / 2];
+            if n % 2 == 1 {
+                lengths.push(1);
+            }
+            lengths
+        }
+    }
+
+    struct Wrong;
+
+    impl WordDictionary for Wrong {
+        fn words(&self, _run: &str, _locale: Option<&str>) -> Vec<usize> {
+            vec![1, 7]
+        }
+    }
+
+    #[test]
+    fn a_dictionary_splits_spaceless_runs() {
+        let text = "a 日本語です";
+        let found: Vec<&str> = segments(text, Some(&Pairs), Some("ja"))
+            .into_iter()
+            .map(|(s, e)| &text[s..e])
+            .collect();
+        assert_eq!(found, vec!["a", " ", "日本", "語で", "す"]);
+    }
+
+    #[test]
+    fn a_dictionary_that_does_not_fit_the_run_is_ignored() {
+        let text = "日本";
+        let found: Vec<&str> = segments(text, Some(&Wrong), None)
+            .into_iter()
+            .map(|(s, e)| &text[s..e])
+            .collect();
+        assert_eq!(found, vec!["日", "本"]);
+    }
+
+    #[test]
+    fn the_word_after_a_boundary_is_preferred() {
+        assert_eq!(word("ab cd", 1), Some("ab"));
+        assert_eq!(word("ab cd", 3), Some("cd"));
+        assert_eq!(word("ab cd", 2), Some("ab"));
+        assert_eq!(word("ab cd", 5), Some("cd"));
+        assert_eq!(word("a  b", 2), Some("  "));
+        assert_eq!(word("", 0), None);
+    }
+}
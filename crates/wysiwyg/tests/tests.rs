@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use wysiwyg::{ComposerModel, Location, TextUpdate};
+use wysiwyg::{
+    parse, ComposerModel, DomNode, HtmlDialect, Location, TextUpdate,
+    MATRIX_LINK_SCHEMES,
+};
 
 #[test]
 fn can_instantiate_a_model_and_call_methods() {
@@ -33,3 +36,28 @@ fn can_instantiate_a_model_and_call_methods() {
         panic!("Expected to receive a ReplaceAll response");
     }
 }
+
+#[test]
+fn can_use_the_dom_without_a_model() {
+    let mut dom = parse(
+        &"<a href=\"https://x.org\">a</a>b<marquee>c</marquee>"
+            .encode_utf16()
+            .collect::<Vec<_>>(),
+    );
+    let link = dom.children(dom.document())[0];
+    if let DomNode::Container(a) = dom.node(link) {
+        assert_eq!(a.name(), "a");
+        assert_eq!(
+            String::from_utf16(a.attr("href").unwrap()).unwrap(),
+            "https://x.org"
+        );
+    } else {
+        panic!("Expected the first child to be a link");
+    }
+
+    dom.sanitize(MATRIX_LINK_SCHEMES);
+    assert_eq!(
+        String::from_utf16(&dom.to_html(HtmlDialect::MatrixMessage)).unwrap(),
+        "<a href=\"https://x.org\">a</a>bc"
+    );
+}
@@ -25,7 +25,11 @@ async function wysiwyg_run() {
 function editor_input(e) {
     const update = process_input(e);
     if (update) {
-        const repl = update.text_update().replace_all;
+        for (const diagnostic of update.diagnostics()) {
+            console.warn(diagnostic);
+        }
+        const text_update = update.text_update();
+        const repl = text_update.replace_all;
         if (repl) {
             replace_editor(
                 repl.replacement_html,
@@ -33,6 +37,10 @@ function editor_input(e) {
                 repl.end_utf16_codeunit
             );
         }
+        const node_update = text_update.update_text_node;
+        if (node_update) {
+            update_text_node(node_update.node_handle, node_update.new_text);
+        }
     }
 }
 
@@ -204,6 +212,23 @@ function replace_editor(html, start_utf16_codeunit, end_utf16_codeunit) {
     sr();
 }
 
+function update_text_node(node_handle, new_text) {
+    console.log("update_text_node", node_handle, new_text);
+    let node = editor;
+    for (const index of node_handle) {
+        node = node && node.childNodes[index];
+    }
+    if (node && node.nodeType === Node.TEXT_NODE) {
+        // Usually the browser has already made this change, so leave the
+        // node (and the caret within it) alone if it already matches.
+        if (node.data !== new_text) {
+            node.data = new_text;
+        }
+    } else {
+        console.error("Failed to find text node", node_handle);
+    }
+}
+
 function process_input(e) {
     switch (e.inputType) {
         case "deleteContentBackward":
@@ -217,6 +242,11 @@ function process_input(e) {
             return composer_model.bold();
         case "insertFromPaste":
         {
+            const html = e.dataTransfer.getData("text/html");
+            if (html) {
+                console.debug(`composer_model.paste_html(${html})`);
+                return composer_model.paste_html(html);
+            }
             const data = e.dataTransfer.getData("text");
             console.debug(`composer_model.replace_text(${data})`);
             return composer_model.replace_text(data);